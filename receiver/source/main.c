@@ -0,0 +1,555 @@
+// The cargo-skyline receiver, which carries out the file operations `cargo skyline install
+// --transport usb` sends over USB on the SD card.
+//
+// Every command is a block of BLOCK_LEN bytes: the magic "SKYI", a u32 command and then its
+// arguments, where paths are a u32 length followed by that many bytes of UTF-8. Every response
+// is a block of the same size: the magic "SKYO", a u32 status (0 or an errno) and then either the
+// command's results or, on failure, a message in the same form as a path. Numbers are little
+// endian. File contents and listings are streamed after the block they belong to, in chunks of
+// at most CHUNK_SIZE bytes.
+//
+// This has to stay in step with src/usb/client.rs, bump PROTOCOL_VERSION in both when changing it.
+
+#include <dirent.h>
+#include <errno.h>
+#include <malloc.h>
+#include <stdarg.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <sys/statvfs.h>
+#include <unistd.h>
+
+#include <switch.h>
+
+#define BLOCK_LEN 0x1000
+#define CHUNK_SIZE 0x100000
+#define PROTOCOL_VERSION 1
+
+// leaves room for the "sdmc:" prefix and a ".old" suffix within FS_MAX_PATH
+#define MAX_PATH_LEN 0x2F0
+
+enum Command {
+    COMMAND_VERSION = 0,
+    COMMAND_MAKE_DIR = 1,
+    COMMAND_PUT = 2,
+    COMMAND_STAT = 3,
+    COMMAND_LIST = 4,
+    COMMAND_RENAME = 5,
+    COMMAND_REMOVE = 6,
+    COMMAND_GET = 7,
+    COMMAND_FREE_SPACE = 8,
+};
+
+enum Kind {
+    KIND_MISSING = 0,
+    KIND_FILE = 1,
+    KIND_DIRECTORY = 2,
+};
+
+typedef struct {
+    u8 *data;
+    size_t offset;
+} Block;
+
+static u8 *command_block;
+static u8 *response_block;
+static u8 *chunk;
+
+static Mutex console_lock;
+
+static void log_line(const char *format, ...) {
+    va_list args;
+    va_start(args, format);
+
+    mutexLock(&console_lock);
+    vprintf(format, args);
+    printf("\n");
+    mutexUnlock(&console_lock);
+
+    va_end(args);
+}
+
+// usbComms can complete a transfer in pieces, so keep going until all of it has been moved
+static bool usb_read(void *buffer, size_t len) {
+    u8 *out = buffer;
+    while (len > 0) {
+        size_t read = usbCommsRead(out, len);
+        if (read == 0) {
+            return false;
+        }
+        out += read;
+        len -= read;
+    }
+
+    return true;
+}
+
+static bool usb_write(const void *buffer, size_t len) {
+    const u8 *in = buffer;
+    while (len > 0) {
+        size_t written = usbCommsWrite(in, len);
+        if (written == 0) {
+            return false;
+        }
+        in += written;
+        len -= written;
+    }
+
+    return true;
+}
+
+static bool take(Block *block, void *out, size_t len) {
+    if (block->offset + len > BLOCK_LEN) {
+        return false;
+    }
+
+    memcpy(out, block->data + block->offset, len);
+    block->offset += len;
+
+    return true;
+}
+
+static bool take_u32(Block *block, u32 *out) {
+    return take(block, out, sizeof(*out));
+}
+
+static bool take_u64(Block *block, u64 *out) {
+    return take(block, out, sizeof(*out));
+}
+
+// Read a path and turn it into one on the SD card
+static bool take_path(Block *block, char *out) {
+    u32 len;
+    if (!take_u32(block, &len) || len == 0 || len >= MAX_PATH_LEN) {
+        return false;
+    }
+
+    memcpy(out, "sdmc:", 5);
+    if (!take(block, out + 5, len)) {
+        return false;
+    }
+    out[5 + len] = '\0';
+
+    // the argument is always absolute, and never allowed to climb out of it
+    size_t end = 5 + len;
+    return out[5] == '/' && strstr(out, "/../") == NULL && !(len >= 3 && strcmp(out + end - 3, "/..") == 0);
+}
+
+static void begin_response(Block *block, u32 status) {
+    memset(response_block, 0, BLOCK_LEN);
+    block->data = response_block;
+    block->offset = 0;
+
+    memcpy(block->data, "SKYO", 4);
+    memcpy(block->data + 4, &status, sizeof(status));
+    block->offset = 8;
+}
+
+static void push(Block *block, const void *data, size_t len) {
+    if (block->offset + len <= BLOCK_LEN) {
+        memcpy(block->data + block->offset, data, len);
+        block->offset += len;
+    }
+}
+
+static void push_u32(Block *block, u32 value) {
+    push(block, &value, sizeof(value));
+}
+
+static void push_u64(Block *block, u64 value) {
+    push(block, &value, sizeof(value));
+}
+
+static bool send_ok(void) {
+    Block block;
+    begin_response(&block, 0);
+
+    return usb_write(response_block, BLOCK_LEN);
+}
+
+static bool send_error(int err, const char *what, const char *path) {
+    Block block;
+    begin_response(&block, err != 0 ? err : EIO);
+
+    char message[0x400];
+    int len = path[0] != '\0'
+        ? snprintf(message, sizeof(message), "%s %s: %s", what, path, strerror(err))
+        : snprintf(message, sizeof(message), "%s: %s", what, strerror(err));
+    if (len < 0) {
+        len = 0;
+    } else if ((size_t)len >= sizeof(message)) {
+        len = sizeof(message) - 1;
+    }
+
+    push_u32(&block, len);
+    push(&block, message, len);
+
+    log_line("Failed: %s", message);
+
+    return usb_write(response_block, BLOCK_LEN);
+}
+
+// Create a folder along with any of its parents that are missing
+static int make_dirs(char *path) {
+    // skip over "sdmc:/"
+    for (char *slash = strchr(path + 6, '/'); slash != NULL; slash = strchr(slash + 1, '/')) {
+        *slash = '\0';
+        int result = mkdir(path, 0777);
+        *slash = '/';
+
+        if (result != 0 && errno != EEXIST) {
+            return errno;
+        }
+    }
+
+    if (mkdir(path, 0777) != 0 && errno != EEXIST) {
+        return errno;
+    }
+
+    return 0;
+}
+
+static bool handle_put(Block *command, const char *path) {
+    u64 len;
+    if (!take_u64(command, &len)) {
+        return send_error(EINVAL, "Malformed upload of", path);
+    }
+
+    FILE *file = fopen(path, "wb");
+    if (file == NULL) {
+        // the contents only get sent once the file has been opened
+        return send_error(errno, "Couldn't open", path);
+    }
+
+    if (!send_ok()) {
+        fclose(file);
+        return false;
+    }
+
+    // everything sent has to be read even if writing fails, or the next command would be read
+    // out of the middle of the file
+    int err = 0;
+    for (u64 remaining = len; remaining > 0;) {
+        size_t size = remaining < CHUNK_SIZE ? remaining : CHUNK_SIZE;
+        if (!usb_read(chunk, size)) {
+            fclose(file);
+            unlink(path);
+            return false;
+        }
+
+        if (err == 0 && fwrite(chunk, 1, size, file) != size) {
+            err = errno != 0 ? errno : EIO;
+        }
+        remaining -= size;
+    }
+
+    if (fclose(file) != 0 && err == 0) {
+        err = errno;
+    }
+
+    if (err != 0) {
+        unlink(path);
+        return send_error(err, "Couldn't write", path);
+    }
+
+    log_line("Received %s (%llu bytes)", path + 5, (unsigned long long)len);
+
+    return send_ok();
+}
+
+static bool handle_get(const char *path) {
+    FILE *file = fopen(path, "rb");
+    if (file == NULL) {
+        return send_error(errno, "Couldn't open", path);
+    }
+
+    struct stat info;
+    if (fstat(fileno(file), &info) != 0) {
+        int err = errno;
+        fclose(file);
+        return send_error(err, "Couldn't read", path);
+    }
+
+    Block block;
+    begin_response(&block, 0);
+    push_u64(&block, info.st_size);
+    if (!usb_write(response_block, BLOCK_LEN)) {
+        fclose(file);
+        return false;
+    }
+
+    // the length has already been promised, so a failed read is padded out and reported after
+    int err = 0;
+    for (u64 remaining = info.st_size; remaining > 0;) {
+        size_t size = remaining < CHUNK_SIZE ? remaining : CHUNK_SIZE;
+
+        size_t read = err == 0 ? fread(chunk, 1, size, file) : 0;
+        if (read != size) {
+            if (err == 0) {
+                err = errno != 0 ? errno : EIO;
+            }
+            memset(chunk + read, 0, size - read);
+        }
+
+        if (!usb_write(chunk, size)) {
+            fclose(file);
+            return false;
+        }
+        remaining -= size;
+    }
+    fclose(file);
+
+    if (err != 0) {
+        return send_error(err, "Couldn't read", path);
+    }
+
+    return send_ok();
+}
+
+static bool handle_stat(const char *path) {
+    struct stat info;
+    if (stat(path, &info) != 0) {
+        if (errno != ENOENT) {
+            return send_error(errno, "Couldn't check", path);
+        }
+
+        Block block;
+        begin_response(&block, 0);
+        push_u32(&block, KIND_MISSING);
+        return usb_write(response_block, BLOCK_LEN);
+    }
+
+    Block block;
+    begin_response(&block, 0);
+    push_u32(&block, S_ISDIR(info.st_mode) ? KIND_DIRECTORY : KIND_FILE);
+    push_u64(&block, info.st_size);
+    push_u64(&block, info.st_mtime);
+
+    return usb_write(response_block, BLOCK_LEN);
+}
+
+static bool handle_list(const char *path) {
+    DIR *dir = opendir(path);
+    if (dir == NULL) {
+        return send_error(errno, "Couldn't list", path);
+    }
+
+    // names separated by newlines, like an FTP NLST
+    char *names = NULL;
+    size_t len = 0;
+    for (struct dirent *entry = readdir(dir); entry != NULL; entry = readdir(dir)) {
+        if (strcmp(entry->d_name, ".") == 0 || strcmp(entry->d_name, "..") == 0) {
+            continue;
+        }
+
+        size_t name_len = strlen(entry->d_name);
+        char *grown = realloc(names, len + name_len + 1);
+        if (grown == NULL) {
+            free(names);
+            closedir(dir);
+            return send_error(ENOMEM, "Couldn't list", path);
+        }
+        names = grown;
+
+        memcpy(names + len, entry->d_name, name_len);
+        names[len + name_len] = '\n';
+        len += name_len + 1;
+    }
+    closedir(dir);
+
+    Block block;
+    begin_response(&block, 0);
+    push_u64(&block, len);
+
+    bool sent = usb_write(response_block, BLOCK_LEN);
+    for (size_t offset = 0; sent && offset < len; offset += CHUNK_SIZE) {
+        size_t size = len - offset < CHUNK_SIZE ? len - offset : CHUNK_SIZE;
+        sent = usb_write(names + offset, size);
+    }
+    free(names);
+
+    return sent;
+}
+
+// The SD card won't rename over an existing file, so whatever is being replaced is moved out of
+// the way first and only deleted once the new file is in place. A failure part way through leaves
+// the old file where it was.
+static bool handle_rename(const char *from, const char *to) {
+    struct stat info;
+    if (stat(to, &info) != 0) {
+        if (rename(from, to) != 0) {
+            return send_error(errno, "Couldn't rename", from);
+        }
+
+        log_line("Installed %s", to + 5);
+        return send_ok();
+    }
+
+    char aside[MAX_PATH_LEN + 0x10];
+    snprintf(aside, sizeof(aside), "%s.old", to);
+    unlink(aside);
+
+    if (rename(to, aside) != 0) {
+        return send_error(errno, "Couldn't move aside", to);
+    }
+
+    if (rename(from, to) != 0) {
+        int err = errno;
+        rename(aside, to);
+        return send_error(err, "Couldn't rename", from);
+    }
+
+    unlink(aside);
+    log_line("Installed %s", to + 5);
+
+    return send_ok();
+}
+
+static bool handle_remove(const char *path) {
+    struct stat info;
+    int result = stat(path, &info) == 0 && S_ISDIR(info.st_mode) ? rmdir(path) : unlink(path);
+    if (result != 0) {
+        return send_error(errno, "Couldn't remove", path);
+    }
+
+    log_line("Removed %s", path + 5);
+
+    return send_ok();
+}
+
+static bool handle_free_space(void) {
+    struct statvfs info;
+    if (statvfs("sdmc:/", &info) != 0) {
+        return send_error(errno, "Couldn't check the free space of", "sdmc:/");
+    }
+
+    Block block;
+    begin_response(&block, 0);
+    push_u64(&block, (u64)info.f_bavail * info.f_frsize);
+
+    return usb_write(response_block, BLOCK_LEN);
+}
+
+// Carry out a single command, returning false if the connection was lost
+static bool handle_command(void) {
+    if (!usb_read(command_block, BLOCK_LEN)) {
+        return false;
+    }
+
+    Block command = { command_block, 0 };
+
+    char magic[4];
+    u32 id;
+    take(&command, magic, sizeof(magic));
+    take_u32(&command, &id);
+    if (memcmp(magic, "SKYI", 4) != 0) {
+        return send_error(EINVAL, "Unknown command block", "");
+    }
+
+    static char path[MAX_PATH_LEN + 5];
+    static char other[MAX_PATH_LEN + 5];
+
+    if (id == COMMAND_VERSION) {
+        Block block;
+        begin_response(&block, 0);
+        push_u32(&block, PROTOCOL_VERSION);
+
+        log_line("Connected to cargo-skyline");
+        return usb_write(response_block, BLOCK_LEN);
+    }
+
+    if (id == COMMAND_FREE_SPACE) {
+        return handle_free_space();
+    }
+
+    if (!take_path(&command, path)) {
+        return send_error(EINVAL, "Malformed path", "");
+    }
+
+    switch (id) {
+        case COMMAND_MAKE_DIR: {
+            int err = make_dirs(path);
+            return err == 0 ? send_ok() : send_error(err, "Couldn't create", path);
+        }
+        case COMMAND_PUT:
+            return handle_put(&command, path);
+        case COMMAND_STAT:
+            return handle_stat(path);
+        case COMMAND_LIST:
+            return handle_list(path);
+        case COMMAND_RENAME:
+            if (!take_path(&command, other)) {
+                return send_error(EINVAL, "Malformed destination for", path);
+            }
+            return handle_rename(path, other);
+        case COMMAND_REMOVE:
+            return handle_remove(path);
+        case COMMAND_GET:
+            return handle_get(path);
+        default:
+            return send_error(ENOSYS, "Unknown command for", path);
+    }
+}
+
+static void serve(void *arg) {
+    (void)arg;
+
+    for (;;) {
+        if (!handle_command()) {
+            // usually the cable being unplugged, wait for the next connection
+            svcSleepThread(100000000ULL);
+        }
+    }
+}
+
+int main(int argc, char **argv) {
+    (void)argc;
+    (void)argv;
+
+    consoleInit(NULL);
+    mutexInit(&console_lock);
+
+    padConfigureInput(1, HidNpadStyleSet_NpadStandard);
+    PadState pad;
+    padInitializeDefault(&pad);
+
+    printf("cargo-skyline receiver\n\n");
+
+    command_block = memalign(0x1000, BLOCK_LEN);
+    response_block = memalign(0x1000, BLOCK_LEN);
+    chunk = memalign(0x1000, CHUNK_SIZE);
+
+    Thread thread;
+    Result rc = command_block && response_block && chunk ? usbCommsInitialize() : MAKERESULT(Module_Libnx, LibnxError_OutOfMemory);
+    if (R_SUCCEEDED(rc)) {
+        rc = threadCreate(&thread, serve, NULL, NULL, 0x10000, 0x2C, -2);
+    }
+    if (R_SUCCEEDED(rc)) {
+        rc = threadStart(&thread);
+    }
+
+    if (R_SUCCEEDED(rc)) {
+        printf("Plug the switch in and run `cargo skyline install --transport usb`\n");
+    } else {
+        printf("Couldn't start listening over USB: 0x%x\n", rc);
+    }
+    printf("Press + to exit\n\n");
+
+    while (appletMainLoop()) {
+        padUpdate(&pad);
+        if (padGetButtonsDown(&pad) & HidNpadButton_Plus) {
+            break;
+        }
+
+        mutexLock(&console_lock);
+        consoleUpdate(NULL);
+        mutexUnlock(&console_lock);
+    }
+
+    // The serving thread is left running rather than joined, as it's usually blocked waiting
+    // for the next command. Exiting the process tears it and the USB interface down.
+    consoleExit(NULL);
+    return 0;
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::fs;
+use structopt::StructOpt;
+use owo_colors::OwoColorize;
+use crate::build::{self, format_size};
+use crate::error::Result;
+use crate::symbols::{Elf, Symbol, SymbolKind};
+
+/// Flags for `bloat`
+#[derive(StructOpt)]
+pub struct BloatOptions {
+    #[structopt(long)]
+    pub release: bool,
+
+    #[structopt(long, conflicts_with = "release", help = "Build with a custom cargo profile, such as one tuned for size")]
+    pub profile: Option<String>,
+
+    #[structopt(short, long, help = "Package in the workspace to look into")]
+    pub package: Option<String>,
+
+    #[structopt(short = "n", long, default_value = "30", help = "How many of the biggest symbols or crates to list")]
+    pub count: usize,
+
+    #[structopt(long, help = "Add up the size of each crate instead of listing symbols")]
+    pub crates: bool,
+}
+
+/// The crate a demangled symbol came from, going by the first part of its path. Trait impls such as
+/// `<alloc::vec::Vec<T> as core::ops::Drop>::drop` count towards the type's crate.
+fn crate_of(demangled: &str) -> &str {
+    let path = demangled.trim_start_matches('<').trim_start_matches('&').trim_start_matches("mut ");
+
+    match path.find("::") {
+        Some(end) if path[..end].chars().all(|c| c.is_alphanumeric() || c == '_') => &path[..end],
+        _ => "[Unknown]"
+    }
+}
+
+fn more(count: usize, what: &str) -> String {
+    format!("{} more {}{}", count, what, if count == 1 { "" } else { "s" })
+}
+
+fn percent(size: u64, total: u64) -> f64 {
+    if total == 0 { 0.0 } else { size as f64 * 100.0 / total as f64 }
+}
+
+/// Build the plugin and break down where its size comes from, using the ELF from before it's stripped
+pub fn bloat(mut args: Vec<String>, options: BloatOptions) -> Result<()> {
+    build::profile_args(&mut args, options.release, options.profile.as_deref());
+    if let Some(package) = &options.package {
+        build::package_args(&mut args, std::slice::from_ref(package));
+    }
+
+    let (elf_path, nro_path) = build::build_get_elf_and_nro(args, false)?;
+    let nro_size = fs::metadata(&nro_path)?.len();
+    let data = fs::read(&elf_path)?;
+    let elf = Elf::parse(&data)?;
+
+    println!(
+        "{} is {}, built from {} ({} with symbols and debug info)\n",
+        nro_path.file_name().unwrap_or_default().to_string_lossy().bright_blue(),
+        format_size(nro_size),
+        elf_path.file_name().unwrap_or_default().to_string_lossy(),
+        format_size(data.len() as u64),
+    );
+
+    // only what's loaded ends up in the NRO, and .bss costs memory without taking up any of the file
+    let mut sections: Vec<_> = elf.sections.iter().filter(|section| section.is_loaded() && section.size != 0).collect();
+    sections.sort_by_key(|section| section.addr);
+
+    println!("{:<24} {:>12} {:>9}", "Section", "Size", "% of NRO");
+    for section in &sections {
+        if section.has_data() {
+            println!("{:<24} {:>12} {:>8.1}%", section.name, format_size(section.size), percent(section.size, nro_size));
+        } else {
+            println!("{:<24} {:>12} {:>9}", section.name, format_size(section.size), "memory");
+        }
+    }
+
+    // aliases share an address, so only count each one once
+    let mut symbols: Vec<Symbol> = elf.symbols()
+        .into_iter()
+        .filter(|symbol| symbol.size != 0 && symbol.kind != SymbolKind::Other)
+        .collect();
+    symbols.dedup_by_key(|symbol| symbol.addr);
+
+    if symbols.is_empty() {
+        println!("\nThe ELF has no symbols to break down, it may have been stripped by its profile");
+        return Ok(());
+    }
+
+    let text_size: u64 = sections.iter().filter(|section| section.name.starts_with(".text")).map(|section| section.size).sum();
+    let function_total: u64 = symbols.iter().filter(|symbol| symbol.kind == SymbolKind::Function).map(|symbol| symbol.size).sum();
+    let named: Vec<(u64, String)> = symbols.iter()
+        .map(|symbol| (symbol.size, format!("{:#}", rustc_demangle::demangle(&symbol.name))))
+        .collect();
+
+    println!();
+    if options.crates {
+        let mut crates: HashMap<&str, u64> = HashMap::new();
+        for (size, name) in &named {
+            *crates.entry(crate_of(name)).or_default() += size;
+        }
+
+        let mut crates: Vec<_> = crates.into_iter().collect();
+        crates.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+        println!("{:>9} {:>12}  Crate", "% of NRO", "Size");
+        for (name, size) in crates.iter().take(options.count) {
+            println!("{:>8.1}% {:>12}  {}", percent(*size, nro_size), format_size(*size), name);
+        }
+
+        let rest: u64 = crates.iter().skip(options.count).map(|(_, size)| size).sum();
+        if rest != 0 {
+            println!("{:>8.1}% {:>12}  {}", percent(rest, nro_size), format_size(rest), more(crates.len() - options.count, "crate"));
+        }
+    } else {
+        let mut named = named;
+        named.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+
+        println!("{:>9} {:>12}  {:<16} Name", "% of NRO", "Size", "Crate");
+        for (size, name) in named.iter().take(options.count) {
+            println!("{:>8.1}% {:>12}  {:<16} {}", percent(*size, nro_size), format_size(*size), crate_of(name), name);
+        }
+
+        let rest: u64 = named.iter().skip(options.count).map(|(size, _)| size).sum();
+        if rest != 0 {
+            println!("{:>8.1}% {:>12}  {:<16} {}", percent(rest, nro_size), format_size(rest), "", more(named.len() - options.count, "symbol"));
+        }
+    }
+
+    // anything left over is padding, or code the linker didn't give a symbol such as PLT stubs
+    println!("\nFunctions make up {} of the {} in .text", format_size(function_total), format_size(text_size));
+
+    Ok(())
+}
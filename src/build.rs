@@ -1,9 +1,14 @@
 use std::process::{Command, Stdio};
-use cargo_metadata::Message;
+use cargo_metadata::{Artifact, Message, PackageId};
 use crate::error::{Result, Error};
-use std::path::PathBuf;
+use crate::cargo_info;
+use crate::symbols;
+use crate::build_info;
+use std::path::{Path, PathBuf};
 use std::env;
-use linkle::format::nxo::NxoFile;
+use linkle::format::{nacp::NacpFile, nxo::NxoFile};
+use structopt::StructOpt;
+use owo_colors::OwoColorize;
 
 fn get_toolchain_bin_dir() -> Result<PathBuf> {
     let rel_path = if cfg!(windows) {
@@ -46,10 +51,13 @@ pub fn clippy() -> Result<()> {
 }
 
 pub fn build_get_artifact(args: Vec<String>) -> Result<PathBuf> {
-    cargo_run_command(CargoCommand::Build, args)?.ok_or(Error::FailParseCargoStream)
+    cargo_run_command(CargoCommand::Build, args)?
+        .pop()
+        .map(|artifact| artifact.filenames[0].clone())
+        .ok_or(Error::FailParseCargoStream)
 }
 
-fn cargo_run_command(command: CargoCommand, args: Vec<String>) -> Result<Option<PathBuf>> {
+fn cargo_run_command(command: CargoCommand, args: Vec<String>) -> Result<Vec<Artifact>> {
     // Ensure rust-lld is added to the PATH on Windows
     if !Command::new("rust-lld").stdout(Stdio::null()).stderr(Stdio::null()).status().is_ok() || cfg!(windows) {
         let toolchain_bin_dir = get_toolchain_bin_dir()?;
@@ -80,6 +88,8 @@ fn cargo_run_command(command: CargoCommand, args: Vec<String>) -> Result<Option<
 
     let current_dir = std::env::current_dir()?;
     let xargo_dir = current_dir.join("..").join("rust-std-skyline-squashed").join("src");
+    let XargoEnv { target_args, target_path, rustflags, metadata } = xargo_env(&current_dir);
+    let is_build = matches!(command, CargoCommand::Build);
 
     let mut command =
         Command::new("xargo")
@@ -88,16 +98,18 @@ fn cargo_run_command(command: CargoCommand, args: Vec<String>) -> Result<Option<
                 "--message-format=json-diagnostic-rendered-ansi", "--color", "always"
             ])
             .args(args)
+            .args(target_args)
             .current_dir(env::current_dir()?)
             // Needed to make crates.io crates use the custom target
-            .env("RUST_TARGET_PATH", current_dir)
+            .env("RUST_TARGET_PATH", target_path)
+            .envs(rustflags.map(|rustflags| ("RUSTFLAGS", rustflags)))
             // ensure xargo can find the rust std
             .env("XARGO_RUST_SRC", xargo_dir)
             .stdout(Stdio::piped())
             .spawn()
             .unwrap();
 
-    let last_artifact =
+    let artifacts: Vec<Artifact> =
         cargo_metadata::parse_messages(command.stdout.as_mut().unwrap())
             .collect::<std::result::Result<Vec<_>, _>>()
             .map_err(|_| Error::FailParseCargoStream)?
@@ -115,38 +127,269 @@ fn cargo_run_command(command: CargoCommand, args: Vec<String>) -> Result<Option<
                     None
                 }
             })
-            .last();
+            .collect();
 
     let exit_status = command.wait().unwrap();
 
     if !exit_status.success() {
-        Err(Error::ExitStatus(exit_status.code().unwrap_or(1)))
-    } else if let Some(artifact) = last_artifact {
-        Ok(Some(artifact.filenames[0].clone()))
-    } else {
-        Ok(None)
+        return Err(Error::ExitStatus(exit_status.code().unwrap_or(1)));
+    }
+
+    if let Some(metadata) = metadata.filter(|metadata| is_build && metadata.embed_build_info) {
+        build_info::stamp(&artifacts, &metadata)?;
+    }
+
+    Ok(artifacts)
+}
+
+/// What the project's metadata changes about how xargo is run
+struct XargoEnv {
+    /// The `--target` to build with, if the project has its own target spec
+    target_args: Vec<String>,
+    /// Where xargo should look for the target spec
+    target_path: PathBuf,
+    /// RUSTFLAGS with the extra flags from `[package.metadata.skyline.build]` added, if there are any
+    rustflags: Option<String>,
+    metadata: Option<cargo_info::Metadata>,
+}
+
+fn xargo_env(current_dir: &Path) -> XargoEnv {
+    let mut xargo_env = XargoEnv { target_args: vec![], target_path: current_dir.to_owned(), rustflags: None, metadata: None };
+
+    let metadata = match cargo_info::get_metadata() {
+        Ok(metadata) => metadata,
+        Err(_) => return xargo_env
+    };
+
+    if let Some(dir) = metadata.target_spec.as_ref().and_then(|spec| spec.parent()) {
+        xargo_env.target_args = vec![String::from("--target"), metadata.target_name()];
+        xargo_env.target_path = dir.to_owned();
+    }
+
+    let mut extra_flags = metadata.rustflags.clone();
+
+    // the same flags go to every command, so switching between check and build doesn't rebuild everything
+    if metadata.embed_build_info {
+        if let Ok(object) = build_info::note_object_path(&metadata) {
+            extra_flags.push(format!("-Clink-arg={}", object.display()));
+        }
+    }
+
+    // keep anything already in RUSTFLAGS rather than replacing it
+    if !extra_flags.is_empty() {
+        let mut rustflags: Vec<String> = env::var("RUSTFLAGS").ok().into_iter().filter(|flags| !flags.is_empty()).collect();
+        rustflags.extend(extra_flags);
+        xargo_env.rustflags = Some(rustflags.join(" "));
+    }
+
+    xargo_env.metadata = Some(metadata);
+
+    xargo_env
+}
+
+/// Find the most recently built copy of a plugin, from a build with any profile
+pub fn latest_built_nro(metadata: &cargo_info::Metadata, name: &str) -> Option<PathBuf> {
+    std::fs::read_dir(metadata.target_dir.join(metadata.target_name())).ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|profile_dir| profile_dir.path().join(name))
+        .filter_map(|path| Some((std::fs::metadata(&path).ok()?.modified().ok()?, path)))
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+}
+
+pub fn build_get_nro(args: Vec<String>, strip: bool) -> Result<PathBuf> {
+    build_get_elf_and_nro(args, strip).map(|(_, nro_path)| nro_path)
+}
+
+/// Build a plugin, giving back the ELF cargo built along with the NRO made from it
+pub fn build_get_elf_and_nro(args: Vec<String>, strip: bool) -> Result<(PathBuf, PathBuf)> {
+    let artifact = cargo_run_command(CargoCommand::Build, args)?
+        .pop()
+        .ok_or(Error::FailParseCargoStream)?;
+    let metadata = cargo_info::get_metadata().ok();
+    let elf_path = artifact.filenames[0].clone();
+    let nro_path = elf_to_nro(&elf_path, metadata.as_ref(), &artifact.package_id, strip)?;
+
+    Ok((elf_path, nro_path))
+}
+
+/// The NACP to embed in a package's NRO, if it's homebrew rather than a plugin
+fn nacp_for<'a>(metadata: Option<&'a cargo_info::Metadata>, id: &PackageId) -> Option<&'a cargo_info::Nacp> {
+    let metadata = metadata?;
+
+    match metadata.members.iter().find(|member| member.id == *id) {
+        Some(member) => member.nacp.as_ref(),
+        None => metadata.nacp.as_ref()
+    }
+}
+
+/// How big a package's NRO is allowed to get, if it or the workspace sets a budget
+fn size_budget_for(metadata: Option<&cargo_info::Metadata>, id: &PackageId) -> Option<cargo_info::SizeBudget> {
+    let metadata = metadata?;
+
+    match metadata.members.iter().find(|member| member.id == *id) {
+        Some(member) => member.size_budget,
+        None => metadata.size_budget
     }
 }
 
-pub fn build_get_nro(args: Vec<String>) -> Result<PathBuf> {
-    let artifact = build_get_artifact(args)?;
+/// Build several plugins at once, such as with `--workspace`, giving back which package each NRO
+/// belongs to
+pub fn build_get_nros(args: Vec<String>, strip: bool) -> Result<Vec<(PackageId, PathBuf)>> {
+    let metadata = cargo_info::get_metadata().ok();
+
+    build_get_plugins(args, |artifact, id| elf_to_nro(artifact, metadata.as_ref(), id, strip))
+}
+
+/// Build several plugins at once as NSOs, to replace a subsdk with
+pub fn build_get_nsos(args: Vec<String>, compress: bool) -> Result<Vec<(PackageId, PathBuf)>> {
+    build_get_plugins(args, |artifact, _| elf_to_nso(artifact, compress))
+}
+
+fn build_get_plugins(args: Vec<String>, convert: impl Fn(&Path, &PackageId) -> Result<PathBuf>) -> Result<Vec<(PackageId, PathBuf)>> {
+    cargo_run_command(CargoCommand::Build, args)?
+        .into_iter()
+        .filter(|artifact| artifact.target.kind.iter().any(|kind| kind == "cdylib"))
+        .map(|artifact| Ok((artifact.package_id.clone(), convert(&artifact.filenames[0], &artifact.package_id)?)))
+        .collect()
+}
 
+fn elf_to_nro(artifact: &Path, metadata: Option<&cargo_info::Metadata>, id: &PackageId, strip: bool) -> Result<PathBuf> {
     let nro_path = artifact.with_extension("nro");
+    let nacp = nacp_for(metadata, id);
+    let previous_size = std::fs::metadata(&nro_path).ok().map(|previous| previous.len());
+
+    if strip {
+        let stripped = symbols::strip_with_sidecar(artifact)?;
+        write_nro(&stripped, &nro_path, nacp)?;
+        let _ = std::fs::remove_file(stripped);
+    } else {
+        // symbols left over from an earlier stripped build wouldn't match this one
+        let _ = std::fs::remove_file(artifact.with_extension("sym"));
+        write_nro(artifact, &nro_path, nacp)?;
+    }
+
+    if let Some(budget) = size_budget_for(metadata, id) {
+        check_nro_size(&nro_path, budget, previous_size)?;
+    }
+
+    Ok(nro_path)
+}
+
+/// Warn about an NRO which is bigger than its budget, or fail if the budget is a hard limit. The NRO
+/// is left in place either way, so it can be looked into.
+fn check_nro_size(nro_path: &Path, budget: cargo_info::SizeBudget, previous_size: Option<u64>) -> Result<()> {
+    let size = std::fs::metadata(nro_path)?.len();
+    if size <= budget.max {
+        return Ok(());
+    }
+
+    let name = nro_path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    if budget.deny {
+        return Err(Error::NroTooBig(name, size, budget.max, previous_size));
+    }
+
+    eprintln!("{}: {}", "Warning".yellow(), size_report(&name, size, budget.max, previous_size));
+
+    Ok(())
+}
+
+/// Describe how far over budget an NRO is, and how much that changed since the last build
+pub fn size_report(name: &str, size: u64, max: u64, previous_size: Option<u64>) -> String {
+    let change = match previous_size {
+        Some(previous) if previous != size => format!(", {} since the last build", format_size_change(size as i64 - previous as i64)),
+        Some(_) => String::from(", the same as the last build"),
+        None => String::new()
+    };
+
+    format!("{} is {}, {} over its budget of {}{}", name, format_size(size), format_size(size - max), format_size(max), change)
+}
+
+/// Show a number of bytes the way sizes are written in `max-nro-size`
+pub fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=0xFFFFF => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.2} MiB", bytes as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+pub fn format_size_change(change: i64) -> String {
+    let sign = if change < 0 { '-' } else { '+' };
+
+    format!("{}{}", sign, format_size(change.unsigned_abs()))
+}
+
+pub fn write_nro(artifact: &Path, nro_path: &Path, nacp: Option<&cargo_info::Nacp>) -> Result<()> {
+    let icon = match nacp.and_then(|nacp| nacp.icon.as_ref()) {
+        Some(icon) => Some(icon.to_str().ok_or(Error::FailWriteNro)?),
+        None => None
+    };
+    let nacp = nacp.map(|nacp| NacpFile {
+        name: Some(nacp.name.clone()),
+        author: Some(nacp.author.clone()),
+        version: Some(nacp.version.clone()),
+        title_id: None,
+        dlc_base_title_id: None,
+        lang: None,
+    });
 
     NxoFile::from_elf(artifact.to_str().ok_or(Error::FailWriteNro)?)?
         .write_nro(
-            &mut std::fs::File::create(&nro_path).map_err(|_| Error::FailWriteNro)?,
+            &mut std::fs::File::create(nro_path).map_err(|_| Error::FailWriteNro)?,
             None,
-            None,
-            None
+            icon,
+            nacp
         )?;
 
-    Ok(nro_path)
+    Ok(())
+}
+
+/// What to put in the NACP when converting with `elf2nro`, in place of `[package.metadata.skyline.nacp]`
+#[derive(StructOpt)]
+pub struct NacpArgs {
+    #[structopt(long, help = "Name to embed in the NRO for hbmenu to show")]
+    pub nacp_name: Option<String>,
+
+    #[structopt(long, help = "Author to embed in the NRO for hbmenu to show")]
+    pub nacp_author: Option<String>,
+
+    #[structopt(long, help = "Version to embed in the NRO for hbmenu to show")]
+    pub nacp_version: Option<String>,
+
+    #[structopt(long, parse(from_str = crate::wsl::translate_path), help = "256x256 JPEG to embed in the NRO as its icon")]
+    pub icon: Option<PathBuf>,
 }
 
-pub fn build_get_nso(args: Vec<String>) -> Result<PathBuf> {
-    let artifact = build_get_artifact(args)?;
+pub fn elf2nro(input: &Path, output: Option<PathBuf>, args: NacpArgs) -> Result<()> {
+    // this can be run outside of a project, in which case only what's passed gets embedded
+    let project = cargo_info::get_metadata().ok().and_then(|metadata| metadata.nacp);
 
+    let nacp = if args.nacp_name.is_some() || args.nacp_author.is_some() || args.nacp_version.is_some() || args.icon.is_some() {
+        let name = input.file_stem().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+
+        Some(cargo_info::Nacp {
+            name: args.nacp_name.or_else(|| project.as_ref().map(|nacp| nacp.name.clone())).unwrap_or(name),
+            author: args.nacp_author.or_else(|| project.as_ref().map(|nacp| nacp.author.clone())).unwrap_or_else(|| String::from("Unknown Author")),
+            version: args.nacp_version.or_else(|| project.as_ref().map(|nacp| nacp.version.clone())).unwrap_or_else(|| String::from("1.0.0")),
+            icon: args.icon.or_else(|| project.as_ref().and_then(|nacp| nacp.icon.clone())),
+        })
+    } else {
+        project
+    };
+
+    let output = output.unwrap_or_else(|| input.with_extension("nro"));
+    write_nro(input, &output, nacp.as_ref())?;
+    println!("Converted {} to {}", input.display(), output.display());
+
+    Ok(())
+}
+
+pub fn build_get_nso(args: Vec<String>, compress: bool) -> Result<PathBuf> {
+    elf_to_nso(&build_get_artifact(args)?, compress)
+}
+
+pub fn elf_to_nso(artifact: &Path, compress: bool) -> Result<PathBuf> {
     let nso_path = artifact.with_extension("nso");
 
     NxoFile::from_elf(artifact.to_str().ok_or(Error::FailWriteNro)?)?
@@ -154,22 +397,288 @@ pub fn build_get_nso(args: Vec<String>) -> Result<PathBuf> {
             &mut std::fs::File::create(&nso_path).map_err(|_| Error::FailWriteNro)?,
         )?;
 
+    // linkle always compresses every segment, so undo it for loaders which can't decompress
+    if !compress {
+        let module = crate::nxo::Module::read(&std::fs::read(&nso_path)?)?;
+        std::fs::write(&nso_path, module.to_nso(false)?)?;
+    }
+
     Ok(nso_path)
 }
 
-pub fn build(mut args: Vec<String>, release: bool, nso: bool, features: Vec<String>) -> Result<()> {
-    if release {
+/// The packages `--workspace` builds, which is every plugin rather than every package so anything
+/// else in the workspace is never built for the switch
+pub fn workspace_packages(metadata: &cargo_info::Metadata) -> Result<Vec<String>> {
+    let packages: Vec<String> = metadata.members.iter().map(|member| member.name.clone()).collect();
+
+    if packages.is_empty() {
+        return Err(Error::NoWorkspacePlugins);
+    }
+
+    Ok(packages)
+}
+
+pub fn package_args(args: &mut Vec<String>, packages: &[String]) {
+    for package in packages {
+        args.push(format!("--package={}", package));
+    }
+}
+
+/// Pick the cargo profile, where a custom one takes the place of `--release`
+pub fn profile_args(args: &mut Vec<String>, release: bool, profile: Option<&str>) {
+    if let Some(profile) = profile {
+        args.push(format!("--profile={}", profile));
+    } else if release {
         args.push("--release".into());
     }
+}
+
+/// Flags for `build`
+#[derive(StructOpt)]
+pub struct BuildOptions {
+    #[structopt(long)]
+    pub release: bool,
+
+    #[structopt(long, conflicts_with = "release", help = "Build with a custom cargo profile, such as one tuned for size")]
+    pub profile: Option<String>,
+
+    #[structopt(long, help = "Convert to an NSO instead of an NRO, for replacing a subsdk with")]
+    pub nso: bool,
+
+    #[structopt(
+        long, conflicts_with_all = &["nso", "workspace", "package", "exefs"], parse(try_from_str = crate::game_paths::parse_subsdk),
+        help = "Build an NSO named after the given subsdk, for games without Skyline's NRO loader"
+    )]
+    pub as_subsdk: Option<String>,
+
+    #[structopt(
+        long, conflicts_with_all = &["nso", "workspace"],
+        help = "Build a complete exefs folder with Skyline and main.npdm, laid out by [package.metadata.skyline.exefs]"
+    )]
+    pub exefs: bool,
+
+    #[structopt(long, help = "Leave the segments of NSOs uncompressed, for loaders which can't decompress them")]
+    pub no_compress: bool,
+
+    #[structopt(long, help = "Strip the plugin before converting it, keeping its symbols in a .sym file beside it")]
+    pub strip: bool,
+
+    #[structopt(
+        long, conflicts_with = "workspace",
+        help = "Have the linker write a map of where everything ended up, kept in a .map file beside the plugin"
+    )]
+    pub emit_map: bool,
+
+    #[structopt(long, help = "Build so the same commit always gives a byte-identical plugin, wherever it's built")]
+    pub reproducible: bool,
+
+    #[structopt(long)]
+    pub features: Vec<String>,
+
+    #[structopt(
+        short, long, visible_alias = "game",
+        parse(try_from_str = crate::titles::resolve_title_id),
+        help = "Title ID of a game to build for, turning on the features Cargo.toml lists for it"
+    )]
+    pub title_id: Option<String>,
+
+    #[structopt(long, help = "Build every plugin in the workspace")]
+    pub workspace: bool,
+
+    #[structopt(short, long, number_of_values = 1, help = "Package in the workspace to build, can be repeated")]
+    pub package: Vec<String>,
+
+    #[structopt(
+        long, parse(from_str = crate::wsl::translate_path),
+        help = "Copy what was built to this path, or into it if it's a directory or several plugins were built"
+    )]
+    pub out: Option<PathBuf>,
+}
+
+pub fn build(mut args: Vec<String>, options: BuildOptions) -> Result<()> {
+    profile_args(&mut args, options.release, options.profile.as_deref());
+
+    // turn on whatever the metadata says the title needs
+    let mut features = options.features;
+    if let Some(title_id) = &options.title_id {
+        features.extend(cargo_info::get_metadata()?.features_for(title_id));
+    }
 
     if !features.is_empty() {
         args.push(format!("--features={}", features.join(",")));
     }
 
-    if nso {
-        build_get_nso(args)?;
+    let packages = if options.workspace {
+        workspace_packages(&cargo_info::get_metadata()?)?
     } else {
-        build_get_nro(args)?;
+        options.package
+    };
+    package_args(&mut args, &packages);
+
+    if options.emit_map && packages.len() > 1 {
+        return Err(Error::MapForSeveralPlugins);
+    }
+
+    if options.reproducible {
+        reproducible_env()?;
+    }
+
+    let link_map = if options.emit_map { Some(link_map_args()?) } else { None };
+
+    if options.exefs {
+        let elf = build_get_artifact(args)?;
+        if let Some(link_map) = &link_map {
+            keep_link_map(link_map, &elf)?;
+        }
+
+        let dir = match options.out {
+            Some(out) => out,
+            None => elf.parent().ok_or(Error::FailWriteNro)?.join("exefs"),
+        };
+
+        return crate::package::build_exefs(&elf, options.title_id.as_deref(), &dir, !options.no_compress);
+    }
+
+    if let Some(subsdk) = &options.as_subsdk {
+        let nso_path = build_get_nso(args, !options.no_compress)?;
+        let subsdk_path = nso_path.with_file_name(subsdk);
+        std::fs::rename(&nso_path, &subsdk_path)?;
+        if let Some(link_map) = &link_map {
+            keep_link_map(link_map, &subsdk_path)?;
+        }
+
+        return match options.out {
+            Some(out) => copy_built(&[subsdk_path], &out),
+            None => Ok(())
+        };
+    }
+
+    let built = if options.workspace || packages.len() > 1 {
+        let built = if options.nso { build_get_nsos(args, !options.no_compress)? } else { build_get_nros(args, options.strip)? };
+        if built.is_empty() {
+            return Err(Error::NoWorkspacePlugins);
+        }
+
+        built.into_iter().map(|(_, path)| path).collect()
+    } else if options.nso {
+        vec![build_get_nso(args, !options.no_compress)?]
+    } else {
+        vec![build_get_nro(args, options.strip)?]
+    };
+
+    if let Some(link_map) = &link_map {
+        keep_link_map(link_map, &built[0])?;
+    }
+
+    match options.out {
+        Some(out) => copy_built(&built, &out),
+        None => Ok(())
+    }
+}
+
+/// Point the linker at a map file in the target directory, as the plugin's name isn't known until
+/// it's been built
+fn link_map_args() -> Result<PathBuf> {
+    let link_map = cargo_info::get_metadata()?.target_dir.join("skyline-link.map");
+    let _ = std::fs::remove_file(&link_map);
+
+    push_rustflags(vec![format!("-Clink-arg=-Map={}", link_map.display())]);
+
+    Ok(link_map)
+}
+
+/// Add to the RUSTFLAGS xargo gets run with, after anything set already
+fn push_rustflags(flags: Vec<String>) {
+    let mut rustflags: Vec<String> = env::var("RUSTFLAGS").ok().into_iter().filter(|flags| !flags.is_empty()).collect();
+    rustflags.extend(flags);
+    env::set_var("RUSTFLAGS", rustflags.join(" "));
+}
+
+/// Take out of the build anything that depends on where or when it was built, so the same commit
+/// gives a byte-identical plugin on any machine
+pub fn reproducible_env() -> Result<()> {
+    let metadata = cargo_info::get_metadata()?;
+
+    // rustc tries later prefixes first, so the more specific ones go last
+    let mut prefixes = vec![];
+    if let Some(home) = dirs::home_dir() {
+        prefixes.push((home.clone(), "~"));
+        prefixes.push((home.join(".xargo"), "/xargo"));
+    }
+    if let Some(cargo_home) = env::var_os("CARGO_HOME").map(PathBuf::from).or_else(|| dirs::home_dir().map(|home| home.join(".cargo"))) {
+        prefixes.push((cargo_home, "/cargo"));
+    }
+    if let Ok(rust_src) = env::current_dir()?.join("..").join("rust-std-skyline-squashed").canonicalize() {
+        prefixes.push((rust_src, "/rust-std-skyline-squashed"));
+    }
+    prefixes.push((metadata.workspace_root.clone(), "."));
+
+    push_rustflags(
+        prefixes.into_iter()
+            .map(|(from, to)| format!("--remap-path-prefix={}={}", from.display(), to))
+            .collect()
+    );
+
+    // incremental builds aren't guaranteed to match a clean one
+    env::set_var("CARGO_INCREMENTAL", "0");
+
+    // for build scripts which embed a date, use the commit's rather than today's
+    if env::var_os("SOURCE_DATE_EPOCH").is_none() {
+        let commit_time = Command::new("git")
+            .args(["log", "-1", "--format=%ct"])
+            .current_dir(&metadata.workspace_root)
+            .stderr(Stdio::null())
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_owned());
+
+        env::set_var("SOURCE_DATE_EPOCH", commit_time.filter(|time| !time.is_empty()).as_deref().unwrap_or("0"));
+    }
+
+    Ok(())
+}
+
+/// Move the linker's map next to what was built. If cargo had nothing to relink, the map from the
+/// last build is already there and still up to date.
+fn keep_link_map(link_map: &Path, built: &Path) -> Result<()> {
+    let map_path = built.with_extension("map");
+
+    if link_map.is_file() {
+        std::fs::rename(link_map, &map_path)?;
+    } else if !map_path.is_file() {
+        return Err(Error::NoLinkMap);
+    }
+
+    println!("Linker map written to {}", map_path.display());
+
+    Ok(())
+}
+
+/// Copy built plugins somewhere outside the target directory, along with their symbols and map if there are any
+fn copy_built(built: &[PathBuf], out: &Path) -> Result<()> {
+    let into_dir = built.len() > 1 || out.is_dir() || out.to_string_lossy().ends_with(std::path::is_separator);
+
+    for path in built {
+        let dest = if into_dir {
+            out.join(path.file_name().ok_or(Error::FailWriteNro)?)
+        } else {
+            out.to_owned()
+        };
+
+        if let Some(dir) = dest.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        std::fs::copy(path, &dest)?;
+        println!("Copied {} to {}", path.display(), dest.display());
+
+        for sidecar in ["sym", "map"] {
+            let sidecar_path = path.with_extension(sidecar);
+            if sidecar_path.is_file() {
+                std::fs::copy(&sidecar_path, dest.with_extension(sidecar))?;
+            }
+        }
     }
 
     Ok(())
@@ -206,6 +715,7 @@ pub fn doc(args: Vec<String>) -> Result<()> {
 
     let current_dir = std::env::current_dir()?;
     let xargo_dir = current_dir.join("..").join("rust-std-skyline-squashed").join("src");
+    let XargoEnv { target_args, target_path, rustflags, .. } = xargo_env(&current_dir);
 
     let mut command =
         Command::new("xargo")
@@ -213,9 +723,11 @@ pub fn doc(args: Vec<String>) -> Result<()> {
                 "doc"
             ])
             .args(args)
+            .args(target_args)
             .current_dir(env::current_dir()?)
             // Needed to make crates.io crates use the custom target
-            .env("RUST_TARGET_PATH", current_dir)
+            .env("RUST_TARGET_PATH", target_path)
+            .envs(rustflags.map(|rustflags| ("RUSTFLAGS", rustflags)))
             // ensure xargo can find the rust std
             .env("XARGO_RUST_SRC", xargo_dir)
             .stdout(Stdio::piped())
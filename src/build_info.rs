@@ -0,0 +1,217 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use cargo_metadata::Artifact;
+use serde::Serialize;
+use crate::cargo_info::Metadata;
+use crate::error::Result;
+use crate::installer::history;
+
+/// An ELF note's name has to be padded to 4 bytes, so this is exactly 8 with the nul
+const NOTE_NAME: &[u8; 8] = b"Skyline\0";
+const NOTE_TYPE: u32 = 1;
+/// Room for the build info, which is filled in after linking so it doesn't need relinking to change
+const DESC_SIZE: usize = 0x100;
+
+const SHT_NOTE: u32 = 7;
+const SHT_PROGBITS: u32 = 1;
+const SHT_STRTAB: u32 = 3;
+const SHF_ALLOC: u64 = 0x2;
+
+/// The note header followed by its name, which is how the note is found again in the ELF and in
+/// the NRO or NSO made from it
+fn signature() -> Vec<u8> {
+    let mut signature = vec![];
+    signature.extend((NOTE_NAME.len() as u32).to_le_bytes());
+    signature.extend((DESC_SIZE as u32).to_le_bytes());
+    signature.extend(NOTE_TYPE.to_le_bytes());
+    signature.extend(NOTE_NAME);
+
+    signature
+}
+
+/// A relocatable object with nothing in it but an empty note, for the linker to put in the plugin
+fn note_object() -> Vec<u8> {
+    let names = b"\0.note.skyline.build-info\0.shstrtab\0.note.GNU-stack\0";
+    let note_offset = 0x40;
+    let note_size = signature().len() + DESC_SIZE;
+    let names_offset = note_offset + note_size;
+    let headers_offset = (names_offset + names.len() + 7) & !7;
+
+    let mut object = vec![];
+    object.extend(b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0");
+    object.extend(1u16.to_le_bytes()); // relocatable
+    object.extend(0xB7u16.to_le_bytes()); // aarch64
+    object.extend(1u32.to_le_bytes());
+    object.extend(0u64.to_le_bytes()); // entry
+    object.extend(0u64.to_le_bytes()); // program headers
+    object.extend((headers_offset as u64).to_le_bytes());
+    object.extend(0u32.to_le_bytes()); // flags
+    object.extend(0x40u16.to_le_bytes());
+    object.extend(0u16.to_le_bytes());
+    object.extend(0u16.to_le_bytes());
+    object.extend(0x40u16.to_le_bytes());
+    object.extend(4u16.to_le_bytes()); // section count
+    object.extend(2u16.to_le_bytes()); // .shstrtab
+
+    object.extend(signature());
+    object.resize(names_offset, 0);
+    object.extend(names);
+    object.resize(headers_offset, 0);
+
+    let mut section = |name: u32, kind: u32, flags: u64, offset: usize, size: usize, align: u64| {
+        object.extend(name.to_le_bytes());
+        object.extend(kind.to_le_bytes());
+        object.extend(flags.to_le_bytes());
+        object.extend(0u64.to_le_bytes());
+        object.extend((offset as u64).to_le_bytes());
+        object.extend((size as u64).to_le_bytes());
+        object.extend(0u32.to_le_bytes());
+        object.extend(0u32.to_le_bytes());
+        object.extend(align.to_le_bytes());
+        object.extend(0u64.to_le_bytes());
+    };
+
+    section(0, 0, 0, 0, 0, 0);
+    section(1, SHT_NOTE, SHF_ALLOC, note_offset, note_size, 4);
+    section(26, SHT_STRTAB, 0, names_offset, names.len(), 1);
+    // like any assembler's output, so linkers don't assume the stack needs to be executable
+    section(36, SHT_PROGBITS, 0, names_offset, 0, 1);
+
+    object
+}
+
+/// Write out the object holding the note if it isn't there already, giving back where it is so it
+/// can be passed to the linker
+pub fn note_object_path(metadata: &Metadata) -> Result<PathBuf> {
+    let path = metadata.target_dir.join("skyline-build-info.o");
+    let object = note_object();
+
+    if fs::read(&path).ok().as_ref() != Some(&object) {
+        fs::create_dir_all(&metadata.target_dir)?;
+        fs::write(&path, object)?;
+    }
+
+    Ok(path)
+}
+
+/// What the note says about the build which made a plugin
+#[derive(Serialize, Debug, Clone)]
+pub struct BuildInfo {
+    pub name: String,
+    pub version: String,
+    /// The short commit hash, ending in -dirty if there were uncommitted changes
+    pub commit: Option<String>,
+    /// Seconds since the unix epoch
+    pub time: u64,
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f, "{} {} ({}, built {})",
+            self.name, self.version, self.commit.as_deref().unwrap_or("no commit"), crate::timestamps::format_time(self.time)
+        )
+    }
+}
+
+impl BuildInfo {
+    fn to_desc(&self) -> Vec<u8> {
+        let mut desc = format!("name={}\nversion={}\ntime={}\n", self.name, self.version, self.time);
+        if let Some(commit) = &self.commit {
+            desc += &format!("commit={}\n", commit);
+        }
+
+        // always leave a nul at the end
+        let mut desc = desc.into_bytes();
+        desc.truncate(DESC_SIZE - 1);
+        desc.resize(DESC_SIZE, 0);
+
+        desc
+    }
+
+    fn from_desc(desc: &[u8]) -> Option<Self> {
+        let end = desc.iter().position(|&byte| byte == 0).unwrap_or(desc.len());
+        let text = std::str::from_utf8(&desc[..end]).ok()?;
+        let field = |key: &str| {
+            text.lines().find_map(|line| line.strip_prefix(key)?.strip_prefix('=').map(String::from))
+        };
+
+        Some(BuildInfo {
+            name: field("name")?,
+            version: field("version")?,
+            commit: field("commit"),
+            time: field("time")?.parse().ok()?,
+        })
+    }
+}
+
+/// Find the note's contents in an ELF, NRO or uncompressed NSO
+fn find_desc(data: &[u8]) -> Option<usize> {
+    let signature = signature();
+
+    data.windows(signature.len())
+        .position(|window| window == signature.as_slice())
+        .map(|start| start + signature.len())
+        .filter(|desc| desc + DESC_SIZE <= data.len())
+}
+
+/// Read the build info back out of a plugin, if it was built with one
+pub fn read(data: &[u8]) -> Option<BuildInfo> {
+    let desc = find_desc(data)?;
+
+    BuildInfo::from_desc(&data[desc..desc + DESC_SIZE])
+}
+
+/// Read the build info of an NRO or NSO on the console, decompressing an NSO's segments if needed
+pub fn read_module(data: &[u8]) -> Option<BuildInfo> {
+    read(data).or_else(|| {
+        let module = crate::nxo::Module::read(data).ok()?;
+        read(&module.rodata.data)
+    })
+}
+
+/// Fill in the note of each plugin cargo just built, which is skipped for any built without it
+pub fn stamp(artifacts: &[Artifact], metadata: &Metadata) -> Result<()> {
+    let commit = history::git_hash(&metadata.workspace_root);
+
+    // a reproducible build is dated by its commit, not when it was built
+    let time = std::env::var("SOURCE_DATE_EPOCH").ok()
+        .and_then(|time| time.parse().ok())
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0));
+
+    for artifact in artifacts.iter().filter(|artifact| artifact.target.kind.iter().any(|kind| kind == "cdylib")) {
+        let (name, version) = match metadata.members.iter().find(|member| member.id == artifact.package_id) {
+            Some(member) => (member.name.clone(), member.version.clone()),
+            None => (artifact.target.name.clone(), String::new()),
+        };
+
+        let info = BuildInfo { name, version, commit: commit.clone(), time };
+        stamp_file(&artifact.filenames[0], &info, artifact.fresh)?;
+    }
+
+    Ok(())
+}
+
+/// Fill in a plugin's note, leaving it alone if cargo didn't relink the plugin so that an unchanged
+/// plugin stays byte for byte the same and isn't uploaded again
+fn stamp_file(path: &Path, info: &BuildInfo, fresh: bool) -> Result<()> {
+    let mut data = fs::read(path)?;
+    let desc = match find_desc(&data) {
+        Some(desc) => desc,
+        None => return Ok(())
+    };
+
+    if fresh && BuildInfo::from_desc(&data[desc..desc + DESC_SIZE]).is_some() {
+        return Ok(());
+    }
+
+    let new_desc = info.to_desc();
+    if data[desc..desc + DESC_SIZE] != new_desc[..] {
+        data[desc..desc + DESC_SIZE].copy_from_slice(&new_desc);
+        fs::write(path, data)?;
+    }
+
+    Ok(())
+}
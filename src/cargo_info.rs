@@ -1,49 +1,290 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use serde::Deserialize;
-use crate::error::Result;
+use crate::error::{Error, Result};
+use crate::settings;
+use crate::wsl;
 
 #[derive(Deserialize)]
 pub struct Metadata {
     pub name: String,
+    /// The name to show for the plugin, rather than the crate's name
+    pub display_name: Option<String>,
+    /// The first title the plugin is for
     pub title_id: Option<String>,
+    /// Every title the plugin is for, as `titleid` can be either one title id or a list of them
+    pub title_ids: Vec<String>,
+    /// The file name to install the plugin as, rather than the one cargo gives it
+    pub nro_name: Option<String>,
+    /// Where to install the plugin, in the same form as `install --install-path`
+    pub install_dir: Option<String>,
+    /// A directory inside the plugin directory to group the plugin and its assets under
+    pub plugin_subdir: Option<String>,
+    /// Where to install the plugin for specific titles, keyed by title id
+    pub plugin_dirs: HashMap<String, String>,
+    /// Cargo features to turn on when building for specific titles, keyed by title id
+    pub title_features: HashMap<String, Vec<String>>,
+    /// Cargo features install and run turn on when none are passed
+    pub install_features: Vec<String>,
+    /// Whether install and run should build without the crate's default features
+    pub no_default_features: bool,
+    /// A target spec to build with instead of aarch64-skyline-switch, for games which need a tweaked one
+    pub target_spec: Option<PathBuf>,
+    /// Extra flags for rustc from `[package.metadata.skyline.build]`, with link args already turned
+    /// into `-C link-arg=...`
+    pub rustflags: Vec<String>,
+    /// How big the NRO is allowed to get, from `max-nro-size`
+    pub size_budget: Option<SizeBudget>,
+    /// Whether to link a note with the version, commit and build time into plugins, unless turned
+    /// off with `build-info = false`
+    pub embed_build_info: bool,
+    /// Where the game's Skyline looks for plugins: 'romfs', 'exefs' or 'npdm-patch'
+    pub layout: Option<String>,
     pub npdm_path: Option<String>,
     pub subsdk_name: Option<String>,
+    pub mod_name: Option<String>,
+    pub ftp_port: Option<String>,
+    pub ftp_user: Option<String>,
+    pub ftp_pass: Option<String>,
+    pub ftp_mode: Option<String>,
+    /// The Skyline release to install, when the project pins one
+    pub skyline_release: Option<String>,
+    /// The oldest Skyline version the plugin works with
+    pub min_skyline_version: Option<String>,
+    /// A saved device the project should be installed to
+    pub device: Option<String>,
+    /// The address of the switch the project should be installed to
+    pub ip: Option<String>,
     pub plugin_dependencies: Vec<Dependency>,
+    /// Plugins which cleaning the plugin directory should never delete
+    pub protected_plugins: Vec<String>,
+    /// The file names of the plugins built by the workspace
+    pub plugin_names: Vec<String>,
+    /// Every package in the workspace which builds a plugin, leaving out any which set `skip = true`
+    /// or aren't in the workspace's `members` list
+    pub members: Vec<Member>,
+    /// Exactly which files should be on the switch, if the workspace says
+    pub deploy: Option<Vec<DeployFile>>,
+    /// Local directories uploaded alongside the plugin
+    pub assets: Vec<AssetDir>,
+    /// What goes in the exefs folder `build --exefs` makes, besides main.npdm
+    pub exefs: Vec<ExefsFile>,
+    /// The NACP and icon to embed in the NRO, for homebrew run from hbmenu rather than plugins
+    pub nacp: Option<Nacp>,
+    pub target_dir: PathBuf,
+    pub workspace_root: PathBuf,
+}
+
+/// A package in the workspace which builds a plugin, with the metadata it sets for itself
+#[derive(Deserialize)]
+pub struct Member {
+    pub id: cargo_metadata::PackageId,
+    pub name: String,
+    pub version: String,
+    pub display_name: Option<String>,
+    /// The titles the package is for, which are the workspace's if it doesn't list its own
+    pub title_ids: Vec<String>,
+    pub nro_name: Option<String>,
+    pub nacp: Option<Nacp>,
+    pub size_budget: Option<SizeBudget>,
+}
+
+impl Metadata {
+    /// The name of the target the plugin is built for, which is also the directory cargo builds it in
+    pub fn target_name(&self) -> String {
+        self.target_spec.as_ref()
+            .and_then(|spec| spec.file_stem())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| String::from("aarch64-skyline-switch"))
+    }
+
+    /// The cargo features a build for the given title needs
+    pub fn features_for(&self, title_id: &str) -> Vec<String> {
+        self.title_features.get(&title_id.to_ascii_uppercase()).cloned().unwrap_or_default()
+    }
+}
+
+/// Check that a name to install the plugin as is one Skyline will load, which means a file directly
+/// in the plugin directory ending in .nro
+pub fn check_nro_name(name: &str) -> Result<()> {
+    let stem = name.strip_suffix(".nro").unwrap_or_default();
+
+    if stem.is_empty() || name.contains(['/', '\\']) {
+        Err(Error::BadNroName(name.to_owned()))
+    } else {
+        Ok(())
+    }
+}
+
+/// A file listed in `[package.metadata.skyline.deploy]`
+#[derive(Deserialize, Debug)]
+pub struct DeployFile {
+    /// Where the file goes, relative to the plugin directory unless absolute
+    pub path: String,
+    /// A URL to download the file from, or a local path resolved against the package's directory
+    pub source: String,
+}
+
+/// `[package.metadata.skyline.nacp]`, with anything left out taken from the package itself
+#[derive(Deserialize, Debug, Clone)]
+pub struct Nacp {
+    pub name: String,
+    pub author: String,
+    pub version: String,
+    /// A 256x256 JPEG, resolved against the package's directory
+    pub icon: Option<PathBuf>,
+}
+
+/// `max-nro-size`, past which building warns, or fails if `deny-oversized` is set
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct SizeBudget {
+    pub max: u64,
+    pub deny: bool,
+}
+
+/// Parse a size in bytes, such as `524288`, "512K" or "1.5 MiB". K and M are always powers of
+/// 1024, whether they're written K, KB or KiB.
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split = size.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+
+    let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1024,
+        "m" | "mb" | "mib" => 1024 * 1024,
+        _ => return None
+    };
+
+    let number: f64 = number.parse().ok()?;
+
+    Some((number * multiplier as f64) as u64)
+}
+
+/// A file listed in `[package.metadata.skyline.exefs]`
+#[derive(Deserialize, Debug)]
+pub struct ExefsFile {
+    /// The name of the file in the exefs, such as `subsdk9` or `rtld`
+    pub name: String,
+    pub source: ExefsSource,
+}
+
+#[derive(Deserialize, Debug)]
+pub enum ExefsSource {
+    /// The subsdk from the Skyline release
+    Skyline,
+    /// The plugin itself, converted to an NSO
+    Plugin,
+    /// A local file, resolved against the package's directory
+    File(PathBuf),
+}
+
+/// The names Atmosphere loads from an exefs
+pub const EXEFS_NAMES: &[&str] = &[
+    "rtld", "main", "main.npdm", "sdk",
+    "subsdk0", "subsdk1", "subsdk2", "subsdk3", "subsdk4", "subsdk5", "subsdk6", "subsdk7", "subsdk8", "subsdk9",
+];
+
+/// A directory listed in `[package.metadata.skyline.assets]`, whose contents are uploaded with
+/// every install
+#[derive(Deserialize, Debug)]
+pub struct AssetDir {
+    /// Where the directory goes, relative to the plugin directory unless absolute
+    pub path: String,
+    /// The local directory, resolved against the package's directory
+    pub source: PathBuf,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct Dependency {
     pub name: String,
-    pub url: String
+    pub url: String,
+    pub version: Option<String>,
+}
+
+fn get_value<'a>(md: &'a serde_json::Value, key: &str) -> Option<&'a serde_json::Value> {
+    md.get("skyline")?
+        .as_object()?
+        .get(key)
+}
+
+fn get_str(md: &serde_json::Value, key: &str) -> Option<String> {
+    Some(get_value(md, key)?.as_str()?.into())
+}
+
+/// Title ids given either as a single string or as an array of them
+fn get_title_ids(md: &serde_json::Value) -> Option<Vec<String>> {
+    match get_value(md, "titleid")? {
+        serde_json::Value::String(title_id) => Some(vec![title_id.clone()]),
+        serde_json::Value::Array(title_ids) => Some(
+            title_ids.iter().filter_map(|title_id| title_id.as_str().map(String::from)).collect()
+        ),
+        _ => None
+    }
+}
+
+/// `rustflags` and `link-args` from `[package.metadata.skyline.build]`, as flags to pass to rustc
+fn get_rustflags(md: &serde_json::Value) -> Option<Vec<String>> {
+    let build = get_value(md, "build")?.as_object()?;
+    let strings = |key: &str| {
+        build.get(key)
+            .and_then(serde_json::Value::as_array)
+            .map(|values| values.iter().filter_map(|x| x.as_str().map(String::from)).collect::<Vec<_>>())
+            .unwrap_or_default()
+    };
+
+    let mut flags = strings("rustflags");
+    flags.extend(strings("link-args").into_iter().map(|arg| format!("-Clink-arg={}", arg)));
+
+    Some(flags)
 }
 
-fn get_title_id(md: &serde_json::Value) -> Option<String> {
+/// The packages listed in `members` under `[workspace.metadata.skyline]`, which cargo doesn't give
+/// back so it's read from the workspace's manifest
+fn workspace_members(workspace_root: &Path) -> Option<Vec<String>> {
+    let manifest: toml::Value = toml::from_str(&std::fs::read_to_string(workspace_root.join("Cargo.toml")).ok()?).ok()?;
+
     Some(
-        md.get("skyline")?
-            .as_object()?
-            .get("titleid")?
-            .as_str()?
-            .into()
+        manifest.get("workspace")?
+            .get("metadata")?
+            .get("skyline")?
+            .get("members")?
+            .as_array()?
+            .iter()
+            .filter_map(|member| member.as_str().map(String::from))
+            .collect()
     )
 }
 
-fn get_npdm_path(md: &serde_json::Value) -> Option<String> {
+/// `[package.metadata.skyline.features]`, where each title id (or game name) maps to a feature or
+/// a list of them
+fn get_title_features(md: &serde_json::Value) -> Option<HashMap<String, Vec<String>>> {
     Some(
-        md.get("skyline")?
+        get_value(md, "features")?
             .as_object()?
-            .get("custom-npdm")?
-            .as_str()?
-            .into()
+            .iter()
+            .filter_map(|(game, features)| {
+                let title_id = crate::titles::resolve_title_id(game).ok()?;
+                let features = match features {
+                    serde_json::Value::String(feature) => vec![feature.clone()],
+                    serde_json::Value::Array(features) => features.iter().filter_map(|x| x.as_str().map(String::from)).collect(),
+                    _ => return None
+                };
+
+                Some((title_id, features))
+            })
+            .collect()
     )
 }
 
+/// The subsdk to install skyline as, either `subsdk-name = "subsdk9"` or `subsdk-slot = 9`
 fn get_subsdk_name(md: &serde_json::Value) -> Option<String> {
-    Some(
-        md.get("skyline")?
-            .as_object()?
-            .get("subsdk-name")?
-            .as_str()?
-            .into()
-    )
+    get_str(md, "subsdk-name").or_else(|| match get_value(md, "subsdk-slot")? {
+        serde_json::Value::Number(slot) => Some(format!("subsdk{}", slot.as_u64()?)),
+        serde_json::Value::String(slot) if slot.starts_with("subsdk") => Some(slot.clone()),
+        serde_json::Value::String(slot) => Some(format!("subsdk{}", slot)),
+        _ => None
+    })
 }
 
 fn get_dep_urls(md: &serde_json::Value) -> Option<Vec<Dependency>> {
@@ -57,38 +298,211 @@ fn get_dep_urls(md: &serde_json::Value) -> Option<Vec<Dependency>> {
                 let dep = x.as_object().unwrap();
                 let name = dep.get("name").unwrap().as_str().unwrap().into();
                 let url = dep.get("url").unwrap().as_str().unwrap().into();
-                Dependency { name, url }
+                let version = dep.get("version").and_then(|version| version.as_str()).map(String::from);
+                Dependency { name, url, version }
+            })
+            .collect()
+    )
+}
+
+/// Dependencies given as `[package.metadata.skyline.dependencies]`, mapping the name of each
+/// plugin to either its url or a table containing a `url` key
+fn get_dep_table(md: &serde_json::Value) -> Option<Vec<Dependency>> {
+    Some(
+        md.get("skyline")?
+            .as_object()?
+            .get("dependencies")?
+            .as_object()?
+            .iter()
+            .filter_map(|(name, dep)| {
+                let url = dep.as_str().or_else(|| dep.get("url")?.as_str())?;
+                let version = dep.get("version").and_then(|version| version.as_str()).map(String::from);
+
+                Some(Dependency { name: name.clone(), url: url.into(), version })
+            })
+            .collect()
+    )
+}
+
+fn get_str_array(md: &serde_json::Value, key: &str) -> Option<Vec<String>> {
+    Some(
+        md.get("skyline")?
+            .as_object()?
+            .get(key)?
+            .as_array()?
+            .iter()
+            .filter_map(|x| x.as_str().map(String::from))
+            .collect()
+    )
+}
+
+fn get_plugin_dirs(md: &serde_json::Value) -> Option<HashMap<String, String>> {
+    Some(
+        md.get("skyline")?
+            .as_object()?
+            .get("plugin-dirs")?
+            .as_object()?
+            .iter()
+            .filter_map(|(title_id, dir)| Some((title_id.to_ascii_uppercase(), dir.as_str()?.to_owned())))
+            .collect()
+    )
+}
+
+fn get_assets(md: &serde_json::Value, package_dir: &std::path::Path) -> Option<Vec<AssetDir>> {
+    Some(
+        md.get("skyline")?
+            .as_object()?
+            .get("assets")?
+            .as_object()?
+            .iter()
+            .filter_map(|(path, source)| {
+                let source = package_dir.join(wsl::translate_path(source.as_str()?));
+
+                Some(AssetDir { path: path.clone(), source })
             })
             .collect()
     )
 }
 
+fn get_deploy(md: &serde_json::Value, package_dir: &std::path::Path) -> Option<Vec<DeployFile>> {
+    Some(
+        md.get("skyline")?
+            .as_object()?
+            .get("deploy")?
+            .as_object()?
+            .iter()
+            .filter_map(|(path, source)| {
+                let source = source.as_str()?;
+                let source = if source.starts_with("http://") || source.starts_with("https://") {
+                    source.to_owned()
+                } else {
+                    package_dir.join(wsl::translate_path(source)).to_string_lossy().into_owned()
+                };
+
+                Some(DeployFile { path: path.clone(), source })
+            })
+            .collect()
+    )
+}
+
+fn get_exefs(md: &serde_json::Value, package_dir: &std::path::Path) -> Option<Vec<ExefsFile>> {
+    Some(
+        md.get("skyline")?
+            .as_object()?
+            .get("exefs")?
+            .as_object()?
+            .iter()
+            .filter_map(|(name, source)| {
+                let source = match source.as_str()? {
+                    "skyline" => ExefsSource::Skyline,
+                    "plugin" => ExefsSource::Plugin,
+                    path => ExefsSource::File(package_dir.join(wsl::translate_path(path))),
+                };
+
+                Some(ExefsFile { name: name.clone(), source })
+            })
+            .collect()
+    )
+}
+
+fn get_size_budget(md: &serde_json::Value) -> Option<SizeBudget> {
+    let max = match get_value(md, "max-nro-size")? {
+        serde_json::Value::Number(max) => max.as_u64()?,
+        serde_json::Value::String(max) => parse_size(max)?,
+        _ => return None
+    };
+    let deny = get_value(md, "deny-oversized").and_then(serde_json::Value::as_bool).unwrap_or(false);
+
+    Some(SizeBudget { max, deny })
+}
+
+fn get_nacp(package: &cargo_metadata::Package) -> Option<Nacp> {
+    let nacp = get_value(&package.metadata, "nacp")?.as_object()?;
+    let field = |key: &str| nacp.get(key).and_then(serde_json::Value::as_str).map(String::from);
+
+    // authors are usually written as `Name <email>`, only the name fits in the NACP
+    let authors: Vec<&str> = package.authors.iter()
+        .map(|author| author.split('<').next().unwrap_or_default().trim())
+        .collect();
+
+    Some(Nacp {
+        name: field("name")
+            .or_else(|| get_str(&package.metadata, "display-name"))
+            .unwrap_or_else(|| package.name.clone()),
+        author: field("author").unwrap_or_else(|| authors.join(", ")),
+        version: field("version").unwrap_or_else(|| package.version.to_string()),
+        icon: field("icon")
+            .and_then(|icon| Some(package.manifest_path.parent()?.join(wsl::translate_path(&icon)))),
+    })
+}
+
 pub fn get_metadata() -> Result<Metadata> {
     let metadata = cargo_metadata::MetadataCommand::new().exec()?;
 
-    let name = metadata.workspace_members.first()
-        .unwrap()
-        .repr.split(" ").next()
-        .unwrap()
-        .to_string();
+    // newer versions of cargo no longer start package ids with the name, so look it up instead
+    let member = metadata.workspace_members.first().unwrap();
+    let name = metadata.packages.iter()
+        .find(|package| package.id == *member)
+        .map(|package| package.name.clone())
+        .unwrap_or_else(|| member.repr.split(" ").next().unwrap().to_string());
 
-    let title_id =
+    // Take the first package in the workspace which sets the given key
+    let first_str = |key: &str| {
         metadata.packages.iter()
             .fold(None, |x, y| x.or_else(||{
-                get_title_id(&y.metadata)
-            }));
-    
-    let npdm_path =
-        metadata.packages.iter()
-            .fold(None, |x, y| x.or_else(||{
-                get_npdm_path(&y.metadata)
-            }));
-    
-    let subsdk_name =
-        metadata.packages.iter()
-            .fold(None, |x, y| x.or_else(||{
-                get_subsdk_name(&y.metadata)
-            }));
+                get_str(&y.metadata, key)
+            }))
+    };
+
+    // anything set in the environment takes priority, so CI can change it without editing files
+    let env_or = |name: &str, key: &str| settings::env(name).or_else(|| first_str(key));
+
+    let title_ids = match settings::env("title_id") {
+        Some(title_id) => vec![title_id],
+        None => metadata.packages.iter()
+            .find_map(|package| get_title_ids(&package.metadata))
+            .unwrap_or_default()
+    };
+    let title_id = title_ids.first().cloned();
+    let display_name = first_str("display-name");
+    let nro_name = first_str("nro-name");
+    let install_dir = first_str("install-dir");
+    let plugin_subdir = first_str("plugin-subdir");
+    let plugin_dirs = metadata.packages.iter()
+        .find_map(|package| get_plugin_dirs(&package.metadata))
+        .unwrap_or_default();
+    let title_features = metadata.packages.iter()
+        .find_map(|package| get_title_features(&package.metadata))
+        .unwrap_or_default();
+    let install_features = metadata.packages.iter()
+        .find_map(|package| get_str_array(&package.metadata, "install-features"))
+        .unwrap_or_default();
+    let no_default_features = metadata.packages.iter()
+        .find_map(|package| get_value(&package.metadata, "no-default-features").and_then(serde_json::Value::as_bool))
+        .unwrap_or(false);
+    let target_spec = metadata.packages.iter().find_map(|package| {
+        Some(package.manifest_path.parent()?.join(wsl::translate_path(&get_str(&package.metadata, "target")?)))
+    });
+    let rustflags = metadata.packages.iter()
+        .find_map(|package| get_rustflags(&package.metadata))
+        .unwrap_or_default();
+    let size_budget = metadata.packages.iter().find_map(|package| get_size_budget(&package.metadata));
+    let embed_build_info = metadata.packages.iter()
+        .find_map(|package| get_value(&package.metadata, "build-info").and_then(serde_json::Value::as_bool))
+        .unwrap_or(true);
+    let layout = first_str("layout");
+    let npdm_path = first_str("custom-npdm")
+        .map(|path| wsl::translate_path(&path).to_string_lossy().into_owned());
+    let subsdk_name = metadata.packages.iter().find_map(|package| get_subsdk_name(&package.metadata));
+    let mod_name = first_str("mod-name");
+    let ftp_port = env_or("ftp_port", "ftp-port");
+    let ftp_user = env_or("ftp_user", "ftp-user");
+    let ftp_pass = env_or("ftp_pass", "ftp-pass");
+    let ftp_mode = env_or("ftp_mode", "ftp-mode");
+    let skyline_release = first_str("skyline-release");
+    let min_skyline_version = first_str("min-skyline-version");
+    let device = first_str("device");
+    let ip = first_str("ip");
 
     let plugin_dependencies =
         metadata.packages.iter()
@@ -96,14 +510,177 @@ pub fn get_metadata() -> Result<Metadata> {
                 x.append(
                     &mut get_dep_urls(&y.metadata).unwrap_or_default()
                 );
+                x.append(
+                    &mut get_dep_table(&y.metadata).unwrap_or_default()
+                );
                 x
             });
 
+    let protected_plugins =
+        metadata.packages.iter()
+            .flat_map(|package| get_str_array(&package.metadata, "protected-plugins").unwrap_or_default())
+            .collect();
+
+    let plugin_names =
+        metadata.packages.iter()
+            .filter(|package| metadata.workspace_members.contains(&package.id))
+            .flat_map(|package| package.targets.iter())
+            .filter(|target| target.kind.iter().any(|kind| kind == "cdylib"))
+            .map(|target| format!("lib{}.nro", target.name.replace('-', "_")))
+            .collect();
+
+    let allowed = workspace_members(&metadata.workspace_root);
+    let members = metadata.packages.iter()
+        .filter(|package| metadata.workspace_members.contains(&package.id))
+        .filter(|package| package.targets.iter().any(|target| target.kind.iter().any(|kind| kind == "cdylib")))
+        .filter(|package| !get_value(&package.metadata, "skip").and_then(serde_json::Value::as_bool).unwrap_or(false))
+        .filter(|package| allowed.as_ref().is_none_or(|allowed| allowed.contains(&package.name)))
+        .map(|package| Member {
+            id: package.id.clone(),
+            name: package.name.clone(),
+            version: package.version.to_string(),
+            display_name: get_str(&package.metadata, "display-name"),
+            title_ids: settings::env("title_id")
+                .map(|title_id| vec![title_id])
+                .or_else(|| get_title_ids(&package.metadata))
+                .unwrap_or_else(|| title_ids.clone()),
+            nro_name: get_str(&package.metadata, "nro-name"),
+            nacp: get_nacp(package),
+            size_budget: get_size_budget(&package.metadata),
+        })
+        .collect();
+
+    let assets = metadata.packages.iter()
+        .flat_map(|package| {
+            package.manifest_path.parent()
+                .and_then(|dir| get_assets(&package.metadata, dir))
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let deploy = metadata.packages.iter()
+        .fold(None, |x, y| x.or_else(|| {
+            get_deploy(&y.metadata, y.manifest_path.parent()?)
+        }));
+
+    let exefs = metadata.packages.iter()
+        .find_map(|package| get_exefs(&package.metadata, package.manifest_path.parent()?))
+        .unwrap_or_default();
+
+    let nacp = metadata.packages.iter().find_map(get_nacp);
+
     Ok(Metadata {
         name,
+        display_name,
         title_id,
+        title_ids,
+        nro_name,
+        install_dir,
+        plugin_subdir,
+        plugin_dirs,
+        title_features,
+        install_features,
+        no_default_features,
+        target_spec,
+        rustflags,
+        size_budget,
+        embed_build_info,
+        layout,
         npdm_path,
         subsdk_name,
-        plugin_dependencies
+        mod_name,
+        ftp_port,
+        ftp_user,
+        ftp_pass,
+        ftp_mode,
+        skyline_release,
+        min_skyline_version,
+        device,
+        ip,
+        plugin_dependencies,
+        protected_plugins,
+        plugin_names,
+        members,
+        deploy,
+        exefs,
+        nacp,
+        assets,
+        target_dir: metadata.target_directory.clone(),
+        workspace_root: metadata.workspace_root.clone(),
     })
 }
+
+/// Set a string key in `[package.metadata.skyline]` by editing the text of a manifest, so
+/// everything else in it is left exactly as it was. The table is added if there isn't one.
+fn set_metadata_key(text: &str, key: &str, value: &str) -> String {
+    let new_line = format!("{} = {}", key, toml::Value::String(value.to_owned()));
+    let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut lines: Vec<String> = text.lines().map(String::from).collect();
+
+    let is_header = |line: &str| line.trim_start().starts_with('[');
+    let table_name = |line: &str| -> String {
+        line.trim().trim_start_matches('[').split(']').next().unwrap_or("").chars().filter(|c| !c.is_whitespace()).collect()
+    };
+
+    let header = match lines.iter().position(|line| is_header(line) && table_name(line) == "package.metadata.skyline") {
+        Some(header) => header,
+        None => {
+            if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(String::from("[package.metadata.skyline]"));
+            lines.push(new_line);
+
+            return lines.join(newline) + newline;
+        }
+    };
+
+    let end = lines[header + 1..].iter().position(|line| is_header(line)).map(|i| header + 1 + i).unwrap_or(lines.len());
+
+    let existing = (header + 1..end).find(|i| {
+        let line = lines[*i].trim_start();
+        line.split('=').next().is_some_and(|name| name.trim().trim_matches('"') == key) && line.contains('=')
+    });
+
+    match existing {
+        Some(i) => {
+            let indent: String = lines[i].chars().take_while(|c| c.is_whitespace()).collect();
+
+            // an array can carry on over several lines, all of which are replaced
+            let mut last = i;
+            let value_text = lines[i].split_once('=').map(|(_, value)| value.trim()).unwrap_or("");
+            if value_text.starts_with('[') && !value_text.contains(']') {
+                while last + 1 < end && !lines[last].contains(']') {
+                    last += 1;
+                }
+            }
+
+            lines.splice(i..=last, std::iter::once(format!("{}{}", indent, new_line)));
+        }
+        None => lines.insert(header + 1, new_line),
+    }
+
+    lines.join(newline) + newline
+}
+
+/// Write a title id into the Cargo.toml of the current package
+pub fn set_title_id(game: &str) -> Result<()> {
+    let title_id = crate::titles::resolve_title_id(game).map_err(Error::BadTitleId)?;
+
+    let metadata = cargo_metadata::MetadataCommand::new().no_deps().exec()?;
+    let member = metadata.workspace_members.first().unwrap();
+    let manifest_path: &Path = metadata.packages.iter()
+        .find(|package| package.id == *member)
+        .map(|package| package.manifest_path.as_ref())
+        .ok_or(Error::NoTitleId)?;
+
+    let text = std::fs::read_to_string(manifest_path)?;
+    std::fs::write(manifest_path, set_metadata_key(&text, "titleid", &title_id))?;
+
+    match crate::titles::game_name(&title_id) {
+        Some(name) => println!("Set the title id to {} ({})", title_id, name),
+        None => println!("Set the title id to {}", title_id),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use std::fs;
+use std::path::PathBuf;
+use structopt::StructOpt;
+use owo_colors::OwoColorize;
+use crate::build;
+use crate::cargo_info;
+use crate::error::{Error, Result};
+use crate::installer::{self, dependencies};
+use crate::nm::read_dynamic_symbols;
+use crate::skyline_version;
+
+/// Flags for `check-imports`
+#[derive(StructOpt)]
+pub struct CheckImportsOptions {
+    #[structopt(
+        parse(from_str = crate::wsl::translate_path),
+        help = "The ELF, NRO or NSO to check, building the plugin if not given"
+    )]
+    pub file: Option<PathBuf>,
+
+    #[structopt(long)]
+    pub release: bool,
+
+    #[structopt(long, conflicts_with = "release", help = "Build with a custom cargo profile")]
+    pub profile: Option<String>,
+
+    #[structopt(short, long, help = "Package in the workspace to build")]
+    pub package: Option<String>,
+
+    #[structopt(
+        short, long,
+        help = "URL of the Skyline release zip to check against, defaults to the one pinned in Cargo.toml or the latest beta"
+    )]
+    pub skyline_release: Option<String>,
+
+    #[structopt(
+        long, conflicts_with = "skyline-release", parse(from_str = crate::wsl::translate_path),
+        help = "A build of Skyline's subsdk to check against instead of a release"
+    )]
+    pub skyline: Option<PathBuf>,
+
+    #[structopt(
+        long = "with", parse(from_str = crate::wsl::translate_path),
+        help = "Another module the plugin can import from, such as a plugin it needs or a dump of the game's SDK"
+    )]
+    pub with: Vec<PathBuf>,
+
+    #[structopt(short, long, help = "Also list the imports left for the game to provide")]
+    pub verbose: bool,
+}
+
+/// C runtime functions the game's SDK provides, which Rust's std on the switch imports
+const SDK_FUNCTIONS: &[&str] = &[
+    "abort", "calloc", "clock_gettime", "close", "exit", "free", "fstat", "getenv", "malloc", "memalign",
+    "memchr", "memcmp", "memcpy", "memmove", "memrchr", "memset", "nanosleep", "open", "posix_memalign",
+    "read", "realloc", "sched_yield", "strerror_r", "strlen", "sysconf", "write", "_exit", "bcmp",
+];
+const SDK_PREFIXES: &[&str] = &["__cxa_", "_Unwind_", "pthread_", "__errno", "__assert", "__stack_chk_", "__gxx_"];
+
+/// Whether an import is one the game resolves rather than Skyline or a plugin, which is anything
+/// from the SDK: its C++ API such as `nn::` and the C runtime under it
+fn from_game(name: &str) -> bool {
+    let is_cpp = name.starts_with("_Z") && rustc_demangle::try_demangle(name).is_err();
+
+    is_cpp || SDK_FUNCTIONS.contains(&name) || SDK_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+}
+
+/// Check that everything the plugin imports will be there when Skyline loads it, going by what
+/// Skyline, the plugin's dependencies and any other modules given export
+pub fn check_imports(options: CheckImportsOptions) -> Result<()> {
+    let metadata = cargo_info::get_metadata().ok();
+
+    let path = match &options.file {
+        Some(path) => path.clone(),
+        None => {
+            let mut args = vec![];
+            build::profile_args(&mut args, options.release, options.profile.as_deref());
+            if let Some(package) = &options.package {
+                build::package_args(&mut args, std::slice::from_ref(package));
+            }
+
+            build::build_get_elf_and_nro(args, false)?.0
+        }
+    };
+
+    let (skyline, skyline_name) = match &options.skyline {
+        Some(skyline) => (fs::read(skyline)?, skyline.display().to_string()),
+        None => {
+            let url = installer::skyline_release_url(options.skyline_release.clone(), metadata.as_ref());
+            let subsdk = crate::package::get_subsdk_cached(&url)?;
+            let version = skyline_version::from_binary(&subsdk).or_else(|| skyline_version::from_release(&url));
+
+            (subsdk, version.map(|version| format!("Skyline {}", version)).unwrap_or_else(|| String::from("Skyline")))
+        }
+    };
+
+    // everything the plugin can import from, by name, with what it exports
+    let mut providers = vec![(skyline_name, read_dynamic_symbols(&skyline)?)];
+    for dep in metadata.iter().flat_map(|metadata| &metadata.plugin_dependencies) {
+        providers.push((dep.name.clone(), read_dynamic_symbols(&dependencies::fetch(dep)?)?));
+    }
+    for with in &options.with {
+        providers.push((with.display().to_string(), read_dynamic_symbols(&fs::read(with)?)?));
+    }
+
+    let imports: Vec<_> = read_dynamic_symbols(&fs::read(&path)?)?
+        .into_iter()
+        .filter(|symbol| symbol.imported)
+        .collect();
+
+    println!("Checking the {} imports of {}\n", imports.len(), path.display().to_string().bright_blue());
+
+    let mut resolved = vec![0; providers.len()];
+    let mut game = vec![];
+    let mut unresolved = vec![];
+    for import in &imports {
+        let provider = providers.iter().position(|(_, exports)| {
+            exports.iter().any(|export| !export.imported && export.name == import.name)
+        });
+
+        match provider {
+            Some(index) => resolved[index] += 1,
+            None if from_game(&import.name) => game.push(import),
+            // a weak import is allowed to be missing, it's just null
+            None if import.weak => {}
+            None => unresolved.push(import),
+        }
+    }
+
+    for ((name, _), count) in providers.iter().zip(&resolved) {
+        println!("  {:>5} from {}", count, name);
+    }
+    println!("  {:>5} left for the game's SDK to provide", game.len());
+    if options.verbose {
+        for import in &game {
+            println!("          {}", format!("{:#}", rustc_demangle::demangle(&import.name)).bright_black());
+        }
+    }
+
+    if unresolved.is_empty() {
+        println!("\n{}", "Every import can be resolved".green());
+        return Ok(());
+    }
+
+    println!("\n{}", "Not exported by anything the plugin is loaded with:".red());
+    for import in &unresolved {
+        println!("  {:#}", rustc_demangle::demangle(&import.name));
+    }
+
+    Err(Error::UnresolvedImports(unresolved.len()))
+}
@@ -0,0 +1,396 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+use owo_colors::OwoColorize;
+use crate::cargo_info;
+use crate::error::Result;
+use crate::installer::{self, Destination};
+use crate::symbolicate::{self, Symbolizer};
+
+/// Flags for `crash`
+#[derive(StructOpt)]
+pub struct CrashOptions {
+    #[structopt(flatten)]
+    pub dest: Destination,
+
+    #[structopt(help = "Name of the report to show, the newest one by default")]
+    pub report: Option<String>,
+
+    #[structopt(long, help = "List the crash reports on the console instead of showing one")]
+    pub list: bool,
+
+    #[structopt(
+        long, conflicts_with_all = &["list", "report"], parse(from_str = crate::wsl::translate_path),
+        help = "Read a crash report already downloaded instead of getting one from the console"
+    )]
+    pub local: Option<PathBuf>,
+
+    #[structopt(
+        long, parse(from_str = crate::wsl::translate_path),
+        help = "The unstripped ELF or .sym of the plugin, the copy from the last install or build by default"
+    )]
+    pub elf: Option<PathBuf>,
+
+    #[structopt(long)]
+    pub release: bool,
+
+    #[structopt(long, conflicts_with = "release", help = "Build with a custom cargo profile")]
+    pub profile: Option<String>,
+
+    #[structopt(short, long, help = "Package in the workspace to build")]
+    pub package: Option<String>,
+
+    #[structopt(long, help = "Print the report as it is, with only the plugin's addresses looked up")]
+    pub raw: bool,
+}
+
+/// A module which was loaded when the game crashed
+struct CrashModule {
+    start: u64,
+    end: u64,
+    name: String,
+    build_id: String,
+}
+
+/// What's worth showing from one of creport's crash reports
+#[derive(Default)]
+struct Report {
+    result: Option<String>,
+    exception: Vec<(String, String)>,
+    thread_name: Option<String>,
+    /// The crashed thread's registers in the order they're listed, X[00] to X[28] then FP, LR, SP and PC
+    registers: Vec<(String, u64)>,
+    stack_trace: Vec<u64>,
+    modules: Vec<CrashModule>,
+}
+
+/// The address a value in a report starts with, which may be followed by where it is like
+/// `(main + 0x1234)`
+fn parse_value(value: &str) -> Option<u64> {
+    symbolicate::parse_address(value.split_whitespace().next()?).ok()
+}
+
+impl Report {
+    /// Read the sections of a report, which are indented under headings ending in ':'
+    fn parse(text: &str) -> Self {
+        let mut report = Report::default();
+        let mut section = "";
+        let mut subsection = "";
+
+        for line in text.lines() {
+            let indent = line.len() - line.trim_start().len();
+            let (key, value) = match line.trim().split_once(':') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => continue,
+            };
+
+            match indent {
+                0 => {
+                    section = key;
+                    subsection = "";
+                    if key == "Result" {
+                        report.result = Some(value.to_owned());
+                    }
+                    continue;
+                }
+                4 => subsection = key,
+                _ => {}
+            }
+
+            match (section, subsection) {
+                ("Exception Info", _) if indent == 4 => report.exception.push((key.to_owned(), value.to_owned())),
+                ("Crashed Thread Info", "Thread Name") => report.thread_name = Some(value.to_owned()),
+                ("Crashed Thread Info", "Registers") | ("Crashed Thread Info", "General Purpose Registers") if indent > 4 => {
+                    if let Some(register) = parse_value(value) {
+                        report.registers.push((key.replace(['[', ']'], ""), register));
+                    }
+                }
+                ("Crashed Thread Info", "Stack Trace") if indent > 4 => report.stack_trace.extend(parse_value(value)),
+                ("Module Info", _) if indent == 4 && key.starts_with("Module ") => report.modules.push(CrashModule {
+                    start: 0, end: 0, name: String::new(), build_id: String::new(),
+                }),
+                ("Module Info", _) if indent > 4 => {
+                    let module = match report.modules.last_mut() {
+                        Some(module) => module,
+                        None => continue,
+                    };
+
+                    match key {
+                        "Address" => if let Some((start, end)) = value.split_once('-') {
+                            module.start = parse_value(start).unwrap_or(0);
+                            module.end = parse_value(end).unwrap_or(0);
+                        },
+                        "Name" => module.name = value.to_owned(),
+                        "Build Id" => module.build_id = value.to_owned(),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        report
+    }
+
+    fn module_of(&self, address: u64) -> Option<&CrashModule> {
+        self.modules.iter().find(|module| address >= module.start && address < module.end)
+    }
+}
+
+/// The local plugin the report's addresses are looked up in
+struct Plugin<'a> {
+    /// The NRO's name without its extension, which is what creport calls the module
+    name: String,
+    build_id: Option<String>,
+    symbolizer: Symbolizer<'a>,
+}
+
+/// A build id without the zeroes padding it out, so ones printed at different lengths compare equal
+fn trim_build_id(build_id: &str) -> String {
+    let mut build_id = build_id.trim().to_uppercase();
+    while build_id.ends_with("00") {
+        build_id.truncate(build_id.len() - 2);
+    }
+
+    build_id
+}
+
+impl Plugin<'_> {
+    fn has_name_of(&self, module: &CrashModule) -> bool {
+        module.name.trim_end_matches(".nro").rsplit(['/', '\\']).next() == Some(self.name.as_str())
+    }
+
+    /// Whether a module in the report is this plugin, by build id if possible and by name otherwise
+    fn matches(&self, module: &CrashModule) -> bool {
+        match &self.build_id {
+            Some(build_id) if !module.build_id.is_empty() => *build_id == trim_build_id(&module.build_id),
+            _ => self.has_name_of(module),
+        }
+    }
+}
+
+/// Describe an address: which module it's in and, if it's in the plugin, which function and line
+fn describe(report: &Report, plugin: Option<&Plugin>, address: u64) -> Vec<String> {
+    let module = match report.module_of(address) {
+        Some(module) => module,
+        None => return vec![String::new()],
+    };
+
+    let offset = address - module.start;
+    let place = format!("{} + 0x{:x}", module.name, offset);
+
+    match plugin.filter(|plugin| plugin.matches(module)) {
+        Some(plugin) => {
+            let mut lines = plugin.symbolizer.lookup(offset).describe();
+            lines[0] = format!("{}  {}", place.bright_blue(), lines[0]);
+            lines
+        }
+        None => vec![place.bright_black().to_string()],
+    }
+}
+
+fn print_address(label: &str, report: &Report, plugin: Option<&Plugin>, address: u64) {
+    for (index, line) in describe(report, plugin, address).into_iter().enumerate() {
+        if index == 0 {
+            println!("{}", format!("  {:<6} {:016X}  {}", label, address, line).trim_end());
+        } else {
+            println!("  {:<6} {:16}  {}", "", "", line);
+        }
+    }
+}
+
+/// Print the report's registers, stack trace and modules, looking up the plugin's addresses
+fn print_report(report: &Report, plugin: Option<&Plugin>) {
+    if let Some(result) = &report.result {
+        println!("{:<10} {}", "Result", result);
+    }
+    for (key, value) in &report.exception {
+        println!("{:<10} {}", key, value);
+    }
+    if let Some(thread_name) = &report.thread_name {
+        println!("{:<10} {}", "Thread", thread_name);
+    }
+
+    // the registers which say where it crashed go first
+    println!("\n{}", "Registers".bright_blue());
+    let (mut first, mut rest): (Vec<_>, Vec<_>) = report.registers.iter().partition(|(name, _)| matches!(name.as_str(), "PC" | "LR" | "SP" | "FP"));
+    first.sort_by_key(|(name, _)| ["PC", "LR", "SP", "FP"].iter().position(|first| first == name));
+    rest.retain(|(_, value)| report.module_of(*value).is_some());
+    for (name, value) in first.into_iter().chain(rest) {
+        print_address(name, report, plugin, *value);
+    }
+
+    if !report.stack_trace.is_empty() {
+        println!("\n{}", "Stack trace".bright_blue());
+        for (index, address) in report.stack_trace.iter().enumerate() {
+            print_address(&format!("#{:02}", index), report, plugin, *address);
+        }
+    }
+
+    if !report.modules.is_empty() {
+        println!("\n{}", "Modules".bright_blue());
+        for module in &report.modules {
+            let mark = if plugin.is_some_and(|plugin| plugin.matches(module)) { "*" } else { " " };
+            println!("  {} {:016X}-{:016X}  {:<24} {}", mark, module.start, module.end, module.name, module.build_id.bright_black());
+        }
+    }
+}
+
+/// Print a report in a format that isn't creport's as it is, adding the function and line after
+/// anything written like `libplugin + 0x1234`
+fn print_raw(text: &str, plugin: Option<&Plugin>) {
+    for line in text.lines() {
+        println!("{}", line);
+
+        let plugin = match plugin {
+            Some(plugin) => plugin,
+            None => continue,
+        };
+
+        let pattern = format!("{} + ", plugin.name);
+        for (start, _) in line.match_indices(&pattern) {
+            let offset = line[start + pattern.len()..].split(|c: char| !c.is_ascii_hexdigit() && c != 'x').next();
+            if let Some(offset) = offset.and_then(|offset| symbolicate::parse_address(offset).ok()) {
+                for described in plugin.symbolizer.lookup(offset).describe() {
+                    println!("    {} {}", "^".bright_blue(), described);
+                }
+            }
+        }
+    }
+}
+
+/// The build id of the NRO next to a plugin's ELF or .sym
+fn build_id_of(symbols_path: &Path) -> Option<String> {
+    let nro = fs::read(symbols_path.with_extension("nro")).ok()?;
+    let module = crate::nxo::Module::read(&nro).ok()?;
+
+    Some(trim_build_id(&module.build_id.iter().map(|byte| format!("{:02X}", byte)).collect::<String>()))
+}
+
+/// Download the newest crash report from the console and show what happened, looking up whichever
+/// addresses are in the plugin
+pub fn crash(options: CrashOptions) -> Result<()> {
+    let (name, data) = match &options.local {
+        Some(local) => (local.display().to_string(), fs::read(local)?),
+        None => match installer::get_crash_report(options.dest.clone(), options.report.as_deref(), options.list)? {
+            Some(report) => report,
+            None => return Ok(()),
+        },
+    };
+    let text = String::from_utf8_lossy(&data);
+
+    // without a project there's nothing to look addresses up in, but the report is still worth showing
+    let symbols_path = if options.elf.is_some() || cargo_info::get_metadata().is_ok() {
+        match symbolicate::find_symbols(options.elf.as_deref(), options.release, options.profile.as_deref(), options.package.as_deref()) {
+            Ok(path) => Some(path),
+            Err(_) => {
+                println!("{}: Couldn't find the plugin's ELF, so its addresses won't be looked up", "Warning".yellow());
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let elf_data = match &symbols_path {
+        Some(path) => symbolicate::read_elf(path)?,
+        None => None,
+    };
+    let plugin = match &symbols_path {
+        Some(path) => Some(Plugin {
+            name: path.file_stem().unwrap_or_default().to_string_lossy().into_owned(),
+            build_id: build_id_of(path),
+            symbolizer: match &elf_data {
+                Some(data) => Symbolizer::from_elf(data)?,
+                None => Symbolizer::from_sym_file(path)?,
+            },
+        }),
+        None => None,
+    };
+
+    // creport names reports `<time>_<program id>.log`
+    let title_id = name.rsplit('/').next().and_then(|file| file.trim_end_matches(".log").rsplit('_').next()).map(str::to_uppercase);
+    let game = title_id.as_deref().and_then(crate::titles::game_name);
+    println!("{}{}\n", name.bright_blue(), game.map(|game| format!(" ({})", game)).unwrap_or_default());
+
+    let report = Report::parse(&text);
+    if let Some(plugin) = &plugin {
+        if report.modules.iter().any(|module| plugin.has_name_of(module) && !plugin.matches(module)) {
+            println!(
+                "{}: The {} which crashed isn't the build being looked up in, pass the ELF it was built from with --elf\n",
+                "Warning".yellow(), plugin.name
+            );
+        }
+    }
+
+    if options.raw || (report.registers.is_empty() && report.modules.is_empty()) {
+        print_raw(&text, plugin.as_ref());
+    } else {
+        print_report(&report, plugin.as_ref());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPORT: &str = "\
+Atmosphère Crash Report (v1.6):
+Result:                          0x000000EA8 (2168-0001)
+
+Exception Info:
+    Type:                        Data Abort
+    Address:                     0000000000000018
+Crashed Thread Info:
+    Thread ID:                   0000000000000045
+    Thread Name:                 MainThread
+    Registers:
+        X[00]:                   0000000000000000
+        X[01]:                   0000000812345000 (libplugin + 0x45000)
+        FP:                      0000001234567890
+        LR:                      0000000812300104 (libplugin + 0x104)
+        PC:                      0000000800001230 (main + 0x1230)
+    Stack Trace:
+        ReturnAddress[00]:       0000000812300104
+        ReturnAddress[01]:       0000000800004560
+Module Info:
+    Number of Modules:           2
+    Module 00:
+        Address:                 0000000800000000-0000000801000000
+        Name:                    main
+        Build Id:                0123456789ABCDEF0000000000000000
+    Module 01:
+        Address:                 0000000812300000-0000000812400000
+        Name:                    libplugin.nro
+        Build Id:                FEDCBA9876543210
+";
+
+    #[test]
+    fn parse_report() {
+        let report = Report::parse(REPORT);
+
+        assert_eq!(report.result.as_deref(), Some("0x000000EA8 (2168-0001)"));
+        assert_eq!(report.exception[0], (String::from("Type"), String::from("Data Abort")));
+        assert_eq!(report.thread_name.as_deref(), Some("MainThread"));
+
+        let registers: Vec<&str> = report.registers.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(registers, ["X00", "X01", "FP", "LR", "PC"]);
+        assert_eq!(report.registers[3].1, 0x812300104);
+
+        assert_eq!(report.stack_trace, [0x812300104, 0x800004560]);
+
+        assert_eq!(report.modules.len(), 2);
+        assert_eq!(report.modules[1].name, "libplugin.nro");
+        assert_eq!((report.modules[1].start, report.modules[1].end), (0x812300000, 0x812400000));
+        assert_eq!(report.module_of(0x800004560).map(|module| module.name.as_str()), Some("main"));
+        assert!(report.module_of(0x900000000).is_none());
+    }
+
+    #[test]
+    fn build_ids_ignore_padding() {
+        assert_eq!(trim_build_id("0123456789abcdef0000000000000000"), "0123456789ABCDEF");
+        assert_eq!(trim_build_id("0123456789ABCDEF"), "0123456789ABCDEF");
+    }
+}
@@ -0,0 +1,159 @@
+use std::io::Write;
+use std::net::{Ipv4Addr, SocketAddr, TcpStream, UdpSocket};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use owo_colors::OwoColorize;
+use crate::error::{Error, Result};
+use crate::ftp::DEFAULT_PORT;
+use crate::ip_addr::{self, Device};
+use crate::mdns;
+use crate::tcp_listen::LOG_PORT;
+
+// plenty for the handful of hosts on a home network, without opening thousands of sockets at once
+const SCAN_THREADS: usize = 64;
+
+const FTP_SERVICE: &str = "_ftp._tcp.local";
+
+/// Find the address of this computer on the local network, by asking the OS which interface it
+/// would route through. Nothing is actually sent.
+fn local_ipv4() -> Option<Ipv4Addr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:80").ok()?;
+
+    match socket.local_addr().ok()? {
+        SocketAddr::V4(addr) if !addr.ip().is_loopback() => Some(*addr.ip()),
+        _ => None
+    }
+}
+
+/// Parse a subnet given as `a.b.c.d/prefix`
+fn parse_subnet(subnet: &str) -> Result<(u32, u32)> {
+    let (addr, prefix) = subnet.split_once('/').unwrap_or((subnet, "24"));
+    let addr: Ipv4Addr = addr.parse().map_err(|_| Error::BadSubnet(subnet.to_owned()))?;
+    let prefix: u32 = prefix.parse().ok().filter(|prefix| (16..=30).contains(prefix))
+        .ok_or_else(|| Error::BadSubnet(subnet.to_owned()))?;
+
+    let mask = u32::MAX << (32 - prefix);
+    let network = u32::from(addr) & mask;
+
+    // skip the network and broadcast addresses
+    Ok((network + 1, (network | !mask) - 1))
+}
+
+fn port_open(ip: Ipv4Addr, port: u16, timeout: Duration) -> bool {
+    TcpStream::connect_timeout(&SocketAddr::new(ip.into(), port), timeout).is_ok()
+}
+
+fn prompt(message: &str) -> String {
+    print!("{}", message);
+    let _ = std::io::stdout().lock().flush();
+
+    let mut line = String::new();
+    let _ = std::io::stdin().read_line(&mut line);
+
+    line.trim().to_owned()
+}
+
+/// A console which answered the scan
+struct Found {
+    ip: Ipv4Addr,
+    /// The name it announced itself as over mDNS, which stays the same when DHCP gives it a new address
+    host: Option<String>,
+    port: u16,
+    open: Vec<u16>,
+}
+
+/// Scan the local subnet for anything with an FTP server or skyline's logger listening, and offer
+/// to save what's found as devices
+pub fn discover(subnet: Option<String>, port: Option<u16>, timeout_ms: u64) -> Result<()> {
+    let subnet = match subnet {
+        Some(subnet) => subnet,
+        None => format!("{}/24", local_ipv4().ok_or(Error::NoLocalNetwork)?)
+    };
+
+    let (first, last) = parse_subnet(&subnet)?;
+    let port = port.unwrap_or(DEFAULT_PORT);
+    let timeout = Duration::from_millis(timeout_ms);
+
+    println!("Scanning {} for ports {} and {}...", subnet, port, LOG_PORT);
+
+    let next = AtomicU32::new(first);
+    let found = Mutex::new(vec![]);
+
+    std::thread::scope(|scope| {
+        for _ in 0..SCAN_THREADS {
+            scope.spawn(|| loop {
+                let addr = next.fetch_add(1, Ordering::Relaxed);
+                if addr > last {
+                    break;
+                }
+
+                let ip = Ipv4Addr::from(addr);
+                let open: Vec<u16> = [port, LOG_PORT].iter()
+                    .copied()
+                    .filter(|port| port_open(ip, *port, timeout))
+                    .collect();
+
+                if !open.is_empty() {
+                    found.lock().unwrap().push(Found { ip, host: None, port, open });
+                }
+            });
+        }
+    });
+
+    let mut found = found.into_inner().unwrap();
+
+    // some ftpd builds announce themselves, which also finds consoles outside of the subnet scanned
+    for service in mdns::browse(FTP_SERVICE) {
+        match found.iter_mut().find(|found| found.ip == service.ip) {
+            Some(found) => found.host = Some(service.host),
+            None => found.push(Found { ip: service.ip, host: Some(service.host), port: service.port, open: vec![service.port] }),
+        }
+    }
+
+    found.sort_by_key(|found| found.ip);
+
+    if found.is_empty() {
+        println!("No consoles found, make sure the switch is awake and running an FTP server");
+        return Ok(());
+    }
+
+    let saved = ip_addr::saved_devices();
+
+    for found in found {
+        let open: Vec<String> = found.open.iter().map(u16::to_string).collect();
+        let address = found.host.clone().unwrap_or_else(|| found.ip.to_string());
+
+        match &found.host {
+            Some(host) => println!("{} {} at {} (open: {})", "Found".green(), host, found.ip, open.join(", ")),
+            None => println!("{} {} (open: {})", "Found".green(), found.ip, open.join(", ")),
+        }
+
+        let existing = saved.iter()
+            .filter(|device| device.port.unwrap_or(DEFAULT_PORT) == found.port)
+            .find(|device| device.ip == address || device.ip == found.ip.to_string());
+
+        if let Some(device) = existing {
+            println!("    already saved as '{}'", device.name);
+            continue;
+        }
+
+        let name = prompt("    Name to save it as (leave empty to skip): ");
+        if name.is_empty() {
+            continue;
+        }
+
+        ip_addr::add_device(Device {
+            name,
+            ip: address,
+            port: Some(found.port).filter(|port| *port != DEFAULT_PORT),
+            user: None,
+            pass: None,
+            transport: None,
+            ftp_mode: None,
+        })?;
+    }
+
+    Ok(())
+}
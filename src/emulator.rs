@@ -0,0 +1,59 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use crate::error::{Error, Result};
+
+/// Emulators which can be installed to directly instead of over the network
+#[derive(Copy, Clone)]
+pub enum Emulator {
+    Ryujinx,
+    Yuzu,
+    Suyu,
+}
+
+impl FromStr for Emulator {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match &*s.to_lowercase() {
+            "ryujinx" => Ok(Emulator::Ryujinx),
+            "yuzu" => Ok(Emulator::Yuzu),
+            "suyu" => Ok(Emulator::Suyu),
+            _ => Err(format!("Unknown emulator '{}', expected 'ryujinx', 'yuzu' or 'suyu'", s))
+        }
+    }
+}
+
+impl Emulator {
+    /// Whether the emulator uses per-mod `load/<title id>/<mod name>` folders rather than an
+    /// atmosphere-style SD card layout
+    pub fn uses_load_dir(self) -> bool {
+        match self {
+            Emulator::Ryujinx => false,
+            Emulator::Yuzu | Emulator::Suyu => true,
+        }
+    }
+
+    /// Get the directory to install into, either the root of the emulated SD card or the mod
+    /// load directory depending on the emulator
+    pub fn install_root(self) -> Result<PathBuf> {
+        match self {
+            Emulator::Ryujinx => Ok(data_dir(dirs::config_dir(), "Ryujinx")?.join("sdcard")),
+            Emulator::Yuzu => Ok(data_dir(dirs::data_dir(), "yuzu")?.join("load")),
+            Emulator::Suyu => Ok(data_dir(dirs::data_dir(), "suyu")?.join("load")),
+        }
+    }
+}
+
+// Ryujinx keeps its data in the config dir, yuzu and its forks in the data dir. On Windows and
+// macOS these are the same place (%APPDATA% and ~/Library/Application Support)
+fn data_dir(base: Option<PathBuf>, name: &str) -> Result<PathBuf> {
+    let dir = base
+        .ok_or(Error::NoHomeDir)?
+        .join(name);
+
+    if dir.exists() {
+        Ok(dir)
+    } else {
+        Err(Error::NoEmulatorDir(dir))
+    }
+}
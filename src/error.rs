@@ -1,14 +1,19 @@
 use std::io;
+use std::net::IpAddr;
+use std::path::PathBuf;
 use crate::ftp::FtpError;
+use crate::usb::UsbError;
 use owo_colors::OwoColorize;
 
+#[derive(Debug)]
 pub enum Error {
     NoIpFound,
     BadIpAddr,
+    UnresolvedHost(String),
     NoHomeDir,
     NoPathFound,
     CreateSwitchDirDenied,
-    WriteIpDenied,
+    WriteSettingDenied(String),
     //NoCargoToml,
     //BadCargoToml,
     NoTitleId,
@@ -21,16 +26,61 @@ pub enum Error {
     NoNpdmFileFound,
     IoError(io::Error),
     FtpError(FtpError),
+    UsbError(UsbError),
     CargoError(cargo_metadata::Error),
     ExitStatus(i32),
     AbsSwitchPath,
-    BadSdPath
+    BadSdPath,
+    NoEmulatorDir(PathBuf),
+    NoSdCardFound(PathBuf),
+    BadSetting(&'static str),
+    VerifyFailed(String),
+    InsufficientSpace(u64, u64),
+    SdCardFull,
+    NoDeployManifest,
+    MissingDeployFile(PathBuf),
+    MissingAssetDir(PathBuf),
+    UnknownDevice(String),
+    NoDevices,
+    TooManyDevices,
+    BadSubnet(String),
+    NoLocalNetwork,
+    InstallsFailed(usize),
+    BadLockfile(PathBuf),
+    BadConfigFile(PathBuf),
+    ConfigTooNew(PathBuf),
+    UnknownSetting(String),
+    BadTitleId(String),
+    InvalidMetadata(usize),
+    NotWsl,
+    NoWindowsHome,
+    SwitchDirExists(PathBuf),
+    LockedHashMismatch(String),
+    UnknownPlugin(String),
+    PreflightFailed(String),
+    NoReleasePlugin(String, String),
+    /// The title, the Skyline version it has and the version the plugin needs
+    SkylineTooOld(String, String, String),
+    NoWorkspacePlugins,
+    UnknownPackage(String),
+    BadNroName(String),
+    BadModule,
+    CorruptModule(String, String),
+    UnresolvedImports(usize),
+    NoCrashReport(Option<String>),
+    BadElf,
+    MapForSeveralPlugins,
+    NoLinkMap,
+    NoDeployedCopy(String),
+    /// The NRO's name, its size, the most it can be and how big it was after the last build
+    NroTooBig(String, u64, u64, Option<u64>),
+    NotLogging(IpAddr),
 }
 
 pub type Result<T> = core::result::Result<T, Error>;
 
-pub static NO_IP: &str = "\n\nNo ip address found. Configure using `cargo skyline set-ip [addr]`, set using the SWITCH_IP environment variable, or pass as an argument.";
-pub static BAD_IP_ADDR: &str = "\n\nCould not parse IP address: likely is not correctly formatted.";
+pub static NO_IP: &str = "\n\nNo ip address found. Configure using `cargo skyline set-ip [addr]`, set using the CARGO_SKYLINE_IP or SWITCH_IP environment variables, or pass as an argument.";
+pub static BAD_IP_ADDR: &str = "\n\nCould not parse IP address or hostname: likely is not correctly formatted.";
 
 pub fn no_title_id() {
     eprintln!(concat!(
@@ -47,6 +97,12 @@ impl From<FtpError> for Error {
     }
 }
 
+impl From<UsbError> for Error {
+    fn from(err: UsbError) -> Self {
+        Self::UsbError(err)
+    }
+}
+
 impl From<cargo_metadata::Error> for Error {
     fn from(err: cargo_metadata::Error) -> Self {
         Self::CargoError(err)
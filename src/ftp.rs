@@ -1,26 +1,247 @@
-use std::net::{IpAddr, TcpStream};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
 use std::io::prelude::*;
 use std::io::{self, BufReader};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::fmt;
+use crate::progress;
+use crate::timeouts::Timeouts;
+use indicatif::ProgressBar;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub use sftp::SftpClient;
+
+mod sftp;
 
 #[derive(Debug)]
 pub enum FtpError {
     Io(io::Error),
     ParseFail,
     UnexpectedStatus(usize),
+    Sftp(String),
+    DataConnectionTimeout,
+    IncompleteUpload(usize, usize),
 }
 
 type Result<T> = std::result::Result<T, FtpError>;
 
+pub const DEFAULT_PORT: u16 = 5000;
+
+const ACTIVE_ACCEPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How data connections (used for listings and file transfers) are established
+#[derive(Copy, Clone, PartialEq)]
+pub enum DataMode {
+    /// The server listens and we connect, negotiated with PASV
+    Passive,
+    /// Passive, but negotiated with EPSV so only a port is sent back
+    ExtendedPassive,
+    /// We listen and the server connects, negotiated with PORT
+    Active,
+    /// Active, but negotiated with EPRT
+    ExtendedActive,
+}
+
+impl FromStr for DataMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match &*s.to_lowercase() {
+            "passive" | "pasv" => Ok(DataMode::Passive),
+            "epsv" => Ok(DataMode::ExtendedPassive),
+            "active" | "port" => Ok(DataMode::Active),
+            "eprt" => Ok(DataMode::ExtendedActive),
+            _ => Err(format!("Unknown FTP mode '{}', expected 'passive', 'epsv', 'active' or 'eprt'", s))
+        }
+    }
+}
+
+impl fmt::Display for DataMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            DataMode::Passive => "passive",
+            DataMode::ExtendedPassive => "epsv",
+            DataMode::Active => "active",
+            DataMode::ExtendedActive => "eprt",
+        })
+    }
+}
+
+// saved with devices, under the same names it's given on the command line
+impl Serialize for DataMode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for DataMode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A transfer rate in bytes per second, parsed from a number with an optional `K`, `M` or `G`
+/// suffix (e.g. `500K`)
+#[derive(Copy, Clone)]
+pub struct ByteRate(pub u64);
+
+impl FromStr for ByteRate {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim();
+        let (number, multiplier) = match s.chars().last().map(|c| c.to_ascii_uppercase()) {
+            Some('K') => (&s[..s.len() - 1], 1024),
+            Some('M') => (&s[..s.len() - 1], 1024 * 1024),
+            Some('G') => (&s[..s.len() - 1], 1024 * 1024 * 1024),
+            _ => (s, 1),
+        };
+
+        match number.parse::<u64>().ok().and_then(|rate| rate.checked_mul(multiplier)) {
+            Some(rate) if rate > 0 => Ok(ByteRate(rate)),
+            _ => Err(format!("Invalid rate '{}', expected a number of bytes per second such as '500K' or '2M'", s))
+        }
+    }
+}
+
+/// How many times to attempt an operation before giving up, backing off exponentially
+#[derive(Copy, Clone)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 3,
+            delay: Duration::from_millis(500),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Run an operation, retrying it if the connection failed
+    pub fn run<T>(&self, mut op: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut attempt = 1;
+        let mut delay = self.delay;
+        loop {
+            match op() {
+                Err(FtpError::Io(_)) | Err(FtpError::DataConnectionTimeout) if attempt < self.attempts => {
+                    #[cfg(feature = "debug")] {
+                        println!("[FTP] attempt {} failed, retrying in {:?}", attempt, delay);
+                    }
+
+                    std::thread::sleep(delay);
+                    delay *= 2;
+                    attempt += 1;
+                }
+                result => break result
+            }
+        }
+    }
+}
+
+/// Settings used to connect and log in to an FTP server
+#[derive(Clone)]
+pub struct FtpOptions {
+    pub port: u16,
+    pub user: String,
+    pub pass: String,
+    pub data_mode: DataMode,
+    pub retry: RetryPolicy,
+    pub rate_limit: Option<ByteRate>,
+    pub timeouts: Timeouts,
+    pub keepalive: Option<Duration>,
+}
+
+impl Default for FtpOptions {
+    fn default() -> Self {
+        FtpOptions {
+            port: DEFAULT_PORT,
+            user: String::from("anonymous"),
+            pass: String::from("anonymous"),
+            data_mode: DataMode::Passive,
+            retry: RetryPolicy::default(),
+            rate_limit: None,
+            timeouts: Timeouts::default(),
+            keepalive: Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+/// A data connection which has been negotiated but, in active mode, not yet accepted
+pub enum DataChannel {
+    Connected(TcpStream),
+    Listening(TcpListener),
+}
+
+impl DataChannel {
+    /// Get the underlying stream, waiting for the server to connect if needed. In active mode
+    /// this must only be called after the command using the channel has been sent.
+    pub fn into_stream(self, timeouts: &Timeouts) -> Result<TcpStream> {
+        match self {
+            DataChannel::Connected(stream) => Ok(stream),
+            DataChannel::Listening(listener) => {
+                listener.set_nonblocking(true)?;
+
+                let start = Instant::now();
+                loop {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            stream.set_nonblocking(false)?;
+                            timeouts.apply(&stream)?;
+                            break Ok(stream)
+                        }
+                        Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                            if start.elapsed() > ACTIVE_ACCEPT_TIMEOUT {
+                                break Err(FtpError::DataConnectionTimeout)
+                            }
+                            std::thread::sleep(Duration::from_millis(10));
+                        }
+                        Err(err) => break Err(err.into())
+                    }
+                }
+            }
+        }
+    }
+}
+
+const CHUNK_SIZE: usize = 0x8000;
+const MAX_RESUME_ATTEMPTS: usize = 3;
+const MAX_PARALLEL_UPLOADS: usize = 4;
+
+// files at least this big are split up and sent over several connections at once
+const SEGMENT_THRESHOLD: usize = 8 * 1024 * 1024;
+
 pub struct FtpClient {
-    pub tcp: BufReader<TcpStream>
+    pub tcp: BufReader<TcpStream>,
+    addr: SocketAddr,
+    login: Option<(String, String)>,
+    data_mode: DataMode,
+    show_progress: bool,
+    retry: RetryPolicy,
+    rate_limit: Option<ByteRate>,
+    timeouts: Timeouts,
+    keepalive: Option<Duration>,
+    last_sent: Instant,
 }
 
 impl FtpClient {
-    pub fn connect(ip: IpAddr) -> Result<Self> {
+    pub fn connect(ip: IpAddr, port: u16, timeouts: Timeouts) -> Result<Self> {
+        let addr = SocketAddr::new(ip, port);
         let mut client = FtpClient {
-            tcp: BufReader::new(TcpStream::connect((ip, 5000))?)
+            tcp: BufReader::new(timeouts.connect(addr)?),
+            timeouts,
+            keepalive: None,
+            last_sent: Instant::now(),
+            addr,
+            login: None,
+            data_mode: DataMode::Passive,
+            show_progress: false,
+            retry: RetryPolicy::default(),
+            rate_limit: None,
         };
 
         let status = client.next()?.0;
@@ -33,6 +254,9 @@ impl FtpClient {
     
     pub fn next(&mut self) -> Result<(usize, String)> {
         let mut status = self.next_line()?;
+        if status.len() < 4 {
+            return Err(FtpError::ParseFail)
+        }
         let line = status.split_off(4);
         let num: usize = status[..3].parse().map_err(|_| FtpError::ParseFail)?;
         Ok((num, line))
@@ -40,9 +264,13 @@ impl FtpClient {
 
     pub fn next_line(&mut self) -> Result<String> {
         let mut line = String::new();
-        self.tcp.read_line(&mut line)
+        let len = self.tcp.read_line(&mut line)
             .map_err(|e| FtpError::Io(e))?;
 
+        if len == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())
+        }
+
         #[cfg(feature = "debug")] {
             println!("<FTP> {}", line.trim_end_matches("\n"));
         }
@@ -53,14 +281,102 @@ impl FtpClient {
         let _ = self.tcp.get_mut().set_read_timeout(Some(Duration::from_millis(20)));
         let mut dump = vec![];
         let _ = self.tcp.read_to_end(&mut dump);
-        let _ = self.tcp.get_mut().set_read_timeout(Some(Duration::from_millis(500)));
+        let _ = self.tcp.get_mut().set_read_timeout(Some(self.timeouts.read.unwrap_or(Duration::from_millis(500))));
+    }
+
+    pub fn set_data_mode(&mut self, mode: DataMode) {
+        self.data_mode = mode;
+    }
+
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    /// Cap how fast files are uploaded
+    pub fn set_rate_limit(&mut self, rate_limit: Option<ByteRate>) {
+        self.rate_limit = rate_limit;
+    }
+
+    /// Send a NOOP whenever the control connection has been idle this long
+    pub fn set_keepalive(&mut self, interval: Option<Duration>) {
+        self.keepalive = interval;
+    }
+
+    pub fn keepalive(&self) -> Option<Duration> {
+        self.keepalive
+    }
+
+    /// Stop the server from timing out the connection if nothing has been sent for a while
+    pub fn keep_alive(&mut self) -> Result<()> {
+        match self.keepalive {
+            Some(interval) if self.last_sent.elapsed() >= interval => {
+                self.clear_status();
+                self.send("NOOP")?;
+                self.expect_success()
+            }
+            _ => Ok(())
+        }
+    }
+
+    /// Run an operation, reconnecting and trying again if the connection drops
+    pub fn with_retry<T>(&mut self, mut op: impl FnMut(&mut Self) -> Result<T>) -> Result<T> {
+        let retry = self.retry;
+        let mut first = true;
+
+        retry.run(|| {
+            if !first {
+                self.reconnect()?;
+            }
+            first = false;
+
+            op(self)
+        })
+    }
+
+    /// Show a progress bar while uploading files
+    pub fn set_show_progress(&mut self, show: bool) {
+        self.show_progress = show;
     }
 
     pub fn login(&mut self, user: &str, pass: &str) -> Result<&mut Self> {
+        self.login = Some((user.to_owned(), pass.to_owned()));
+
         self.user(user)?
             .pass(pass)
     }
 
+    /// Open a second session to the same server with the same settings
+    pub fn try_clone(&self) -> Result<Self> {
+        let mut client = FtpClient::connect(self.addr.ip(), self.addr.port(), self.timeouts)?;
+        client.data_mode = self.data_mode;
+        client.show_progress = self.show_progress;
+        client.retry = self.retry;
+        client.rate_limit = self.rate_limit;
+        client.keepalive = self.keepalive;
+
+        if let Some((user, pass)) = &self.login {
+            client.login(user, pass)?;
+        }
+
+        Ok(client)
+    }
+
+    /// Open a fresh control connection to the same server, logging back in if needed
+    pub fn reconnect(&mut self) -> Result<()> {
+        self.tcp = BufReader::new(self.timeouts.connect(self.addr)?);
+
+        let status = self.next()?.0;
+        if status != 220 {
+            return Err(FtpError::UnexpectedStatus(status))
+        }
+
+        if let Some((user, pass)) = self.login.clone() {
+            self.user(&user)?.pass(&pass)?;
+        }
+
+        Ok(())
+    }
+
     pub fn expect_success(&mut self) -> Result<()> {
         let (num, _) = self.next()?;
 
@@ -77,6 +393,7 @@ impl FtpClient {
             println!("[FTP] {}", string);
         }
         write!(self.tcp.get_mut(), "{}\n", string)?;
+        self.last_sent = Instant::now();
 
         Ok(())
     }
@@ -84,9 +401,12 @@ impl FtpClient {
     pub fn user(&mut self, username: &str) -> Result<&mut Self> {
         self.send(format!("USER {}", username))?;
 
-        self.expect_success()?;
-
-        Ok(self)
+        // 331 means the server wants a password, which is sent next anyways
+        match self.next()?.0 {
+            331 => Ok(self),
+            num if (200..299).contains(&num) => Ok(self),
+            num => Err(FtpError::UnexpectedStatus(num))
+        }
     }
 
     pub fn pass(&mut self, password: &str) -> Result<&mut Self> {
@@ -102,59 +422,132 @@ impl FtpClient {
         self.expect_success()
     }
 
-    pub fn open_passive_channel(&mut self) -> Result<(String, TcpStream)> {
-        self.clear_status();
-        self.send("PASV")?;
-        
-        let ip = loop {
+    fn wait_for_status(&mut self, expected: usize) -> Result<String> {
+        loop {
             match self.next()? {
-                (227, ip) => {
-                    break ip
+                (status, line) if status == expected => {
+                    break Ok(line)
                 }
                 (status, _) if !(200..299).contains(&status) => {
                     return Err(FtpError::UnexpectedStatus(status))
                 }
                 _ => continue
             };
+        }
+    }
+
+    pub fn open_data_channel(&mut self) -> Result<DataChannel> {
+        self.clear_status();
+
+        // PASV can only describe IPv4 addresses, so use EPSV instead when connected over IPv6
+        let data_mode = match (self.data_mode, self.addr.ip()) {
+            (DataMode::Passive, IpAddr::V6(_)) => DataMode::ExtendedPassive,
+            (mode, _) => mode,
         };
 
-        let ip: Vec<_> = ip.split(",").map(String::from).map(|mut x| { x.retain(char::is_numeric); x }).collect();
+        match data_mode {
+            DataMode::Passive => {
+                self.send("PASV")?;
+                let ip = self.wait_for_status(227)?;
 
-        if ip.len() < 6 {
-            Err(FtpError::ParseFail)
-        } else {
-            let ip: String = ip[0..4].join(".") + ":" + &((int(&ip[4])? << 8) + int(&ip[5])?).to_string();
+                let ip: Vec<_> = ip.split(",").map(String::from).map(|mut x| { x.retain(char::is_numeric); x }).collect();
+
+                if ip.len() < 6 {
+                    Err(FtpError::ParseFail)
+                } else {
+                    let ip: String = ip[0..4].join(".") + ":" + &((int(&ip[4])? << 8) + int(&ip[5])?).to_string();
+                    let addr = ip.parse().map_err(|_| FtpError::ParseFail)?;
 
-            let stream = TcpStream::connect(&ip)?;
+                    Ok(DataChannel::Connected(self.timeouts.connect(addr)?))
+                }
+            }
+            DataMode::ExtendedPassive => {
+                self.send("EPSV")?;
+                let line = self.wait_for_status(229)?;
+
+                // 229 Entering Extended Passive Mode (|||port|)
+                let port = line.split('|').nth(3).ok_or(FtpError::ParseFail)?;
+                let port = int(port)? as u16;
+                let ip = self.tcp.get_ref().peer_addr()?.ip();
+
+                Ok(DataChannel::Connected(self.timeouts.connect(SocketAddr::new(ip, port))?))
+            }
+            DataMode::Active | DataMode::ExtendedActive => {
+                let local_ip = self.tcp.get_ref().local_addr()?.ip();
+                let listener = TcpListener::bind((local_ip, 0))?;
+                let port = listener.local_addr()?.port();
 
-            Ok((ip, stream))
+                match (data_mode, local_ip) {
+                    (DataMode::Active, IpAddr::V4(ip)) => {
+                        let [a, b, c, d] = ip.octets();
+                        self.send(format!("PORT {},{},{},{},{},{}", a, b, c, d, port >> 8, port & 0xFF))?;
+                    }
+                    (_, ip) => {
+                        let proto = if ip.is_ipv4() { 1 } else { 2 };
+                        self.send(format!("EPRT |{}|{}|{}|", proto, ip, port))?;
+                    }
+                }
+                self.wait_for_status(200)?;
+
+                Ok(DataChannel::Listening(listener))
+            }
         }
     }
 
     pub fn ls(&mut self, dir: Option<&str>) -> Result<String> {
-        let mut channel = self.open_passive_channel()?.1;
+        let channel = self.open_data_channel()?;
 
         if let Some(dir) = dir {
             self.change_dir(dir)?;
         }
 
         self.send("LIST")?;
+        self.expect_success()?;
+
+        let mut channel = channel.into_stream(&self.timeouts)?;
 
         let mut string = String::new();
 
         channel.read_to_string(&mut string)?;
-        
+
+        // 226 once the listing has been sent
+        self.expect_success()?;
+
         Ok(string)
     }
 
+    /// List just the names of the files in a directory
+    pub fn names(&mut self, dir: &str) -> Result<Vec<String>> {
+        self.clear_status();
+        let channel = self.open_data_channel()?;
+
+        self.send(format!("NLST {}", dir))?;
+        self.expect_success()?;
+
+        let mut listing = String::new();
+        channel.into_stream(&self.timeouts)?.read_to_string(&mut listing)?;
+
+        // 226 once the listing has been sent
+        self.expect_success()?;
+
+        // some servers give back full paths rather than just names
+        Ok(
+            listing.lines()
+                .filter_map(|line| line.trim().rsplit('/').next())
+                .filter(|name| !name.is_empty() && *name != "." && *name != "..")
+                .map(String::from)
+                .collect()
+        )
+    }
+
     pub fn file_exists<S: AsRef<str>>(&mut self, path: S) -> Result<bool> {
         self.clear_status();
-        let (_, mut channel) = match self.open_passive_channel() {
+        let channel = match self.open_data_channel() {
             Err(FtpError::UnexpectedStatus(550)) => {
                     return Ok(false);
             },
             x => x,
-        }.unwrap();
+        }?;
 
 
         self.send(format!("LIST {}", path.as_ref()))?;
@@ -163,7 +556,9 @@ impl FtpClient {
             return Ok(false);
         }
 
-        let _ = self.next_line().unwrap();
+        let _ = self.next_line()?;
+
+        let mut channel = channel.into_stream(&self.timeouts)?;
 
         // Return true if stream is non-empty, i.e. the listing contains an item
         Ok(
@@ -181,38 +576,464 @@ impl FtpClient {
         self.expect_success()
     }
 
-    pub fn rm(&mut self, path: String) -> Result<()> {
+    /// Move a file on the server, replacing anything already at the destination
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        self.clear_status();
+
+        self.send(format!("RNFR {}", from))?;
+        self.wait_for_status(350)?;
+
+        self.send(format!("RNTO {}", to))?;
+        match self.expect_success() {
+            // not every server will rename over an existing file, so only then make room for it
+            Err(FtpError::UnexpectedStatus(status)) if status >= 500 => {
+                self.send(format!("DELE {}", to))?;
+                let _ = self.next_line()?;
+
+                self.send(format!("RNFR {}", from))?;
+                self.wait_for_status(350)?;
+
+                self.send(format!("RNTO {}", to))?;
+                self.expect_success()
+            }
+            result => result
+        }
+    }
+
+    /// Check whether a path is a directory by trying to change into it
+    pub fn is_dir(&mut self, path: &str) -> Result<bool> {
+        self.clear_status();
+        match self.change_dir(path) {
+            Ok(()) => {
+                self.change_dir("/")?;
+                Ok(true)
+            }
+            Err(FtpError::UnexpectedStatus(_)) => Ok(false),
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Delete a file, failing if the server couldn't
+    pub fn delete(&mut self, path: &str) -> Result<()> {
         self.clear_status();
-        println!("{}", path);
         self.send(format!("DELE {}", path))?;
 
-        Ok(())
+        self.expect_success()
+    }
+
+    pub fn size<S: AsRef<str>>(&mut self, path: S) -> Result<u64> {
+        self.clear_status();
+        self.send(format!("SIZE {}", path.as_ref()))?;
+
+        match self.next()? {
+            (213, size) => size.trim().parse().map_err(|_| FtpError::ParseFail),
+            (status, _) => Err(FtpError::UnexpectedStatus(status))
+        }
+    }
+
+    pub fn modified_time<S: AsRef<str>>(&mut self, path: S) -> Result<String> {
+        self.clear_status();
+        self.send(format!("MDTM {}", path.as_ref()))?;
+
+        match self.next()? {
+            (213, time) => Ok(time.trim().to_owned()),
+            (status, _) => Err(FtpError::UnexpectedStatus(status))
+        }
+    }
+
+    /// Get the CRC-32 of a file on the server, which not every server supports
+    pub fn crc<S: AsRef<str>>(&mut self, path: S) -> Result<u32> {
+        self.clear_status();
+        self.send(format!("XCRC {}", path.as_ref()))?;
+
+        match self.next()? {
+            (250, crc) => u32::from_str_radix(crc.trim(), 16).map_err(|_| FtpError::ParseFail),
+            (status, _) => Err(FtpError::UnexpectedStatus(status))
+        }
+    }
+
+    /// Get how many bytes are free in the given directory, using the AVBL extension
+    pub fn available<S: AsRef<str>>(&mut self, dir: S) -> Result<u64> {
+        self.clear_status();
+        self.send(format!("AVBL {}", dir.as_ref()))?;
+
+        match self.next()? {
+            (213, bytes) => bytes.trim().parse().map_err(|_| FtpError::ParseFail),
+            (status, _) => Err(FtpError::UnexpectedStatus(status))
+        }
+    }
+
+    /// Download a file from the server
+    pub fn get<S: AsRef<str>>(&mut self, path: S) -> Result<Vec<u8>> {
+        self.clear_status();
+        self.send("TYPE I")?;
+        self.expect_success()?;
+
+        let channel = self.open_data_channel()?;
+
+        self.send(format!("RETR {}", path.as_ref()))?;
+        self.expect_success()?;
+
+        let mut data = Vec::new();
+        channel.into_stream(&self.timeouts)?.read_to_end(&mut data)?;
+
+        // 226 once the transfer is done
+        self.expect_success()?;
+
+        Ok(data)
+    }
+
+    /// The address of the server this client is connected to
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
     }
 
     pub fn put<S: AsRef<str>, D: AsRef<[u8]>>(&mut self, path: S, file: D) -> Result<()> {
+        self.upload(path.as_ref(), file.as_ref(), false)
+    }
+
+    fn upload(&mut self, path: &str, file: &[u8], resume: bool) -> Result<()> {
+        let bar = progress::transfer_bar(file.len() as u64, self.show_progress);
+        let offset = if resume { self.partial_size(path, file.len())? } else { 0 };
+
+        // not every server can take a file in segments, so those get it in one piece instead
+        let segmented = offset == 0 && file.len() >= SEGMENT_THRESHOLD && self.put_segmented(path, file, &bar)?;
+        if !segmented {
+            self.put_with_progress(path, file, &bar, offset)?;
+        }
+
+        bar.finish_and_clear();
+
+        Ok(())
+    }
+
+    /// How much of a file an interrupted upload left at the path, going by its size
+    fn partial_size(&mut self, path: &str, len: usize) -> Result<usize> {
         self.clear_status();
-        self.send(format!("DELE {}", path.as_ref()))?;
+        self.send("TYPE I")?;
+        self.expect_success()?;
 
-        let _ = self.next_line()?;
+        match self.size(path) {
+            Ok(size) if size as usize <= len => Ok(size as usize),
+            // nothing there yet, SIZE isn't supported or what's there is too big to be part of
+            // the file, all of which mean starting from the beginning
+            Ok(_) | Err(FtpError::UnexpectedStatus(_)) | Err(FtpError::ParseFail) => Ok(0),
+            Err(err) => Err(err)
+        }
+    }
 
+    /// Open extra sessions to share a transfer with, splitting the rate limit evenly between
+    /// them and this one. Returns the original rate limit so it can be restored afterwards.
+    ///
+    /// Servers often cap how many sessions can be open at once, so this stops at the first one
+    /// that can't be opened and shares the transfer between however many did, if any.
+    fn open_workers(&mut self, workers: usize) -> (Vec<FtpClient>, Option<ByteRate>) {
+        let rate_limit = self.rate_limit;
+        let mut clients: Vec<FtpClient> = (1..workers)
+            .map_while(|_| self.try_clone().ok())
+            .collect();
+
+        let share = rate_limit.map(|ByteRate(rate)| ByteRate((rate / (clients.len() as u64 + 1)).max(1)));
+        self.rate_limit = share;
+        for client in &mut clients {
+            client.rate_limit = share;
+        }
+
+        (clients, rate_limit)
+    }
+
+    /// Upload a file in segments over several connections at once, using REST to place each one.
+    /// Returns `false` without sending anything if the server doesn't support REST or won't open
+    /// another session.
+    fn put_segmented(&mut self, path: &str, file: &[u8], bar: &ProgressBar) -> Result<bool> {
+        // REST 0 doesn't change where the next transfer starts, so it's safe to check with
+        self.clear_status();
+        self.send("REST 0")?;
+        if self.next()?.0 != 350 {
+            return Ok(false);
+        }
+
+        let (mut clients, rate_limit) = self.open_workers(MAX_PARALLEL_UPLOADS);
+        if clients.is_empty() {
+            self.rate_limit = rate_limit;
+            return Ok(false);
+        }
+
+        let segment_len = file.len().div_ceil(clients.len() + 1);
+
+        let opened = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let store = |client: &mut FtpClient, index: usize| -> Result<()> {
+            // Segments are started in order, as some servers truncate the file to the REST
+            // offset when a STOR starts
+            while opened.load(Ordering::SeqCst) < index {
+                if failed.load(Ordering::SeqCst) {
+                    return Err(FtpError::IncompleteUpload(0, file.len()));
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+
+            let start = index * segment_len;
+            let end = (start + segment_len).min(file.len());
+            let result = client.store_segment(path, &file[start..end], start, bar, || {
+                opened.fetch_add(1, Ordering::SeqCst);
+            });
+
+            if result.is_err() {
+                failed.store(true, Ordering::SeqCst);
+            }
+
+            result
+        };
+
+        let store = &store;
+        let result = std::thread::scope(|scope| {
+            let handles: Vec<_> = clients.iter_mut()
+                .enumerate()
+                .map(|(i, client)| scope.spawn(move || store(client, i + 1)))
+                .collect();
+
+            let result = store(self, 0);
+
+            handles.into_iter()
+                .map(|handle| handle.join().unwrap())
+                .fold(result, |result, other| result.and(other))
+        });
+
+        self.rate_limit = rate_limit;
+        result?;
+
+        match self.size(path)? as usize {
+            size if size == file.len() => Ok(true),
+            size => Err(FtpError::IncompleteUpload(size, file.len()))
+        }
+    }
+
+    /// Upload several files at once, each over its own session
+    pub fn put_all(&mut self, files: &[(String, Vec<u8>)]) -> Result<()> {
+        self.upload_all(files, false)
+    }
+
+    /// Upload several files at once, carrying on from whatever interrupted uploads left at the
+    /// paths. Only for paths which are unique to the file's contents, as anything there is taken
+    /// to be the start of it.
+    pub fn resume_all(&mut self, files: &[(String, Vec<u8>)]) -> Result<()> {
+        self.upload_all(files, true)
+    }
+
+    fn upload_all(&mut self, files: &[(String, Vec<u8>)], resume: bool) -> Result<()> {
+        // a single file is better off split up across the connections instead
+        if let [(path, file)] = files {
+            return self.with_retry(|client| client.upload(path, file, resume));
+        }
+
+        let workers = files.len().min(MAX_PARALLEL_UPLOADS);
+        let total = files.iter().map(|(_, file)| file.len() as u64).sum();
+        let bar = progress::transfer_bar(total, self.show_progress);
+
+        let (mut clients, rate_limit) = self.open_workers(workers);
+
+        let next = AtomicUsize::new(0);
+        let upload = |client: &mut FtpClient| -> Result<()> {
+            while let Some((path, file)) = files.get(next.fetch_add(1, Ordering::SeqCst)) {
+                // only this file needs sending again if the connection drops, not the whole batch
+                client.with_retry(|client| {
+                    let offset = if resume { client.partial_size(path, file.len())? } else { 0 };
+                    client.put_with_progress(path, file, &bar, offset)
+                })?;
+            }
+
+            Ok(())
+        };
+
+        let upload = &upload;
+        let result = std::thread::scope(|scope| {
+            let handles: Vec<_> = clients.iter_mut()
+                .map(|client| scope.spawn(move || upload(client)))
+                .collect();
+
+            let result = upload(self);
+
+            handles.into_iter()
+                .map(|handle| handle.join().unwrap())
+                .fold(result, |result, other| result.and(other))
+        });
+
+        bar.finish_and_clear();
+        self.rate_limit = rate_limit;
+
+        result
+    }
+
+    /// Upload a file starting from `start`, which is how much of it is already at the path
+    fn put_with_progress(&mut self, path: &str, file: &[u8], bar: &ProgressBar, start: usize) -> Result<()> {
+        // how much of this file the progress bar currently accounts for
+        let mut counted = 0;
+
+        let result = self.put_from(path, file, bar, start, &mut counted);
+        if result.is_err() {
+            // take it back off, so the bar doesn't count it twice if the upload gets retried
+            bar.dec(counted as u64);
+        }
+
+        result
+    }
+
+    fn put_from(&mut self, path: &str, file: &[u8], bar: &ProgressBar, start: usize, counted: &mut usize) -> Result<()> {
+        // a previous upload already got the whole file there
+        if start != 0 && start >= file.len() {
+            bar.inc(file.len() as u64);
+            *counted = file.len();
+            return Ok(());
+        }
+
+        if start == 0 {
+            self.clear_status();
+            self.send(format!("DELE {}", path))?;
+
+            let _ = self.next_line()?;
+        }
+
+        bar.inc(start as u64);
+        *counted = start;
+
+        let mut offset = start;
+        let mut attempts = 0;
+        loop {
+            // Check how much actually made it, as a dropped connection doesn't always show up as
+            // a failed write
+            let stored = match self.store_from(path, file, offset, bar, counted) {
+                // the server giving up on the transfer part way still leaves whatever made it
+                Ok(()) | Err(FtpError::UnexpectedStatus(426)) | Err(FtpError::UnexpectedStatus(451)) => Ok(()),
+                Err(err) => Err(err),
+            }
+                .and_then(|_| match self.size(path) {
+                    Ok(size) => Ok(Some(size as usize)),
+                    // 5xx other than "file unavailable" means SIZE isn't supported
+                    Err(FtpError::UnexpectedStatus(550)) => Err(FtpError::UnexpectedStatus(550)),
+                    Err(FtpError::UnexpectedStatus(_)) => Ok(None),
+                    Err(err) => Err(err),
+                });
+
+            match stored {
+                // SIZE isn't supported, so there's no way to tell if anything went missing
+                Ok(None) => break,
+                Ok(Some(size)) if size >= file.len() => break,
+                Ok(Some(size)) if attempts < MAX_RESUME_ATTEMPTS => {
+                    attempts += 1;
+                    offset = size;
+                }
+                Ok(Some(size)) => return Err(FtpError::IncompleteUpload(size, file.len())),
+                Err(FtpError::Io(_)) if attempts < MAX_RESUME_ATTEMPTS => {
+                    attempts += 1;
+
+                    // The connection dropped, pick back up from however much made it
+                    self.reconnect()?;
+                    self.send("TYPE I")?;
+                    self.expect_success()?;
+
+                    offset = match self.size(path) {
+                        Ok(size) => (size as usize).min(file.len()),
+                        // the connection dropped before the server created the file
+                        Err(FtpError::UnexpectedStatus(550)) => 0,
+                        Err(err) => return Err(err)
+                    };
+                }
+                Err(err) => return Err(err)
+            }
+
+            if *counted > offset {
+                bar.dec((*counted - offset) as u64);
+            } else {
+                bar.inc((offset - *counted) as u64);
+            }
+            *counted = offset;
+        }
+
+        Ok(())
+    }
+
+    fn store_from(&mut self, path: &str, file: &[u8], offset: usize, bar: &ProgressBar, counted: &mut usize) -> Result<()> {
+        self.clear_status();
         self.send("TYPE I")?;
+        self.expect_success()?;
+
+        let channel = self.open_data_channel()?;
+
+        if offset == 0 {
+            self.send(format!("STOR {}", path))?;
+        } else {
+            self.send(format!("REST {}", offset))?;
+
+            if self.next()?.0 == 350 {
+                self.send(format!("STOR {}", path))?;
+            } else {
+                // REST isn't supported for uploads, appending gets us the same thing
+                self.send(format!("APPE {}", path))?;
+            }
+        }
+
+        match self.next()?.0 {
+            125 | 150 => {}
+            status => return Err(FtpError::UnexpectedStatus(status))
+        }
+
+        let mut channel = channel.into_stream(&self.timeouts)?;
 
-        //self.next_line()?);
-        //self.expect_success()?;
+        self.write_data(&mut channel, &file[offset..], bar, counted)?;
+        drop(channel);
+
+        // 226 once the server has the whole file
+        self.expect_success()
+    }
+
+    /// Store part of a file at the given offset, calling `on_open` once the server has opened
+    /// the file for writing
+    fn store_segment(&mut self, path: &str, data: &[u8], offset: usize, bar: &ProgressBar, on_open: impl FnOnce()) -> Result<()> {
         self.clear_status();
+        self.send("TYPE I")?;
+        self.expect_success()?;
 
-        let (_ip, mut channel) = self.open_passive_channel()?;
+        let channel = self.open_data_channel()?;
 
-        //println!("Transferring data over {}...", ip);
-        
-        self.send(format!("STOR {}", path.as_ref()))?;
+        if offset != 0 {
+            self.send(format!("REST {}", offset))?;
+            self.wait_for_status(350)?;
+        }
 
-        channel.write_all(file.as_ref())?;
+        self.send(format!("STOR {}", path))?;
+        match self.next()?.0 {
+            125 | 150 => on_open(),
+            status => return Err(FtpError::UnexpectedStatus(status))
+        }
+
+        let mut channel = channel.into_stream(&self.timeouts)?;
+        self.write_data(&mut channel, data, bar, &mut 0)?;
+        drop(channel);
+
+        // 226 once the server has the whole segment
+        self.expect_success()
+    }
+
+    /// Send a file over a data connection, keeping the progress bar and rate limit up to date
+    fn write_data(&self, channel: &mut TcpStream, data: &[u8], bar: &ProgressBar, counted: &mut usize) -> Result<()> {
+        let start = Instant::now();
+        let mut sent = 0;
+        for chunk in data.chunks(CHUNK_SIZE) {
+            channel.write_all(chunk)?;
+            bar.inc(chunk.len() as u64);
+            *counted += chunk.len();
+            sent += chunk.len() as u64;
+
+            // sleep off however far ahead of the limit we are
+            if let Some(ByteRate(rate)) = self.rate_limit {
+                let expected = Duration::from_secs_f64(sent as f64 / rate as f64);
+                if let Some(ahead) = expected.checked_sub(start.elapsed()) {
+                    std::thread::sleep(ahead);
+                }
+            }
+        }
 
-        std::thread::sleep(Duration::from_millis(500));
-        
-        let _ = self.next_line()?;
-        //let _ = dbg!(self.next_line()?);
         Ok(())
     }
 }
@@ -233,7 +1054,64 @@ impl fmt::Display for FtpError {
         match self {
             Self::ParseFail => write!(f, "Failed to parse"),
             Self::Io(io) => write!(f, "IoError: {}", io),
-            Self::UnexpectedStatus(status) => write!(f, "Unexpected status {}", status)
+            Self::UnexpectedStatus(status) => write!(f, "Unexpected status {}", status),
+            Self::Sftp(err) => write!(f, "sftp failed: {}", err),
+            Self::DataConnectionTimeout => write!(f, "Timed out waiting for the server to open a data connection"),
+            Self::IncompleteUpload(sent, len) => write!(f, "Upload incomplete, only {} of {} bytes were stored", sent, len),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rates() {
+        let rate = |s: &str| s.parse::<ByteRate>().map(|ByteRate(rate)| rate);
+
+        assert_eq!(rate("500"), Ok(500));
+        assert_eq!(rate("500K"), Ok(500 * 1024));
+        assert_eq!(rate(" 2m "), Ok(2 * 1024 * 1024));
+        assert_eq!(rate("1G"), Ok(1024 * 1024 * 1024));
+
+        assert!(rate("").is_err());
+        assert!(rate("0K").is_err());
+        assert!(rate("-5").is_err());
+        assert!(rate("1.5M").is_err());
+        assert!(rate("K").is_err());
+        assert!(rate("99999999999999G").is_err());
+    }
+
+    #[test]
+    fn parse_data_modes() {
+        let modes = [
+            ("passive", DataMode::Passive),
+            ("epsv", DataMode::ExtendedPassive),
+            ("active", DataMode::Active),
+            ("eprt", DataMode::ExtendedActive),
+        ];
+
+        for (name, mode) in modes.iter() {
+            assert!(name.parse::<DataMode>() == Ok(*mode));
+            assert_eq!(mode.to_string(), *name);
         }
+
+        assert!("PASV".parse::<DataMode>() == Ok(DataMode::Passive));
+        assert!("port".parse::<DataMode>() == Ok(DataMode::Active));
+        assert!("ftp".parse::<DataMode>().is_err());
+    }
+
+    #[test]
+    fn save_data_modes() {
+        #[derive(Serialize, Deserialize)]
+        struct Saved {
+            mode: DataMode,
+        }
+
+        let saved = toml::to_string(&Saved { mode: DataMode::ExtendedPassive }).unwrap();
+        assert_eq!(saved.trim(), "mode = \"epsv\"");
+        assert!(toml::from_str::<Saved>(&saved).unwrap().mode == DataMode::ExtendedPassive);
+        assert!(toml::from_str::<Saved>("mode = \"sideways\"").is_err());
     }
 }
@@ -0,0 +1,189 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use super::{FtpError, Result};
+
+static STAGED_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// A file of our own in the temp dir for handing data to and from `sftp`, removed once dropped
+struct StagingFile {
+    path: PathBuf,
+}
+
+impl StagingFile {
+    /// Create a file nothing else is using, whether that's another device being installed to at
+    /// the same time or another run of cargo-skyline. Creating it fresh also means a symlink left
+    /// at the name can't redirect what gets written.
+    fn create() -> io::Result<(Self, File)> {
+        loop {
+            let path = std::env::temp_dir().join(format!(
+                "cargo-skyline-sftp-{}-{}",
+                std::process::id(),
+                STAGED_FILES.fetch_add(1, Ordering::SeqCst)
+            ));
+
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok((StagingFile { path }, file)),
+                // left behind by an earlier run which happened to have the same pid
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Drop for StagingFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// A client which drives the OpenSSH `sftp` binary in batch mode.
+///
+/// Batch mode can't prompt for a password, so authentication has to be key-based (either via an
+/// ssh-agent or an explicit identity file).
+pub struct SftpClient {
+    host: String,
+    port: u16,
+    identity: Option<PathBuf>,
+}
+
+impl SftpClient {
+    pub fn connect(ip: IpAddr, user: Option<&str>, port: u16, identity: Option<PathBuf>) -> Result<Self> {
+        let host = match ip {
+            IpAddr::V4(ip) => ip.to_string(),
+            IpAddr::V6(ip) => format!("[{}]", ip),
+        };
+
+        let host = match user {
+            Some(user) => format!("{}@{}", user, host),
+            None => host,
+        };
+
+        let client = SftpClient { host, port, identity };
+
+        // Make sure we can actually authenticate before doing anything else
+        client.batch("pwd")?;
+
+        Ok(client)
+    }
+
+    /// The `[user@]host:port` this client connects to
+    pub fn host(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    fn batch(&self, commands: &str) -> Result<String> {
+        let mut command = Command::new("sftp");
+        command
+            .args(["-b", "-", "-o", "BatchMode=yes", "-P"])
+            .arg(self.port.to_string());
+
+        if let Some(identity) = &self.identity {
+            command.arg("-i").arg(identity);
+        }
+
+        let mut child = command
+            .arg(&self.host)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        #[cfg(feature = "debug")] {
+            println!("[SFTP] {}", commands);
+        }
+
+        child.stdin.take().unwrap().write_all(commands.as_bytes())?;
+
+        let output = child.wait_with_output()?;
+
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        } else {
+            Err(FtpError::Sftp(String::from_utf8_lossy(&output.stderr).trim().to_owned()))
+        }
+    }
+
+    pub fn mkdir<S: AsRef<str>>(&mut self, dir: S) -> Result<()> {
+        // a leading '-' tells sftp to ignore failures, e.g. the directory already existing
+        self.batch(&format!("-mkdir \"{}\"\n", dir.as_ref())).map(|_| ())
+    }
+
+    pub fn put<S: AsRef<str>, D: AsRef<[u8]>>(&mut self, path: S, file: D) -> Result<()> {
+        let (staged, mut local_file) = StagingFile::create()?;
+        local_file.write_all(file.as_ref())?;
+        drop(local_file);
+
+        self.batch(&format!("put \"{}\" \"{}\"\n", staged.path.display(), path.as_ref())).map(|_| ())
+    }
+
+    pub fn get<S: AsRef<str>>(&mut self, path: S) -> Result<Vec<u8>> {
+        // sftp writes over the empty file, so it's ours rather than whatever is at the name
+        let (staged, _) = StagingFile::create()?;
+
+        self.batch(&format!("get \"{}\" \"{}\"\n", path.as_ref(), staged.path.display()))?;
+
+        Ok(std::fs::read(&staged.path)?)
+    }
+
+    pub fn remove(&mut self, path: &str) -> Result<()> {
+        self.batch(&format!("rm \"{}\"\n", path)).map(|_| ())
+    }
+
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        // OpenSSH's sftp renames with posix-rename@openssh.com when the server supports it, which
+        // replaces the destination in one step. Plain SFTP renames fail if the destination
+        // exists, so only then make room for it.
+        match self.batch(&format!("rename \"{}\" \"{}\"\n", from, to)) {
+            Err(FtpError::Sftp(_)) => {
+                self.batch(&format!("-rm \"{}\"\nrename \"{}\" \"{}\"\n", to, from, to)).map(|_| ())
+            }
+            result => result.map(|_| ())
+        }
+    }
+
+    pub fn is_dir(&mut self, path: &str) -> Result<bool> {
+        match self.batch(&format!("cd \"{}\"\n", path)) {
+            Ok(_) => Ok(true),
+            Err(FtpError::Sftp(_)) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn file_exists<S: AsRef<str>>(&mut self, path: S) -> Result<bool> {
+        match self.batch(&format!("ls \"{}\"\n", path.as_ref())) {
+            Ok(_) => Ok(true),
+            Err(FtpError::Sftp(_)) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn names(&mut self, dir: &str) -> Result<Vec<String>> {
+        let output = self.batch(&format!("ls -1 \"{}\"\n", dir))?;
+
+        Ok(
+            output.lines()
+                .filter(|line| !line.starts_with("sftp>"))
+                .filter_map(|line| line.trim().rsplit('/').next())
+                .filter(|name| !name.is_empty())
+                .map(String::from)
+                .collect()
+        )
+    }
+
+    pub fn ls(&mut self, dir: &str) -> Result<String> {
+        let output = self.batch(&format!("ls -l \"{}\"\n", dir))?;
+
+        // sftp echoes each batch command back prefixed with "sftp> "
+        Ok(
+            output.lines()
+                .filter(|line| !line.starts_with("sftp>"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        )
+    }
+}
@@ -1,9 +1,59 @@
-pub fn get_plugins_path(title_id: &str) -> String {
-    format!("/atmosphere/contents/{}/romfs/skyline/plugins", title_id)
+use std::str::FromStr;
+use crate::cargo_info::Metadata;
+use crate::error::{Error, Result};
+use crate::settings;
+
+/// Where a game's Skyline expects to find everything, set with `layout` in the metadata
+#[derive(Copy, Clone, Default, PartialEq)]
+pub enum GameLayout {
+    /// Plugins in `romfs/skyline/plugins`, which is where current Skyline releases look
+    #[default]
+    Romfs,
+
+    /// Plugins in `exefs/skyline/plugins`, for older setups which don't use the romfs at all
+    Exefs,
+
+    /// Plugins in the romfs, for games which only load Skyline with a patched `main.npdm`, so
+    /// it's installed every time rather than only when missing
+    NpdmPatch,
+}
+
+impl FromStr for GameLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "romfs" => Ok(GameLayout::Romfs),
+            "exefs" => Ok(GameLayout::Exefs),
+            "npdm-patch" => Ok(GameLayout::NpdmPatch),
+            _ => Err(format!("Unknown layout '{}', expected 'romfs', 'exefs' or 'npdm-patch'", s))
+        }
+    }
+}
+
+impl GameLayout {
+    /// The plugin directory, relative to the game's directory
+    pub fn plugins_dir(self) -> &'static str {
+        match self {
+            GameLayout::Romfs | GameLayout::NpdmPatch => "romfs/skyline/plugins",
+            GameLayout::Exefs => "exefs/skyline/plugins",
+        }
+    }
+
+    pub fn always_installs_npdm(self) -> bool {
+        self == GameLayout::NpdmPatch
+    }
+}
+
+pub fn get_layout(metadata: Option<&Metadata>) -> Result<GameLayout> {
+    match metadata.and_then(|metadata| metadata.layout.as_ref()) {
+        Some(layout) => layout.parse().map_err(|_| Error::BadSetting("layout")),
+        None => Ok(GameLayout::default())
+    }
 }
 
-pub fn get_plugin_path(title_id: &str, plugin_name: &str) -> String {
-    format!("/atmosphere/contents/{}/romfs/skyline/plugins/{}", title_id, plugin_name)
+pub fn get_plugins_path(title_id: &str, layout: GameLayout) -> String {
+    format!("/atmosphere/contents/{}/{}", title_id, layout.plugins_dir())
 }
 
 pub fn get_game_path(title_id: &str) -> String {
@@ -14,10 +64,29 @@ pub fn get_subsdk_path(title_id: &str, subsdk_name: &str) -> String {
     format!("atmosphere/contents/{}/exefs/{}", title_id, subsdk_name)
 }
 
+/// Parse a subsdk given as either a slot number or its name, such as `8` or `subsdk8`
+pub fn parse_subsdk(slot: &str) -> std::result::Result<String, String> {
+    match slot.strip_prefix("subsdk").unwrap_or(slot).parse::<u8>() {
+        Ok(slot @ 0..=9) => Ok(format!("subsdk{}", slot)),
+        _ => Err(format!("'{}' isn't a subsdk, expected a slot between 0 and 9", slot))
+    }
+}
+
 pub fn get_npdm_path(title_id: &str) -> String {
     format!("atmosphere/contents/{}/exefs/main.npdm", title_id)
 }
 
-pub fn get_plugin_nro_path(title_id: &str, nro_file_name: &str) -> String {
-    format!("atmosphere/contents/{}/romfs/skyline/plugins/{}", title_id, nro_file_name)
+pub fn get_plugin_nro_path(title_id: &str, layout: GameLayout, nro_file_name: &str) -> String {
+    format!("atmosphere/contents/{}/{}/{}", title_id, layout.plugins_dir(), nro_file_name)
+}
+
+/// Find where plugins for a title should go when it's been overridden, in the same form as
+/// `install --install-path`. A title's entry in `[package.metadata.skyline.plugin-dirs]` wins over
+/// the `plugin_dirs.<title id>` setting, which wins over `install-dir`.
+pub fn plugin_dir_override(title_id: &str, metadata: Option<&Metadata>) -> Option<String> {
+    let title_id = title_id.to_ascii_uppercase();
+
+    metadata.and_then(|metadata| metadata.plugin_dirs.get(&title_id).cloned())
+        .or_else(|| settings::get(&format!("plugin_dirs.{}", title_id)))
+        .or_else(|| metadata.and_then(|metadata| metadata.install_dir.clone()))
 }
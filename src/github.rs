@@ -0,0 +1,84 @@
+use std::io::{Cursor, Read};
+use serde::Deserialize;
+use zip::ZipArchive;
+use crate::error::{Error, Result};
+
+/// Plugins which can be installed by name rather than by `owner/repo`
+static KNOWN_PLUGINS: &[(&str, &str)] = &[
+    ("nro-hook", "ultimate-research/nro-hook-plugin"),
+    ("arcropolis", "Raytwo/ARCropolis"),
+];
+
+#[derive(Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub browser_download_url: String,
+}
+
+/// Find the GitHub repository for a plugin, given either its name or `owner/repo`
+pub fn resolve_repo(name: &str) -> Result<String> {
+    if name.contains('/') {
+        return Ok(name.to_owned());
+    }
+
+    KNOWN_PLUGINS.iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(name))
+        .map(|(_, repo)| String::from(*repo))
+        .ok_or_else(|| Error::UnknownPlugin(name.to_owned()))
+}
+
+fn download(url: &str) -> Result<Vec<u8>> {
+    // GitHub refuses API requests without a user agent
+    attohttpc::get(url)
+        .header("User-Agent", "cargo-skyline")
+        .send()
+        .map_err(|_| Error::DownloadError)?
+        .bytes()
+        .map_err(|_| Error::DownloadError)
+}
+
+pub fn latest_release(repo: &str) -> Result<Release> {
+    let url = format!("https://api.github.com/repos/{}/releases/latest", repo);
+
+    serde_json::from_slice(&download(&url)?).map_err(|_| Error::DownloadError)
+}
+
+/// Download every NRO in a release, either uploaded directly or inside a zip, as (file name, contents)
+pub fn release_plugins(repo: &str, release: &Release) -> Result<Vec<(String, Vec<u8>)>> {
+    let nros: Vec<_> = release.assets.iter().filter(|asset| asset.name.ends_with(".nro")).collect();
+
+    if !nros.is_empty() {
+        return nros.into_iter()
+            .map(|asset| Ok((asset.name.clone(), download(&asset.browser_download_url)?)))
+            .collect();
+    }
+
+    let mut plugins = vec![];
+    for asset in release.assets.iter().filter(|asset| asset.name.ends_with(".zip")) {
+        let mut zip = ZipArchive::new(Cursor::new(download(&asset.browser_download_url)?))?;
+
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i)?;
+            let name = match file.name().rsplit('/').next() {
+                Some(name) if name.ends_with(".nro") => name.to_owned(),
+                _ => continue
+            };
+
+            let mut data = vec![];
+            file.read_to_end(&mut data)?;
+            plugins.push((name, data));
+        }
+    }
+
+    if plugins.is_empty() {
+        return Err(Error::NoReleasePlugin(repo.to_owned(), release.tag_name.clone()));
+    }
+
+    Ok(plugins)
+}
@@ -0,0 +1,16 @@
+use sha2::{Digest, Sha256};
+
+/// Get the SHA-256 of some data as a lowercase hex string
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Get the CRC-32 of some data, as used by the FTP `XCRC` command
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(data);
+    hasher.finalize()
+}
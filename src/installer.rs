@@ -1,246 +1,2300 @@
+use structopt::StructOpt;
+use std::collections::HashMap;
 use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 use crate::error::{Result, Error};
 use crate::{build, cargo_info};
-use crate::ftp::FtpClient;
-use crate::tcp_listen;
+use crate::ftp::{FtpClient, FtpError, FtpOptions, RetryPolicy, SftpClient};
+use crate::{settings, tcp_listen};
 use crate::ip_addr::{get_ip, verify_ip};
-use crate::game_paths::{get_game_path, get_plugin_path, get_plugins_path};
+use crate::game_paths::{self, get_game_path, get_npdm_path, get_subsdk_path, GameLayout};
+use crate::usb::UsbError;
+#[cfg(feature = "usb")]
+use crate::usb::UsbClient;
 use temp_git::TempGitDir;
+use local::{LocalBackend, Layout};
+use upload_cache::UploadCache;
+use lockfile::Lockfile;
+use manifest::{ManifestEntry, PluginManifest, SkylineEntry};
+use session::Session;
+use crate::hash::sha256_hex;
+use crate::skyline_version::{self, Version};
 use owo_colors::OwoColorize;
 
+pub use backend::{Backend, Destination, FileInfo, Transport};
+pub use options::InstallOptions;
+
 mod temp_git;
+mod backend;
+mod backup;
+pub mod dependencies;
+pub mod history;
+mod local;
+mod lockfile;
+mod manifest;
+mod options;
+mod session;
+mod shell;
+mod upload_cache;
+
+fn parse_port(port: String, name: &'static str) -> Result<u16> {
+    port.parse().map_err(|_| Error::BadSetting(name))
+}
+
+/// Resolve FTP settings, preferring command line flags, then `CARGO_SKYLINE_*` environment
+/// variables, then Cargo.toml, then `$HOME/.switch`
+fn ftp_options(dest: &Destination) -> Result<FtpOptions> {
+    let metadata = cargo_info::get_metadata().ok();
+    let defaults = FtpOptions::default();
+
+    let port = match dest.port {
+        Some(port) => port,
+        None => metadata.as_ref()
+            .and_then(|md| md.ftp_port.clone())
+            .map(|port| parse_port(port, "ftp-port"))
+            .or_else(|| settings::get("ftp_port").map(|port| parse_port(port, "ftp_port")))
+            .transpose()?
+            .unwrap_or(defaults.port)
+    };
+
+    let attempts = match dest.retries {
+        Some(attempts) => Some(attempts),
+        None => settings::get("ftp_retries")
+            .map(|attempts| attempts.parse().map_err(|_| Error::BadSetting("ftp_retries")))
+            .transpose()?
+    };
+
+    let retry = attempts
+        .map(|attempts| RetryPolicy { attempts, ..defaults.retry })
+        .unwrap_or(defaults.retry);
+
+    let rate_limit = match dest.limit_rate {
+        Some(rate) => Some(rate),
+        None => settings::get("limit_rate")
+            .map(|rate| rate.parse().map_err(|_| Error::BadSetting("limit_rate")))
+            .transpose()?
+    };
+
+    let user = dest.user.clone()
+        .or_else(|| metadata.as_ref().and_then(|md| md.ftp_user.clone()))
+        .or_else(|| settings::get("ftp_user"))
+        .unwrap_or(defaults.user);
+
+    let pass = dest.pass.clone()
+        .or_else(|| metadata.as_ref().and_then(|md| md.ftp_pass.clone()))
+        .or_else(|| settings::get("ftp_pass"))
+        .unwrap_or(defaults.pass);
+
+    let data_mode = match dest.ftp_mode {
+        Some(mode) => mode,
+        None => metadata.as_ref()
+            .and_then(|md| md.ftp_mode.clone())
+            .map(|mode| mode.parse().map_err(|_| Error::BadSetting("ftp-mode")))
+            .or_else(|| settings::get("ftp_mode").map(|mode| mode.parse().map_err(|_| Error::BadSetting("ftp_mode"))))
+            .transpose()?
+            .unwrap_or(defaults.data_mode)
+    };
+
+    let timeouts = dest.timeouts.resolve()?;
+
+    // a keepalive interval of 0 turns it off
+    let keepalive = match settings::get("ftp_keepalive") {
+        Some(secs) => match secs.parse::<u64>() {
+            Ok(0) => None,
+            Ok(secs) => Some(std::time::Duration::from_secs(secs)),
+            Err(_) => return Err(Error::BadSetting("ftp_keepalive"))
+        },
+        None => defaults.keepalive
+    };
+
+    Ok(FtpOptions { port, user, pass, data_mode, retry, rate_limit, timeouts, keepalive })
+}
+
+fn connect(ip: IpAddr, options: &FtpOptions, print: bool) -> Result<FtpClient> {
+    if print {
+        println!("Connecting to ip '{}'...", ip);
+    }
+
+    // sys-ftpd tends to drop the first connection after the console wakes up
+    let mut client = options.retry.run(|| {
+        let mut client = FtpClient::connect(ip, options.port, options.timeouts)?;
+        client.login(&options.user, &options.pass)?;
+
+        Ok(client)
+    })?;
+    client.set_data_mode(options.data_mode);
+    client.set_retry_policy(options.retry);
+    client.set_rate_limit(options.rate_limit);
+    client.set_keepalive(options.keepalive);
+
+    if print {
+        println!("{}", "Connected!".green());
+    }
+
+    Ok(client)
+}
+
+#[cfg(feature = "usb")]
+fn connect_usb(print: bool) -> Result<UsbClient> {
+    if print {
+        println!("Connecting over USB...");
+    }
+
+    let client = UsbClient::connect()?;
+
+    if print {
+        println!("{}", "Connected!".green());
+    }
+
+    Ok(client)
+}
+
+fn connect_sftp(ip: IpAddr, dest: &Destination, print: bool) -> Result<SftpClient> {
+    if print {
+        println!("Connecting to ip '{}' over SFTP...", ip);
+    }
+
+    let port = match dest.port {
+        Some(port) => port,
+        None => settings::get("sftp_port")
+            .map(|port| parse_port(port, "sftp_port"))
+            .transpose()?
+            .unwrap_or(22)
+    };
+
+    let client = SftpClient::connect(
+        ip,
+        dest.user.clone().or_else(|| settings::get("sftp_user")).as_deref(),
+        port,
+        settings::get("sftp_identity").map(|path| crate::wsl::translate_path(&path))
+    )?;
+
+    if print {
+        println!("{}", "Connected!".green());
+    }
+
+    Ok(client)
+}
+
+/// Whether an error just means the switch can't be reached yet, such as while it's rebooting
+fn is_unreachable(err: &Error) -> bool {
+    matches!(
+        err,
+        Error::IoError(_) | Error::FtpError(FtpError::Io(_)) | Error::FtpError(FtpError::Sftp(_))
+            | Error::UsbError(UsbError::NoDevice)
+    )
+}
+
+fn connect_backend(dest: Destination, print: bool) -> Result<Box<dyn Backend>> {
+    if !dest.wait {
+        return connect_backend_once(dest, print);
+    }
+
+    let mut waiting = false;
+    loop {
+        match connect_backend_once(dest.clone(), print && !waiting) {
+            Err(err) if is_unreachable(&err) => {
+                if !waiting {
+                    eprintln!("Waiting for the switch to become reachable...");
+                    waiting = true;
+                }
+
+                std::thread::sleep(Duration::from_secs(1));
+            }
+            result => break result
+        }
+    }
+}
+
+fn connect_backend_once(dest: Destination, print: bool) -> Result<Box<dyn Backend>> {
+    let dest = resolve_device(dest)?;
+
+    if let Some(emulator) = dest.emulator {
+        let root = emulator.install_root()?;
+        let layout = if emulator.uses_load_dir() {
+            let metadata = cargo_info::get_metadata()?;
+            Layout::LoadDir {
+                mod_name: metadata.mod_name.unwrap_or(metadata.name)
+            }
+        } else {
+            Layout::SdCard
+        };
+
+        if print {
+            println!("Using '{}'...", root.display());
+        }
+
+        return Ok(Box::new(LocalBackend::new(root, layout)));
+    }
+
+    if let Some(sd_path) = dest.sd_path {
+        if !sd_path.is_dir() {
+            return Err(Error::NoSdCardFound(sd_path));
+        }
+
+        if print {
+            println!("Using SD card at '{}'...", sd_path.display());
+        }
+
+        return Ok(Box::new(LocalBackend::new(sd_path, Layout::SdCard)));
+    }
+
+    let transport = match dest.transport {
+        Some(transport) => transport,
+        None => settings::get("transport")
+            .map(|transport| transport.parse().map_err(|_| Error::BadSetting("transport")))
+            .transpose()?
+            .unwrap_or(Transport::Ftp)
+    };
+
+    match transport {
+        Transport::Ftp => {
+            let options = ftp_options(&dest)?;
+            let mut client = connect(verify_ip(get_ip(dest.ip)?)?, &options, print)?;
+            client.set_show_progress(print && !dest.quiet);
+            Ok(Box::new(client))
+        }
+        #[cfg(feature = "usb")]
+        Transport::Usb => {
+            let mut client = connect_usb(print)?;
+            client.set_show_progress(print && !dest.quiet);
+            Ok(Box::new(client))
+        }
+        #[cfg(not(feature = "usb"))]
+        Transport::Usb => Err(UsbError::Unsupported.into()),
+        Transport::Sftp => Ok(Box::new(connect_sftp(verify_ip(get_ip(dest.ip.clone())?)?, &dest, print)?)),
+    }
+}
+
+fn warn_if_old_skyline_subsdk(client: &mut dyn Backend, exefs_path: &str) {
+
+    let list = match client.ls(exefs_path) {
+        Ok(list) => list,
+        Err(_) => return
+    };
+
+    let subsdk_count = list.matches("subsdk").count();
+    
+    if subsdk_count > 1 {
+        println!("{}: An old install of skyline is detected, this may cause problems.", "WARNING".yellow());
+        return;
+    }
+}
+
+fn parse_tid(tid: &str) -> u64 {
+    u64::from_str_radix(tid, 16).expect("Invalid Title ID")
+}
+
+static SKYLINE_URL: &str = "https://github.com/skyline-dev/skyline/releases/download/beta/skyline.zip";
+static TEMPLATE_NPDM: &[u8] = include_bytes!("template.npdm");
+
+pub fn generate_npdm(tid: &str) -> Vec<u8> {
+    [
+        &TEMPLATE_NPDM[..0x340],
+        &parse_tid(tid).to_le_bytes()[..],
+        &TEMPLATE_NPDM[0x348..]
+    ].concat()
+}
+
+/// Where a plugin gets installed to on the SD card
+struct PluginLocation {
+    /// Every directory leading up to the plugin, outermost first
+    dirs: Vec<String>,
+
+    /// The name of the plugin, if the install path gave one
+    file_name: Option<String>,
+}
+
+impl PluginLocation {
+    fn folder(&self) -> &str {
+        self.dirs.last().map(|dir| &**dir).unwrap_or("")
+    }
+
+    /// Group the plugin under a subdirectory, unless the install path already named the exact file
+    fn with_subdir(mut self, subdir: Option<&str>) -> Self {
+        if let (Some(subdir), None) = (subdir, &self.file_name) {
+            for dir in subdir.split('/').filter(|dir| !dir.is_empty()) {
+                let folder = format!("{}/{}", self.folder(), dir);
+                self.dirs.push(folder);
+            }
+        }
+
+        self
+    }
+}
+
+/// The directory plugins for a title live in, following any override for it
+fn plugins_dir(title_id: &str, metadata: Option<&cargo_info::Metadata>) -> Result<String> {
+    let layout = game_paths::get_layout(metadata)?;
+
+    match game_paths::plugin_dir_override(title_id, metadata) {
+        Some(install_path) => Ok(plugin_location(title_id, Some(&install_path), layout)?.folder().to_owned()),
+        None => Ok(game_paths::get_plugins_path(title_id, layout))
+    }
+}
+
+/// Where a file in the plugin directory of a title goes
+fn plugin_path(title_id: &str, name: &str, metadata: Option<&cargo_info::Metadata>) -> Result<String> {
+    Ok(format!("{}/{}", plugins_dir(title_id, metadata)?, name))
+}
+
+fn plugin_location(title_id: &str, install_path: Option<&str>, layout: GameLayout) -> Result<PluginLocation> {
+    let (mut folder, path) = if let Some(path) = install_path {
+        if let Some(local_path) = path.strip_prefix("rom:/") {
+            Ok((format!("{}/romfs", get_game_path(title_id)), local_path))
+        } else if let Some(absolute_path) = path.strip_prefix("sd:/") {
+            Ok((String::from(""), absolute_path))
+        } else {
+            Err(Error::BadSdPath)
+        }?
+    } else {
+        (get_game_path(title_id), layout.plugins_dir())
+    };
+
+    let dirs = path.split('/')
+        .filter(|x| !x.is_empty() && !x.ends_with(".nro"))
+        .map(|dir| {
+            folder = format!("{}/{}", folder, dir);
+            folder.clone()
+        })
+        .collect();
+
+    let file_name = if path.ends_with(".nro") {
+        path.split('/').last().map(String::from)
+    } else {
+        None
+    };
+
+    Ok(PluginLocation { dirs, file_name })
+}
+
+/// Split a destination naming several devices into one destination per device, labelled
+/// with the device's name
+fn device_destinations(dest: &Destination) -> Result<Vec<(String, Destination)>> {
+    let names = if dest.all_devices {
+        let devices = crate::ip_addr::saved_devices();
+        if devices.is_empty() {
+            return Err(Error::NoDevices);
+        }
+
+        devices.into_iter().map(|device| device.name).collect()
+    } else {
+        dest.device.clone()
+    };
+
+    if names.is_empty() {
+        return Ok(vec![(String::new(), dest.clone())]);
+    }
+
+    // progress bars from several uploads at once would draw over each other
+    let quiet = dest.quiet || names.len() > 1;
+
+    names.into_iter()
+        .map(|name| {
+            let device = crate::ip_addr::device(&name)?;
+            let dest = Destination { quiet, ..with_device(dest, device)? };
+
+            Ok((name, dest))
+        })
+        .collect()
+}
+
+/// Fill in anything not given on the command line from a saved device
+fn with_device(dest: &Destination, device: crate::ip_addr::Device) -> Result<Destination> {
+    let transport = match dest.transport {
+        Some(transport) => Some(transport),
+        None => device.transport
+            .map(|transport| transport.parse().map_err(|_| Error::BadSetting("transport")))
+            .transpose()?
+    };
+
+    Ok(Destination {
+        ip: Some(device.ip),
+        port: dest.port.or(device.port),
+        user: dest.user.clone().or(device.user),
+        pass: dest.pass.clone().or(device.pass),
+        ftp_mode: dest.ftp_mode.or(device.ftp_mode),
+        transport,
+        device: vec![],
+        all_devices: false,
+        ..dest.clone()
+    })
+}
+
+/// Resolve the device a destination names, falling back to the default device when it doesn't
+/// give an IP either
+fn resolve_device(dest: Destination) -> Result<Destination> {
+    if dest.all_devices || dest.device.len() > 1 {
+        return Err(Error::TooManyDevices);
+    }
+
+    if let Some(name) = dest.device.first() {
+        let device = crate::ip_addr::device(name)?;
+        return with_device(&dest, device);
+    }
+
+    if dest.ip.is_none() && crate::ip_addr::env_ip().is_none() {
+        if let Some(device) = crate::ip_addr::preferred_device()? {
+            return with_device(&dest, device);
+        }
+    }
+
+    Ok(dest)
+}
+
+pub fn install(dest: Destination, options: InstallOptions) -> Result<()> {
+    install_sessions(dest, &options)?;
+
+    Ok(())
+}
+
+/// Build once, then install to every device the destination names in parallel, giving back
+/// the open sessions
+fn install_sessions(dest: Destination, options: &InstallOptions) -> Result<Vec<Session>> {
+    let mut targets = device_destinations(&dest)?;
+
+    if targets.len() == 1 {
+        let mut session = Session::new(targets.remove(0).1, true);
+        install_with(&mut session, options)?;
+        return Ok(vec![session]);
+    }
+
+    let builds = build_plugin(options)?;
+    let builds = &builds;
+
+    let results: Vec<(String, Session, Result<()>)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = targets.into_iter()
+            .map(|(name, dest)| scope.spawn(move || {
+                println!("Installing to {}...", name);
+                let mut session = Session::new(dest, false);
+                let result = install_builds(&mut session, options, builds);
+                (name, session, result)
+            }))
+            .collect();
+
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+
+    let mut sessions = vec![];
+    let mut failed = 0;
+    for (name, session, result) in results {
+        match result {
+            Ok(()) => println!("{}", format!("Installed to {}", name).green()),
+            Err(err) => {
+                eprintln!("{}: Failed to install to {}", "ERROR".red(), name);
+                crate::print_error(err);
+                failed += 1;
+            }
+        }
+        sessions.push(session);
+    }
+
+    if failed > 0 {
+        return Err(Error::InstallsFailed(failed));
+    }
+
+    Ok(sessions)
+}
+
+fn install_with(session: &mut Session, options: &InstallOptions) -> Result<()> {
+    let builds = build_plugin(options)?;
+
+    install_builds(session, options, &builds)
+}
+
+/// A build of the plugin, along with the titles it's for when they need features of their own or
+/// it's one of several plugins in the workspace
+struct PluginBuild {
+    /// The built plugin, which is an NSO rather than an NRO when installing as a subsdk
+    nro_path: PathBuf,
+    title_ids: Vec<String>,
+    display_name: Option<String>,
+    /// The file name to install it as, rather than the one cargo gives it
+    nro_name: Option<String>,
+}
+
+/// Build the current plugin once for every set of features the titles being installed for need,
+/// unless it's going to be installed from a directory instead
+fn build_plugin(options: &InstallOptions) -> Result<Vec<PluginBuild>> {
+    if options.dir.is_some() {
+        return Ok(vec![]);
+    }
+
+    let metadata = cargo_info::get_metadata()?;
+
+    if options.workspace || options.package.len() > 1 {
+        return build_workspace(options, &metadata);
+    }
+
+    let packages = &options.package;
+
+    // building one package of the workspace goes by what it says about itself
+    let member = match options.package.first() {
+        Some(name) => Some(
+            metadata.members.iter()
+                .find(|member| member.name == *name)
+                .ok_or_else(|| Error::UnknownPackage(name.clone()))?
+        ),
+        None => None
+    };
+
+    let title_ids = if !options.title_id.is_empty() {
+        &options.title_id
+    } else if let Some(member) = member {
+        &member.title_ids
+    } else {
+        &metadata.title_ids
+    };
+
+    let display_name = member.map(|member| member.display_name.clone()).unwrap_or_else(|| metadata.display_name.clone());
+    let nro_name = member.map(|member| member.nro_name.clone()).unwrap_or_else(|| metadata.nro_name.clone());
+
+    let base_features = install_features(options, &metadata);
+
+    // titles which need the same features can share a build
+    let mut groups: Vec<(Vec<String>, Vec<String>)> = vec![];
+    for title_id in title_ids {
+        let mut features = base_features.clone();
+        if !options.all_features {
+            features.extend(metadata.features_for(title_id));
+        }
+        features.sort();
+        features.dedup();
+
+        match groups.iter_mut().find(|(group, _)| *group == features) {
+            Some((_, title_ids)) => title_ids.push(title_id.clone()),
+            None => groups.push((features, vec![title_id.clone()])),
+        }
+    }
+
+    if groups.len() <= 1 {
+        let features = groups.pop().map(|(features, _)| features).unwrap_or(base_features);
+        let nro_path = build_one(options, build_args(options, &metadata, packages, &features))?;
+
+        return Ok(vec![PluginBuild { nro_path, title_ids: title_ids.clone(), display_name, nro_name }]);
+    }
+
+    groups.into_iter()
+        .map(|(features, title_ids)| {
+            println!("Building for {}...", title_ids.join(", "));
+            let nro_path = build_one(options, build_args(options, &metadata, packages, &features))?;
+
+            // every build is written to the same place, so keep each one before the next replaces it
+            let name = if features.is_empty() { String::from("default") } else { features.join("+") };
+            let dir = nro_path.parent().ok_or(Error::FailWriteNro)?.join("features").join(name);
+            std::fs::create_dir_all(&dir)?;
+
+            let kept_path = dir.join(nro_path.file_name().ok_or(Error::FailWriteNro)?);
+            std::fs::copy(&nro_path, &kept_path)?;
+
+            Ok(PluginBuild { nro_path: kept_path, title_ids, display_name: display_name.clone(), nro_name: nro_name.clone() })
+        })
+        .collect()
+}
+
+fn build_one(options: &InstallOptions, args: Vec<String>) -> Result<PathBuf> {
+    if options.as_subsdk.is_some() {
+        build::build_get_nso(args, !options.no_compress)
+    } else {
+        build::build_get_nro(args, options.strip)
+    }
+}
+
+/// Build every plugin in the workspace (or every package asked for) at once, each to be installed
+/// for its own titles
+fn build_workspace(options: &InstallOptions, metadata: &cargo_info::Metadata) -> Result<Vec<PluginBuild>> {
+    // an install path naming a file would have every plugin overwrite the last
+    if options.install_path.as_deref().is_some_and(|path| path.ends_with(".nro")) {
+        return Err(Error::BadSdPath);
+    }
+
+    let packages = if options.workspace {
+        build::workspace_packages(metadata)?
+    } else {
+        options.package.clone()
+    };
+
+    let builds: Vec<PluginBuild> = build::build_get_nros(build_args(options, metadata, &packages, &install_features(options, metadata)), options.strip)?
+        .into_iter()
+        .filter_map(|(id, nro_path)| {
+            let member = metadata.members.iter().find(|member| member.id == id)?;
+
+            Some(PluginBuild {
+                nro_path,
+                title_ids: if options.title_id.is_empty() { member.title_ids.clone() } else { options.title_id.clone() },
+                display_name: Some(member.display_name.clone().unwrap_or_else(|| member.name.clone())),
+                nro_name: member.nro_name.clone(),
+            })
+        })
+        .collect();
+
+    if builds.is_empty() {
+        return Err(Error::NoWorkspacePlugins);
+    }
+
+    Ok(builds)
+}
+
+/// The features passed on the command line, or the ones Cargo.toml says to install with
+fn install_features(options: &InstallOptions, metadata: &cargo_info::Metadata) -> Vec<String> {
+    if options.features.is_empty() && !options.all_features {
+        metadata.install_features.clone()
+    } else {
+        options.features.clone()
+    }
+}
+
+fn build_args(options: &InstallOptions, metadata: &cargo_info::Metadata, packages: &[String], features: &[String]) -> Vec<String> {
+    let mut args = vec![];
+
+    build::profile_args(&mut args, !options.debug, options.profile.as_deref());
+    build::package_args(&mut args, packages);
+
+    if options.all_features {
+        args.push(String::from("--all-features"));
+    } else if !features.is_empty() {
+        args.push(format!("--features={}", features.join(",")));
+    }
+
+    if options.no_default_features || metadata.no_default_features {
+        args.push(String::from("--no-default-features"));
+    }
+
+    args
+}
+
+/// Install each build of the plugin for the titles it was built for
+fn install_builds(session: &mut Session, options: &InstallOptions, builds: &[PluginBuild]) -> Result<()> {
+    if builds.is_empty() {
+        return install_built(session, options, None);
+    }
+
+    for build in builds {
+        let mut options = options.clone();
+        if !build.title_ids.is_empty() {
+            options.title_id = build.title_ids.clone();
+        }
+
+        install_built(session, &options, Some(build))?;
+    }
+
+    Ok(())
+}
+
+fn install_built(session: &mut Session, options: &InstallOptions, build: Option<&PluginBuild>) -> Result<()> {
+    let build = match (build, &options.dir) {
+        (Some(build), _) => build,
+        (None, Some(dir)) => return install_dir(session, dir, options),
+        (None, None) => return Err(Error::FailWriteNro),
+    };
+
+    if let Some(subsdk) = &options.as_subsdk {
+        return install_as_subsdk(session, options, build, subsdk);
+    }
+
+    let mut client = session.backend()?;
+
+    let metadata = cargo_info::get_metadata()?;
+    let nro_path = &build.nro_path;
+
+    let title_ids = if !options.title_id.is_empty() {
+        options.title_id.clone()
+    } else if !metadata.title_ids.is_empty() {
+        metadata.title_ids.clone()
+    } else {
+        return Err(Error::NoTitleId);
+    };
+
+    let nro_data = std::fs::read(nro_path)?;
+    crate::nxo::ensure_valid(&nro_path.display().to_string(), &nro_data)?;
+    let hash = sha256_hex(&nro_data);
+    let mut cache = UploadCache::load(nro_path);
+
+    let mut lockfile = Lockfile::load(&metadata.workspace_root)?;
+    lockfile.retain(&metadata.plugin_dependencies);
+
+    // Everything that needs uploading is collected first so it can all be sent at once
+    let mut uploads = vec![];
+
+    // everything being installed which the upload cache keeps track of, with cache keys and hashes
+    let mut installed = vec![];
+
+    // the installed copies of the plugin being replaced
+    let mut plugins = vec![];
+
+    // plugin directories to clean and what to keep in each, which waits until everything that could
+    // fail has been done so a failed install doesn't leave the directory empty
+    let mut cleans = vec![];
+
+    if let Some(display_name) = &build.display_name {
+        println!("Installing {}...", display_name);
+    }
+
+    println!("Ensuring directory exists...");
+
+    let layout = game_paths::get_layout(Some(&metadata))?;
+
+    for title_id in &title_ids {
+        let install_path = options.install_path.clone()
+            .or_else(|| game_paths::plugin_dir_override(title_id, Some(&metadata)));
+        let location = plugin_location(title_id, install_path.as_deref(), layout)?
+            .with_subdir(metadata.plugin_subdir.as_deref());
+
+        if options.preflight {
+            preflight(&mut **client, title_id, &location)?;
+        }
+
+        // this is where subsdk9 goes, it doesn't depend on the path
+        let _ = client.mkdir(&(get_game_path(title_id) + "/exefs"));
+
+        for dir in &location.dirs {
+            let _ = client.mkdir(dir);
+        }
+
+        let nro_name = match location.file_name.as_ref().or(build.nro_name.as_ref()) {
+            Some(name) => name,
+            None => nro_path.file_name().map(|x| x.to_str()).flatten().ok_or(Error::FailWriteNro)?
+        };
+        cargo_info::check_nro_name(nro_name)?;
+
+        // the plugin and its dependencies are about to be installed anyway, keeping them
+        // avoids transferring them again if they're unchanged
+        let mut keep = protected_plugins(Some(&metadata));
+        keep.extend(metadata.plugin_dependencies.iter().map(|dep| dep.name.clone()));
+        keep.push(nro_name.to_owned());
+        keep.push(format!("{}.bak", nro_name));
+
+        check_skyline_version(&mut **client, title_id, &metadata, options)?;
+        uploads.extend(skyline_uploads(&mut **client, title_id, Some(&metadata))?);
+
+        for dep in &metadata.plugin_dependencies {
+            let dep_path = plugin_path(title_id, &dep.name, Some(&metadata))?;
+            let dep_data = dependencies::fetch(dep)?;
+            let dep_hash = sha256_hex(&dep_data);
+            lockfile.check(dep, &dep_hash)?;
+
+            let key = format!("{}{}", client.location(), dep_path);
+            let fingerprint = client.fingerprint(&dep_path).unwrap_or(None);
+
+            if options.force || !cache.is_current(&key, &dep_hash, fingerprint.as_deref()) {
+                println!("Installing dependency {}...", dep.name);
+                uploads.push((dep_path.clone(), dep_data));
+                installed.push((dep_path, key, dep_hash));
+            }
+        }
+
+        for asset in &metadata.assets {
+            let base = if asset.path.starts_with('/') {
+                asset.path.trim_end_matches('/').to_owned()
+            } else if let Some(absolute_path) = asset.path.strip_prefix("sd:/") {
+                format!("/{}", absolute_path.trim_end_matches('/'))
+            } else {
+                format!("{}/{}", location.folder(), asset.path.trim_end_matches('/'))
+            };
+
+            for (relative, local) in asset_files(&asset.source)? {
+                let asset_path = format!("{}/{}", base, relative);
+                if let Some(name) = asset_path.strip_prefix(&format!("{}/", location.folder())) {
+                    keep.push(name.split('/').next().unwrap_or(name).to_owned());
+                }
+
+                let data = std::fs::read(&local)?;
+                let asset_hash = sha256_hex(&data);
+
+                let key = format!("{}{}", client.location(), asset_path);
+                let fingerprint = client.fingerprint(&asset_path).unwrap_or(None);
+
+                if options.force || !cache.is_current(&key, &asset_hash, fingerprint.as_deref()) {
+                    if let Some((dir, _)) = asset_path.rsplit_once('/') {
+                        mkdir_all(&mut **client, dir);
+                    }
+
+                    println!("Installing asset {}...", relative);
+                    uploads.push((asset_path.clone(), data));
+                    installed.push((asset_path, key, asset_hash));
+                }
+            }
+        }
+
+        let remote_path = format!("{}/{}", location.folder(), nro_name);
+        let key = format!("{}{}", client.location(), remote_path);
+        let fingerprint = client.fingerprint(&remote_path).unwrap_or(None);
+
+        if options.force || !cache.is_current(&key, &hash, fingerprint.as_deref()) {
+            uploads.push((remote_path.clone(), nro_data.clone()));
+            plugins.push((title_id.clone(), remote_path.clone()));
+            installed.push((remote_path, key, hash.clone()));
+        } else if title_ids.len() > 1 {
+            println!("{}", format!("Installed plugin for {} is unchanged, skipping transfer", title_id).green());
+        } else {
+            println!("{}", "Installed plugin is unchanged, skipping transfer".green());
+        }
+
+        if options.clean {
+            cleans.push((location.folder().to_owned(), keep));
+        }
+    }
+
+    lockfile.save()?;
+
+    let backups: Vec<String> = if options.backup {
+        plugins.iter().map(|(_, remote_path)| remote_path.clone()).collect()
+    } else {
+        vec![]
+    };
+
+    for (folder, keep) in &cleans {
+        println!("Cleaning {}...", folder);
+        clean_plugin_dir(&mut **client, folder, keep)?;
+    }
+    transfer(&mut **client, &uploads, &backups, options.verify)?;
+
+    let git_hash = history::git_hash(&metadata.workspace_root);
+    let deployments: Vec<_> = plugins.into_iter()
+        .map(|(title_id, remote_path)| {
+            history::Deployment::new(git_hash.clone(), client.location(), title_id, remote_path, hash.clone())
+        })
+        .collect();
+    if history::record(&deployments).is_err() {
+        println!("{}: Could not record the install in the deployment history", "WARNING".yellow());
+    }
+    if !deployments.is_empty() && crate::size_diff::keep_deployed(&metadata, nro_path).is_err() {
+        println!("{}: Could not keep a copy of the installed plugin for size-diff", "WARNING".yellow());
+    }
+
+    let mut recorded = false;
+    for (remote_path, key, hash) in installed {
+        if let Ok(Some(fingerprint)) = client.fingerprint(&remote_path) {
+            cache.record(key, hash, fingerprint);
+            recorded = true;
+        }
+    }
+
+    if recorded {
+        let _ = cache.save();
+    }
+
+    Ok(())
+}
+
+/// Install the plugin in place of a subsdk rather than in the plugin directory, which leaves
+/// Skyline out entirely since the game can't load plugins anyway
+fn install_as_subsdk(session: &mut Session, options: &InstallOptions, build: &PluginBuild, subsdk: &str) -> Result<()> {
+    let mut client = session.backend()?;
+
+    let metadata = cargo_info::get_metadata()?;
+
+    let title_ids = if !options.title_id.is_empty() {
+        options.title_id.clone()
+    } else if !metadata.title_ids.is_empty() {
+        metadata.title_ids.clone()
+    } else {
+        return Err(Error::NoTitleId);
+    };
+
+    let nso_data = std::fs::read(&build.nro_path)?;
+    crate::nxo::ensure_valid(&build.nro_path.display().to_string(), &nso_data)?;
+    let hash = sha256_hex(&nso_data);
+    let mut cache = UploadCache::load(&build.nro_path);
+
+    let mut uploads = vec![];
+    let mut installed = vec![];
+
+    for title_id in &title_ids {
+        let exefs_dir = get_game_path(title_id) + "/exefs";
+        mkdir_all(&mut **client, &exefs_dir);
+
+        let npdm_path = format!("{}/main.npdm", exefs_dir);
+        match &metadata.npdm_path {
+            Some(path) => uploads.push((npdm_path, std::fs::read(path).map_err(|_| Error::NoNpdmFileFound)?)),
+            None if !client.file_exists(&npdm_path).unwrap_or(false) => {
+                println!("No npdm installed for the given title, generating and installing...");
+                uploads.push((npdm_path, generate_npdm(title_id)));
+            }
+            None => {}
+        }
+
+        let remote_path = format!("{}/{}", exefs_dir, subsdk);
+        let key = format!("{}{}", client.location(), remote_path);
+        let fingerprint = client.fingerprint(&remote_path).unwrap_or(None);
+
+        if options.force || !cache.is_current(&key, &hash, fingerprint.as_deref()) {
+            println!("Installing over {}...", subsdk);
+            uploads.push((remote_path.clone(), nso_data.clone()));
+            installed.push((remote_path, key));
+        } else {
+            println!("{}", format!("Installed {} for {} is unchanged, skipping transfer", subsdk, title_id).green());
+        }
+    }
+
+    let backups: Vec<String> = if options.backup {
+        installed.iter().map(|(remote_path, _)| remote_path.clone()).collect()
+    } else {
+        vec![]
+    };
+    transfer(&mut **client, &uploads, &backups, options.verify)?;
+
+    let mut recorded = false;
+    for (remote_path, key) in installed {
+        if let Ok(Some(fingerprint)) = client.fingerprint(&remote_path) {
+            cache.record(key, hash.clone(), fingerprint);
+            recorded = true;
+        }
+    }
+
+    if recorded {
+        let _ = cache.save();
+    }
+
+    Ok(())
+}
+
+/// Every file in an asset directory, along with its path relative to the directory using `/`
+fn asset_files(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    if !dir.is_dir() {
+        return Err(Error::MissingAssetDir(dir.to_owned()));
+    }
+
+    let mut files = vec![];
+    let mut dirs = vec![dir.to_owned()];
+
+    while let Some(current) = dirs.pop() {
+        for entry in std::fs::read_dir(&current)? {
+            let path = entry?.path();
+
+            if path.is_dir() {
+                dirs.push(path);
+            } else if let Ok(relative) = path.strip_prefix(dir) {
+                let relative: Vec<_> = relative.components()
+                    .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                    .collect();
+
+                files.push((relative.join("/"), path.clone()));
+            }
+        }
+    }
+
+    files.sort();
+
+    Ok(files)
+}
+
+/// Install every NRO in a directory as-is, without building anything
+fn install_dir(session: &mut Session, dir: &Path, options: &InstallOptions) -> Result<()> {
+    let title_ids = if !options.title_id.is_empty() {
+        options.title_id.clone()
+    } else {
+        match cargo_info::get_metadata().map(|metadata| metadata.title_ids) {
+            Ok(title_ids) if !title_ids.is_empty() => title_ids,
+            _ => vec![resolve_title_id(None)?]
+        }
+    };
+
+    let mut plugins: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "nro"))
+        .collect();
+    plugins.sort();
+
+    if plugins.is_empty() {
+        println!("{}: No plugins found in {}", "WARNING".yellow(), dir.display());
+        return Ok(());
+    }
+
+    let plugins: Vec<(PathBuf, Vec<u8>)> = plugins.into_iter()
+        .map(|plugin| {
+            let data = std::fs::read(&plugin)?;
+            crate::nxo::ensure_valid(&plugin.display().to_string(), &data)?;
+            Ok((plugin, data))
+        })
+        .collect::<Result<_>>()?;
+
+    let mut client = session.backend()?;
+
+    let mut uploads = vec![];
+    let mut backups = vec![];
+    let mut cleans = vec![];
+
+    println!("Ensuring directory exists...");
+
+    let metadata = cargo_info::get_metadata().ok();
+    let layout = game_paths::get_layout(metadata.as_ref())?;
+
+    for title_id in &title_ids {
+        let install_path = options.install_path.clone()
+            .or_else(|| game_paths::plugin_dir_override(title_id, metadata.as_ref()));
+        let location = plugin_location(title_id, install_path.as_deref(), layout)?;
+
+        if location.file_name.is_some() {
+            return Err(Error::BadSdPath);
+        }
+
+        if options.preflight {
+            preflight(&mut **client, title_id, &location)?;
+        }
+
+        let _ = client.mkdir(&(get_game_path(title_id) + "/exefs"));
+        for dir in &location.dirs {
+            let _ = client.mkdir(dir);
+        }
+
+        // cleaned once everything else has been checked, right before the new plugins go in
+        if options.clean {
+            cleans.push(location.folder().to_owned());
+        }
+
+        uploads.extend(skyline_uploads(&mut **client, title_id, metadata.as_ref())?);
+
+        for (plugin, data) in &plugins {
+            let name = plugin.file_name().and_then(|name| name.to_str()).ok_or(Error::FailWriteNro)?;
+            let remote_path = format!("{}/{}", location.folder(), name);
+
+            println!("Queueing {}...", name);
+            if options.backup {
+                backups.push(remote_path.clone());
+            }
+            uploads.push((remote_path, data.clone()));
+        }
+    }
+
+    // the plugins being installed are replaced anyway, and keeping them leaves them for --backup
+    let mut keep = protected_plugins(metadata.as_ref());
+    for (plugin, _) in &plugins {
+        let name = plugin.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        keep.push(name.to_owned());
+        keep.push(format!("{}.bak", name));
+    }
+    for folder in &cleans {
+        println!("Cleaning {}...", folder);
+        clean_plugin_dir(&mut **client, folder, &keep)?;
+    }
+
+    transfer(&mut **client, &uploads, &backups, options.verify)?;
+
+    println!("{}", format!("Installed {} plugins", plugins.len()).green());
+
+    Ok(())
+}
+
+/// Check that everything needed for a plugin to load is already on the SD card, asking before
+/// anything missing gets installed
+fn preflight(client: &mut dyn Backend, title_id: &str, location: &PluginLocation) -> Result<()> {
+    let exefs = get_game_path(title_id) + "/exefs";
+    let checks = [
+        ("Skyline (exefs/subsdk9)", client.file_exists(&format!("{}/subsdk9", exefs)).unwrap_or(false)),
+        ("exefs/main.npdm", client.file_exists(&format!("{}/main.npdm", exefs)).unwrap_or(false)),
+        ("the plugin directory", client.is_dir(location.folder()).unwrap_or(false)),
+    ];
+
+    println!("Checking {}...", title_id);
+    for (name, present) in &checks {
+        if *present {
+            println!("    {} {}", "found".green(), name);
+        } else {
+            println!("    {} {}", "missing".red(), name);
+        }
+    }
+
+    if checks.iter().all(|(_, present)| *present) {
+        return Ok(());
+    }
+
+    if confirm("Install what's missing?") {
+        Ok(())
+    } else {
+        Err(Error::PreflightFailed(title_id.to_owned()))
+    }
+}
+
+/// Make sure the Skyline a title has, or is about to be given, is at least `min-skyline-version`
+fn check_skyline_version(client: &mut dyn Backend, title_id: &str, metadata: &cargo_info::Metadata, options: &InstallOptions) -> Result<()> {
+    let required = match &metadata.min_skyline_version {
+        Some(version) => version.parse::<Version>().map_err(|_| Error::BadSetting("min-skyline-version"))?,
+        None => return Ok(())
+    };
+
+    let subsdk_name = metadata.subsdk_name.as_deref().unwrap_or("subsdk9");
+    let subsdk_path = format!("/{}", get_subsdk_path(title_id, subsdk_name));
+
+    let found = if client.file_exists(&subsdk_path).unwrap_or(false) {
+        let subsdk = client.get(&subsdk_path)?;
+        let sha256 = sha256_hex(&subsdk);
+
+        // the release recorded for exactly this build is more trustworthy than a string found in it
+        PluginManifest::load(client, title_id).skyline
+            .filter(|entry| entry.sha256 == sha256)
+            .and_then(|entry| skyline_version::from_release(&entry.release))
+            .or_else(|| skyline_version::from_binary(&subsdk))
+    } else {
+        // it's about to be installed from whichever release the project uses
+        skyline_version::from_release(&skyline_release_url(None, Some(metadata)))
+    };
+
+    match found {
+        Some(found) if found >= required => Ok(()),
+        Some(found) if options.ignore_skyline_version => {
+            println!("{}: The plugin needs Skyline {} or newer, but {} has {}", "WARNING".yellow(), required, title_id, found);
+            Ok(())
+        }
+        Some(found) => Err(Error::SkylineTooOld(title_id.to_owned(), found.to_string(), required.to_string())),
+        None => {
+            println!("{}: Couldn't tell which version of Skyline {} has, the plugin needs {} or newer", "WARNING".yellow(), title_id, required);
+            Ok(())
+        }
+    }
+}
+
+/// Skyline itself, for any title which doesn't have it installed yet
+fn skyline_uploads(client: &mut dyn Backend, title_id: &str, metadata: Option<&cargo_info::Metadata>) -> Result<Vec<(String, Vec<u8>)>> {
+    warn_if_old_skyline_subsdk(client, &(get_game_path(title_id) + "/exefs/"));
+
+    let mut uploads = vec![];
+
+    // Ensure skyline is installed if it doesn't exist
+    let subsdk_path = get_game_path(title_id) + "/exefs/subsdk9";
+    if !client.file_exists(&subsdk_path).unwrap_or(false){
+        println!("Skyline subsdk not installed for the given title, downloading...");
+        let exefs = crate::package::get_exefs(&skyline_release_url(None, metadata))?;
+        println!("Installing over subsdk9...");
+        uploads.push((subsdk_path, exefs.subsdk1));
+    }
+
+    let npdm_path = get_game_path(title_id) + "/exefs/main.npdm";
+    if game_paths::get_layout(metadata)?.always_installs_npdm() {
+        // the game won't load Skyline with its own npdm, so make sure the patched one is there
+        println!("Installing patched npdm...");
+        let npdm = match metadata.and_then(|md| md.npdm_path.as_ref()) {
+            Some(path) => std::fs::read(path).map_err(|_| Error::NoNpdmFileFound)?,
+            None => generate_npdm(title_id),
+        };
+        uploads.push((npdm_path, npdm));
+    } else if !client.file_exists(&npdm_path).unwrap_or(false) {
+        println!("Skyline npdm not installed for the given title, generating and installing...");
+        uploads.push((npdm_path, generate_npdm(title_id)));
+    }
+
+    Ok(uploads)
+}
+
+/// Upload files in one go, keeping a `.bak` of anything in `backups` which gets replaced
+fn transfer(client: &mut dyn Backend, uploads: &[(String, Vec<u8>)], backups: &[String], verify: bool) -> Result<()> {
+    if uploads.is_empty() {
+        return Ok(());
+    }
+
+    println!("Transferring {}...", if uploads.len() == 1 { "file" } else { "files" });
+    let needed = uploads.iter().map(|(_, file)| file.len() as u64).sum();
+    match client.free_space("/")? {
+        Some(available) if available < needed => return Err(Error::InsufficientSpace(needed, available)),
+        _ => {}
+    }
+
+    // Files are uploaded next to where they belong and only moved into place once fully
+    // transferred, so a dropped connection can't leave a truncated file for the game to load.
+    // They're named after what's in them, so the next install of the same build can carry on
+    // from wherever an interrupted one got to.
+    let staged: Vec<(String, Vec<u8>)> = uploads.iter()
+        .map(|(path, file)| (staging_path(path, file), file.clone()))
+        .collect();
+
+    remove_stale_staging(client, uploads, &staged);
+    client.resume_all(&staged).map_err(out_of_space)?;
+
+    for ((path, _), (staged_path, _)) in uploads.iter().zip(&staged) {
+        // keep the plugin being replaced around for `cargo skyline rollback`
+        if backups.contains(path) && client.file_exists(path).unwrap_or(false) {
+            client.rename(path, &format!("{}.bak", path))?;
+        }
+
+        client.rename(staged_path, path)?;
+    }
+
+    if verify {
+        println!("Verifying uploaded files...");
+        for (path, file) in uploads {
+            match client.verify(path, file)? {
+                Some(true) => {}
+                Some(false) => return Err(Error::VerifyFailed(path.clone())),
+                None => {
+                    println!("{}: Uploads can't be verified over {}", "WARNING".yellow(), client.location());
+                    break;
+                }
+            }
+        }
+        println!("{}", "Verified!".green());
+    }
+
+    Ok(())
+}
+
+/// Servers turn down writes with 452 or 552 once the SD card is full
+fn out_of_space(err: Error) -> Error {
+    match err {
+        Error::FtpError(FtpError::UnexpectedStatus(452)) | Error::FtpError(FtpError::UnexpectedStatus(552)) => Error::SdCardFull,
+        err => err,
+    }
+}
+
+fn staging_path(path: &str, file: &[u8]) -> String {
+    format!("{}.{}.tmp", path, &sha256_hex(file)[..16])
+}
+
+/// Check if `name` is where a version of the file `target` would have been staged
+fn is_staging_of(name: &str, target: &str) -> bool {
+    match name.strip_prefix(target).and_then(|rest| rest.strip_suffix(".tmp")) {
+        // older versions staged everything at the same path
+        Some("") => true,
+        Some(hash) => hash.len() == 17 && hash.starts_with('.') && hash[1..].bytes().all(|b| b.is_ascii_hexdigit()),
+        None => false,
+    }
+}
+
+/// Remove what interrupted installs of other builds left staged, which would otherwise be
+/// stuck taking up space on the SD card
+fn remove_stale_staging(client: &mut dyn Backend, uploads: &[(String, Vec<u8>)], staged: &[(String, Vec<u8>)]) {
+    let mut listings = HashMap::new();
+
+    for ((path, _), (staged_path, _)) in uploads.iter().zip(staged) {
+        let (dir, target) = match path.rsplit_once('/') {
+            Some(split) => split,
+            None => continue,
+        };
+
+        let names = listings.entry(dir.to_owned())
+            .or_insert_with(|| client.names(dir).unwrap_or_default());
+
+        let stale: Vec<String> = names.iter()
+            .filter(|name| is_staging_of(name, target))
+            .map(|name| format!("{}/{}", dir, name))
+            .filter(|other| other != staged_path)
+            .collect();
+
+        for other in stale {
+            let _ = client.remove(&other);
+        }
+    }
+}
+
+pub fn from_git(git: &str, dest: Destination, options: InstallOptions) -> Result<()> {
+    let temp_dir = TempGitDir::clone_to_current_dir(git)?;
+
+    install(dest, options)?;
+
+    temp_dir.delete();
+
+    Ok(())
+}
+
+use std::io::Write;
+use std::time::Duration;
+use std::net::TcpStream;
+
+const RESTART_PLUGIN_PORT: u16 = 45423;
+
+pub fn restart_game(ip: Option<String>, title_id: Option<String>) -> Result<()> {
+    let ip = verify_ip(get_ip(ip)?)?;
+
+    let mut port = TcpStream::connect_timeout(
+        &(ip, RESTART_PLUGIN_PORT).into(),
+        Duration::from_secs(1)
+    )?;
+
+    let metadata = cargo_info::get_metadata()?;
+
+    let title_id =
+            title_id.or_else(|| metadata.title_id.clone())
+                    .ok_or(Error::NoTitleId)?;
+
+    let title_id: u64 = u64::from_str_radix(&title_id, 0x10).unwrap_or(0);
+
+    port.write_all(&title_id.to_be_bytes())?;
+
+    Ok(())
+}
+
+pub fn install_and_run(dest: Destination, options: InstallOptions, restart: bool) -> Result<()> {
+    // restarting can only bring up one game, so go with the first one installed to
+    let title_id = options.title_id.first().cloned();
+
+    // the session is kept open until the command exits so nothing needs to reconnect
+    let sessions = install_sessions(dest, &options)?;
+    let session = &sessions[0];
+    if sessions.len() > 1 {
+        println!("Listening for logs from the first device...");
+    }
+
+    let ip = session.destination().ip.clone();
+    let timeouts = session.destination().timeouts.resolve()?;
+
+    if restart {
+        let restart_ip = ip.clone();
+        std::thread::spawn(move || {
+            // Give logger some time to spin up
+            std::thread::sleep(std::time::Duration::from_millis(50));
+
+            restart_game(restart_ip, title_id);
+        });
+    }
+    
+    // the game is only just starting, so it won't be logging yet
+    tcp_listen::listen(ip, timeouts, true)
+}
+
+/// Where the current plugin is installed to, without needing to build it
+fn installed_plugin_path(title_id: Option<String>, install_path: Option<&str>) -> Result<String> {
+    let metadata = cargo_info::get_metadata()?;
+
+    let title_id =
+            title_id.or_else(|| metadata.title_id.clone())
+                    .ok_or(Error::NoTitleId)?;
+
+    let install_path = install_path.map(String::from)
+        .or_else(|| game_paths::plugin_dir_override(&title_id, Some(&metadata)));
+    let location = plugin_location(&title_id, install_path.as_deref(), game_paths::get_layout(Some(&metadata))?)?
+        .with_subdir(metadata.plugin_subdir.as_deref());
+
+    // cargo names the built plugin after the crate, with dashes swapped for underscores
+    let nro_name = location.file_name.clone()
+        .or_else(|| metadata.nro_name.clone())
+        .unwrap_or_else(|| format!("lib{}.nro", metadata.name.replace('-', "_")));
+    cargo_info::check_nro_name(&nro_name)?;
+
+    Ok(format!("{}/{}", location.folder(), nro_name))
+}
+
+/// Install Skyline itself for a game, without needing a plugin to install alongside it
+pub fn install_skyline(dest: Destination, title_id: Option<String>, skyline_release: Option<String>, force: bool) -> Result<()> {
+    let metadata = cargo_info::get_metadata().ok();
+    let skyline_release = skyline_release_url(skyline_release, metadata.as_ref());
+
+    let title_id =
+            title_id.or_else(|| metadata.as_ref().and_then(|md| md.title_id.clone()))
+                    .ok_or(Error::NoTitleId)?;
+
+    let subsdk_name = metadata.as_ref().and_then(|md| md.subsdk_name.as_deref()).unwrap_or("subsdk9");
+    let subsdk_path = format!("/{}", get_subsdk_path(&title_id, subsdk_name));
+    let npdm_path = format!("/{}", get_npdm_path(&title_id));
+
+    let mut session = Session::new(dest, true);
+    let mut client = session.backend()?;
+
+    if !force && client.file_exists(&subsdk_path).unwrap_or(false) {
+        println!("Skyline is already installed at {}, pass --force to replace it", subsdk_path);
+        return Ok(());
+    }
+
+    println!("Downloading Skyline...");
+    let exefs = crate::package::get_exefs(&skyline_release)?;
+
+    let npdm = match metadata.as_ref().and_then(|md| md.npdm_path.as_ref()) {
+        Some(path) => std::fs::read(path).map_err(|_| Error::NoNpdmFileFound)?,
+        None => generate_npdm(&title_id),
+    };
+
+    let sha256 = sha256_hex(&exefs.subsdk1);
+
+    mkdir_all(&mut **client, &(get_game_path(&title_id) + "/exefs"));
+    transfer(&mut **client, &[(subsdk_path, exefs.subsdk1), (npdm_path, npdm)], &[], false)?;
+
+    let mut manifest = PluginManifest::load(&mut **client, &title_id);
+    manifest.skyline = Some(SkylineEntry { release: skyline_release, sha256 });
+    manifest.save(&mut **client, &title_id)?;
+
+    println!("{}", format!("Installed Skyline for {}", title_id).green());
+
+    Ok(())
+}
+
+/// The Skyline release to use, preferring the command line, then the release pinned in Cargo.toml
+pub fn skyline_release_url(skyline_release: Option<String>, metadata: Option<&cargo_info::Metadata>) -> String {
+    skyline_release
+        .or_else(|| metadata.and_then(|md| md.skyline_release.clone()))
+        .unwrap_or_else(|| String::from(SKYLINE_URL))
+}
+
+/// Compare the installed Skyline against a release, replacing it if asked to
+pub fn update_skyline(dest: Destination, title_id: Option<String>, skyline_release: Option<String>, replace: bool) -> Result<()> {
+    let metadata = cargo_info::get_metadata().ok();
+    let skyline_release = skyline_release_url(skyline_release, metadata.as_ref());
+
+    let title_id =
+            title_id.or_else(|| metadata.as_ref().and_then(|md| md.title_id.clone()))
+                    .ok_or(Error::NoTitleId)?;
+
+    let subsdk_name = metadata.as_ref().and_then(|md| md.subsdk_name.as_deref()).unwrap_or("subsdk9");
+    let subsdk_path = format!("/{}", get_subsdk_path(&title_id, subsdk_name));
+
+    let mut session = Session::new(dest, true);
+    let mut client = session.backend()?;
+
+    if !client.file_exists(&subsdk_path).unwrap_or(false) {
+        println!("Skyline isn't installed for {}, install it using `cargo skyline install-skyline`", title_id);
+        return Ok(());
+    }
+
+    let installed = sha256_hex(&client.get(&subsdk_path)?);
+    let mut manifest = PluginManifest::load(&mut **client, &title_id);
+
+    // the hash is all there is to go off, so the manifest is the only way to know where it came from
+    match &manifest.skyline {
+        Some(entry) if entry.sha256 == installed => println!("Installed: {} (from {})", &installed[..12], entry.release),
+        _ => println!("Installed: {}", &installed[..12]),
+    }
+
+    println!("Downloading Skyline...");
+    let exefs = crate::package::get_exefs(&skyline_release)?;
+    let available = sha256_hex(&exefs.subsdk1);
+    println!("Available: {} (from {})", &available[..12], skyline_release);
+
+    if installed == available {
+        println!("{}", "Skyline is up to date".green());
+        return Ok(());
+    }
+
+    if !replace {
+        println!("{}: The installed Skyline doesn't match the release, pass --replace to update it", "WARNING".yellow());
+        return Ok(());
+    }
+
+    transfer(&mut **client, &[(subsdk_path, exefs.subsdk1)], &[], false)?;
+
+    manifest.skyline = Some(SkylineEntry { release: skyline_release, sha256: available });
+    manifest.save(&mut **client, &title_id)?;
+
+    println!("{}", "Updated Skyline!".green());
+
+    Ok(())
+}
+
+/// Install the plugins from the latest GitHub release of a third-party plugin
+pub fn install_plugin(dest: Destination, title_id: Option<String>, name: String) -> Result<()> {
+    let title_id = resolve_title_id(title_id)?;
+    let repo = crate::github::resolve_repo(&name)?;
+
+    println!("Finding the latest release of {}...", repo);
+    let release = crate::github::latest_release(&repo)?;
+
+    println!("Downloading {} {}...", repo, release.tag_name);
+    let plugins = crate::github::release_plugins(&repo, &release)?;
+    for (name, data) in &plugins {
+        crate::nxo::ensure_valid(name, data)?;
+    }
+
+    let mut session = Session::new(dest, true);
+    let mut client = session.backend()?;
+
+    let plugins_path = plugins_dir(&title_id, cargo_info::get_metadata().ok().as_ref())?;
+    mkdir_all(&mut **client, &plugins_path);
+
+    let uploads: Vec<(String, Vec<u8>)> = plugins.iter()
+        .map(|(name, data)| (format!("{}/{}", plugins_path, name), data.clone()))
+        .collect();
+
+    transfer(&mut **client, &uploads, &[], false)?;
+
+    // remembered so that `outdated` knows where to look for newer versions
+    let mut manifest = PluginManifest::load(&mut **client, &title_id);
+    for (name, _) in &plugins {
+        manifest.plugins.insert(name.clone(), ManifestEntry { repo: repo.clone(), version: release.tag_name.clone() });
+    }
+    manifest.save(&mut **client, &title_id)?;
+
+    println!("{}", format!("Installed {} {}", repo, release.tag_name).green());
+
+    Ok(())
+}
+
+/// Check whether the switch answers pings and which of the ports cargo-skyline uses are open
+pub fn ping(dest: Destination) -> Result<()> {
+    let timeouts = dest.timeouts.resolve()?;
+
+    for (name, dest) in device_destinations(&dest)? {
+        let dest = resolve_device(dest)?;
+        let ip = verify_ip(get_ip(dest.ip.clone())?)?;
+        if name.is_empty() {
+            println!("Checking {}...", ip);
+        } else {
+            println!("Checking {} ({})...", name, ip);
+        }
+
+        if icmp_ping(ip) {
+            println!("    {} answers pings", "ok".green());
+        } else {
+            println!("    {} no reply to pings, check the switch is awake and on the same network", "failed".red());
+        }
+
+        let ports = [
+            (ftp_options(&dest)?.port, "FTP", "is an FTP sysmodule like sys-ftpd running?"),
+            (tcp_listen::LOG_PORT, "logging", "only open while a plugin using skyline's logger is running"),
+            (RESTART_PLUGIN_PORT, "restart", "only open when the restart plugin is installed and the game is running"),
+        ];
+
+        for (port, what, hint) in &ports {
+            match timeouts.connect(std::net::SocketAddr::new(ip, *port)) {
+                Ok(_) => println!("    {} port {} ({}) is open", "ok".green(), port, what),
+                Err(err) if err.kind() == std::io::ErrorKind::ConnectionRefused => {
+                    println!("    {} port {} ({}) is closed, {}", "failed".red(), port, what, hint)
+                }
+                Err(_) => println!("    {} port {} ({}) didn't respond, {}", "failed".red(), port, what, hint),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Send a single ICMP echo using the system's `ping`, as sending one directly needs raw sockets
+fn icmp_ping(ip: IpAddr) -> bool {
+    let mut command = std::process::Command::new("ping");
+
+    if cfg!(windows) {
+        command.args(["-n", "1", "-w", "1000"]);
+    } else {
+        command.args(["-c", "1", "-W", "1"]);
+    }
+
+    command.arg(ip.to_string())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+/// List every title with a folder under `atmosphere/contents`, and whether Skyline is installed for it
+pub fn titles(dest: Destination) -> Result<()> {
+    let mut session = Session::new(dest, false);
+    let mut client = session.backend()?;
+
+    let mut title_ids: Vec<String> = client.names("/atmosphere/contents")?
+        .into_iter()
+        .filter(|name| crate::titles::is_title_id(name))
+        .collect();
+    title_ids.sort();
+
+    if title_ids.is_empty() {
+        println!("No titles found in /atmosphere/contents");
+        return Ok(());
+    }
+
+    for title_id in &title_ids {
+        let name = crate::titles::game_name(title_id).unwrap_or("Unknown game");
+        let skyline = client.file_exists(&(get_game_path(title_id) + "/exefs/subsdk9")).unwrap_or(false);
+
+        if skyline {
+            println!("{}  {} {}", title_id, name, "(skyline)".green());
+        } else {
+            println!("{}  {}", title_id, name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Show whether the device can be reached, which Skyline is installed and what plugins are installed
+pub fn status(dest: Destination, title_id: Option<String>) -> Result<()> {
+    let title_id = resolve_title_id(title_id)?;
+
+    let mut session = Session::new(dest, false);
+
+    let start = std::time::Instant::now();
+    let mut client = match session.backend() {
+        Ok(client) => client,
+        Err(err) => {
+            println!("{}: {}", "Device".bright_blue(), "unreachable".red());
+            return Err(err);
+        }
+    };
+    println!("{}: {} (connected in {} ms)", "Device".bright_blue(), client.location(), start.elapsed().as_millis());
+
+    let manifest = PluginManifest::load(&mut **client, &title_id);
+
+    let subsdk_path = get_game_path(&title_id) + "/exefs/subsdk9";
+    let skyline = if client.file_exists(&subsdk_path).unwrap_or(false) {
+        let installed = sha256_hex(&client.get(&subsdk_path)?);
+        match &manifest.skyline {
+            Some(entry) if entry.sha256 == installed => format!("{} (from {})", &installed[..12], entry.release),
+            _ => installed[..12].to_string(),
+        }
+    } else {
+        "not installed".red().to_string()
+    };
+    println!("{}: {}", "Skyline".bright_blue(), skyline);
+
+    let npdm_path = get_game_path(&title_id) + "/exefs/main.npdm";
+    if !client.file_exists(&npdm_path).unwrap_or(false) {
+        println!("{}: {}", "main.npdm".bright_blue(), "not installed".red());
+    }
+
+    let plugins_path = plugins_dir(&title_id, cargo_info::get_metadata().ok().as_ref())?;
+    let mut plugins: Vec<String> = client.names(&plugins_path).unwrap_or_default()
+        .into_iter()
+        .filter(|name| name.ends_with(".nro") || name.ends_with(".nro.disabled"))
+        .collect();
+    plugins.sort();
+
+    println!("{} for {}:", "Plugins".bright_blue(), title_id);
+    if plugins.is_empty() {
+        println!("    none");
+    }
+
+    let plugin_names = cargo_info::get_metadata().map(|metadata| metadata.plugin_names).unwrap_or_default();
+
+    for name in &plugins {
+        let mut version = manifest.plugins.get(name).map(|entry| format!(" {}", entry.version)).unwrap_or_default();
+
+        // this project's own plugins are downloaded to tell which build of them is installed
+        if plugin_names.iter().any(|plugin| name.trim_end_matches(".disabled") == plugin) {
+            let info = client.get(&format!("{}/{}", plugins_path, name)).ok().and_then(|data| crate::build_info::read_module(&data));
+            if let Some(info) = info {
+                version = format!(" {}", info.to_string().bright_black());
+            }
+        }
+
+        match name.strip_suffix(".disabled") {
+            Some(name) => println!("    {}{} {}", name, version, "(disabled)".bright_black()),
+            None => println!("    {}{}", name, version),
+        }
+    }
+
+    Ok(())
+}
+
+/// List the installed plugins which have a newer release than the one installed by `install-plugin`
+pub fn outdated(dest: Destination, title_id: Option<String>) -> Result<()> {
+    let title_id = resolve_title_id(title_id)?;
+
+    let mut session = Session::new(dest, true);
+    let mut client = session.backend()?;
+
+    let manifest = PluginManifest::load(&mut **client, &title_id);
+    let installed: Vec<String> = client.names(&plugins_dir(&title_id, cargo_info::get_metadata().ok().as_ref())?)?
+        .into_iter()
+        .filter(|name| name.ends_with(".nro"))
+        .collect();
+
+    // plugins from the same release only need checking once
+    let mut latest: HashMap<String, Option<String>> = HashMap::new();
+    let mut outdated = 0;
+
+    for name in &installed {
+        let entry = match manifest.plugins.get(name) {
+            Some(entry) => entry,
+            None => {
+                println!("{} {}", name, "(not installed with install-plugin, can't check)".bright_black());
+                continue;
+            }
+        };
+
+        let newest = latest.entry(entry.repo.clone())
+            .or_insert_with(|| crate::github::latest_release(&entry.repo).ok().map(|release| release.tag_name));
+
+        match newest {
+            Some(newest) if *newest != entry.version => {
+                outdated += 1;
+                println!("{} {} -> {} ({})", name, entry.version, newest.green(), entry.repo);
+            }
+            Some(_) => println!("{} {} {}", name, entry.version, "(up to date)".bright_black()),
+            None => println!("{} {} {}", name, entry.version, "(couldn't check for a newer release)".yellow()),
+        }
+    }
+
+    if outdated > 0 {
+        println!("\n{} {} can be updated using `cargo skyline install-plugin`", outdated, if outdated == 1 { "plugin" } else { "plugins" });
+    }
+
+    Ok(())
+}
+
+/// Show the most recent installs, newest first
+pub fn deployments(limit: usize, title_id: Option<String>) -> Result<()> {
+    let deployments = history::load()?;
+
+    let shown: Vec<_> = deployments.iter()
+        .rev()
+        .filter(|deployment| title_id.as_ref().is_none_or(|title_id| deployment.title_id == *title_id))
+        .take(limit)
+        .collect();
+
+    if shown.is_empty() {
+        println!("No deployments have been recorded");
+        return Ok(());
+    }
+
+    for deployment in shown {
+        println!(
+            "{}  {}  {}{}  {} ({})",
+            crate::timestamps::format_time(deployment.time).bright_black(),
+            deployment.git_hash.as_deref().unwrap_or("no commit").yellow(),
+            deployment.location,
+            deployment.path,
+            &deployment.sha256[..12],
+            deployment.title_id,
+        );
+    }
+
+    Ok(())
+}
+
+/// Swap the installed plugin with the one it replaced, when installed with `--backup`
+pub fn rollback(dest: Destination, title_id: Option<String>, install_path: Option<String>) -> Result<()> {
+    let remote_path = installed_plugin_path(title_id, install_path.as_deref())?;
+    let backup_path = format!("{}.bak", remote_path);
+    let swap_path = format!("{}.swap", remote_path);
+
+    let mut session = Session::new(dest, true);
+    let mut client = session.backend()?;
+
+    if !client.file_exists(&backup_path)? {
+        println!("{}: No previous version of '{}' was kept, install with --backup to keep one", "WARNING".yellow(), remote_path);
+        return Ok(());
+    }
+
+    // swapping rather than overwriting means running rollback again undoes it
+    let installed = client.file_exists(&remote_path)?;
+    if installed {
+        client.rename(&remote_path, &swap_path)?;
+    }
+    client.rename(&backup_path, &remote_path)?;
+    if installed {
+        client.rename(&swap_path, &backup_path)?;
+    }
+
+    println!("{}", format!("Rolled back {}", remote_path).green());
+
+    Ok(())
+}
+
+pub fn uninstall(dest: Destination, title_id: Option<String>, install_path: Option<String>) -> Result<()> {
+    let remote_path = installed_plugin_path(title_id, install_path.as_deref())?;
+
+    let mut session = Session::new(dest, true);
+    let mut client = session.backend()?;
+
+    if !client.file_exists(&remote_path)? {
+        println!("{}: '{}' is not installed", "WARNING".yellow(), remote_path);
+        return Ok(());
+    }
+
+    println!("Removing {}...", remote_path);
+    client.remove(&remote_path)?;
+
+    println!("{}", "Uninstalled!".green());
+
+    Ok(())
+}
+
+/// Use the given title id, falling back on `CARGO_SKYLINE_TITLE_ID` then the one set in Cargo.toml
+fn resolve_title_id(title_id: Option<String>) -> Result<String> {
+    match title_id.or_else(|| settings::env("title_id")) {
+        Some(title_id) => Ok(title_id),
+        None => cargo_info::get_metadata()?.title_id.ok_or(Error::NoTitleId)
+    }
+}
+
+// Plugins that skyline itself provides, which everything else is liable to depend on
+static PROTECTED_PLUGINS: &[&str] = &["libnro_hook.nro"];
+
+/// Every plugin which shouldn't be deleted in bulk, including those listed under
+/// `protected-plugins` in Cargo.toml
+fn protected_plugins(metadata: Option<&cargo_info::Metadata>) -> Vec<String> {
+    PROTECTED_PLUGINS.iter()
+        .map(|name| String::from(*name))
+        .chain(metadata.into_iter().flat_map(|metadata| metadata.protected_plugins.iter().cloned()))
+        .collect()
+}
+
+/// Delete everything in a plugin directory other than the plugins listed in `keep`
+fn clean_plugin_dir(client: &mut dyn Backend, dir: &str, keep: &[String]) -> Result<()> {
+    for name in client.names(dir)? {
+        if keep.contains(&name) {
+            continue;
+        }
+
+        let path = format!("{}/{}", dir, name);
+        if client.remove(&path).is_err() {
+            println!("{}: Could not remove {}", "WARNING".yellow(), path);
+        }
+    }
+
+    Ok(())
+}
 
-fn connect(ip: IpAddr, print: bool) -> Result<FtpClient> {
-    if print {
-        println!("Connecting to ip '{}'...", ip);
-    }
+fn confirm(prompt: &str) -> bool {
+    print!("{} (y/n) ", prompt);
+    let _ = std::io::stdout().lock().flush();
 
-    let mut client = FtpClient::connect(ip)?;
-    client.login("anonymous", "anonymous")?;
+    let mut s = String::new();
+    let _ = std::io::stdin().read_line(&mut s);
 
-    if print {
-        println!("{}", "Connected!".green());
+    s.trim().eq_ignore_ascii_case("y")
+}
+
+pub fn purge(dest: Destination, title_id: Option<String>, keep: Vec<String>, yes: bool) -> Result<()> {
+    let metadata = cargo_info::get_metadata().ok();
+    let plugins_path = plugins_dir(&resolve_title_id(title_id)?, metadata.as_ref())?;
+    let protected = protected_plugins(metadata.as_ref());
+
+    let mut session = Session::new(dest, true);
+    let mut client = session.backend()?;
+
+    let (kept, to_remove): (Vec<String>, Vec<String>) = client.names(&plugins_path)?
+        .into_iter()
+        .partition(|name| protected.contains(name) || keep.contains(name));
+
+    for name in &kept {
+        println!("Keeping {}", name);
     }
 
-    Ok(client)
-}
+    if to_remove.is_empty() {
+        println!("No plugins to remove");
+        return Ok(());
+    }
 
-fn warn_if_old_skyline_subsdk(client: &mut FtpClient, exefs_path: &str) {
+    for name in &to_remove {
+        println!("    {}", name);
+    }
 
-    let list = client.ls(Some(exefs_path)).unwrap();
+    if !yes && !confirm(&format!("\nDelete these {} files from {}?", to_remove.len(), plugins_path)) {
+        return Ok(());
+    }
 
-    let subsdk_count = list.matches("subsdk").count();
-    
-    if subsdk_count > 1 {
-        println!("{}: An old install of skyline is detected, this may cause problems.", "WARNING".yellow());
-        return;
+    for name in &to_remove {
+        let path = format!("{}/{}", plugins_path, name);
+        if client.remove(&path).is_err() {
+            println!("{}: Could not remove {}", "WARNING".yellow(), path);
+        }
     }
-}
 
-fn parse_tid(tid: &str) -> u64 {
-    u64::from_str_radix(tid, 16).expect("Invalid Title ID")
+    println!("{}", "Purged!".green());
+
+    Ok(())
 }
 
-static SKYLINE_URL: &str = "https://github.com/skyline-dev/skyline/releases/download/beta/skyline.zip";
-static TEMPLATE_NPDM: &[u8] = include_bytes!("template.npdm");
+/// Remove every plugin for the game which isn't built by this workspace or one of its dependencies
+pub fn clean_remote(dest: Destination, title_id: Option<String>, yes: bool) -> Result<()> {
+    let metadata = cargo_info::get_metadata()?;
+    let title_id =
+            title_id.or_else(|| metadata.title_id.clone())
+                    .ok_or(Error::NoTitleId)?;
 
-pub fn generate_npdm(tid: &str) -> Vec<u8> {
-    [
-        &TEMPLATE_NPDM[..0x340],
-        &parse_tid(tid).to_le_bytes()[..],
-        &TEMPLATE_NPDM[0x348..]
-    ].concat()
-}
+    let plugins_path = plugins_dir(&title_id, Some(&metadata))?;
+    let protected = protected_plugins(Some(&metadata));
 
-pub fn install(ip: Option<String>, title_id: Option<String>, release: bool, features: Vec<String>, path: Option<String>) -> Result<()> {
-    let mut args = if release {
-        vec![String::from("--release")]
-    } else {
-        vec![]
-    };
+    let mut session = Session::new(dest, true);
+    let mut client = session.backend()?;
 
-    if !features.is_empty() {
-        args.push(format!("--features={}", features.join(",")));
+    let stale: Vec<String> = client.names(&plugins_path)?
+        .into_iter()
+        .filter(|name| name.ends_with(".nro"))
+        .filter(|name| !metadata.plugin_names.contains(name))
+        .filter(|name| !metadata.plugin_dependencies.iter().any(|dep| dep.name == *name))
+        .filter(|name| !protected.contains(name))
+        .collect();
+
+    if stale.is_empty() {
+        println!("No plugins from outside this workspace are installed");
+        return Ok(());
     }
 
-    let (path, is_rom) = if let Some(path) = path.as_ref() {
-        if let Some(local_path) = path.strip_prefix("rom:/") {
-            Ok((local_path, true))
-        } else if let Some(absolute_path) = path.strip_prefix("sd:/") {
-            Ok((absolute_path, false))
-        } else {
-            Err(Error::BadSdPath)
-        }?
-    } else {
-        ("skyline/plugins", true)
-    };
+    for name in &stale {
+        println!("    {}", name);
+    }
+
+    if !yes && !confirm(&format!("\nThese {} plugins aren't built by this workspace, delete them?", stale.len())) {
+        return Ok(());
+    }
 
-    let nro_path = build::build_get_nro(args)?;
+    for name in &stale {
+        let path = format!("{}/{}", plugins_path, name);
+        if client.remove(&path).is_err() {
+            println!("{}: Could not remove {}", "WARNING".yellow(), path);
+        }
+    }
 
-    let ip = verify_ip(get_ip(ip)?)?;
+    println!("{}", "Cleaned!".green());
 
-    let mut client = connect(ip, true)?;
+    Ok(())
+}
 
-    let metadata = cargo_info::get_metadata()?;
+/// Create a directory along with any parents which don't exist yet
+fn mkdir_all(client: &mut dyn Backend, dir: &str) {
+    let mut path = String::new();
+    for part in dir.split('/').filter(|part| !part.is_empty()) {
+        path = format!("{}/{}", path, part);
+        let _ = client.mkdir(&path);
+    }
+}
 
+/// Make the plugins installed on the switch match `[package.metadata.skyline.deploy]`, uploading
+/// anything missing or out of date and removing any other plugins
+pub fn sync(dest: Destination, title_id: Option<String>, dry_run: bool) -> Result<()> {
+    let metadata = cargo_info::get_metadata()?;
     let title_id =
             title_id.or_else(|| metadata.title_id.clone())
                     .ok_or(Error::NoTitleId)?;
 
-    println!("Ensuring directory exists...");
+    let plugins_path = plugins_dir(&title_id, Some(&metadata))?;
+    let deploy = metadata.deploy.as_ref().ok_or(Error::NoDeployManifest)?;
 
-    // this is where subsdk9 goes, it doesn't depend on the path
-    let _ = client.mkdir(&(get_game_path(&title_id) + "/exefs"));
+    let mut session = Session::new(dest, true);
+    let mut client = session.backend()?;
 
-    let dirs: Vec<&str> = path.split('/').filter_map(|x| {
-        if !x.is_empty() && !x.ends_with(".nro") {
-            Some(x)
+    let mut cache = UploadCache::load(&metadata.target_dir.join("skyline-sync"));
+    let mut uploads = vec![];
+    let mut wanted = vec![];
+
+    for file in deploy {
+        let remote_path = if file.path.starts_with('/') {
+            file.path.clone()
+        } else if let Some(absolute_path) = file.path.strip_prefix("sd:/") {
+            format!("/{}", absolute_path)
+        } else {
+            plugin_path(&title_id, &file.path, Some(&metadata))?
+        };
+        wanted.push(remote_path.clone());
+
+        let data = if file.source.starts_with("http://") || file.source.starts_with("https://") {
+            println!("Downloading {}...", file.path);
+            attohttpc::get(&file.source).send()
+                .map_err(|_| Error::DownloadError)?
+                .bytes().map_err(|_| Error::DownloadError)?
         } else {
-            None
+            std::fs::read(&file.source).map_err(|_| Error::MissingDeployFile(PathBuf::from(&file.source)))?
+        };
+
+        let key = format!("{}{}", client.location(), remote_path);
+        let fingerprint = client.fingerprint(&remote_path).unwrap_or(None);
+        if !cache.is_current(&key, &sha256_hex(&data), fingerprint.as_deref()) {
+            println!("{} {}", "Uploading".green(), remote_path);
+            uploads.push((remote_path, data));
         }
-    }).collect();
+    }
 
-    let mut plugin_folder_path = if is_rom { 
-        format!("{}/romfs", get_game_path(&title_id))
-    } else {
-        String::from("")
-    };
+    let extras: Vec<String> = client.names(&plugins_path)?
+        .into_iter()
+        .map(|name| format!("{}/{}", plugins_path, name))
+        .filter(|path| !wanted.contains(path) && !client.is_dir(path).unwrap_or(true))
+        .collect();
 
-    for dir in dirs.into_iter() {
-        plugin_folder_path = format!("{}/{}", plugin_folder_path, dir);
-        let _ = client.mkdir(&plugin_folder_path);
+    for path in &extras {
+        println!("{} {}", "Removing".red(), path);
     }
 
-    warn_if_old_skyline_subsdk(&mut client, &(get_game_path(&title_id) + "/exefs/"));
+    if uploads.is_empty() && extras.is_empty() {
+        println!("{}", "Already in sync".green());
+        return Ok(());
+    }
 
-    // Ensure skyline is installed if it doesn't exist
-    let subsdk_path = get_game_path(&title_id) + "/exefs/subsdk9";
-    if !client.file_exists(&subsdk_path).unwrap_or(false){
-        println!("Skyline subsdk not installed for the given title, downloading...");
-        let exefs = crate::package::get_exefs(SKYLINE_URL)?;
-        println!("Installing over subsdk9...");
-        client.put(&subsdk_path, exefs.subsdk1)?;
+    if dry_run {
+        return Ok(());
     }
 
-    let npdm_path = get_game_path(&title_id) + "/exefs/main.npdm";
-    if !client.file_exists(&npdm_path).unwrap_or(false) {
-        println!("Skyline npdm not installed for the given title, generating and installing...");
-        client.put(&npdm_path, generate_npdm(&title_id))?;
+    for (path, _) in &uploads {
+        if let Some((dir, _)) = path.rsplit_once('/') {
+            mkdir_all(&mut **client, dir);
+        }
     }
 
-    for dep in &metadata.plugin_dependencies {
-        let dep_path = get_plugin_path(&title_id, &dep.name);
-        if !client.file_exists(&dep_path).unwrap_or(false) {
-            println!("Downloading dependency {}...", dep.name);
-            let dep_data =
-                attohttpc::get(&dep.url).send()
-                    .map_err(|_| Error::DownloadError)?
-                    .bytes().map_err(|_| Error::DownloadError)?;
-            println!("Installing dependency {}...", dep.name);
-            client.put(
-                dep_path,
-                &dep_data
-            ).unwrap();
+    transfer(&mut **client, &uploads, &[], false)?;
+
+    for (path, data) in &uploads {
+        if let Ok(Some(fingerprint)) = client.fingerprint(path) {
+            cache.record(format!("{}{}", client.location(), path), sha256_hex(data), fingerprint);
         }
     }
+    let _ = cache.save();
+
+    for path in &extras {
+        client.remove(path)?;
+    }
 
-    let nro_name = if path.ends_with(".nro") {
-        path.split('/').last().unwrap()
+    println!("{}", "Synced!".green());
+
+    Ok(())
+}
+
+/// Stop skyline from loading a plugin by renaming it to `<name>.nro.disabled`, or undo that
+pub fn set_enabled(dest: Destination, title_id: Option<String>, plugin: String, enable: bool) -> Result<()> {
+    let name = if plugin.ends_with(".nro") { plugin } else { format!("{}.nro", plugin) };
+    let enabled_path = plugin_path(&resolve_title_id(title_id)?, &name, cargo_info::get_metadata().ok().as_ref())?;
+    let disabled_path = format!("{}.disabled", enabled_path);
+
+    let (from, to) = if enable {
+        (disabled_path, enabled_path)
     } else {
-        nro_path.file_name().map(|x| x.to_str()).flatten().ok_or(Error::FailWriteNro)?
+        (enabled_path, disabled_path)
     };
 
-    println!("Transferring file...");
-    client.put(
-        format!("{}/{}", plugin_folder_path, nro_name),
-        std::fs::read(nro_path)?
-    )?;
+    let mut session = Session::new(dest, false);
+    let mut client = session.backend()?;
+
+    if !client.file_exists(&from)? {
+        println!("{}: '{}' does not exist", "WARNING".yellow(), from);
+        return Ok(());
+    }
+
+    client.rename(&from, &to)?;
+
+    println!("{} {}", if enable { "Enabled" } else { "Disabled" }, name);
 
     Ok(())
 }
 
-pub fn from_git(git: &str, ip: Option<String>, title_id: Option<String>, release: bool, features: Vec<String>, path: Option<String>) -> Result<()> {
-    let temp_dir = TempGitDir::clone_to_current_dir(git)?;
+/// How `list` should order its entries
+#[derive(Copy, Clone, PartialEq)]
+pub enum SortOrder {
+    Name,
+    Size,
+    Time,
+}
 
-    install(ip, title_id, release, features, path)?;
+impl std::str::FromStr for SortOrder {
+    type Err = String;
 
-    temp_dir.delete();
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(SortOrder::Name),
+            "size" => Ok(SortOrder::Size),
+            "time" => Ok(SortOrder::Time),
+            _ => Err(format!("Unknown sort order '{}', expected 'name', 'size' or 'time'", s))
+        }
+    }
+}
 
-    Ok(())
+/// Flags for getting more out of `list` than the server's own listing
+#[derive(StructOpt, Clone, Default)]
+pub struct ListOptions {
+    #[structopt(short, long, help = "List the contents of subfolders too")]
+    pub recursive: bool,
+
+    #[structopt(short, long, help = "Show file sizes and modification times")]
+    pub long: bool,
+
+    #[structopt(long, help = "Sort by 'name', 'size' or 'time' (newest first)")]
+    pub sort: Option<SortOrder>,
+
+    #[structopt(long, help = "Reverse the sort order")]
+    pub reverse: bool,
+
+    #[structopt(long, help = "Print the listing as JSON, for use by other tools")]
+    pub json: bool,
+
+    #[structopt(long, help = "Mark this workspace's plugins as CURRENT or OUTDATED compared to the latest local build")]
+    pub compare: bool,
+
+    #[structopt(long, help = "Download each plugin to show the version and commit it was built from")]
+    pub build_info: bool,
 }
 
-use std::io::Write;
-use std::time::Duration;
-use std::net::TcpStream;
+impl ListOptions {
+    fn is_plain(&self) -> bool {
+        !self.recursive && !self.long && self.sort.is_none() && !self.reverse && !self.json && !self.compare && !self.build_info
+    }
+}
 
-const RESTART_PLUGIN_PORT: u16 = 45423;
+/// An entry of `list --json`
+#[derive(serde::Serialize)]
+struct ListEntry<'a> {
+    name: &'a str,
+    path: &'a str,
+    is_dir: bool,
+    size: Option<u64>,
+    /// Seconds since the unix epoch
+    modified: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    build_info: Option<&'a crate::build_info::BuildInfo>,
+}
 
-pub fn restart_game(ip: Option<String>, title_id: Option<String>) -> Result<()> {
-    let ip = verify_ip(get_ip(ip)?)?;
+/// Read the build info out of every plugin being listed, which means downloading each of them
+fn remote_build_info(client: &mut dyn Backend, entries: &[(String, FileInfo)]) -> Result<HashMap<String, crate::build_info::BuildInfo>> {
+    let mut build_info = HashMap::new();
 
-    let mut port = TcpStream::connect_timeout(
-        &(ip, RESTART_PLUGIN_PORT).into(),
-        Duration::from_secs(1)
-    )?;
+    for (path, info) in entries {
+        if info.is_dir || !is_plugin_file(path) {
+            continue;
+        }
+
+        if let Some(info) = crate::build_info::read_module(&client.get(path)?) {
+            build_info.insert(path.clone(), info);
+        }
+    }
+
+    Ok(build_info)
+}
+
+/// Whether a file in a plugin directory is a plugin, including disabled ones and backups
+fn is_plugin_file(path: &str) -> bool {
+    path.ends_with(".nro") || path.ends_with(".nro.disabled") || path.ends_with(".nro.bak")
+}
 
+/// Compare each of this workspace's plugins against the latest local build of it by hash
+fn compare_with_local(client: &mut dyn Backend, entries: &[(String, FileInfo)]) -> Result<HashMap<String, &'static str>> {
     let metadata = cargo_info::get_metadata()?;
+    let mut statuses = HashMap::new();
 
-    let title_id =
-            title_id.or_else(|| metadata.title_id.clone())
-                    .ok_or(Error::NoTitleId)?;
+    for (path, _) in entries {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        if !metadata.plugin_names.iter().any(|plugin| plugin == name) {
+            continue;
+        }
 
-    let title_id: u64 = u64::from_str_radix(&title_id, 0x10).unwrap_or(0);
+        let local = match build::latest_built_nro(&metadata, name) {
+            Some(local) => std::fs::read(local)?,
+            None => {
+                statuses.insert(path.clone(), "NOT BUILT");
+                continue;
+            }
+        };
 
-    port.write_all(&title_id.to_be_bytes())?;
+        let current = sha256_hex(&client.get(path)?) == sha256_hex(&local);
+        statuses.insert(path.clone(), if current { "CURRENT" } else { "OUTDATED" });
+    }
 
-    Ok(())
+    Ok(statuses)
 }
 
-pub fn install_and_run(ip: Option<String>, title_id: Option<String>, release: bool, restart: bool, features: Vec<String>, path: Option<String>) -> Result<()> {
-    install(ip.clone(), title_id.clone(), release, features, path)?;
+/// Collect every entry in a directory, along with the contents of subfolders if recursing
+fn walk(client: &mut dyn Backend, dir: &str, recursive: bool, entries: &mut Vec<(String, FileInfo)>) -> Result<()> {
+    for name in client.names(dir)? {
+        let path = format!("{}/{}", dir.trim_end_matches('/'), name);
+        let info = client.stat(&path)?;
+        let is_dir = info.is_dir;
 
-    if restart {
-        let restart_ip = ip.clone();
-        std::thread::spawn(move || {
-            // Give logger some time to spin up
-            std::thread::sleep(std::time::Duration::from_millis(50));
+        entries.push((path.clone(), info));
 
-            restart_game(restart_ip, title_id);
-        });
+        if recursive && is_dir {
+            walk(client, &path, recursive, entries)?;
+        }
     }
-    
-    tcp_listen::listen(ip)
+
+    Ok(())
 }
 
-pub fn list(ip: Option<String>, title_id: Option<String>, path: Option<String>) -> Result<()> {
-    let ip = verify_ip(get_ip(ip)?)?;
+pub fn list(dest: Destination, title_id: Option<String>, path: Option<String>, options: ListOptions) -> Result<()> {
+    let metadata = cargo_info::get_metadata().ok();
+
+    // without a path or title id, list the plugins of every title the project is for
+    let dirs = match (path, title_id) {
+        (Some(path), _) => vec![path],
+        (None, Some(title_id)) => vec![plugins_dir(&title_id, metadata.as_ref())?],
+        (None, None) => match metadata.as_ref().map(|metadata| &metadata.title_ids) {
+            Some(title_ids) if title_ids.len() > 1 => {
+                title_ids.iter().map(|title_id| plugins_dir(title_id, metadata.as_ref())).collect::<Result<_>>()?
+            }
+            _ => vec![plugins_dir(&resolve_title_id(None)?, metadata.as_ref())?]
+        }
+    };
 
-    let mut client = connect(ip, false)?;
+    let mut session = Session::new(dest, false);
+    let mut client = session.backend()?;
 
-    if path.is_some() {
-        println!("{}", 
-            client.ls(
-                Some(&path.unwrap())
-            )?);
-        return Ok(());
-    }
+    // with --json everything is printed at the end as one array
+    let mut json = vec![];
+    let mut json_statuses = HashMap::new();
+    let mut json_build_info = HashMap::new();
 
-    let metadata = cargo_info::get_metadata()?;
+    for (i, dir) in dirs.iter().enumerate() {
+        if dirs.len() > 1 && !options.json {
+            if i > 0 {
+                println!();
+            }
+            println!("{}:", dir.bright_blue());
+        }
 
-    let title_id =
-            title_id.or_else(|| metadata.title_id)
-                    .ok_or(Error::NoTitleId)?;
+        // a project for several games may not be installed for all of them yet
+        if dirs.len() > 1 && !client.is_dir(dir).unwrap_or(false) {
+            if !options.json {
+                println!("{}", "nothing installed".bright_black());
+            }
+            continue;
+        }
+
+        if options.is_plain() {
+            println!("{}", client.ls(dir)?);
+            continue;
+        }
+
+        let mut entries = vec![];
+        walk(&mut **client, dir, options.recursive, &mut entries)?;
+
+        match options.sort.unwrap_or(SortOrder::Name) {
+            SortOrder::Name => entries.sort_by(|(a, _), (b, _)| a.cmp(b)),
+            SortOrder::Size => entries.sort_by_key(|(_, info)| std::cmp::Reverse(info.size)),
+            SortOrder::Time => entries.sort_by_key(|(_, info)| std::cmp::Reverse(info.modified)),
+        }
+
+        if options.reverse {
+            entries.reverse();
+        }
+
+        let statuses = if options.compare {
+            compare_with_local(&mut **client, &entries)?
+        } else {
+            HashMap::new()
+        };
+
+        let build_info = if options.build_info {
+            remote_build_info(&mut **client, &entries)?
+        } else {
+            HashMap::new()
+        };
+
+        if options.json {
+            json.extend(entries);
+            json_statuses.extend(statuses);
+            json_build_info.extend(build_info);
+            continue;
+        }
+
+        for (path, info) in &entries {
+            // paths are shown relative to the directory being listed
+            let name = path.strip_prefix(dir.as_str()).unwrap_or(path).trim_start_matches('/');
+            let mut name = if info.is_dir { format!("{}/", name).bright_blue().to_string() } else { name.to_owned() };
+
+            match statuses.get(path) {
+                Some(&"CURRENT") => name = format!("{} {}", name, "CURRENT".green()),
+                Some(status) => name = format!("{} {}", name, status.yellow()),
+                None => {}
+            }
+
+            if let Some(info) = build_info.get(path) {
+                name = format!("{} {}", name, info.to_string().bright_black());
+            }
+
+            if options.long {
+                let size = info.size.map(|size| size.to_string()).unwrap_or_else(|| String::from("-"));
+                let modified = info.modified.map(crate::timestamps::format_time).unwrap_or_else(|| String::from("-"));
+                println!("{:>10}  {:>23}  {}", size, modified, name);
+            } else {
+                println!("{}", name);
+            }
+        }
+    }
 
-    println!("{}", client.ls(
-        Some(&get_plugins_path(&title_id))
-    )?);
+    if options.json {
+        let entries: Vec<ListEntry> = json.iter()
+            .map(|(path, info)| ListEntry {
+                name: path.rsplit('/').next().unwrap_or(path),
+                path,
+                is_dir: info.is_dir,
+                size: info.size,
+                modified: info.modified,
+                status: json_statuses.get(path).copied(),
+                build_info: json_build_info.get(path),
+            })
+            .collect();
+
+        println!("{}", serde_json::to_string_pretty(&entries).unwrap());
+    }
 
     Ok(())
 }
 
 /* There are really three cases here:
- ** 1. Filename is populated, and starts with '/'. Install path is filename treated as absolute path.
+ ** 1. Filename is populated, and starts with '/' or 'sd:/'. Install path is filename treated as absolute path.
  ** 2. Filename is populated, but is a relative path. Install path is filename treated as relative path to plugin directory.
  ** 3. Filename isn't populated. Install path is current plugin NRO's default install path.
 */
@@ -250,34 +2304,205 @@ fn get_install_path(title_id: Option<String>, filename: Option<String>) -> Resul
         if filename_str.starts_with("/") {
             return Ok(filename_str.to_string());
         }
+        if let Some(absolute_path) = filename_str.strip_prefix("sd:/") {
+            return Ok(format!("/{}", absolute_path));
+        }
     }
 
     let metadata = cargo_info::get_metadata()?;
 
-    let filename = filename.unwrap_or(format!("lib{}.nro", metadata.name));
+    let filename = filename.unwrap_or(format!("lib{}.nro", metadata.name.replace('-', "_")));
 
     let title_id =
-    title_id.or_else(|| metadata.title_id)
+    title_id.or_else(|| metadata.title_id.clone())
             .ok_or(Error::NoTitleId)?;
 
-    Ok(get_plugin_path(&title_id, &filename))
+    plugin_path(&title_id, &filename, Some(&metadata))
 }
 
-pub fn rm(ip: Option<String>, title_id: Option<String>, filename: Option<String>) -> Result<()> {
-    let ip = verify_ip(get_ip(ip)?)?;
+pub fn rm(dest: Destination, title_id: Option<String>, filename: Option<String>) -> Result<()> {
+    let path = get_install_path(title_id, filename)?;
+
+    let mut session = Session::new(dest, false);
+    let mut client = session.backend()?;
+
+    if !client.file_exists(&path)? {
+        println!("{}: '{}' does not exist", "WARNING".yellow(), path);
+        return Ok(());
+    }
+
+    client.remove(&path)?;
+
+    println!("Removed {}", path);
+
+    Ok(())
+}
+
+fn pull_file(client: &mut dyn Backend, remote: &str, local: &Path) -> Result<()> {
+    println!("Pulling {} to {}...", remote, local.display());
+
+    let data = client.get(remote)?;
+    if let Some(parent) = local.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(local, data)?;
 
-    let mut client = connect(ip, false)?;
+    Ok(())
+}
+
+fn pull_dir(client: &mut dyn Backend, remote: &str, local: &Path) -> Result<()> {
+    std::fs::create_dir_all(local)?;
+
+    for name in client.names(remote)? {
+        let remote_path = format!("{}/{}", remote.trim_end_matches('/'), name);
+        let local_path = local.join(&name);
+
+        if client.is_dir(&remote_path)? {
+            pull_dir(client, &remote_path, &local_path)?;
+        } else {
+            pull_file(client, &remote_path, &local_path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Save every plugin installed for a game into a local archive
+pub fn backup(dest: Destination, title_id: Option<String>, out: Option<PathBuf>) -> Result<()> {
+    let title_id = resolve_title_id(title_id)?;
+
+    let out = out.unwrap_or_else(|| {
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or(0);
+
+        PathBuf::from(format!("{}-plugins-{}.tar.gz", title_id, time))
+    });
+
+    let mut session = Session::new(dest, true);
+    let mut client = session.backend()?;
+
+    let count = backup::backup(&mut **client, &plugins_dir(&title_id, cargo_info::get_metadata().ok().as_ref())?, &out)?;
+
+    println!("{}", format!("Backed up {} files to {}", count, out.display()).green());
+
+    Ok(())
+}
+
+/// Upload the plugins from an archive made by `backup`
+pub fn restore(dest: Destination, title_id: Option<String>, archive: PathBuf) -> Result<()> {
+    let plugins_path = plugins_dir(&resolve_title_id(title_id)?, cargo_info::get_metadata().ok().as_ref())?;
+
+    let mut session = Session::new(dest, true);
+    let mut client = session.backend()?;
+
+    let _ = client.mkdir(&plugins_path);
+    let count = backup::restore(&mut **client, &plugins_path, &archive)?;
+
+    println!("{}", format!("Restored {} files", count).green());
+
+    Ok(())
+}
+
+/// Browse the switch interactively, starting in the plugin directory for the given game
+pub fn shell(dest: Destination, title_id: Option<String>) -> Result<()> {
+    let root = plugins_dir(&resolve_title_id(title_id)?, cargo_info::get_metadata().ok().as_ref())?;
+
+    let mut session = Session::new(dest, true);
+    let mut client = session.backend()?;
+
+    shell::run(&mut **client, root)
+}
+
+/// Download a file or directory from the switch, relative to the plugin directory unless absolute
+pub fn pull(dest: Destination, title_id: Option<String>, remote: String, local: Option<PathBuf>) -> Result<()> {
+    let remote = get_install_path(title_id, Some(remote))?;
+    let name = remote.trim_end_matches('/').rsplit('/').next().unwrap_or_default().to_owned();
 
-    client.rm(get_install_path(title_id, filename)?)?;
+    let local = match local {
+        Some(local) if local.is_dir() => local.join(&name),
+        Some(local) => local,
+        None => PathBuf::from(&name),
+    };
+
+    let mut session = Session::new(dest, false);
+    let mut client = session.backend()?;
+
+    if client.is_dir(&remote)? {
+        pull_dir(&mut **client, &remote, &local)?;
+    } else if client.file_exists(&remote)? {
+        pull_file(&mut **client, &remote, &local)?;
+    } else {
+        println!("{}: '{}' does not exist", "WARNING".yellow(), remote);
+        return Ok(());
+    }
+
+    println!("{}", "Done!".green());
 
     Ok(())
 }
 
+/// Where crash reports end up: Atmosphère's creport writes one for every crash, and Skyline keeps
+/// its own logs of the crashes it catches
+pub static CRASH_REPORT_DIRS: &[&str] = &["/atmosphere/crash_reports", "/skyline/crash_reports"];
+
+/// Download the newest crash report, or the one named, giving back its path and contents. With
+/// `list` the reports are only listed, newest first.
+pub fn get_crash_report(dest: Destination, name: Option<&str>, list: bool) -> Result<Option<(String, Vec<u8>)>> {
+    let mut session = Session::new(dest, false);
+    let mut client = session.backend()?;
+
+    let mut reports = vec![];
+    for dir in CRASH_REPORT_DIRS {
+        if !client.is_dir(dir).unwrap_or(false) {
+            continue;
+        }
+
+        for file in client.names(dir)? {
+            let file = file.rsplit('/').next().unwrap_or(&file).to_owned();
+            if !(file.ends_with(".log") || file.ends_with(".txt")) {
+                continue;
+            }
+
+            let path = format!("{}/{}", dir, file);
+            let modified = client.stat(&path).ok().and_then(|info| info.modified);
+            reports.push((modified, file, path));
+        }
+    }
+
+    // creport names reports after when they happened, which keeps them in order without a time
+    reports.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| b.1.cmp(&a.1)));
+
+    if list {
+        if reports.is_empty() {
+            println!("No crash reports found");
+        }
+        for (modified, _, path) in &reports {
+            let time = modified.map(crate::timestamps::format_time).unwrap_or_default();
+            println!("{:<24} {}", time, path);
+        }
+        return Ok(None);
+    }
+
+    let report = match name {
+        Some(name) => reports.iter().find(|(_, file, path)| file == name || path.trim_start_matches('/') == name.trim_start_matches('/')),
+        None => reports.first(),
+    };
+
+    match report {
+        Some((_, _, path)) => Ok(Some((path.clone(), client.get(path)?))),
+        None => Err(Error::NoCrashReport(name.map(String::from))),
+    }
+}
+
 // for now, we assume src is local and dest is Switch
 pub fn cp(ip: Option<String>, title_id: Option<String>, src: String, dest: String) -> Result<()> {
     let ip = verify_ip(get_ip(ip)?)?;
 
-    let mut client = connect(ip, false)?;
+    let options = ftp_options(&Destination::default())?;
+    let mut client = connect(ip, &options, false)?;
+    client.set_show_progress(true);
 
     // TODO: remove once two-way CP is supported
     if dest.starts_with("/") {
@@ -298,10 +2523,28 @@ pub fn cp(ip: Option<String>, title_id: Option<String>, src: String, dest: Strin
     }
 
     println!("Transferring file to {}...", install_path);
-    client.put(
-        install_path,
-        std::fs::read(src_path.to_str().unwrap().to_string())?
-    )?;
+    let file = std::fs::read(src_path.to_str().unwrap().to_string())?;
+    client.with_retry(|client| client.put(&install_path, &file))?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_staged_versions() {
+        let staged = staging_path("/plugins/libfoo.nro", b"foo");
+        assert!(is_staging_of(staged.rsplit('/').next().unwrap(), "libfoo.nro"));
+
+        assert!(is_staging_of("libfoo.nro.0123456789abcdef.tmp", "libfoo.nro"));
+        assert!(is_staging_of("libfoo.nro.tmp", "libfoo.nro"));
+
+        assert!(!is_staging_of("libfoo.nro", "libfoo.nro"));
+        assert!(!is_staging_of("libfoo.nro.bak", "libfoo.nro"));
+        assert!(!is_staging_of("libfoo.nro.notahexhash12345.tmp", "libfoo.nro"));
+        assert!(!is_staging_of("libfoobar.nro.0123456789abcdef.tmp", "libfoo.nro"));
+        assert!(!is_staging_of("libbar.nro.0123456789abcdef.tmp", "libfoo.nro"));
+    }
+}
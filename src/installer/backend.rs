@@ -0,0 +1,374 @@
+use std::str::FromStr;
+use std::path::PathBuf;
+use std::time::Duration;
+use structopt::StructOpt;
+use crate::emulator::Emulator;
+use crate::error::Result;
+use crate::hash::{crc32, sha256_hex};
+use crate::ftp::{ByteRate, DataMode, FtpClient, FtpError, SftpClient};
+use crate::timeouts::TimeoutArgs;
+#[cfg(feature = "usb")]
+use crate::usb::UsbClient;
+
+/// The transport used to get files onto the switch
+#[derive(Copy, Clone)]
+pub enum Transport {
+    Ftp,
+    Usb,
+    Sftp,
+}
+
+impl FromStr for Transport {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "ftp" => Ok(Transport::Ftp),
+            "usb" => Ok(Transport::Usb),
+            "sftp" => Ok(Transport::Sftp),
+            _ => Err(format!("Unknown transport '{}', expected 'ftp', 'usb' or 'sftp'", s))
+        }
+    }
+}
+
+/// Where an install should end up, shared between every command that installs
+#[derive(StructOpt, Clone, Default)]
+pub struct Destination {
+    #[structopt(short, long, help = "IP address or hostname of the switch")]
+    pub ip: Option<String>,
+
+    #[structopt(long, number_of_values = 1, help = "Name of a device saved with `set-ip --name` to install to, can be repeated")]
+    pub device: Vec<String>,
+
+    #[structopt(long, help = "Install to every device saved with `set-ip --name`")]
+    pub all_devices: bool,
+
+    #[structopt(long, help = "Port of the FTP (or SFTP) server on the switch")]
+    pub port: Option<u16>,
+
+    #[structopt(long, help = "Username to log in to the switch with")]
+    pub user: Option<String>,
+
+    #[structopt(long, help = "Password to log in to the switch with")]
+    pub pass: Option<String>,
+
+    #[structopt(
+        long,
+        help = "How FTP data connections are opened: 'passive' (default), 'epsv', 'active' or 'eprt'"
+    )]
+    pub ftp_mode: Option<DataMode>,
+
+    #[structopt(long, help = "How many times to attempt FTP operations before giving up")]
+    pub retries: Option<u32>,
+
+    #[structopt(long, help = "Cap FTP upload speed in bytes per second, e.g. '500K' or '2M'")]
+    pub limit_rate: Option<ByteRate>,
+
+    #[structopt(
+        long,
+        help = "Transport used to talk to the switch: 'ftp' (default), 'usb' or 'sftp'"
+    )]
+    pub transport: Option<Transport>,
+
+    #[structopt(
+        long,
+        help = "Use the mod directory of an emulator instead of a switch: 'ryujinx', 'yuzu' or 'suyu'"
+    )]
+    pub emulator: Option<Emulator>,
+
+    #[structopt(
+        long, parse(from_str = crate::wsl::translate_path),
+        help = "Use an SD card mounted at the given path instead of a switch"
+    )]
+    pub sd_path: Option<PathBuf>,
+
+    #[structopt(short, long, help = "Don't show progress while transferring files")]
+    pub quiet: bool,
+
+    #[structopt(long, help = "Keep trying until the switch can be reached instead of failing")]
+    pub wait: bool,
+
+    #[structopt(flatten)]
+    pub timeouts: TimeoutArgs,
+}
+
+/// What's known about a file on the switch, which depends on what the backend can tell
+pub struct FileInfo {
+    pub is_dir: bool,
+    pub size: Option<u64>,
+    /// Seconds since the unix epoch
+    pub modified: Option<u64>,
+}
+
+/// The set of operations the installer needs from wherever it is installing to
+pub trait Backend: Send {
+    fn mkdir(&mut self, dir: &str) -> Result<()>;
+    fn put(&mut self, path: &str, file: &[u8]) -> Result<()>;
+    fn file_exists(&mut self, path: &str) -> Result<bool>;
+    fn ls(&mut self, dir: &str) -> Result<String>;
+
+    /// List just the names of the files in a directory
+    fn names(&mut self, dir: &str) -> Result<Vec<String>> {
+        Ok(self.ls(dir)?.lines().map(str::trim).filter(|name| !name.is_empty()).map(String::from).collect())
+    }
+
+    /// Move a file into place, replacing anything already there
+    fn rename(&mut self, from: &str, to: &str) -> Result<()>;
+
+    fn remove(&mut self, path: &str) -> Result<()>;
+
+    fn get(&mut self, path: &str) -> Result<Vec<u8>>;
+    fn is_dir(&mut self, path: &str) -> Result<bool>;
+
+    fn stat(&mut self, path: &str) -> Result<FileInfo> {
+        Ok(FileInfo { is_dir: self.is_dir(path)?, size: None, modified: None })
+    }
+
+    /// Upload a set of files, which backends can override to do concurrently
+    fn put_all(&mut self, files: &[(String, Vec<u8>)]) -> Result<()> {
+        for (path, file) in files {
+            self.put(path, file)?;
+        }
+
+        Ok(())
+    }
+
+    /// Upload a set of files to paths which are unique to their contents, so anything already at
+    /// one is the start of an interrupted upload that backends can carry on from
+    fn resume_all(&mut self, files: &[(String, Vec<u8>)]) -> Result<()> {
+        self.put_all(files)
+    }
+
+    /// Check that the file at the given path matches what was uploaded, or `None` if the
+    /// backend has no way of checking
+    fn verify(&mut self, _path: &str, _file: &[u8]) -> Result<Option<bool>> {
+        Ok(None)
+    }
+
+    /// Get how many bytes are free in the given directory, or `None` if there's no way to tell
+    fn free_space(&mut self, _dir: &str) -> Result<Option<u64>> {
+        Ok(None)
+    }
+
+    /// How often `keep_alive` needs calling while the connection is otherwise idle, if at all
+    fn keepalive_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    fn keep_alive(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// A human readable description of where this backend installs to
+    fn location(&self) -> String;
+
+    /// Get a value which changes whenever the given file does, if the backend can provide one
+    fn fingerprint(&mut self, _path: &str) -> Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+impl Backend for FtpClient {
+    fn mkdir(&mut self, dir: &str) -> Result<()> {
+        Ok(self.with_retry(|client| client.mkdir(dir))?)
+    }
+
+    fn put(&mut self, path: &str, file: &[u8]) -> Result<()> {
+        Ok(self.with_retry(|client| client.put(path, file))?)
+    }
+
+    fn file_exists(&mut self, path: &str) -> Result<bool> {
+        Ok(self.with_retry(|client| client.file_exists(path))?)
+    }
+
+    fn ls(&mut self, dir: &str) -> Result<String> {
+        Ok(self.with_retry(|client| client.ls(Some(dir)))?)
+    }
+
+    fn names(&mut self, dir: &str) -> Result<Vec<String>> {
+        Ok(self.with_retry(|client| client.names(dir))?)
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        Ok(self.with_retry(|client| client.rename(from, to))?)
+    }
+
+    fn remove(&mut self, path: &str) -> Result<()> {
+        Ok(self.with_retry(|client| client.delete(path))?)
+    }
+
+    fn get(&mut self, path: &str) -> Result<Vec<u8>> {
+        Ok(self.with_retry(|client| client.get(path))?)
+    }
+
+    fn is_dir(&mut self, path: &str) -> Result<bool> {
+        Ok(self.with_retry(|client| client.is_dir(path))?)
+    }
+
+    fn stat(&mut self, path: &str) -> Result<FileInfo> {
+        if self.with_retry(|client| client.is_dir(path))? {
+            return Ok(FileInfo { is_dir: true, size: None, modified: None });
+        }
+
+        // SIZE and MDTM are both extensions, so just go without if they aren't supported
+        Ok(FileInfo {
+            is_dir: false,
+            size: self.size(path).ok(),
+            modified: self.modified_time(path).ok().and_then(|time| crate::timestamps::parse_mdtm(&time)),
+        })
+    }
+
+    fn put_all(&mut self, files: &[(String, Vec<u8>)]) -> Result<()> {
+        // retried a file at a time, so a dropped connection doesn't send everything again
+        Ok(FtpClient::put_all(self, files)?)
+    }
+
+    fn resume_all(&mut self, files: &[(String, Vec<u8>)]) -> Result<()> {
+        Ok(FtpClient::resume_all(self, files)?)
+    }
+
+    fn verify(&mut self, path: &str, file: &[u8]) -> Result<Option<bool>> {
+        if self.size(path)? != file.len() as u64 {
+            return Ok(Some(false));
+        }
+
+        // XCRC is an extension, so fall back to downloading the whole file to compare
+        match self.crc(path) {
+            Ok(crc) => Ok(Some(crc == crc32(file))),
+            Err(FtpError::UnexpectedStatus(_)) | Err(FtpError::ParseFail) => {
+                let remote = self.get(path)?;
+                Ok(Some(sha256_hex(&remote) == sha256_hex(file)))
+            }
+            Err(err) => Err(err.into())
+        }
+    }
+
+    fn free_space(&mut self, dir: &str) -> Result<Option<u64>> {
+        match self.available(dir) {
+            Ok(bytes) => Ok(Some(bytes)),
+            // AVBL isn't widely supported, running out of space shows up once uploading instead
+            Err(FtpError::UnexpectedStatus(_)) | Err(FtpError::ParseFail) => Ok(None),
+            Err(err) => Err(err.into())
+        }
+    }
+
+    fn keepalive_interval(&self) -> Option<Duration> {
+        self.keepalive()
+    }
+
+    fn keep_alive(&mut self) -> Result<()> {
+        Ok(self.with_retry(FtpClient::keep_alive)?)
+    }
+
+    fn location(&self) -> String {
+        format!("ftp://{}", self.addr())
+    }
+
+    fn fingerprint(&mut self, path: &str) -> Result<Option<String>> {
+        let size = match self.size(path) {
+            Ok(size) => size,
+            Err(_) => return Ok(None)
+        };
+
+        // not every server supports MDTM, fall back to just the size
+        Ok(Some(match self.modified_time(path) {
+            Ok(time) => format!("{}@{}", size, time),
+            Err(_) => size.to_string(),
+        }))
+    }
+}
+
+impl Backend for SftpClient {
+    fn mkdir(&mut self, dir: &str) -> Result<()> {
+        Ok(SftpClient::mkdir(self, dir)?)
+    }
+
+    fn put(&mut self, path: &str, file: &[u8]) -> Result<()> {
+        Ok(SftpClient::put(self, path, file)?)
+    }
+
+    fn file_exists(&mut self, path: &str) -> Result<bool> {
+        Ok(SftpClient::file_exists(self, path)?)
+    }
+
+    fn ls(&mut self, dir: &str) -> Result<String> {
+        Ok(SftpClient::ls(self, dir)?)
+    }
+
+    fn names(&mut self, dir: &str) -> Result<Vec<String>> {
+        Ok(SftpClient::names(self, dir)?)
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        Ok(SftpClient::rename(self, from, to)?)
+    }
+
+    fn remove(&mut self, path: &str) -> Result<()> {
+        Ok(SftpClient::remove(self, path)?)
+    }
+
+    fn get(&mut self, path: &str) -> Result<Vec<u8>> {
+        Ok(SftpClient::get(self, path)?)
+    }
+
+    fn is_dir(&mut self, path: &str) -> Result<bool> {
+        Ok(SftpClient::is_dir(self, path)?)
+    }
+
+    fn verify(&mut self, path: &str, file: &[u8]) -> Result<Option<bool>> {
+        let remote = self.get(path)?;
+
+        Ok(Some(sha256_hex(&remote) == sha256_hex(file)))
+    }
+
+    fn location(&self) -> String {
+        format!("sftp://{}", self.host())
+    }
+}
+
+#[cfg(feature = "usb")]
+impl Backend for UsbClient {
+    fn mkdir(&mut self, dir: &str) -> Result<()> {
+        Ok(UsbClient::mkdir(self, dir)?)
+    }
+
+    fn put(&mut self, path: &str, file: &[u8]) -> Result<()> {
+        Ok(UsbClient::put(self, path, file)?)
+    }
+
+    fn file_exists(&mut self, path: &str) -> Result<bool> {
+        Ok(UsbClient::file_exists(self, path)?)
+    }
+
+    fn ls(&mut self, dir: &str) -> Result<String> {
+        Ok(UsbClient::ls(self, dir)?)
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        Ok(UsbClient::rename(self, from, to)?)
+    }
+
+    fn remove(&mut self, path: &str) -> Result<()> {
+        Ok(UsbClient::remove(self, path)?)
+    }
+
+    fn get(&mut self, path: &str) -> Result<Vec<u8>> {
+        Ok(UsbClient::get(self, path)?)
+    }
+
+    fn is_dir(&mut self, path: &str) -> Result<bool> {
+        Ok(UsbClient::is_dir(self, path)?)
+    }
+
+    fn stat(&mut self, path: &str) -> Result<FileInfo> {
+        Ok(UsbClient::stat(self, path)?)
+    }
+
+    fn free_space(&mut self, _dir: &str) -> Result<Option<u64>> {
+        Ok(Some(UsbClient::free_space(self)?))
+    }
+
+    fn location(&self) -> String {
+        String::from("usb")
+    }
+}
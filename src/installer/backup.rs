@@ -0,0 +1,85 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use crate::error::Result;
+use super::Backend;
+
+/// Every file under a remote directory, relative to it
+fn remote_files(client: &mut dyn Backend, root: &str, dir: &str) -> Result<Vec<String>> {
+    let mut files = vec![];
+
+    for name in client.names(&format!("{}/{}", root, dir))? {
+        let path = if dir.is_empty() { name } else { format!("{}/{}", dir, name) };
+
+        if client.is_dir(&format!("{}/{}", root, path))? {
+            files.append(&mut remote_files(client, root, &path)?);
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(files)
+}
+
+/// Archive everything under `root` on the switch into a gzipped tarball, returning how many
+/// files were saved
+pub fn backup(client: &mut dyn Backend, root: &str, out: &Path) -> Result<usize> {
+    let files = remote_files(client, root, "")?;
+
+    let mut archive = tar::Builder::new(GzEncoder::new(File::create(out)?, Compression::default()));
+
+    for path in &files {
+        println!("Backing up {}...", path);
+        let data = client.get(&format!("{}/{}", root, path))?;
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|time| time.as_secs())
+                .unwrap_or(0)
+        );
+        archive.append_data(&mut header, path, &data[..])?;
+    }
+
+    archive.into_inner()?.finish()?;
+
+    Ok(files.len())
+}
+
+/// Upload every file in a tarball made by `backup` back under `root`, returning how many files
+/// were restored
+pub fn restore(client: &mut dyn Backend, root: &str, archive: &Path) -> Result<usize> {
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(archive)?));
+    let mut count = 0;
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let path = entry.path()?.to_string_lossy().replace('\\', "/");
+
+        // make sure every directory leading up to the file exists
+        let mut dir = root.to_owned();
+        for part in path.split('/').rev().skip(1).collect::<Vec<_>>().into_iter().rev() {
+            dir = format!("{}/{}", dir, part);
+            let _ = client.mkdir(&dir);
+        }
+
+        let mut data = vec![];
+        entry.read_to_end(&mut data)?;
+
+        println!("Restoring {}...", path);
+        client.put(&format!("{}/{}", root, path), &data)?;
+        count += 1;
+    }
+
+    Ok(count)
+}
@@ -0,0 +1,33 @@
+use std::path::PathBuf;
+use crate::cargo_info::Dependency;
+use crate::error::{Error, Result};
+use crate::hash::sha256_hex;
+use crate::settings;
+
+/// Where downloaded dependencies are kept, shared between every project
+fn cache_dir() -> Result<PathBuf> {
+    Ok(settings::switch_home_dir()?.join("dependencies"))
+}
+
+/// Get the contents of a binary plugin dependency, only downloading it if it isn't cached
+pub fn fetch(dep: &Dependency) -> Result<Vec<u8>> {
+    // the url is part of the name so that changing it in Cargo.toml downloads the new file
+    let path = cache_dir()?.join(format!("{}-{}", &sha256_hex(dep.url.as_bytes())[..16], dep.name));
+
+    if let Ok(data) = std::fs::read(&path) {
+        return Ok(data);
+    }
+
+    println!("Downloading dependency {}...", dep.name);
+    let data =
+        attohttpc::get(&dep.url).send()
+            .map_err(|_| Error::DownloadError)?
+            .bytes().map_err(|_| Error::DownloadError)?;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, &data)?;
+
+    Ok(data)
+}
@@ -0,0 +1,74 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::error::Result;
+use crate::settings;
+
+/// A plugin which was installed somewhere, appended to `$HOME/.switch/deployments.jsonl`
+#[derive(Serialize, Deserialize)]
+pub struct Deployment {
+    /// Seconds since the unix epoch
+    pub time: u64,
+    pub git_hash: Option<String>,
+    pub location: String,
+    pub title_id: String,
+    pub path: String,
+    pub sha256: String,
+}
+
+fn history_path() -> Result<PathBuf> {
+    Ok(settings::switch_home_dir()?.join("deployments.jsonl"))
+}
+
+/// The commit the workspace is at, marked as dirty if there are uncommitted changes
+pub fn git_hash(workspace_root: &Path) -> Option<String> {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .current_dir(workspace_root)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+    };
+
+    let hash = git(&["rev-parse", "--short", "HEAD"])?;
+
+    match git(&["status", "--porcelain"]) {
+        Some(status) if !status.is_empty() => Some(format!("{}-dirty", hash)),
+        _ => Some(hash)
+    }
+}
+
+impl Deployment {
+    pub fn new(git_hash: Option<String>, location: String, title_id: String, path: String, sha256: String) -> Self {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+
+        Deployment { time, git_hash, location, title_id, path, sha256 }
+    }
+}
+
+pub fn record(deployments: &[Deployment]) -> Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(history_path()?)?;
+
+    for deployment in deployments {
+        writeln!(file, "{}", serde_json::to_string(deployment).unwrap())?;
+    }
+
+    Ok(())
+}
+
+/// Every deployment recorded so far, oldest first
+pub fn load() -> Result<Vec<Deployment>> {
+    let text = match std::fs::read_to_string(history_path()?) {
+        Ok(text) => text,
+        Err(_) => return Ok(vec![])
+    };
+
+    Ok(text.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+}
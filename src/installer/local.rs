@@ -0,0 +1,171 @@
+use std::fs;
+use std::path::PathBuf;
+use crate::error::Result;
+use std::time::UNIX_EPOCH;
+use super::{Backend, FileInfo};
+
+/// How paths on the switch map onto the local directory
+pub enum Layout {
+    /// The directory is the root of an SD card
+    SdCard,
+
+    /// The directory is a yuzu-style `load` folder, where the contents of
+    /// `atmosphere/contents/<title id>` live in `<title id>/<mod name>`
+    LoadDir { mod_name: String },
+}
+
+/// A backend which installs to a directory on this computer
+pub struct LocalBackend {
+    root: PathBuf,
+    layout: Layout,
+}
+
+impl LocalBackend {
+    pub fn new(root: PathBuf, layout: Layout) -> Self {
+        LocalBackend { root, layout }
+    }
+
+    fn resolve(&self, path: &str) -> PathBuf {
+        let path = path.trim_start_matches('/');
+
+        match &self.layout {
+            Layout::SdCard => self.root.join(path),
+            Layout::LoadDir { mod_name } => {
+                let contents_path = path.strip_prefix("atmosphere/contents/").unwrap_or(path);
+                let mut parts = contents_path.splitn(2, '/');
+                let title_id = parts.next().unwrap_or_default();
+
+                let mod_dir = self.root.join(title_id).join(mod_name);
+                match parts.next() {
+                    Some(rest) => mod_dir.join(rest),
+                    None => mod_dir,
+                }
+            }
+        }
+    }
+}
+
+impl Backend for LocalBackend {
+    fn mkdir(&mut self, dir: &str) -> Result<()> {
+        Ok(fs::create_dir_all(self.resolve(dir))?)
+    }
+
+    fn put(&mut self, path: &str, file: &[u8]) -> Result<()> {
+        let path = self.resolve(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        Ok(fs::write(path, file)?)
+    }
+
+    fn file_exists(&mut self, path: &str) -> Result<bool> {
+        Ok(self.resolve(path).exists())
+    }
+
+    fn ls(&mut self, dir: &str) -> Result<String> {
+        let mut names = fs::read_dir(self.resolve(dir))?
+            .filter_map(|entry| Some(entry.ok()?.file_name().to_string_lossy().into_owned()))
+            .collect::<Vec<_>>();
+        names.sort();
+
+        Ok(names.join("\n"))
+    }
+
+    fn stat(&mut self, path: &str) -> Result<FileInfo> {
+        let metadata = fs::metadata(self.resolve(path))?;
+
+        Ok(FileInfo {
+            is_dir: metadata.is_dir(),
+            size: Some(metadata.len()).filter(|_| metadata.is_file()),
+            modified: metadata.modified().ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|time| time.as_secs()),
+        })
+    }
+
+    fn verify(&mut self, path: &str, file: &[u8]) -> Result<Option<bool>> {
+        Ok(Some(fs::read(self.resolve(path))? == file))
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        Ok(fs::rename(self.resolve(from), self.resolve(to))?)
+    }
+
+    fn free_space(&mut self, dir: &str) -> Result<Option<u64>> {
+        // the directory may not have been made yet, such as a load folder's mod directory before the
+        // first install, so ask about the nearest one which does exist
+        let dir = self.resolve(dir);
+        let existing = dir.ancestors().find(|dir| dir.is_dir()).unwrap_or(&self.root);
+
+        Ok(Some(fs2::available_space(existing)?))
+    }
+
+    fn remove(&mut self, path: &str) -> Result<()> {
+        Ok(fs::remove_file(self.resolve(path))?)
+    }
+
+    fn get(&mut self, path: &str) -> Result<Vec<u8>> {
+        Ok(fs::read(self.resolve(path))?)
+    }
+
+    fn is_dir(&mut self, path: &str) -> Result<bool> {
+        Ok(self.resolve(path).is_dir())
+    }
+
+    fn location(&self) -> String {
+        self.root.display().to_string()
+    }
+
+    fn fingerprint(&mut self, path: &str) -> Result<Option<String>> {
+        let metadata = match fs::metadata(self.resolve(path)) {
+            Ok(metadata) => metadata,
+            Err(_) => return Ok(None)
+        };
+
+        let modified = metadata.modified()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|time| time.as_nanos())
+            .unwrap_or(0);
+
+        Ok(Some(format!("{}@{}", metadata.len(), modified)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cargo-skyline-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn install_into_empty_load_dir() {
+        let root = empty_dir("load-dir");
+        let mut backend = LocalBackend::new(root.clone(), Layout::LoadDir { mod_name: String::from("plugin") });
+
+        let plugin = "/atmosphere/contents/01006A800016E000/romfs/skyline/plugins/libplugin.nro";
+        super::super::transfer(&mut backend, &[(plugin.to_owned(), b"NRO".to_vec())], &[], true).unwrap();
+
+        let installed = root.join("01006A800016E000/plugin/romfs/skyline/plugins/libplugin.nro");
+        assert_eq!(fs::read(installed).unwrap(), b"NRO");
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn free_space_of_missing_dir() {
+        let root = empty_dir("free-space");
+        let mut backend = LocalBackend::new(root.clone(), Layout::LoadDir { mod_name: String::from("plugin") });
+
+        assert!(backend.free_space("/").unwrap().is_some());
+        assert!(backend.free_space("/atmosphere/contents/01006A800016E000/exefs").unwrap().is_some());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
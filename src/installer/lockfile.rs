@@ -0,0 +1,87 @@
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::cargo_info::Dependency;
+use crate::error::{Error, Result};
+
+const HEADER: &str = "# This file is generated by cargo-skyline, check it in alongside Cargo.toml\n\n";
+
+/// The resolved binary dependencies of a project, stored as `skyline.lock` in the workspace
+/// root so every install uses exactly the same files
+#[derive(Serialize, Deserialize, Default)]
+pub struct Lockfile {
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    changed: bool,
+    #[serde(default, rename = "dependency")]
+    dependencies: Vec<LockedDependency>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LockedDependency {
+    name: String,
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    sha256: String,
+}
+
+impl Lockfile {
+    pub fn load(workspace_root: &Path) -> Result<Self> {
+        let path = workspace_root.join("skyline.lock");
+
+        let mut lockfile: Self = match std::fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text).map_err(|_| Error::BadLockfile(path.clone()))?,
+            Err(_) => Self::default(),
+        };
+
+        lockfile.path = path;
+
+        Ok(lockfile)
+    }
+
+    /// Check a downloaded dependency against the hash it was locked to, locking it if this is
+    /// the first time it has been seen or its url or version has changed
+    pub fn check(&mut self, dep: &Dependency, hash: &str) -> Result<()> {
+        let locked = self.dependencies.iter()
+            .find(|locked| locked.name == dep.name && locked.url == dep.url && locked.version == dep.version);
+
+        match locked {
+            Some(locked) if locked.sha256 == hash => Ok(()),
+            Some(_) => Err(Error::LockedHashMismatch(dep.name.clone())),
+            None => {
+                self.dependencies.retain(|locked| locked.name != dep.name);
+                self.dependencies.push(LockedDependency {
+                    name: dep.name.clone(),
+                    url: dep.url.clone(),
+                    version: dep.version.clone(),
+                    sha256: hash.to_owned(),
+                });
+                self.changed = true;
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Drop any dependencies which are no longer part of the project
+    pub fn retain(&mut self, deps: &[Dependency]) {
+        let count = self.dependencies.len();
+        self.dependencies.retain(|locked| deps.iter().any(|dep| dep.name == locked.name));
+        self.changed |= self.dependencies.len() != count;
+    }
+
+    pub fn save(&mut self) -> Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+
+        self.dependencies.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let text = toml::to_string(self).unwrap();
+        std::fs::write(&self.path, format!("{}{}", HEADER, text))?;
+        self.changed = false;
+
+        Ok(())
+    }
+}
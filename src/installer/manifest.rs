@@ -0,0 +1,52 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::error::Result;
+use crate::game_paths::get_game_path;
+use super::Backend;
+
+/// A record of which plugins were installed from GitHub releases, kept on the SD card next to
+/// the game's `exefs` and `romfs` folders so it follows the console rather than the computer
+#[derive(Serialize, Deserialize, Default)]
+pub struct PluginManifest {
+    pub plugins: BTreeMap<String, ManifestEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skyline: Option<SkylineEntry>,
+}
+
+/// Which build of Skyline was installed by `install-skyline` or `update-skyline`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SkylineEntry {
+    pub release: String,
+    pub sha256: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ManifestEntry {
+    /// The GitHub repository the plugin came from, as `owner/repo`
+    pub repo: String,
+    /// The tag of the release which was installed
+    pub version: String,
+}
+
+fn path(title_id: &str) -> String {
+    format!("{}/cargo-skyline.json", get_game_path(title_id))
+}
+
+impl PluginManifest {
+    pub fn load(client: &mut dyn Backend, title_id: &str) -> Self {
+        let path = path(title_id);
+
+        if !client.file_exists(&path).unwrap_or(false) {
+            return Self::default();
+        }
+
+        client.get(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, client: &mut dyn Backend, title_id: &str) -> Result<()> {
+        client.put(&path(title_id), &serde_json::to_vec_pretty(self).unwrap())
+    }
+}
@@ -0,0 +1,76 @@
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+/// Flags shared by every command which builds and installs the current plugin
+#[derive(StructOpt, Clone, Default)]
+pub struct InstallOptions {
+    #[structopt(short, long)]
+    pub debug: bool,
+
+    #[structopt(long, conflicts_with = "debug", help = "Build with a custom cargo profile instead of release")]
+    pub profile: Option<String>,
+
+    #[structopt(
+        short, long, number_of_values = 1, visible_alias = "game",
+        parse(try_from_str = crate::titles::resolve_title_id),
+        help = "Title ID of a game to install the plugin for, can be repeated to install for several games and overriden in Cargo.toml",
+    )]
+    pub title_id: Vec<String>,
+
+    #[structopt(long, help = "Cargo features to turn on, instead of the install-features in Cargo.toml")]
+    pub features: Vec<String>,
+
+    #[structopt(long, help = "Build without the crate's default features")]
+    pub no_default_features: bool,
+
+    #[structopt(long, conflicts_with = "features", help = "Build with every feature of the crate turned on")]
+    pub all_features: bool,
+
+    #[structopt(long)]
+    pub install_path: Option<String>,
+
+    #[structopt(long, help = "Build and install every plugin in the workspace, each for its own titles")]
+    pub workspace: bool,
+
+    #[structopt(short, long, number_of_values = 1, help = "Package in the workspace to build and install, can be repeated")]
+    pub package: Vec<String>,
+
+    #[structopt(
+        long, conflicts_with_all = &["workspace", "package"], parse(try_from_str = crate::game_paths::parse_subsdk),
+        help = "Install the plugin as an NSO over the given subsdk, for games without Skyline's NRO loader"
+    )]
+    pub as_subsdk: Option<String>,
+
+    #[structopt(long, help = "Strip the plugin before converting it, keeping its symbols in a .sym file beside it")]
+    pub strip: bool,
+
+    #[structopt(long, requires = "as-subsdk", help = "Leave the segments of the NSO uncompressed when installing as a subsdk")]
+    pub no_compress: bool,
+
+    #[structopt(long, parse(from_str = crate::wsl::translate_path), help = "Install every NRO in a directory instead of building the current plugin")]
+    pub dir: Option<PathBuf>,
+
+    #[structopt(short, long, help = "Upload the plugin even if the installed copy is unchanged")]
+    pub force: bool,
+
+    #[structopt(long, help = "Check that uploaded files match their local copies once transferred")]
+    pub verify: bool,
+
+    #[structopt(
+        long,
+        help = "Check that Skyline is installed for the game before uploading, asking before installing anything missing"
+    )]
+    pub preflight: bool,
+
+    #[structopt(
+        long,
+        help = "Delete everything else in the plugin directory first, other than plugins protected in Cargo.toml"
+    )]
+    pub clean: bool,
+
+    #[structopt(long, help = "Keep the plugin being replaced as <name>.nro.bak so it can be restored with rollback")]
+    pub backup: bool,
+
+    #[structopt(long, help = "Only warn when Skyline on the switch is older than min-skyline-version in Cargo.toml")]
+    pub ignore_skyline_version: bool,
+}
@@ -0,0 +1,60 @@
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::Duration;
+use crate::error::Result;
+use super::{connect_backend, Backend, Destination};
+
+type SharedBackend = Arc<Mutex<Box<dyn Backend>>>;
+
+/// Holds a single connection to the install destination for the length of a command, only
+/// connecting (and logging in) the first time it is needed.
+///
+/// While the session is alive a background thread keeps the connection from idling out for
+/// backends which need it, such as FTP while the plugin builds or logs are being listened to.
+pub struct Session {
+    dest: Destination,
+    print: bool,
+    backend: Option<SharedBackend>,
+}
+
+impl Session {
+    pub fn new(dest: Destination, print: bool) -> Self {
+        Session { dest, print, backend: None }
+    }
+
+    pub fn destination(&self) -> &Destination {
+        &self.dest
+    }
+
+    /// Get the connection for this session, connecting if this is the first use
+    pub fn backend(&mut self) -> Result<MutexGuard<'_, Box<dyn Backend>>> {
+        if self.backend.is_none() {
+            let backend = connect_backend(self.dest.clone(), self.print)?;
+            let interval = backend.keepalive_interval();
+            let backend = Arc::new(Mutex::new(backend));
+
+            if let Some(interval) = interval {
+                spawn_keepalive(Arc::downgrade(&backend), interval);
+            }
+
+            self.backend = Some(backend);
+        }
+
+        Ok(self.backend.as_ref().unwrap().lock().unwrap())
+    }
+}
+
+fn spawn_keepalive(backend: Weak<Mutex<Box<dyn Backend>>>, interval: Duration) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(interval / 2);
+
+        // stop once the session is gone, and leave it be while something else is using it
+        let shared = match backend.upgrade() {
+            Some(shared) => shared,
+            None => break
+        };
+
+        if let Ok(mut backend) = shared.try_lock() {
+            let _ = backend.keep_alive();
+        };
+    });
+}
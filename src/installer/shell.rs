@@ -0,0 +1,117 @@
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use owo_colors::OwoColorize;
+use crate::error::Result;
+use super::{pull_dir, pull_file, Backend};
+
+static HELP: &str = "\
+Commands:
+    ls [path]              list a directory
+    cd <path>              change directory
+    pwd                    print the current directory
+    get <remote> [local]   download a file or directory
+    put <local> [remote]   upload a file
+    rm <remote>            delete a file
+    exit                   leave the shell";
+
+/// Resolve a path typed into the shell against the current directory
+fn join(cwd: &str, path: &str) -> String {
+    let path = match path.strip_prefix("sd:/") {
+        Some(absolute_path) => format!("/{}", absolute_path),
+        None => path.to_owned(),
+    };
+
+    let mut parts: Vec<&str> = if path.starts_with('/') {
+        vec![]
+    } else {
+        cwd.split('/').filter(|part| !part.is_empty()).collect()
+    };
+
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => { parts.pop(); }
+            part => parts.push(part),
+        }
+    }
+
+    format!("/{}", parts.join("/"))
+}
+
+fn file_name(path: &str) -> &str {
+    path.trim_end_matches('/').rsplit('/').next().unwrap_or_default()
+}
+
+fn run_command(client: &mut dyn Backend, cwd: &mut String, command: &str, args: &[&str]) -> Result<()> {
+    match (command, args) {
+        ("ls", []) => println!("{}", client.ls(cwd)?),
+        ("ls", [path]) => println!("{}", client.ls(&join(cwd, path))?),
+        ("cd", [path]) => {
+            let path = join(cwd, path);
+            if client.is_dir(&path)? {
+                *cwd = path;
+            } else {
+                println!("{}: '{}' is not a directory", "ERROR".red(), path);
+            }
+        }
+        ("pwd", []) => println!("{}", cwd),
+        ("get", [remote]) | ("get", [remote, _]) => {
+            let remote = join(cwd, remote);
+            let local = args.get(1).map(PathBuf::from).unwrap_or_else(|| PathBuf::from(file_name(&remote)));
+
+            if client.is_dir(&remote)? {
+                pull_dir(client, &remote, &local)?;
+            } else {
+                pull_file(client, &remote, &local)?;
+            }
+        }
+        ("put", [local]) | ("put", [local, _]) => {
+            let local = Path::new(local);
+            let name = local.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            let remote = join(cwd, args.get(1).copied().unwrap_or(&name));
+
+            println!("Uploading {} to {}...", local.display(), remote);
+            client.put(&remote, &std::fs::read(local)?)?;
+        }
+        ("rm", [remote]) => client.remove(&join(cwd, remote))?,
+        ("help", _) => println!("{}", HELP),
+        _ => println!("{}: Unknown command, type 'help' for a list of commands", "ERROR".red()),
+    }
+
+    Ok(())
+}
+
+/// Read commands from stdin and run them against the switch until told to stop
+pub fn run(client: &mut dyn Backend, root: String) -> Result<()> {
+    let mut cwd = root;
+    let stdin = std::io::stdin();
+
+    println!("{}", HELP);
+
+    loop {
+        print!("{}> ", cwd);
+        let _ = std::io::stdout().lock().flush();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => continue,
+        };
+
+        if command == "exit" || command == "quit" {
+            break;
+        }
+
+        // errors shouldn't end the session, just the command
+        if let Err(err) = run_command(client, &mut cwd, command, args) {
+            crate::print_error(err);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::error::Result;
+
+/// A record of what was last uploaded where, stored next to the built plugin so that
+/// unchanged plugins don't need to be sent again
+#[derive(Serialize, Deserialize, Default)]
+pub struct UploadCache {
+    #[serde(skip)]
+    path: PathBuf,
+    entries: HashMap<String, Entry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    hash: String,
+    fingerprint: String,
+}
+
+impl UploadCache {
+    /// Load the cache for a given plugin, starting fresh if there isn't one
+    pub fn load(plugin_path: &Path) -> Self {
+        let path = plugin_path.with_extension("uploads.json");
+
+        let mut cache: Self = std::fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+
+        cache.path = path;
+
+        cache
+    }
+
+    /// Check if the file at `key` has the given hash locally and hasn't been touched remotely
+    /// since it was uploaded
+    pub fn is_current(&self, key: &str, hash: &str, fingerprint: Option<&str>) -> bool {
+        match (self.entries.get(key), fingerprint) {
+            (Some(entry), Some(fingerprint)) => entry.hash == hash && entry.fingerprint == fingerprint,
+            _ => false
+        }
+    }
+
+    pub fn record(&mut self, key: String, hash: String, fingerprint: String) {
+        self.entries.insert(key, Entry { hash, fingerprint });
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(&self.path, serde_json::to_vec_pretty(self).unwrap())?;
+
+        Ok(())
+    }
+}
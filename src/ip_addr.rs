@@ -1,63 +1,294 @@
-use std::net::IpAddr;
+use std::net::{IpAddr, ToSocketAddrs};
+use serde::{Deserialize, Serialize};
+use owo_colors::OwoColorize;
 use crate::error::{Result, Error};
+use crate::cargo_info::get_metadata;
+use crate::ftp::DataMode;
+use crate::mdns;
+use crate::project_config;
+use crate::settings;
 
-const IP_ADDR_FILE: &str = "ip_addr.txt";
+const IP_ADDR_SETTING: &str = "ip_addr";
+const DEFAULT_DEVICE_SETTING: &str = "default_device";
+
+/// Where devices are kept in `$HOME/.switch/config.toml`
+pub const DEVICES_KEY: &str = "devices";
+
+/// A console saved with `cargo skyline device add`, along with how to connect to it
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Device {
+    pub name: String,
+    pub ip: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pass: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transport: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ftp_mode: Option<DataMode>,
+}
+
+/// Remove the brackets from around an IPv6 literal such as `[::1]`
+fn strip_brackets(ip: &str) -> String {
+    let ip = ip.trim().replace(" ", "");
+
+    match ip.strip_prefix('[').and_then(|ip| ip.strip_suffix(']')) {
+        Some(inner) => inner.to_owned(),
+        None => ip
+    }
+}
 
 pub fn verify_ip(ip: String) -> Result<IpAddr> {
-    let ip: IpAddr = ip.trim()
-                        .replace(" ", "")
-                        .parse()
-                        .map_err(|_| Error::BadIpAddr)?;
+    let ip = strip_brackets(&ip);
+
+    if mdns::is_mdns_name(&ip) {
+        return mdns::resolve(&ip).map(IpAddr::V4).ok_or(Error::UnresolvedHost(ip));
+    }
+
+    if let Ok(ip) = ip.parse() {
+        return Ok(ip);
+    }
+
+    if !is_hostname(&ip) {
+        return Err(Error::BadIpAddr);
+    }
+
+    // the port doesn't matter, it's only there because std can only resolve socket addresses
+    let addrs: Vec<_> = (&*ip, 0).to_socket_addrs()
+        .map_err(|_| Error::UnresolvedHost(ip.clone()))?
+        .map(|addr| addr.ip())
+        .collect();
+
+    addrs.iter()
+        .find(|addr| addr.is_ipv4())
+        .or_else(|| addrs.first())
+        .copied()
+        .ok_or(Error::UnresolvedHost(ip))
+}
+
+/// Whether a string is made up of valid DNS labels
+fn is_hostname(name: &str) -> bool {
+    let name = name.strip_suffix('.').unwrap_or(name);
 
-    Ok(ip)
+    !name.is_empty() && name.len() <= 253 && name.split('.').all(|label| {
+        !label.is_empty() && label.len() <= 63
+            && !label.starts_with('-') && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    })
+}
+
+/// Check an address is something `verify_ip` could connect to. Hostnames are kept as they are
+/// rather than looked up, so they're resolved again each time they're used
+fn check_address(ip: &str) -> Result<String> {
+    let ip = strip_brackets(ip);
+
+    match ip.parse::<IpAddr>() {
+        Ok(ip) => Ok(ip.to_string()),
+        Err(_) if is_hostname(&ip) => Ok(ip),
+        Err(_) => Err(Error::BadIpAddr)
+    }
 }
 
 pub fn get_ip(cli_ip: Option<String>) -> Result<String> {
-    cli_ip
-        .or_else(|| std::env::var("SWITCH_IP").ok())
-        .or_else(get_home_ip)
+    if let Some(ip) = cli_ip.or_else(env_ip) {
+        return Ok(ip);
+    }
+
+    preferred_device()?
+        .map(|device| device.ip)
+        .or_else(|| settings::get(IP_ADDR_SETTING))
         .ok_or(Error::NoIpFound)
 }
 
-pub fn set_ip(ip: String) -> Result<()> {
-    let ip = verify_ip(ip)?;
+/// The address given by `CARGO_SKYLINE_IP` or `SWITCH_IP`, if either is set
+pub fn env_ip() -> Option<String> {
+    settings::env("ip").or_else(|| std::env::var("SWITCH_IP").ok())
+}
 
-    let home = dirs::home_dir().ok_or(Error::NoHomeDir)?;
-    
-    if !home.exists() {
-        return Err(Error::NoHomeDir)
-    }
+/// The device pinned by the project being worked on, either in `.skyline/config.toml` or in
+/// `[package.metadata.skyline]`
+pub fn project_device() -> Result<Option<Device>> {
+    let config = project_config::load()?;
 
-    let switch_home_dir = home.join(".switch");
+    // `config set --project` uses the names of the global settings
+    let name = config.get("device").or_else(|| config.get(DEFAULT_DEVICE_SETTING));
+    let ip = config.get("ip").or_else(|| config.get(IP_ADDR_SETTING));
 
-    if !switch_home_dir.exists() {
-        std::fs::create_dir(&switch_home_dir).map_err(|_| Error::CreateSwitchDirDenied)?;
+    let (name, ip) = match (name, ip) {
+        (None, None) => match get_metadata() {
+            Ok(metadata) => (metadata.device, metadata.ip),
+            Err(_) => (None, None)
+        },
+        pinned => pinned
+    };
+
+    match (name, ip) {
+        (Some(name), _) => device(&name).map(Some),
+        (None, Some(ip)) => Ok(Some(Device {
+            name: String::from("project"), ip, port: None, user: None, pass: None, transport: None, ftp_mode: None
+        })),
+        (None, None) => Ok(None)
     }
+}
 
-    std::fs::write(
-        switch_home_dir.join(IP_ADDR_FILE),
-        ip.to_string()
-    ).map_err(|_| Error::WriteIpDenied)
+/// The device to use when none is given on the command line, preferring one pinned by the
+/// project over the default device
+pub fn preferred_device() -> Result<Option<Device>> {
+    Ok(project_device()?.or_else(default_device))
 }
 
-pub fn show_ip() -> Result<()> {
-    let ip = verify_ip(get_ip(None)?)?;
+/// Every saved device
+pub fn saved_devices() -> Vec<Device> {
+    settings::global_config()
+        .ok()
+        .and_then(|config| config.get_value(DEVICES_KEY)?.clone().try_into().ok())
+        .unwrap_or_default()
+}
+
+fn save_devices(devices: &[Device]) -> Result<()> {
+    let mut config = settings::global_config()?;
+    config.set_value(DEVICES_KEY, toml::Value::try_from(devices).unwrap());
+
+    config.save()
+}
+
+/// Look up a saved device by name
+pub fn device(name: &str) -> Result<Device> {
+    saved_devices()
+        .into_iter()
+        .find(|device| device.name == name)
+        .ok_or_else(|| Error::UnknownDevice(name.to_owned()))
+}
+
+/// The device to use when none is given, if one has been chosen with `device set-default`
+pub fn default_device() -> Option<Device> {
+    let name = settings::get(DEFAULT_DEVICE_SETTING)?;
+
+    saved_devices().into_iter().find(|device| device.name == name)
+}
+
+/// Save a device, replacing any existing device with the same name
+pub fn add_device(mut device: Device) -> Result<()> {
+    device.ip = check_address(&device.ip)?;
+
+    let mut devices: Vec<_> = saved_devices().into_iter().filter(|saved| saved.name != device.name).collect();
+    println!("Saved device '{}' ({})", device.name, device.ip);
+    devices.push(device);
 
-    println!("{}", ip);
+    save_devices(&devices)
+}
+
+pub fn remove_device(name: &str) -> Result<()> {
+    let devices = saved_devices();
+    if !devices.iter().any(|device| device.name == name) {
+        return Err(Error::UnknownDevice(name.to_owned()));
+    }
+
+    let devices: Vec<_> = devices.into_iter().filter(|device| device.name != name).collect();
+    save_devices(&devices)?;
+
+    println!("Removed device '{}'", name);
 
     Ok(())
 }
 
-fn get_home_ip() -> Option<String> {
-    let switch_home_dir = dirs::home_dir()?.join(".switch");
-    if switch_home_dir.exists() {
-        let ip_addr_file = switch_home_dir.join(IP_ADDR_FILE);
-        if ip_addr_file.exists() {
-            std::fs::read_to_string(ip_addr_file).ok()
+pub fn set_default_device(name: &str) -> Result<()> {
+    device(name)?;
+
+    settings::set(DEFAULT_DEVICE_SETTING, name)
+}
+
+pub fn list_devices() -> Result<()> {
+    let devices = saved_devices();
+    if devices.is_empty() {
+        return Err(Error::NoDevices);
+    }
+
+    let default = settings::get(DEFAULT_DEVICE_SETTING);
+
+    for device in &devices {
+        let marker = if default.as_deref() == Some(&*device.name) { "*" } else { " " };
+        let mut details = vec![];
+
+        if let Some(port) = device.port {
+            details.push(format!("port {}", port));
+        }
+        if let Some(user) = &device.user {
+            details.push(format!("user {}", user));
+        }
+        if let Some(transport) = &device.transport {
+            details.push(transport.clone());
+        }
+        if let Some(mode) = device.ftp_mode {
+            details.push(format!("ftp mode {}", mode));
+        }
+
+        if details.is_empty() {
+            println!("{} {}: {}", marker.green(), device.name, device.ip);
         } else {
-            None
+            println!("{} {}: {} ({})", marker.green(), device.name, device.ip, details.join(", "));
         }
-    } else {
-        None
+    }
+
+    Ok(())
+}
+
+pub fn set_ip(ip: String, name: Option<String>) -> Result<()> {
+    let name = match name {
+        Some(name) => name,
+        None => return settings::set(IP_ADDR_SETTING, &check_address(&ip)?)
+    };
+
+    // keep whatever else was saved for the device
+    let device = match device(&name) {
+        Ok(device) => Device { ip, ..device },
+        Err(_) => Device { name, ip, port: None, user: None, pass: None, transport: None, ftp_mode: None },
+    };
+
+    add_device(device)
+}
+
+pub fn show_ip() -> Result<()> {
+    let devices = saved_devices();
+
+    match get_ip(None) {
+        Ok(ip) => println!("{}", check_address(&ip)?),
+        Err(Error::NoIpFound) if !devices.is_empty() => {}
+        Err(err) => return Err(err),
+    }
+
+    for device in devices {
+        println!("{}: {}", device.name, device.ip);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_ipv6_brackets() {
+        assert_eq!(strip_brackets("[::1]"), "::1");
+        assert_eq!(strip_brackets(" [fe80::1] "), "fe80::1");
+        assert_eq!(strip_brackets("::1"), "::1");
+        assert_eq!(strip_brackets("192.168.0.2"), "192.168.0.2");
+        assert_eq!(strip_brackets("[::1"), "[::1");
+    }
+
+    #[test]
+    fn check_addresses() {
+        assert_eq!(check_address("[::1]").unwrap(), "::1");
+        assert_eq!(check_address("fe80:0:0:0:0:0:0:1").unwrap(), "fe80::1");
+        assert_eq!(check_address("192.168.0.2").unwrap(), "192.168.0.2");
+        assert_eq!(check_address("switch.local").unwrap(), "switch.local");
+
+        assert!(check_address("[::g]").is_err());
+        assert!(check_address("192.168.0.2:5000").is_err());
+        assert!(check_address("-switch").is_err());
     }
 }
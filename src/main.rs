@@ -10,10 +10,34 @@ mod cargo_info;
 mod package;
 mod build;
 mod ftp;
+mod usb;
 mod tcp_listen;
 mod ip_addr;
+mod discover;
+mod mdns;
+mod project_config;
+mod wsl;
+mod validate;
+mod settings;
+mod progress;
+mod timeouts;
+mod timestamps;
+mod hash;
 mod git_clone_wrappers;
 mod game_paths;
+mod emulator;
+mod github;
+mod titles;
+mod skyline_version;
+mod nxo;
+mod symbols;
+mod bloat;
+mod size_diff;
+mod build_info;
+mod nm;
+mod check_imports;
+mod symbolicate;
+mod crash;
 
 #[derive(StructOpt)]
 enum SubCommands {
@@ -39,71 +63,81 @@ enum SubCommands {
     Clippy,
     #[structopt(about = "Build the current plugin as an NRO")]
     Build {
-        #[structopt(long)]
-        release: bool,
-
-        #[structopt(long)]
-        nso: bool,
-
-        #[structopt(long)]
-        features: Vec<String>,
+        #[structopt(flatten)]
+        options: build::BuildOptions,
 
         args: Vec<String>
     },
     #[structopt(about = "Build the current plugin and install to a switch over FTP")]
     Install {
-        #[structopt(short, long)]
-        debug: bool,
-
-        #[structopt(short, long)]
-        ip: Option<String>,
-
-        #[structopt(
-            short, long,
-            about = "Title ID of the game to install the plugin for, can be overriden in Cargo.toml",
-        )]
-        title_id: Option<String>,
-
         #[structopt(
             short, long,
             about = "Install a project from a git url to the switch"
         )]
         git: Option<String>,
 
-        #[structopt(long)]
-        features: Vec<String>,
+        #[structopt(flatten)]
+        options: installer::InstallOptions,
 
-        #[structopt(long)]
-        install_path: Option<String>
+        #[structopt(flatten)]
+        dest: installer::Destination,
     },
-    #[structopt(about = "Set the IP address of the switch to install to")]
+    #[structopt(about = "Set the IP address or hostname of the switch to install to")]
     SetIp {
-        ip: String
+        ip: String,
+
+        #[structopt(long, help = "Save the IP as a named device for use with --device instead of the default")]
+        name: Option<String>,
+    },
+    #[structopt(about = "Set the title ID in Cargo.toml, given either the ID or the name of the game")]
+    SetTitleId {
+        game: String,
     },
-    #[structopt(about = "Show the currently configured IP address")]
+    #[structopt(about = "Look up the title IDs of games, which --game accepts the names of")]
+    Games {
+        #[structopt(subcommand)]
+        command: GamesCommand,
+    },
+    #[structopt(about = "Check [package.metadata.skyline] in Cargo.toml for mistakes")]
+    Validate,
+    #[structopt(about = "Inspect and change settings")]
+    Config {
+        #[structopt(subcommand)]
+        command: ConfigCommand,
+    },
+    #[structopt(about = "Inside WSL, share the Windows user's ~/.switch config instead of keeping a separate one")]
+    WslShare {
+        #[structopt(long, help = "Move an existing ~/.switch out of the way to ~/.switch.bak")]
+        force: bool,
+    },
+    #[structopt(about = "Manage the consoles saved for use with --device")]
+    Device {
+        #[structopt(subcommand)]
+        command: DeviceCommand,
+    },
+    #[structopt(about = "Scan the local network for consoles and save them as devices")]
+    Discover {
+        #[structopt(long, help = "Subnet to scan such as '192.168.1.0/24', defaults to this computer's /24")]
+        subnet: Option<String>,
+
+        #[structopt(long, help = "FTP port to look for (default 5000)")]
+        port: Option<u16>,
+
+        #[structopt(long, default_value = "300", help = "Milliseconds to wait on each port before moving on")]
+        timeout: u64,
+    },
+    #[structopt(about = "Show the currently configured IP address and any saved devices")]
     ShowIp,
     #[structopt(about = "Install the current plugin and listen for skyline logging")]
     Run {
-        #[structopt(short, long)]
-        debug: bool,
-
         #[structopt(short, long)]
         restart: bool,
 
-        #[structopt(short, long)]
-        ip: Option<String>,
-
-        #[structopt(
-            short, long,
-            about = "Title ID of the game to install the plugin for, can be overriden in Cargo.toml",
-        )]
-        title_id: Option<String>,
-
-        #[structopt(long)]
-        features: Vec<String>,
+        #[structopt(flatten)]
+        options: installer::InstallOptions,
 
-        #[structopt(long)]
-        install_path: Option<String>
+        #[structopt(flatten)]
+        dest: installer::Destination,
     },
     #[structopt(about = "Install the current plugin and listen for skyline logging")]
     Restart {
@@ -111,7 +145,7 @@ enum SubCommands {
         ip: Option<String>,
 
         #[structopt(
-            short, long,
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
             about = "Title ID of the game to install the plugin for, can be overriden in Cargo.toml",
         )]
         title_id: Option<String>
@@ -121,47 +155,314 @@ enum SubCommands {
         #[structopt(short, long, default_value = "https://github.com/jam1garner/rust-std-skyline-squashed")]
         git: String,
 
-        #[structopt(short, long)]
+        #[structopt(short, long, parse(from_str = wsl::translate_path))]
         std_path: Option<PathBuf>
     },
     #[structopt(about = "Listen for logs being output from a switch running skyline at the given ip")]
     Listen {
         #[structopt(short, long)]
         ip: Option<String>,
+
+        #[structopt(flatten)]
+        timeouts: timeouts::TimeoutArgs,
+
+        #[structopt(long, help = "Keep trying until the game starts logging, and reconnect whenever it restarts")]
+        wait: bool,
     },
     #[structopt(about = "List the files in the plugin directory for the given game")]
     List {
-        #[structopt(short, long)]
-        ip: Option<String>,
+        #[structopt(flatten)]
+        dest: installer::Destination,
 
         #[structopt(
-            short, long,
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
             about = "Title ID of the game to list the installed plugins for, can be overriden in Cargo.toml",
         )]
         title_id: Option<String>,
 
-        path: Option<String>
+        path: Option<String>,
+
+        #[structopt(flatten)]
+        options: installer::ListOptions,
+    },
+    #[structopt(about = "Remove the current plugin from the given game")]
+    Uninstall {
+        #[structopt(flatten)]
+        dest: installer::Destination,
+
+        #[structopt(
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
+            help = "Title ID of the game to uninstall the plugin from, can be overriden in Cargo.toml",
+        )]
+        title_id: Option<String>,
+
+        #[structopt(long, help = "The --install-path the plugin was installed with, if any")]
+        install_path: Option<String>,
+    },
+    #[structopt(about = "Delete every plugin installed for the given game")]
+    Purge {
+        #[structopt(flatten)]
+        dest: installer::Destination,
+
+        #[structopt(
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
+            help = "Title ID of the game to remove the plugins from, can be overriden in Cargo.toml",
+        )]
+        title_id: Option<String>,
+
+        #[structopt(long, help = "A plugin to leave installed, can be passed multiple times")]
+        keep: Vec<String>,
+
+        #[structopt(short, long, help = "Don't ask for confirmation before deleting")]
+        yes: bool,
+    },
+    #[structopt(about = "Delete the plugins for the given game which aren't built by this workspace")]
+    CleanRemote {
+        #[structopt(flatten)]
+        dest: installer::Destination,
+
+        #[structopt(
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
+            help = "Title ID of the game to remove the plugins from, can be overriden in Cargo.toml",
+        )]
+        title_id: Option<String>,
+
+        #[structopt(short, long, help = "Don't ask for confirmation before deleting")]
+        yes: bool,
+    },
+    #[structopt(about = "Make the plugins on the switch match the deploy manifest in Cargo.toml")]
+    Sync {
+        #[structopt(flatten)]
+        dest: installer::Destination,
+
+        #[structopt(
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
+            help = "Title ID of the game to sync the plugins of, can be overriden in Cargo.toml",
+        )]
+        title_id: Option<String>,
+
+        #[structopt(long, help = "Show what would change without changing anything")]
+        dry_run: bool,
+    },
+    #[structopt(about = "Install Skyline itself for a game")]
+    InstallSkyline {
+        #[structopt(flatten)]
+        dest: installer::Destination,
+
+        #[structopt(
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
+            help = "Title ID of the game to install Skyline for, can be overriden in Cargo.toml",
+        )]
+        title_id: Option<String>,
+
+        #[structopt(
+            short, long,
+            help = "URL of the Skyline release zip to install, defaults to the one pinned in Cargo.toml or the latest beta"
+        )]
+        skyline_release: Option<String>,
+
+        #[structopt(short, long, help = "Replace Skyline if it's already installed")]
+        force: bool,
+    },
+    #[structopt(about = "Check whether the installed Skyline matches the latest (or pinned) release")]
+    UpdateSkyline {
+        #[structopt(flatten)]
+        dest: installer::Destination,
+
+        #[structopt(
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
+            help = "Title ID of the game to check Skyline for, can be overriden in Cargo.toml",
+        )]
+        title_id: Option<String>,
+
+        #[structopt(
+            short, long,
+            help = "URL of the Skyline release zip to compare against, defaults to the one pinned in Cargo.toml or the latest beta"
+        )]
+        skyline_release: Option<String>,
+
+        #[structopt(short, long, help = "Replace the installed Skyline if it doesn't match the release")]
+        replace: bool,
+    },
+    #[structopt(about = "Download the latest release of a plugin from GitHub and install it")]
+    InstallPlugin {
+        #[structopt(help = "The name of a known plugin, or the GitHub repository to install from as 'owner/repo'")]
+        name: String,
+
+        #[structopt(flatten)]
+        dest: installer::Destination,
+
+        #[structopt(
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
+            help = "Title ID of the game to install the plugin for, can be overriden in Cargo.toml",
+        )]
+        title_id: Option<String>,
+    },
+    #[structopt(about = "Diagnose problems connecting to the switch")]
+    Ping {
+        #[structopt(flatten)]
+        dest: installer::Destination,
+    },
+    #[structopt(about = "List the games on the SD card which have contents folders")]
+    Titles {
+        #[structopt(flatten)]
+        dest: installer::Destination,
+    },
+    #[structopt(about = "Show whether the switch can be reached and what's installed for a game")]
+    Status {
+        #[structopt(flatten)]
+        dest: installer::Destination,
+
+        #[structopt(
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
+            help = "Title ID of the game to show the status of, can be overriden in Cargo.toml",
+        )]
+        title_id: Option<String>,
+    },
+    #[structopt(about = "Check whether plugins installed with install-plugin have newer releases")]
+    Outdated {
+        #[structopt(flatten)]
+        dest: installer::Destination,
+
+        #[structopt(
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
+            help = "Title ID of the game to check the plugins of, can be overriden in Cargo.toml",
+        )]
+        title_id: Option<String>,
+    },
+    #[structopt(about = "Show which builds have been installed where")]
+    Deployments {
+        #[structopt(short = "n", long, default_value = "20", help = "How many of the most recent installs to show")]
+        limit: usize,
+
+        #[structopt(
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
+            help = "Only show installs for the given title ID"
+        )]
+        title_id: Option<String>,
+    },
+    #[structopt(about = "Restore the version of the current plugin kept by install --backup")]
+    Rollback {
+        #[structopt(flatten)]
+        dest: installer::Destination,
+
+        #[structopt(
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
+            help = "Title ID of the game the plugin is installed for, can be overriden in Cargo.toml",
+        )]
+        title_id: Option<String>,
+
+        #[structopt(long, help = "The --install-path the plugin was installed with, if any")]
+        install_path: Option<String>,
+    },
+    #[structopt(about = "Stop a plugin from being loaded without deleting it")]
+    Disable {
+        #[structopt(flatten)]
+        dest: installer::Destination,
+
+        #[structopt(
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
+            help = "Title ID of the game the plugin is installed for, can be overriden in Cargo.toml",
+        )]
+        title_id: Option<String>,
+
+        #[structopt(help = "Name of the plugin in the plugin directory, the .nro is optional")]
+        plugin: String,
+    },
+    #[structopt(about = "Re-enable a plugin which was disabled")]
+    Enable {
+        #[structopt(flatten)]
+        dest: installer::Destination,
+
+        #[structopt(
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
+            help = "Title ID of the game the plugin is installed for, can be overriden in Cargo.toml",
+        )]
+        title_id: Option<String>,
+
+        #[structopt(help = "Name of the plugin in the plugin directory, the .nro is optional")]
+        plugin: String,
     },
     #[structopt(about = "Delete a file in the plugin directory for the given game")]
     Rm {
-        #[structopt(short, long)]
-        ip: Option<String>,
+        #[structopt(flatten)]
+        dest: installer::Destination,
 
         #[structopt(
-            short, long,
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
             about = "Title ID of the game to list the installed plugins for, can be overriden in Cargo.toml",
         )]
         title_id: Option<String>,
 
+        #[structopt(help = "File to delete, either relative to the plugin directory or absolute (starting with '/' or 'sd:/'). Defaults to the current plugin")]
         filename: Option<String>
     },
+    #[structopt(about = "Save every plugin installed for the given game to a local archive")]
+    Backup {
+        #[structopt(flatten)]
+        dest: installer::Destination,
+
+        #[structopt(
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
+            help = "Title ID of the game to back up the plugins of, can be overriden in Cargo.toml",
+        )]
+        title_id: Option<String>,
+
+        #[structopt(
+            short, long, parse(from_str = wsl::translate_path),
+            help = "Where to save the archive, defaults to '<title id>-plugins-<time>.tar.gz'"
+        )]
+        out: Option<PathBuf>,
+    },
+    #[structopt(about = "Upload the plugins from an archive made by backup")]
+    Restore {
+        #[structopt(flatten)]
+        dest: installer::Destination,
+
+        #[structopt(
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
+            help = "Title ID of the game to restore the plugins of, can be overriden in Cargo.toml",
+        )]
+        title_id: Option<String>,
+
+        #[structopt(parse(from_str = wsl::translate_path))]
+        archive: PathBuf,
+    },
+    #[structopt(about = "Browse the files on the switch interactively")]
+    Shell {
+        #[structopt(flatten)]
+        dest: installer::Destination,
+
+        #[structopt(
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
+            help = "Title ID of the game whose plugin directory to start in, can be overriden in Cargo.toml",
+        )]
+        title_id: Option<String>,
+    },
+    #[structopt(about = "Download a file or directory from the switch")]
+    Pull {
+        #[structopt(flatten)]
+        dest: installer::Destination,
+
+        #[structopt(
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
+            help = "Title ID of the game whose plugin directory relative paths are in, can be overriden in Cargo.toml",
+        )]
+        title_id: Option<String>,
+
+        #[structopt(help = "Path on the switch, either relative to the plugin directory or absolute (starting with '/' or 'sd:/')")]
+        remote: String,
+
+        #[structopt(parse(from_str = wsl::translate_path), help = "Where to save to, defaults to the current directory")]
+        local: Option<PathBuf>,
+    },
     #[structopt(about = "Copy a file over FTP")]
     Cp {
         #[structopt(short, long)]
         ip: Option<String>,
 
         #[structopt(
-            short, long,
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
             about = "Title ID of the game to list the installed plugins for, can be overriden in Cargo.toml",
         )]
         title_id: Option<String>,
@@ -187,17 +488,95 @@ enum SubCommands {
         skyline_release: String,
 
         #[structopt(
-            short, long,
+            short, long, visible_alias = "game", parse(try_from_str = titles::resolve_title_id),
             about = "Title ID of the game to package the plugin for",
         )]
         title_id: Option<String>,
 
         #[structopt(
             short, long,
-            about = "Path to output zip to",
-            default_value = "target/release.zip"
+            about = "Path to output zip to, release.zip in the target directory by default",
         )]
-        out_path: String,
+        out_path: Option<String>,
+
+        #[structopt(long, about = "Ship the plugin with its symbols, rather than stripped with them kept in a .sym file")]
+        no_strip: bool,
+
+        #[structopt(long, about = "Build and zip the plugin so the same commit always gives a byte-identical zip")]
+        reproducible: bool,
+    },
+    #[structopt(about = "Convert an ELF built some other way into an NRO, the same way build does")]
+    Elf2nro {
+        #[structopt(parse(from_str = crate::wsl::translate_path), help = "The ELF to convert")]
+        input: PathBuf,
+
+        #[structopt(parse(from_str = crate::wsl::translate_path), help = "Where to write the NRO, next to the ELF by default")]
+        output: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        nacp: build::NacpArgs,
+    },
+    #[structopt(about = "Convert between NRO and NSO, or dump the segments of either")]
+    Convert {
+        #[structopt(parse(from_str = crate::wsl::translate_path), help = "The NRO or NSO to convert")]
+        input: PathBuf,
+
+        #[structopt(
+            parse(from_str = crate::wsl::translate_path),
+            help = "Where to write the converted module, which is an NRO if it ends in .nro and an NSO otherwise"
+        )]
+        output: Option<PathBuf>,
+
+        #[structopt(
+            long, parse(from_str = crate::wsl::translate_path), conflicts_with = "output",
+            help = "Write each segment to its own file in this directory instead of converting"
+        )]
+        dump: Option<PathBuf>,
+
+        #[structopt(long, help = "Leave the segments uncompressed when converting to an NSO")]
+        no_compress: bool,
+    },
+    #[structopt(about = "Show the header, segments, module name, build id and build info of an NRO or NSO")]
+    NroInfo {
+        #[structopt(parse(from_str = crate::wsl::translate_path), help = "The NRO or NSO to look into")]
+        input: PathBuf,
+    },
+    #[structopt(about = "Build the plugin and show what takes up space in it, by section and by symbol")]
+    Bloat {
+        #[structopt(flatten)]
+        options: bloat::BloatOptions,
+
+        args: Vec<String>
+    },
+    #[structopt(about = "Build the plugin and compare its size with an older build, or the last one installed")]
+    SizeDiff {
+        #[structopt(flatten)]
+        options: size_diff::SizeDiffOptions,
+    },
+    #[structopt(about = "List the symbols a plugin exports and imports, demangled")]
+    Nm {
+        #[structopt(flatten)]
+        options: nm::NmOptions,
+    },
+    #[structopt(about = "Check that everything the plugin imports is exported by Skyline or the plugins it needs")]
+    CheckImports {
+        #[structopt(flatten)]
+        options: check_imports::CheckImportsOptions,
+    },
+    #[structopt(about = "Find the function, file and line of addresses in the plugin, such as from a crash")]
+    Addr2line {
+        #[structopt(flatten)]
+        options: symbolicate::Addr2lineOptions,
+    },
+    #[structopt(about = "Convert between runtime addresses and offsets into the plugin")]
+    Addr {
+        #[structopt(flatten)]
+        options: symbolicate::AddrOptions,
+    },
+    #[structopt(about = "Show the newest crash report from the console, looking up the plugin's addresses in it")]
+    Crash {
+        #[structopt(flatten)]
+        options: crash::CrashOptions,
     },
     #[structopt(about = "Update libraries for current plugin folder")]
     Update,
@@ -211,6 +590,73 @@ enum SubCommands {
     },
 }
 
+#[derive(StructOpt)]
+enum DeviceCommand {
+    #[structopt(about = "Save a console under a name, replacing any existing device with that name")]
+    Add {
+        name: String,
+        ip: String,
+
+        #[structopt(long, help = "Port of the FTP (or SFTP) server on the console")]
+        port: Option<u16>,
+
+        #[structopt(long, help = "Username to log in to the console with")]
+        user: Option<String>,
+
+        #[structopt(long, help = "Password to log in to the console with")]
+        pass: Option<String>,
+
+        #[structopt(long, help = "Transport used to talk to the console: 'ftp', 'usb' or 'sftp'")]
+        transport: Option<String>,
+
+        #[structopt(long, help = "How FTP data connections are opened: 'passive', 'epsv', 'active' or 'eprt'")]
+        ftp_mode: Option<ftp::DataMode>,
+    },
+    #[structopt(about = "List the saved devices, with the default marked")]
+    List,
+    #[structopt(about = "Forget a saved device")]
+    Remove {
+        name: String,
+    },
+    #[structopt(about = "Use a saved device whenever no IP or device is given")]
+    SetDefault {
+        name: String,
+    },
+}
+
+#[derive(StructOpt)]
+enum GamesCommand {
+    #[structopt(about = "Find known games by name, or list all of them")]
+    Search {
+        query: Option<String>,
+    },
+}
+
+#[derive(StructOpt)]
+enum ConfigCommand {
+    #[structopt(about = "Show the value of a setting and where it was set")]
+    Get {
+        name: String,
+    },
+    #[structopt(about = "Change a setting, in ~/.switch/config.toml unless --project is given")]
+    Set {
+        name: String,
+        value: String,
+
+        #[structopt(long, help = "Store the setting in the project's .skyline/config.toml instead")]
+        project: bool,
+    },
+    #[structopt(about = "Remove a setting, so it falls back to whatever is set elsewhere")]
+    Unset {
+        name: String,
+
+        #[structopt(long, help = "Remove the setting from the project's .skyline/config.toml instead")]
+        project: bool,
+    },
+    #[structopt(about = "List every setting, along with its value and where it was set")]
+    List,
+}
+
 #[derive(StructOpt)]
 #[structopt(bin_name = "cargo")]
 enum Args {
@@ -223,64 +669,249 @@ fn main() {
     use SubCommands::*;
 
     let result = match subcommand {
-        Install { ip, title_id, debug, git, features , install_path} => if let Some(git) = git {
-            installer::from_git(&git, ip, title_id, !debug, features, install_path)
+        Install { dest, git, options } => if let Some(git) = git {
+            installer::from_git(&git, dest, options)
         } else {
-            installer::install(ip, title_id, !debug, features, install_path)
+            installer::install(dest, options)
         },
-        SetIp { ip } => ip_addr::set_ip(ip),
+        SetIp { ip, name } => ip_addr::set_ip(ip, name),
         ShowIp => ip_addr::show_ip(),
-        Build { args, release, nso, features } => build::build(args, release, nso, features),
+        Discover { subnet, port, timeout } => discover::discover(subnet, port, timeout),
+        WslShare { force } => wsl::share(force),
+        Validate => validate::validate(),
+        SetTitleId { game } => cargo_info::set_title_id(&game),
+        Games { command: GamesCommand::Search { query } } => titles::games_search(query),
+        Config { command } => match command {
+            ConfigCommand::Get { name } => settings::config_get(&name),
+            ConfigCommand::Set { name, value, project } => settings::config_set(&name, &value, project),
+            ConfigCommand::Unset { name, project } => settings::config_unset(&name, project),
+            ConfigCommand::List => settings::config_list(),
+        },
+        Device { command } => match command {
+            DeviceCommand::Add { name, ip, port, user, pass, transport, ftp_mode } => {
+                ip_addr::add_device(ip_addr::Device { name, ip, port, user, pass, transport, ftp_mode })
+            }
+            DeviceCommand::List => ip_addr::list_devices(),
+            DeviceCommand::Remove { name } => ip_addr::remove_device(&name),
+            DeviceCommand::SetDefault { name } => ip_addr::set_default_device(&name),
+        },
+        Build { args, options } => build::build(args, options),
         Check => build::check(),
         Clippy => build::clippy(),
-        Run { ip, title_id, debug, restart , features, install_path} => installer::install_and_run(ip, title_id, !debug, restart, features, install_path),
+        Run { dest, restart, options } => installer::install_and_run(dest, options, restart),
         Restart { ip, title_id } => installer::restart_game(ip, title_id),
         New { name, template_git, template_git_branch } => git_clone_wrappers::new_plugin(name, template_git, template_git_branch),
         UpdateStd { git, std_path } => git_clone_wrappers::update_std(git, std_path),
-        Listen { ip } => tcp_listen::listen(ip),
-        List { ip, title_id, path } => installer::list(ip, title_id, path),
-        Rm { ip, title_id, filename } => installer::rm(ip, title_id, filename),
+        Listen { ip, timeouts, wait } => timeouts.resolve().and_then(|timeouts| tcp_listen::listen(ip, timeouts, wait)),
+        List { dest, title_id, path, options } => installer::list(dest, title_id, path, options),
+        Uninstall { dest, title_id, install_path } => installer::uninstall(dest, title_id, install_path),
+        Purge { dest, title_id, keep, yes } => installer::purge(dest, title_id, keep, yes),
+        CleanRemote { dest, title_id, yes } => installer::clean_remote(dest, title_id, yes),
+        Sync { dest, title_id, dry_run } => installer::sync(dest, title_id, dry_run),
+        InstallSkyline { dest, title_id, skyline_release, force }
+            => installer::install_skyline(dest, title_id, skyline_release, force),
+        UpdateSkyline { dest, title_id, skyline_release, replace }
+            => installer::update_skyline(dest, title_id, skyline_release, replace),
+        InstallPlugin { name, dest, title_id } => installer::install_plugin(dest, title_id, name),
+        Ping { dest } => installer::ping(dest),
+        Titles { dest } => installer::titles(dest),
+        Status { dest, title_id } => installer::status(dest, title_id),
+        Outdated { dest, title_id } => installer::outdated(dest, title_id),
+        Deployments { limit, title_id } => installer::deployments(limit, title_id),
+        Rollback { dest, title_id, install_path } => installer::rollback(dest, title_id, install_path),
+        Disable { dest, title_id, plugin } => installer::set_enabled(dest, title_id, plugin, false),
+        Enable { dest, title_id, plugin } => installer::set_enabled(dest, title_id, plugin, true),
+        Rm { dest, title_id, filename } => installer::rm(dest, title_id, filename),
+        Backup { dest, title_id, out } => installer::backup(dest, title_id, out),
+        Restore { dest, title_id, archive } => installer::restore(dest, title_id, archive),
+        Shell { dest, title_id } => installer::shell(dest, title_id),
+        Pull { dest, title_id, remote, local } => installer::pull(dest, title_id, remote, local),
         Cp { ip, title_id, src, dest } => installer::cp(ip, title_id, src, dest),
         SelfUpdate { from_master, git } => self_update(from_master, git),
-        Package { skyline_release, title_id, out_path }
-            => package::package(&skyline_release, title_id.as_deref(), &out_path),
+        Package { skyline_release, title_id, out_path, no_strip, reproducible }
+            => package::package(&skyline_release, title_id.as_deref(), out_path.as_deref(), !no_strip, reproducible),
+        Elf2nro { input, output, nacp } => build::elf2nro(&input, output, nacp),
+        Convert { input, output, dump, no_compress } => nxo::convert(&input, output, dump, !no_compress),
+        NroInfo { input } => nxo::info(&input),
+        Bloat { args, options } => bloat::bloat(args, options),
+        SizeDiff { options } => size_diff::size_diff(options),
+        Nm { options } => nm::nm(options),
+        CheckImports { options } => check_imports::check_imports(options),
+        Addr2line { options } => symbolicate::addr2line(options),
+        Addr { options } => symbolicate::addr(options),
+        Crash { options } => crash::crash(options),
         Update => update(),
         Doc { open } => build::doc(if open { vec!["--open".into()] } else { vec![] })
     };
 
     if let Err(err) = result {
-        match err {
-            Error::NoIpFound => eprintln!("{}: {}", "ERROR".red(), error::NO_IP),
-            Error::BadIpAddr => eprintln!("{}: {}", "ERROR".red(), error::BAD_IP_ADDR),
-            Error::FtpError(ftp_err) => {
-                eprintln!("{}{}","An FTP Error Occurred: ".red(), ftp_err)
-            }
-            Error::NoHomeDir => eprintln!("{}: No home directory could be found", "ERROR".red()),
-            Error::NoPathFound => eprintln!("{}: No environment variable PATH could be found.", "ERROR".red()),
-            Error::CreateSwitchDirDenied
-                => eprintln!("{}: Could not create $HOME/.switch", "ERROR".red()),
-            Error::WriteIpDenied => eprintln!("{}: Could not write IP to file", "ERROR".red()),
-            //Error::NoCargoToml => eprintln!("{}", "No Cargo.toml could be found. Make sure you are within your plugin directory.".red()),
-            //Error::BadCargoToml => eprintln!("{}", "Cargo.toml is formatted incorrectly.".red()),
-            Error::NoTitleId => error::no_title_id(),
-            Error::FailParseCargoStream => eprintln!("{}", "Unable to parse cargo output stream"),
-            Error::CargoError(err) => eprintln!("{}{}", "CargoError: ".red(), err),
-            Error::ExitStatus(code) => std::process::exit(code),
-            Error::FailWriteNro => eprintln!("{}: Unable to convert file from ELF to NRO", "ERROR".red()),
-            Error::IoError(err) => eprintln!("{}{}", "IoError: ".red(), err),
-            Error::FailUpdateStd => eprintln!("{}: Could not update std due to a git-related failure", "ERROR".red()),
-            Error::NoStdFound => eprintln!("{}: Could not find stdlib. Make sure you're inside of either your workspace or a plugin folder", "ERROR".red()),
-            Error::DownloadError => eprintln!("{}: Failed to download latest release of Skyline. An internet connection is required.", "ERROR".red()),
-            Error::ZipError => eprintln!("{}: Failed to read Skyline release zip. Either corrupted or missing files.", "ERROR".red()),
-            Error::NoNpdmFileFound => eprintln!("{}: Custom NPDM file specified in Cargo.toml not found at the specified path.", "ERROR".red()),
-            Error::AbsSwitchPath => eprintln!("{}: Absolute Switch paths must be prepended with \"sd:/\"", "ERROR".red()),
-            Error::BadSdPath => eprintln!("{}: Install paths must either start with \"rom:/\" or \"sd:/\"", "ERROR".red())
-        }
+        print_error(err);
 
         std::process::exit(1);
     }
 }
 
+fn print_error(err: Error) {
+    match err {
+        Error::NoIpFound => eprintln!("{}: {}", "ERROR".red(), error::NO_IP),
+        Error::BadIpAddr => eprintln!("{}: {}", "ERROR".red(), error::BAD_IP_ADDR),
+        Error::UnresolvedHost(host) => eprintln!(
+            "{}: Could not find the address of '{}', make sure the switch is on and connected to the same network",
+            "ERROR".red(), host
+        ),
+        Error::FtpError(ftp_err) => {
+            eprintln!("{}{}","An FTP Error Occurred: ".red(), ftp_err)
+        }
+        Error::UsbError(usb_err) => {
+            eprintln!("{}{}","A USB Error Occurred: ".red(), usb_err)
+        }
+        Error::NoHomeDir => eprintln!("{}: No home directory could be found", "ERROR".red()),
+        Error::NoPathFound => eprintln!("{}: No environment variable PATH could be found.", "ERROR".red()),
+        Error::CreateSwitchDirDenied
+            => eprintln!("{}: Could not create $HOME/.switch", "ERROR".red()),
+        Error::WriteSettingDenied(name) => eprintln!("{}: Could not write {} to file", "ERROR".red(), name),
+        //Error::NoCargoToml => eprintln!("{}", "No Cargo.toml could be found. Make sure you are within your plugin directory.".red()),
+        //Error::BadCargoToml => eprintln!("{}", "Cargo.toml is formatted incorrectly.".red()),
+        Error::NoTitleId => error::no_title_id(),
+        Error::FailParseCargoStream => eprintln!("{}", "Unable to parse cargo output stream"),
+        Error::CargoError(err) => eprintln!("{}{}", "CargoError: ".red(), err),
+        Error::ExitStatus(code) => std::process::exit(code),
+        Error::FailWriteNro => eprintln!("{}: Unable to convert file from ELF to NRO", "ERROR".red()),
+        Error::IoError(err) => eprintln!("{}{}", "IoError: ".red(), err),
+        Error::FailUpdateStd => eprintln!("{}: Could not update std due to a git-related failure", "ERROR".red()),
+        Error::NoStdFound => eprintln!("{}: Could not find stdlib. Make sure you're inside of either your workspace or a plugin folder", "ERROR".red()),
+        Error::DownloadError => eprintln!("{}: Failed to download latest release of Skyline. An internet connection is required.", "ERROR".red()),
+        Error::ZipError => eprintln!("{}: Failed to read Skyline release zip. Either corrupted or missing files.", "ERROR".red()),
+        Error::NoNpdmFileFound => eprintln!("{}: Custom NPDM file specified in Cargo.toml not found at the specified path.", "ERROR".red()),
+        Error::AbsSwitchPath => eprintln!("{}: Absolute Switch paths must be prepended with \"sd:/\"", "ERROR".red()),
+        Error::BadSdPath => eprintln!("{}: Install paths must either start with \"rom:/\" or \"sd:/\"", "ERROR".red()),
+        Error::NoEmulatorDir(dir) => eprintln!("{}: Could not find emulator data directory at '{}'", "ERROR".red(), dir.display()),
+        Error::NoSdCardFound(dir) => eprintln!("{}: No SD card is mounted at '{}'", "ERROR".red(), dir.display()),
+        Error::BadSetting(name) => eprintln!("{}: Invalid value for the '{}' setting", "ERROR".red(), name),
+        Error::UnknownDevice(name) => eprintln!(
+            "{}: No device named '{}' has been saved, add it using `cargo skyline device add {} [addr]`",
+            "ERROR".red(), name, name
+        ),
+        Error::NoDevices => eprintln!(
+            "{}: No devices have been saved, add some using `cargo skyline device add [name] [addr]`",
+            "ERROR".red()
+        ),
+        Error::BadSubnet(subnet) => eprintln!(
+            "{}: Could not parse subnet '{}', expected something like '192.168.1.0/24' (between /16 and /30)",
+            "ERROR".red(), subnet
+        ),
+        Error::NoLocalNetwork => eprintln!(
+            "{}: Could not work out which network this computer is on, pass one using --subnet", "ERROR".red()
+        ),
+        Error::TooManyDevices => eprintln!(
+            "{}: Only install and run can use several devices at once", "ERROR".red()
+        ),
+        Error::InstallsFailed(count) => eprintln!(
+            "{}: Installing to {} {} failed",
+            "ERROR".red(), count, if count == 1 { "device" } else { "devices" }
+        ),
+        Error::BadLockfile(path) => eprintln!(
+            "{}: Could not parse {}, delete it to have it regenerated", "ERROR".red(), path.display()
+        ),
+        Error::BadConfigFile(path) => eprintln!("{}: Could not parse {}", "ERROR".red(), path.display()),
+        Error::ConfigTooNew(path) => eprintln!(
+            "{}: {} was written by a newer version of cargo-skyline, update with `cargo install cargo-skyline`",
+            "ERROR".red(), path.display()
+        ),
+        Error::NotWsl => eprintln!("{}: This only works when running inside WSL", "ERROR".red()),
+        Error::NoWindowsHome => eprintln!(
+            "{}: Could not find the Windows user's home directory using cmd.exe", "ERROR".red()
+        ),
+        Error::SwitchDirExists(path) => eprintln!(
+            "{}: {} already exists, pass --force to move it aside and share the Windows config instead",
+            "ERROR".red(), path.display()
+        ),
+        Error::InvalidMetadata(count) => eprintln!(
+            "{}: Found {} problem{} in the skyline metadata", "ERROR".red(), count, if count == 1 { "" } else { "s" }
+        ),
+        Error::BadTitleId(message) => eprintln!("{}: {}", "ERROR".red(), message),
+        Error::UnknownSetting(name) => eprintln!(
+            "{}: Unknown setting '{}', use `cargo skyline config list` to see every setting", "ERROR".red(), name
+        ),
+        Error::LockedHashMismatch(name) => eprintln!(
+            concat!(
+                "{}: The downloaded {} doesn't match the hash in skyline.lock. If the new file is expected, ",
+                "remove its entry from skyline.lock to lock it again"
+            ),
+            "ERROR".red(), name
+        ),
+        Error::PreflightFailed(title_id) => eprintln!(
+            "{}: {} isn't set up to load plugins, install Skyline using `cargo skyline install-skyline`",
+            "ERROR".red(), title_id
+        ),
+        Error::UnknownPlugin(name) => eprintln!(
+            "{}: No known plugin is named '{}', try passing its GitHub repository as 'owner/repo' instead",
+            "ERROR".red(), name
+        ),
+        Error::NoReleasePlugin(repo, tag) => eprintln!(
+            "{}: The {} release of {} doesn't contain any plugins", "ERROR".red(), tag, repo
+        ),
+        Error::InsufficientSpace(needed, available) => eprintln!(
+            "{}: Not enough free space on the SD card, {} bytes are needed but only {} are available",
+            "ERROR".red(), needed, available
+        ),
+        Error::SdCardFull => eprintln!("{}: The SD card ran out of space, free some up and try again", "ERROR".red()),
+        Error::NoDeployManifest => {
+            eprintln!("{}: No deploy manifest was found in Cargo.toml", "ERROR".red());
+            eprintln!("\n{}:\n\n[package.metadata.skyline.deploy]\n\"libmy_plugin.nro\" = \"target/aarch64-skyline-switch/release/libmy_plugin.nro\"\n\"libnro_hook.nro\" = \"https://github.com/ultimate-research/nro-hook-plugin/releases/download/v0.1.1/libnro_hook.nro\"\n", "Example".bright_blue());
+        }
+        Error::MissingDeployFile(path) => eprintln!("{}: '{}' is listed in the deploy manifest but doesn't exist, has it been built?", "ERROR".red(), path.display()),
+        Error::MissingAssetDir(path) => eprintln!(
+            "{}: '{}' is listed under assets in Cargo.toml but isn't a directory", "ERROR".red(), path.display()
+        ),
+        Error::UnknownPackage(name) => eprintln!("{}: '{}' isn't a plugin in this workspace", "ERROR".red(), name),
+        Error::MapForSeveralPlugins => eprintln!("{}: --emit-map can only be used when building one plugin, as every plugin would be linked to the same map", "ERROR".red()),
+        Error::NroTooBig(name, size, max, previous_size) => {
+            eprintln!("{}: {}", "ERROR".red(), build::size_report(&name, size, max, previous_size));
+            eprintln!("{}: Raise max-nro-size in Cargo.toml, or remove deny-oversized to only warn about it", "NOTE".bright_blue());
+        }
+        Error::NoDeployedCopy(name) => {
+            eprintln!("{}: There's no installed copy of {} to compare with", "ERROR".red(), name);
+            eprintln!("{}: Install the plugin to keep a copy of it, or pass an older NRO, NSO or ELF to compare with", "NOTE".bright_blue());
+        }
+        Error::NotLogging(ip) => {
+            eprintln!("{}: Nothing is sending logs from {}", "ERROR".red(), ip);
+            eprintln!("{}: Make sure the game is running with a plugin that uses skyline's logger, or pass --wait to keep trying until it is", "NOTE".bright_blue());
+        }
+        Error::NoLinkMap => {
+            eprintln!("{}: The linker didn't write a map", "ERROR".red());
+            eprintln!("{}: Make sure the target's linker understands -Map, as rust-lld does", "NOTE".bright_blue());
+        }
+        Error::BadElf => eprintln!("{}: Not a 64-bit little endian ELF, or it's been cut short", "ERROR".red()),
+        Error::BadModule => eprintln!("{}: Not a valid NRO or NSO, or its segments can't be laid out as the other", "ERROR".red()),
+        Error::CorruptModule(name, reason) => {
+            eprintln!("{}: {} looks corrupt and wasn't installed: {}", "ERROR".red(), name, reason);
+            eprintln!("{}: An interrupted build can leave a plugin like this, building it again usually fixes it", "NOTE".bright_blue());
+        }
+        Error::UnresolvedImports(count) => {
+            eprintln!("{}: {} of the plugin's imports can't be resolved, so it will fail to load", "ERROR".red(), count);
+            eprintln!("{}: A newer Skyline may export them, or they may come from another plugin which can be passed with --with", "NOTE".bright_blue());
+        }
+        Error::NoCrashReport(Some(name)) => {
+            eprintln!("{}: There's no crash report named '{}'", "ERROR".red(), name);
+            eprintln!("{}: `cargo skyline crash --list` shows the ones on the console", "NOTE".bright_blue());
+        }
+        Error::NoCrashReport(None) => {
+            eprintln!("{}: There are no crash reports on the console, in {}", "ERROR".red(), installer::CRASH_REPORT_DIRS.join(" or "));
+        }
+        Error::BadNroName(name) => {
+            eprintln!("{}: The plugin can't be installed as '{}', Skyline only loads files ending in .nro from the plugin directory", "ERROR".red(), name);
+            eprintln!("{}: nro-name in Cargo.toml should be a file name such as \"libmy_plugin.nro\"", "NOTE".bright_blue());
+        }
+        Error::NoWorkspacePlugins => eprintln!("{}: No plugins to build, does the workspace have any cdylib crates which aren't skipped?", "ERROR".red()),
+        Error::SkylineTooOld(title_id, found, required) => {
+            eprintln!("{}: The plugin needs Skyline {} or newer, but {} has {}", "ERROR".red(), required, title_id, found);
+            eprintln!("\n{}: Update it with `cargo skyline update-skyline --replace`, or pass --ignore-skyline-version to install anyway", "NOTE".bright_blue());
+        }
+        Error::VerifyFailed(path) => eprintln!("{}: Uploaded file '{}' does not match the local copy, try installing again", "ERROR".red(), path),
+    }
+}
+
 fn self_update(from_master: bool, git: String) -> Result<()> {
     let mut args = vec!["install", "--force"];
 
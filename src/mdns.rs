@@ -0,0 +1,216 @@
+//! Just enough multicast DNS to find consoles running an ftpd which announces itself
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+const MDNS_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_SRV: u16 = 33;
+
+// class IN, with the top bit asking for the answer to be sent straight back to us
+const CLASS_IN_UNICAST: u16 = 0x8001;
+
+const RESOLVE_ATTEMPTS: usize = 3;
+const RESPONSE_WAIT: Duration = Duration::from_millis(1000);
+
+/// A service found by browsing
+pub struct Service {
+    /// The host the service is running on, such as `switch.local`
+    pub host: String,
+    pub ip: Ipv4Addr,
+    pub port: u16,
+}
+
+/// Whether a name should be looked up using mDNS rather than regular DNS
+pub fn is_mdns_name(name: &str) -> bool {
+    name.trim_end_matches('.').to_ascii_lowercase().ends_with(".local")
+}
+
+fn query(name: &str, record_type: u16) -> Vec<u8> {
+    // id, flags, one question and no other records
+    let mut packet = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+
+    for label in name.trim_end_matches('.').split('.') {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+
+    packet.extend_from_slice(&record_type.to_be_bytes());
+    packet.extend_from_slice(&CLASS_IN_UNICAST.to_be_bytes());
+
+    packet
+}
+
+fn read_u16(packet: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*packet.get(pos)?, *packet.get(pos + 1)?]))
+}
+
+/// Read a possibly compressed name, returning it along with the position after it
+fn read_name(packet: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels = vec![];
+    let mut end = None;
+
+    // bound the number of jumps so a malicious packet can't loop forever
+    for _ in 0..128 {
+        let len = *packet.get(pos)? as usize;
+
+        if len == 0 {
+            return Some((labels.join("."), end.unwrap_or(pos + 1)));
+        } else if len & 0xc0 == 0xc0 {
+            let offset = (read_u16(packet, pos)? & 0x3fff) as usize;
+            end.get_or_insert(pos + 2);
+            pos = offset;
+        } else {
+            labels.push(String::from_utf8_lossy(packet.get(pos + 1..pos + 1 + len)?).into_owned());
+            pos += len + 1;
+        }
+    }
+
+    None
+}
+
+/// A resource record from a response, with its data left unparsed
+struct Record {
+    name: String,
+    record_type: u16,
+    data_pos: usize,
+    data: Vec<u8>,
+}
+
+fn parse_records(packet: &[u8]) -> Option<Vec<Record>> {
+    let questions = read_u16(packet, 4)?;
+    let records = read_u16(packet, 6)? as usize + read_u16(packet, 8)? as usize + read_u16(packet, 10)? as usize;
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = read_name(packet, pos)?.1 + 4;
+    }
+
+    let mut parsed = vec![];
+    for _ in 0..records {
+        let (name, next) = read_name(packet, pos)?;
+        let record_type = read_u16(packet, next)?;
+        let len = read_u16(packet, next + 8)? as usize;
+        let data_pos = next + 10;
+
+        parsed.push(Record { name, record_type, data_pos, data: packet.get(data_pos..data_pos + len)?.to_vec() });
+        pos = data_pos + len;
+    }
+
+    Some(parsed)
+}
+
+fn socket() -> Option<UdpSocket> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.set_read_timeout(Some(Duration::from_millis(100))).ok()?;
+
+    Some(socket)
+}
+
+/// Send a query and gather every record which comes back within the wait
+fn ask(socket: &UdpSocket, name: &str, record_type: u16, mut done: impl FnMut(&[u8], &[Record]) -> bool) {
+    if socket.send_to(&query(name, record_type), SocketAddr::new(MDNS_ADDR.into(), MDNS_PORT)).is_err() {
+        return;
+    }
+
+    let start = Instant::now();
+    let mut buf = [0u8; 9000];
+
+    while start.elapsed() < RESPONSE_WAIT {
+        let len = match socket.recv_from(&mut buf) {
+            Ok((len, _)) => len,
+            Err(_) => continue,
+        };
+
+        let packet = &buf[..len];
+        if let Some(records) = parse_records(packet) {
+            if done(packet, &records) {
+                return;
+            }
+        }
+    }
+}
+
+fn a_record(record: &Record) -> Option<Ipv4Addr> {
+    match record.data[..] {
+        [a, b, c, d] if record.record_type == TYPE_A => Some(Ipv4Addr::new(a, b, c, d)),
+        _ => None
+    }
+}
+
+/// Look up the address of a `.local` host
+pub fn resolve(name: &str) -> Option<Ipv4Addr> {
+    let socket = socket()?;
+    let name = name.trim_end_matches('.');
+    let mut found = None;
+
+    for _ in 0..RESOLVE_ATTEMPTS {
+        ask(&socket, name, TYPE_A, |_, records| {
+            found = records.iter()
+                .filter(|record| record.name.eq_ignore_ascii_case(name))
+                .find_map(a_record);
+
+            found.is_some()
+        });
+
+        if found.is_some() {
+            break;
+        }
+    }
+
+    found
+}
+
+/// Find everything advertising the given service, such as `_ftp._tcp.local`
+pub fn browse(service: &str) -> Vec<Service> {
+    let socket = match socket() {
+        Some(socket) => socket,
+        None => return vec![]
+    };
+
+    let mut instances = vec![];
+    let mut targets = HashMap::new();
+    let mut addrs = HashMap::new();
+
+    ask(&socket, service, TYPE_PTR, |packet, records| {
+        for record in records {
+            match record.record_type {
+                TYPE_PTR if record.name.eq_ignore_ascii_case(service) => {
+                    if let Some((instance, _)) = read_name(packet, record.data_pos) {
+                        instances.push(instance.to_ascii_lowercase());
+                    }
+                }
+                TYPE_SRV => {
+                    if let (Some(port), Some((host, _))) = (read_u16(&record.data, 4), read_name(packet, record.data_pos + 6)) {
+                        targets.insert(record.name.to_ascii_lowercase(), (host, port));
+                    }
+                }
+                TYPE_A => {
+                    if let Some(ip) = a_record(record) {
+                        addrs.insert(record.name.to_ascii_lowercase(), ip);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        false
+    });
+
+    instances.sort();
+    instances.dedup();
+
+    instances.into_iter()
+        .filter_map(|instance| {
+            let (host, port) = targets.get(&instance)?.clone();
+            // not every responder sends the address along with the service, so ask for it
+            let ip = addrs.get(&host.to_ascii_lowercase()).copied().or_else(|| resolve(&host))?;
+
+            Some(Service { host, ip, port })
+        })
+        .collect()
+}
@@ -0,0 +1,96 @@
+use std::fs;
+use std::path::PathBuf;
+use structopt::StructOpt;
+use owo_colors::OwoColorize;
+use crate::build;
+use crate::error::{Error, Result};
+use crate::nxo::Module;
+use crate::symbols::{self, DynamicSymbol, Elf};
+
+/// Flags for `nm`
+#[derive(StructOpt)]
+pub struct NmOptions {
+    #[structopt(
+        parse(from_str = crate::wsl::translate_path),
+        help = "The ELF, NRO or NSO to list the symbols of, building the plugin if not given"
+    )]
+    pub file: Option<PathBuf>,
+
+    #[structopt(long)]
+    pub release: bool,
+
+    #[structopt(long, conflicts_with = "release", help = "Build with a custom cargo profile")]
+    pub profile: Option<String>,
+
+    #[structopt(short, long, help = "Package in the workspace to build")]
+    pub package: Option<String>,
+
+    #[structopt(long, conflicts_with = "imports", help = "Only list what the plugin exports")]
+    pub exports: bool,
+
+    #[structopt(long, help = "Only list what the plugin needs from other modules")]
+    pub imports: bool,
+
+    #[structopt(long, help = "Show the names as they are in the symbol table")]
+    pub no_demangle: bool,
+}
+
+/// The dynamic symbols of an ELF, or of an NRO or NSO whose .dynsym is part of its .rodata
+pub fn read_dynamic_symbols(data: &[u8]) -> Result<Vec<DynamicSymbol>> {
+    if data.starts_with(b"\x7fELF") {
+        return Ok(Elf::parse(data)?.dynamic_symbols());
+    }
+
+    let module = Module::read(data)?;
+    let (strtab, symtab) = module.dynamic_tables().ok_or(Error::BadModule)?;
+    let rodata = &module.rodata;
+    let region = |addr: u64, size: u32| {
+        let offset = addr.checked_sub(rodata.addr as u64).ok_or(Error::BadModule)? as usize;
+        rodata.data.get(offset..offset + size as usize).ok_or(Error::BadModule)
+    };
+
+    Ok(symbols::dynamic_symbols(region(symtab, module.dynsym.1)?, region(strtab, module.dynstr.1)?))
+}
+
+/// List the symbols a plugin exports and the ones it imports, like `nm -D` would
+pub fn nm(options: NmOptions) -> Result<()> {
+    let path = match &options.file {
+        Some(path) => path.clone(),
+        None => {
+            let mut args = vec![];
+            build::profile_args(&mut args, options.release, options.profile.as_deref());
+            if let Some(package) = &options.package {
+                build::package_args(&mut args, std::slice::from_ref(package));
+            }
+
+            build::build_get_elf_and_nro(args, false)?.0
+        }
+    };
+
+    let mut symbols: Vec<DynamicSymbol> = read_dynamic_symbols(&fs::read(&path)?)?
+        .into_iter()
+        .filter(|symbol| if symbol.imported { !options.exports } else { !options.imports })
+        .collect();
+
+    // exports first in the order they're laid out, then imports by name
+    symbols.sort_by(|a, b| a.imported.cmp(&b.imported).then(a.addr.cmp(&b.addr)).then_with(|| a.name.cmp(&b.name)));
+
+    for symbol in &symbols {
+        let name = if options.no_demangle {
+            symbol.name.clone()
+        } else {
+            format!("{:#}", rustc_demangle::demangle(&symbol.name))
+        };
+
+        if symbol.imported {
+            println!("{:16} {} {}", "", symbol.letter().bright_black(), name);
+        } else {
+            println!("{:016x} {} {}", symbol.addr, symbol.letter().bright_blue(), name);
+        }
+    }
+
+    let imports = symbols.iter().filter(|symbol| symbol.imported).count();
+    eprintln!("\n{} exported, {} imported", symbols.len() - imports, imports);
+
+    Ok(())
+}
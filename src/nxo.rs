@@ -0,0 +1,521 @@
+use std::convert::TryInto;
+use std::fs;
+use std::path::{Path, PathBuf};
+use sha2::{Digest, Sha256};
+use crate::error::{Error, Result};
+
+const NRO_HEADER_START: usize = 0x10;
+const NRO_HEADER_END: usize = 0x80;
+const NSO_HEADER_SIZE: usize = 0x100;
+
+const DT_NULL: u64 = 0;
+const DT_STRTAB: u64 = 5;
+const DT_SYMTAB: u64 = 6;
+
+// NSO flags, one bit per segment for each of compression and hash checking
+const NSO_COMPRESSED: u32 = 0b000_111;
+const NSO_HASHED: u32 = 0b111_000;
+
+/// One of the three segments of a module, as it's laid out in memory
+pub struct Segment {
+    pub addr: u32,
+    pub data: Vec<u8>,
+}
+
+/// The contents of an NRO or NSO, which only differ in how they're stored
+pub struct Module {
+    pub text: Segment,
+    pub rodata: Segment,
+    pub data: Segment,
+    pub bss_size: u32,
+    pub build_id: [u8; 0x20],
+    /// The .dynstr and .dynsym regions as offsets and sizes, relative to .rodata
+    pub dynstr: (u32, u32),
+    pub dynsym: (u32, u32),
+}
+
+fn read_u32(file: &[u8], offset: usize) -> Result<u32> {
+    file.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(Error::BadModule)
+}
+
+fn slice(file: &[u8], offset: u32, size: u32) -> Result<&[u8]> {
+    file.get(offset as usize..offset as usize + size as usize).ok_or(Error::BadModule)
+}
+
+/// Round up to the next page, which every segment has to start on
+fn page_align(size: usize) -> usize {
+    (size + 0xFFF) & !0xFFF
+}
+
+impl Module {
+    /// Read an NRO or NSO, going by its magic
+    pub fn read(file: &[u8]) -> Result<Self> {
+        match (file.get(..4), file.get(NRO_HEADER_START..NRO_HEADER_START + 4)) {
+            (Some(b"NSO0"), _) => Self::from_nso(file),
+            (_, Some(b"NRO0")) => Self::from_nro(file),
+            _ => Err(Error::BadModule)
+        }
+    }
+
+    pub fn from_nro(file: &[u8]) -> Result<Self> {
+        let segment = |index: usize| -> Result<Segment> {
+            let offset = read_u32(file, 0x20 + index * 8)?;
+            let size = read_u32(file, 0x24 + index * 8)?;
+
+            // NROs are loaded as-is, so where a segment is in the file is where it is in memory
+            Ok(Segment { addr: offset, data: slice(file, offset, size)?.to_vec() })
+        };
+
+        Ok(Module {
+            text: segment(0)?,
+            rodata: segment(1)?,
+            data: segment(2)?,
+            bss_size: read_u32(file, 0x38)?,
+            build_id: slice(file, 0x40, 0x20)?.try_into().unwrap(),
+            dynstr: (read_u32(file, 0x70)?, read_u32(file, 0x74)?),
+            dynsym: (read_u32(file, 0x78)?, read_u32(file, 0x7C)?),
+        })
+    }
+
+    pub fn from_nso(file: &[u8]) -> Result<Self> {
+        let flags = read_u32(file, 0xC)?;
+
+        let segment = |index: usize| -> Result<Segment> {
+            let header = 0x10 + index * 0x10;
+            let offset = read_u32(file, header)?;
+            let addr = read_u32(file, header + 4)?;
+            let size = read_u32(file, header + 8)?;
+            let compressed_size = read_u32(file, 0x60 + index * 4)?;
+
+            let data = if flags & (1 << index) != 0 {
+                lz4::block::decompress(slice(file, offset, compressed_size)?, Some(size as i32))
+                    .map_err(|_| Error::BadModule)?
+            } else {
+                slice(file, offset, size)?.to_vec()
+            };
+
+            Ok(Segment { addr, data })
+        };
+
+        Ok(Module {
+            text: segment(0)?,
+            rodata: segment(1)?,
+            data: segment(2)?,
+            bss_size: read_u32(file, 0x3C)?,
+            build_id: slice(file, 0x40, 0x20)?.try_into().unwrap(),
+            dynstr: (read_u32(file, 0x90)?, read_u32(file, 0x94)?),
+            dynsym: (read_u32(file, 0x98)?, read_u32(file, 0x9C)?),
+        })
+    }
+
+    fn segments(&self) -> [&Segment; 3] {
+        [&self.text, &self.rodata, &self.data]
+    }
+
+    /// Read a little endian value from wherever it is in the module once loaded
+    fn read_at(&self, addr: u64, size: usize) -> Option<u64> {
+        let segments = self.segments();
+        let segment = segments.iter().rev().find(|segment| addr >= segment.addr as u64)?;
+        let offset = (addr - segment.addr as u64) as usize;
+        let bytes = segment.data.get(offset..offset + size)?;
+
+        Some(bytes.iter().rev().fold(0, |value, &byte| value << 8 | byte as u64))
+    }
+
+    /// The entries of the module's .dynamic, found through MOD0 the same way rtld finds them, or what
+    /// stops them being found
+    pub fn dynamic(&self) -> std::result::Result<Vec<(u64, u64)>, String> {
+        let mod0 = self.read_at(4, 4).ok_or("its .text is too small to point to MOD0")?;
+        if self.read_at(mod0, 4) != Some(u32::from_le_bytes(*b"MOD0") as u64) {
+            return Err(format!("there's no MOD0 header at 0x{:x}, which rtld needs to load it", mod0));
+        }
+
+        let dynamic = self.read_at(mod0 + 4, 4).ok_or("its MOD0 header is cut short")?;
+        let dynamic = (mod0 as i64 + dynamic as i32 as i64) as u64;
+
+        let mut entries = vec![];
+        for entry in (dynamic..).step_by(0x10) {
+            let tag = self.read_at(entry, 8).zip(self.read_at(entry + 8, 8));
+            match tag {
+                Some((DT_NULL, _)) => return Ok(entries),
+                Some(entry) => entries.push(entry),
+                None => return Err(format!("its .dynamic at 0x{:x} runs off the end of the module", dynamic)),
+            }
+        }
+
+        unreachable!()
+    }
+
+    /// Where .dynstr and .dynsym are, going by .dynamic. These are what rtld goes by, so they're
+    /// trusted over the offsets in the header.
+    pub fn dynamic_tables(&self) -> Option<(u64, u64)> {
+        let dynamic = self.dynamic().ok()?;
+        let find = |tag: u64| dynamic.iter().find(|(entry, _)| *entry == tag).map(|(_, value)| *value);
+
+        Some((find(DT_STRTAB)?, find(DT_SYMTAB)?))
+    }
+
+    pub fn to_nso(&self, compress: bool) -> Result<Vec<u8>> {
+        let mut header = vec![0; NSO_HEADER_SIZE];
+        let mut body = vec![];
+
+        let put = |header: &mut Vec<u8>, offset: usize, value: u32| {
+            header[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        };
+
+        header[..4].copy_from_slice(b"NSO0");
+        put(&mut header, 0xC, if compress { NSO_COMPRESSED | NSO_HASHED } else { NSO_HASHED });
+
+        for (index, segment) in self.segments().iter().enumerate() {
+            let stored = if compress {
+                lz4::block::compress(&segment.data, None, false).map_err(|_| Error::BadModule)?
+            } else {
+                segment.data.clone()
+            };
+
+            let header_offset = 0x10 + index * 0x10;
+            put(&mut header, header_offset, (NSO_HEADER_SIZE + body.len()) as u32);
+            put(&mut header, header_offset + 4, segment.addr);
+            put(&mut header, header_offset + 8, segment.data.len() as u32);
+            put(&mut header, 0x60 + index * 4, stored.len() as u32);
+
+            let hash_offset = 0xA0 + index * 0x20;
+            header[hash_offset..hash_offset + 0x20].copy_from_slice(&Sha256::digest(&segment.data));
+
+            body.extend(stored);
+        }
+
+        put(&mut header, 0x3C, self.bss_size);
+        header[0x40..0x60].copy_from_slice(&self.build_id);
+        put(&mut header, 0x90, self.dynstr.0);
+        put(&mut header, 0x94, self.dynstr.1);
+        put(&mut header, 0x98, self.dynsym.0);
+        put(&mut header, 0x9C, self.dynsym.1);
+
+        header.extend(body);
+
+        Ok(header)
+    }
+
+    pub fn to_nro(&self) -> Result<Vec<u8>> {
+        // the header lives inside .text, in the space crt0 leaves for it after the branch and MOD0 offset
+        if self.text.addr != 0 || self.text.data.len() < NRO_HEADER_END {
+            return Err(Error::BadModule);
+        }
+
+        // NROs have a single base, so any gap between segments has to be filled in
+        let mut file = vec![];
+        let mut offsets = vec![];
+        for segment in self.segments() {
+            if (segment.addr as usize) < file.len() {
+                return Err(Error::BadModule);
+            }
+
+            file.resize(segment.addr as usize, 0);
+            offsets.push((file.len() as u32, page_align(segment.data.len()) as u32));
+            file.extend(&segment.data);
+            file.resize(page_align(file.len()), 0);
+        }
+
+        let put = |file: &mut Vec<u8>, offset: usize, value: u32| {
+            file[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        };
+
+        file[NRO_HEADER_START..NRO_HEADER_END].iter_mut().for_each(|byte| *byte = 0);
+        file[NRO_HEADER_START..NRO_HEADER_START + 4].copy_from_slice(b"NRO0");
+        let total_size = file.len() as u32;
+        put(&mut file, 0x18, total_size);
+
+        for (index, (offset, size)) in offsets.into_iter().enumerate() {
+            put(&mut file, 0x20 + index * 8, offset);
+            put(&mut file, 0x24 + index * 8, size);
+        }
+
+        put(&mut file, 0x38, self.bss_size);
+        file[0x40..0x60].copy_from_slice(&self.build_id);
+        put(&mut file, 0x70, self.dynstr.0);
+        put(&mut file, 0x74, self.dynstr.1);
+        put(&mut file, 0x78, self.dynsym.0);
+        put(&mut file, 0x7C, self.dynsym.1);
+
+        Ok(file)
+    }
+}
+
+/// Look for anything which would stop rtld from loading an NRO or NSO, or crash the game when it
+/// tries to, giving back what's wrong
+pub fn validate(file: &[u8]) -> std::result::Result<(), String> {
+    let is_nro = file.get(NRO_HEADER_START..NRO_HEADER_START + 4) == Some(b"NRO0");
+    let is_nso = file.get(..4) == Some(b"NSO0");
+    if !is_nro && !is_nso {
+        return Err(String::from("it doesn't start with an NRO or NSO header"));
+    }
+
+    if is_nro {
+        let read = |offset: usize| read_u32(file, offset).map_err(|_| format!("it's only {} bytes, too small for an NRO header", file.len()));
+        let size = read(0x18)? as usize;
+        if size > file.len() {
+            return Err(format!("the header says it's 0x{:x} bytes but the file is only 0x{:x}, it was cut short", size, file.len()));
+        }
+
+        let mut end = 0;
+        for (index, name) in [".text", ".rodata", ".data"].iter().enumerate() {
+            let offset = read(0x20 + index * 8)? as usize;
+            let segment_size = read(0x24 + index * 8)? as usize;
+
+            if page_align(offset) != offset {
+                return Err(format!("{} doesn't start on a page boundary (0x{:x})", name, offset));
+            }
+            if offset < end {
+                return Err(format!("{} overlaps the segment before it", name));
+            }
+            end = offset + segment_size;
+            if end > size {
+                return Err(format!("{} runs past the end of the NRO", name));
+            }
+        }
+    }
+
+    let module = Module::read(file).map_err(|_| String::from("its segments can't be read"))?;
+    for (name, segment) in [(".text", &module.text), (".rodata", &module.rodata), (".data", &module.data)] {
+        if page_align(segment.addr as usize) != segment.addr as usize {
+            return Err(format!("{} doesn't start on a page boundary (0x{:x})", name, segment.addr));
+        }
+    }
+
+    let dynamic = module.dynamic()?;
+    let in_rodata = |addr: u64| addr >= module.rodata.addr as u64 && addr < (module.rodata.addr as usize + module.rodata.data.len()) as u64;
+    for (tag, name) in [(DT_STRTAB, ".dynstr"), (DT_SYMTAB, ".dynsym")] {
+        match dynamic.iter().find(|(entry, _)| *entry == tag) {
+            Some((_, addr)) if in_rodata(*addr) => {}
+            Some((_, addr)) => return Err(format!("its .dynamic points to {} at 0x{:x}, outside of .rodata", name, addr)),
+            None => return Err(format!("its .dynamic has no {}", name)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Refuse an NRO or NSO which wouldn't load, before it ends up on the console
+pub fn ensure_valid(name: &str, file: &[u8]) -> Result<()> {
+    validate(file).map_err(|reason| Error::CorruptModule(name.to_owned(), reason))
+}
+
+/// The name rtld knows a module by, which Skyline plugins put at the start of .rodata as a zero,
+/// a length and then the name
+fn module_name(rodata: &[u8]) -> Option<String> {
+    let length = read_u32(rodata, 4).ok()? as usize;
+    if read_u32(rodata, 0).ok()? != 0 || length == 0 || length > 0x200 {
+        return None;
+    }
+
+    let name = rodata.get(8..8 + length)?;
+    let name = &name[..name.iter().position(|&byte| byte == 0).unwrap_or(name.len())];
+
+    std::str::from_utf8(name).ok().filter(|name| !name.is_empty()).map(String::from)
+}
+
+/// A string from a fixed size field of a NACP, which is nul padded
+fn nacp_str(nacp: &[u8], offset: usize, size: usize) -> String {
+    let field = nacp.get(offset..offset + size).unwrap_or_default();
+    let end = field.iter().position(|&byte| byte == 0).unwrap_or(field.len());
+
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// What's in homebrew's asset section, which follows the NRO itself in the file
+fn print_assets(file: &[u8], nro_size: usize) {
+    if file.get(nro_size..nro_size + 4) != Some(b"ASET") {
+        return;
+    }
+
+    let asset = |index: usize| -> Option<(u32, u32)> {
+        let header = nro_size + 8 + index * 0x10;
+        Some((read_u32(file, header).ok()?, read_u32(file, header + 8).ok()?))
+    };
+
+    println!("\nAssets:");
+    for (index, name) in ["icon", "nacp", "romfs"].iter().enumerate() {
+        match asset(index) {
+            Some((_, 0)) | None => println!("  {:<7} none", name),
+            Some((_, size)) => println!("  {:<7} 0x{:x} bytes", name, size),
+        }
+    }
+
+    if let Some((offset, size)) = asset(1).filter(|(_, size)| *size >= 0x4000) {
+        if let Ok(nacp) = slice(file, nro_size as u32 + offset, size) {
+            // the first language's title, then the display version
+            println!("  name    {}", nacp_str(nacp, 0, 0x200));
+            println!("  author  {}", nacp_str(nacp, 0x200, 0x100));
+            println!("  version {}", nacp_str(nacp, 0x3060, 0x10));
+        }
+    }
+}
+
+/// Print everything worth knowing about an NRO or NSO when working out where it came from
+pub fn info(input: &Path) -> Result<()> {
+    let file = fs::read(input)?;
+    let module = Module::read(&file)?;
+    let is_nro = file.get(NRO_HEADER_START..NRO_HEADER_START + 4) == Some(b"NRO0");
+
+    println!("{}: {}, 0x{:x} bytes", input.display(), if is_nro { "NRO" } else { "NSO" }, file.len());
+
+    if is_nro {
+        println!("Header version 0x{:x}, size 0x{:x}, flags 0x{:x}", read_u32(&file, 0x14)?, read_u32(&file, 0x18)?, read_u32(&file, 0x1C)?);
+    } else {
+        let flags = read_u32(&file, 0xC)?;
+        println!(
+            "Flags 0x{:x} ({}compressed, {}hashed)",
+            flags, if flags & NSO_COMPRESSED == 0 { "un" } else { "" }, if flags & NSO_HASHED == 0 { "not " } else { "" }
+        );
+    }
+
+    println!("\n{:<7} {:>10} {:>10}", "Segment", "Address", "Size");
+    for (name, segment) in [(".text", &module.text), (".rodata", &module.rodata), (".data", &module.data)] {
+        println!("{:<7} 0x{:08x} 0x{:08x}", name, segment.addr, segment.data.len());
+    }
+    println!("{:<7} {:>10} 0x{:08x}", ".bss", "", module.bss_size);
+
+    // build ids are usually shorter than the space for them, the rest is zeroes
+    let build_id_end = module.build_id.iter().rposition(|&byte| byte != 0).map(|end| end + 1).unwrap_or(0);
+    let build_id: String = module.build_id[..build_id_end].iter().map(|byte| format!("{:02X}", byte)).collect();
+    println!("\nBuild id:    {}", if build_id.is_empty() { "none" } else { &build_id });
+
+    if let Ok(mod0) = read_u32(&module.text.data, 4) {
+        let mod0 = mod0 as usize;
+        if module.text.data.get(mod0..mod0 + 4) == Some(b"MOD0") {
+            println!("MOD0:        0x{:x}, .dynamic at 0x{:x}", mod0, mod0 as i64 + read_u32(&module.text.data, mod0 + 4)? as i32 as i64);
+        }
+    }
+
+    println!("Module name: {}", module_name(&module.rodata.data).unwrap_or_else(|| String::from("none")));
+    println!("Dynamic:     .dynstr 0x{:x} bytes, .dynsym 0x{:x} bytes", module.dynstr.1, module.dynsym.1);
+
+    match crate::build_info::read(&module.rodata.data) {
+        Some(info) => println!("Built from:  {}", info),
+        None => println!("Built from:  unknown, it has no cargo-skyline build info"),
+    }
+
+    if is_nro {
+        print_assets(&file, read_u32(&file, 0x18)? as usize);
+    }
+
+    Ok(())
+}
+
+/// Convert between NRO and NSO going by the output's extension, or dump each segment to a directory
+pub fn convert(input: &Path, output: Option<PathBuf>, dump: Option<PathBuf>, compress: bool) -> Result<()> {
+    let module = Module::read(&fs::read(input)?)?;
+
+    if let Some(dir) = dump {
+        fs::create_dir_all(&dir)?;
+
+        for (name, segment) in [("text", &module.text), ("rodata", &module.rodata), ("data", &module.data)] {
+            let path = dir.join(format!("{}.bin", name));
+            fs::write(&path, &segment.data)?;
+            println!("{:<7} 0x{:08x} 0x{:08x} -> {}", name, segment.addr, segment.data.len(), path.display());
+        }
+        println!("bss     0x{:08x}", module.bss_size);
+
+        return Ok(());
+    }
+
+    let is_nso = input.extension().is_some_and(|ext| ext == "nso");
+    let output = output.unwrap_or_else(|| input.with_extension(if is_nso { "nro" } else { "nso" }));
+
+    // anything but an NRO is taken to be an NSO, as subsdks don't have an extension
+    let converted = if output.extension().is_some_and(|ext| ext == "nro") {
+        module.to_nro()?
+    } else {
+        module.to_nso(compress)?
+    };
+
+    fs::write(&output, converted)?;
+    println!("Converted {} to {}", input.display(), output.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOD0: usize = 0x80;
+
+    /// A module with just enough in it to load: MOD0 in .text pointing at a .dynamic in .data,
+    /// which in turn points at .dynstr and .dynsym in .rodata
+    fn module(strtab: u64) -> Module {
+        let mut text = vec![0; 0x1000];
+        text[4..8].copy_from_slice(&(MOD0 as u32).to_le_bytes());
+        text[MOD0..MOD0 + 4].copy_from_slice(b"MOD0");
+        text[MOD0 + 4..MOD0 + 8].copy_from_slice(&(0x2000 - MOD0 as u32).to_le_bytes());
+
+        let mut data = vec![0; 0x1000];
+        let entries = [(DT_STRTAB, strtab), (DT_SYMTAB, 0x1200), (DT_NULL, 0)];
+        for (index, (tag, value)) in entries.iter().enumerate() {
+            data[index * 0x10..index * 0x10 + 8].copy_from_slice(&tag.to_le_bytes());
+            data[index * 0x10 + 8..index * 0x10 + 0x10].copy_from_slice(&value.to_le_bytes());
+        }
+
+        Module {
+            text: Segment { addr: 0, data: text },
+            rodata: Segment { addr: 0x1000, data: vec![0xAA; 0x1000] },
+            data: Segment { addr: 0x2000, data },
+            bss_size: 0x3000,
+            build_id: [0x12; 0x20],
+            dynstr: (0x100, 0x40),
+            dynsym: (0x200, 0x18),
+        }
+    }
+
+    fn assert_same(a: &Module, b: &Module) {
+        for (a, b) in a.segments().iter().zip(&b.segments()) {
+            assert_eq!(a.addr, b.addr);
+            assert_eq!(a.data.len(), b.data.len());
+        }
+        assert_eq!(a.rodata.data, b.rodata.data);
+        assert_eq!(a.data.data, b.data.data);
+        assert_eq!(a.bss_size, b.bss_size);
+        assert_eq!(a.build_id, b.build_id);
+        assert_eq!((a.dynstr, a.dynsym), (b.dynstr, b.dynsym));
+    }
+
+    #[test]
+    fn nro_nso_round_trip() {
+        let nro = module(0x1100).to_nro().unwrap();
+        validate(&nro).unwrap();
+
+        let from_nro = Module::read(&nro).unwrap();
+        assert_same(&from_nro, &module(0x1100));
+
+        for compress in [false, true].iter() {
+            let nso = from_nro.to_nso(*compress).unwrap();
+            validate(&nso).unwrap();
+
+            let from_nso = Module::read(&nso).unwrap();
+            assert_same(&from_nso, &from_nro);
+            assert_eq!(from_nso.to_nro().unwrap(), nro);
+        }
+    }
+
+    #[test]
+    fn rejects_broken_modules() {
+        let nro = module(0x1100).to_nro().unwrap();
+
+        assert!(validate(b"not a module at all").unwrap_err().contains("NRO or NSO header"));
+        assert!(validate(&nro[..0x2000]).unwrap_err().contains("cut short"));
+
+        let mut misaligned = nro.clone();
+        misaligned[0x28..0x2C].copy_from_slice(&0x1010u32.to_le_bytes());
+        assert!(validate(&misaligned).unwrap_err().contains("page boundary"));
+
+        let mut no_mod0 = nro.clone();
+        no_mod0[MOD0..MOD0 + 4].copy_from_slice(&[0; 4]);
+        assert!(validate(&no_mod0).unwrap_err().contains("no MOD0 header"));
+
+        let outside = module(0x2100).to_nro().unwrap();
+        assert!(validate(&outside).unwrap_err().contains("outside of .rodata"));
+    }
+}
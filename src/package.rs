@@ -1,12 +1,14 @@
 use owo_colors::OwoColorize;
-use crate::game_paths::{get_subsdk_path, get_npdm_path, get_plugin_nro_path};
+use crate::game_paths::{self, get_subsdk_path, get_npdm_path, get_plugin_nro_path};
 use crate::error::{Error, Result};
-use crate::cargo_info;
+use crate::cargo_info::{self, ExefsFile, ExefsSource};
+use crate::skyline_version::{self, Version};
 use crate::build;
 use std::io::{Read, Cursor, Write};
 use std::result::Result as StdResult;
-use zip::{ZipArchive, ZipWriter};
+use zip::{DateTime, ZipArchive, ZipWriter, write::FileOptions};
 use std::fs;
+use std::path::{Path, PathBuf};
 
 pub struct Exefs {
     pub main_npdm: Vec<u8>,
@@ -21,7 +23,7 @@ pub fn get_exefs(url: &str) -> Result<Exefs> {
             .bytes().map_err(|_| Error::DownloadError)?
     );
 
-    let mut zip = ZipArchive::new(zip_reader).unwrap();
+    let mut zip = ZipArchive::new(zip_reader)?;
 
     let subsdk1 = zip.by_name("exefs/subsdk9")?.bytes().collect::<StdResult<_, _>>()?;
     let main_npdm = zip.by_name("exefs/main.npdm")?.bytes().collect::<StdResult<_, _>>()?;
@@ -32,28 +34,145 @@ pub fn get_exefs(url: &str) -> Result<Exefs> {
     })
 }
 
-pub fn package(skyline_url: &str, title_id: Option<&str>, out_path: &str) -> Result<()> {
+/// Get Skyline's subsdk from a release, keeping a copy so a pinned release is only downloaded once
+/// and an unpinned one can still be used offline
+pub fn get_subsdk_cached(url: &str) -> Result<Vec<u8>> {
+    let path = crate::settings::switch_home_dir()?
+        .join("skyline-releases")
+        .join(format!("{}-subsdk9", &crate::hash::sha256_hex(url.as_bytes())[..16]));
+
+    // a release like `beta` gets replaced, so only one with a version is trusted not to change
+    let cached = fs::read(&path).ok();
+    if let (Some(subsdk), Some(_)) = (&cached, skyline_version::from_release(url)) {
+        return Ok(subsdk.clone());
+    }
+
+    match get_exefs(url) {
+        Ok(exefs) => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, &exefs.subsdk1)?;
+
+            Ok(exefs.subsdk1)
+        }
+        Err(err) => match cached {
+            Some(subsdk) => {
+                println!("{}: Couldn't download {}, using the copy downloaded before", "Warning".yellow(), url);
+                Ok(subsdk)
+            }
+            None => Err(err)
+        }
+    }
+}
+
+/// Write out an exefs folder for the plugin, with whatever `[package.metadata.skyline.exefs]` lists
+/// and Skyline as the subsdk if it lists nothing
+pub fn build_exefs(elf: &Path, title_id: Option<&str>, dir: &Path, compress: bool) -> Result<()> {
+    let metadata = cargo_info::get_metadata()?;
+
+    let default_files = [ExefsFile {
+        name: metadata.subsdk_name.clone().unwrap_or_else(|| String::from("subsdk9")),
+        source: ExefsSource::Skyline,
+    }];
+    let files = if metadata.exefs.is_empty() { &default_files[..] } else { &metadata.exefs[..] };
+
+    fs::create_dir_all(dir)?;
+
+    // only download Skyline once, and only if something needs it
+    let mut skyline = None;
+    for file in files {
+        let data = match &file.source {
+            ExefsSource::Skyline => {
+                if skyline.is_none() {
+                    let release = crate::installer::skyline_release_url(None, Some(&metadata));
+                    println!("Downloading Skyline from {}...", release);
+                    skyline = Some(get_exefs(&release)?.subsdk1);
+                }
+
+                skyline.clone().unwrap_or_default()
+            }
+            ExefsSource::Plugin => fs::read(build::elf_to_nso(elf, compress)?)?,
+            ExefsSource::File(path) => fs::read(path)?,
+        };
+
+        fs::write(dir.join(&file.name), data)?;
+    }
+
+    // the game won't load any subsdk without an npdm asking for it
+    if !files.iter().any(|file| file.name == "main.npdm") {
+        let main_npdm = match &metadata.npdm_path {
+            Some(path) => fs::read(path).map_err(|_| Error::NoNpdmFileFound)?,
+            None => {
+                let title_id = title_id.map(String::from).or_else(|| metadata.title_id.clone()).ok_or(Error::NoTitleId)?;
+                crate::installer::generate_npdm(&title_id)
+            }
+        };
+
+        fs::write(dir.join("main.npdm"), main_npdm)?;
+    }
+
+    println!("Finished building exefs at '{}'", dir.display());
+
+    Ok(())
+}
+
+pub fn package(skyline_url: &str, title_id: Option<&str>, out_path: Option<&str>, strip: bool, reproducible: bool) -> Result<()> {
+    if reproducible {
+        build::reproducible_env()?;
+    }
+
     let args = vec![String::from("--release")];
-    let nro_path = build::build_get_nro(args)?;
+    let nro_path = build::build_get_nro(args, strip)?;
     let plugin_name = nro_path.file_name().unwrap().to_string_lossy();
     println!("Built {:?}!", plugin_name);
 
     let metadata = cargo_info::get_metadata()?;
 
-    let title_id =
-            title_id.or_else(|| metadata.title_id.as_deref())
-                    .ok_or(Error::NoTitleId)?;
+    // package for every title the project is for unless one is given
+    let title_ids = match title_id {
+        Some(title_id) => vec![title_id.to_owned()],
+        None => metadata.title_ids.clone()
+    };
+
+    if title_ids.is_empty() {
+        return Err(Error::NoTitleId);
+    }
+
+    let layout = game_paths::get_layout(Some(&metadata))?;
+
+    // the target directory isn't always ./target, such as with CARGO_TARGET_DIR or a shared one
+    let out_path = match out_path {
+        Some(out_path) => PathBuf::from(out_path),
+        None => metadata.target_dir.join("release.zip"),
+    };
 
     println!("Downloading latest Skyline release...");
     let exefs = get_exefs(skyline_url)?;
+
+    if let Some(required) = &metadata.min_skyline_version {
+        let required = required.parse::<Version>().map_err(|_| Error::BadSetting("min-skyline-version"))?;
+
+        match skyline_version::from_release(skyline_url).or_else(|| skyline_version::from_binary(&exefs.subsdk1)) {
+            Some(found) if found < required => {
+                eprintln!("{}: The plugin needs Skyline {} or newer, but is being packaged with {}", "Warning".yellow(), required, found);
+            }
+            Some(_) => {}
+            None => eprintln!("{}: Couldn't tell which version of Skyline is being packaged", "Warning".yellow()),
+        }
+    }
     
     println!("Building Zip File...");
     let plugin_data = fs::read(&nro_path)?;
     
-    let mut zip = ZipWriter::new(fs::File::create(out_path)?);
+    let mut zip = ZipWriter::new(fs::File::create(&out_path)?);
 
-    zip.start_file(get_plugin_nro_path(title_id, plugin_name.as_ref()), Default::default())?;
-    zip.write_all(&plugin_data)?;
+    // files are dated when the zip is made unless it has to be the same every time
+    let options = if reproducible {
+        FileOptions::default().last_modified_time(DateTime::default()).unix_permissions(0o644)
+    } else {
+        FileOptions::default()
+    };
 
     // main.npdm
     let main_npdm =
@@ -62,26 +181,29 @@ pub fn package(skyline_url: &str, title_id: Option<&str>, out_path: &str) -> Res
             .map(|path| fs::read(path))
             .transpose()
             .map_err(|_| Error::NoNpdmFileFound)?;
-    let generated_npdm = crate::installer::generate_npdm(&title_id);
-    let main_npdm =
-        main_npdm
-            .as_ref()
-            .unwrap_or_else(|| {
-                eprintln!("\n{}: defaulting to a generated NPDM.", "Warning".yellow());
-                eprintln!("{}: To specify a custom npdm add the following to your Cargo.toml:", "NOTE".bright_blue());
-                eprintln!("\n{}\n", "[package.metadata.skyline]".bright_blue());
-                eprintln!("{}\n", "custom-npdm = \"path/to/your.npdm\"".bright_blue());
-                &generated_npdm
-            });
-    zip.start_file(get_npdm_path(title_id), Default::default())?;
-    zip.write_all(main_npdm)?;
-
-    // subsdk
-    let subsdk_name = metadata.subsdk_name.as_deref().unwrap_or("subsdk9");
-    zip.start_file(get_subsdk_path(title_id, subsdk_name), Default::default())?;
-    zip.write_all(&exefs.subsdk1)?;
-
-    println!("Finished building zip at '{}'", out_path);
+
+    if main_npdm.is_none() {
+        eprintln!("\n{}: defaulting to a generated NPDM.", "Warning".yellow());
+        eprintln!("{}: To specify a custom npdm add the following to your Cargo.toml:", "NOTE".bright_blue());
+        eprintln!("\n{}\n", "[package.metadata.skyline]".bright_blue());
+        eprintln!("{}\n", "custom-npdm = \"path/to/your.npdm\"".bright_blue());
+    }
+
+    for title_id in &title_ids {
+        zip.start_file(get_plugin_nro_path(title_id, layout, plugin_name.as_ref()), options)?;
+        zip.write_all(&plugin_data)?;
+
+        let generated_npdm = crate::installer::generate_npdm(title_id);
+        zip.start_file(get_npdm_path(title_id), options)?;
+        zip.write_all(main_npdm.as_ref().unwrap_or(&generated_npdm))?;
+
+        // subsdk
+        let subsdk_name = metadata.subsdk_name.as_deref().unwrap_or("subsdk9");
+        zip.start_file(get_subsdk_path(title_id, subsdk_name), options)?;
+        zip.write_all(&exefs.subsdk1)?;
+    }
+
+    println!("Finished building zip at '{}'", out_path.display());
 
     Ok(())
 }
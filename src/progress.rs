@@ -0,0 +1,19 @@
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// Create a progress bar for transferring `len` bytes, which is hidden if `show` is false
+pub fn transfer_bar(len: u64, show: bool) -> ProgressBar {
+    if !show {
+        return ProgressBar::hidden()
+    }
+
+    let bar = ProgressBar::new(len);
+    bar.set_style(
+        ProgressStyle::with_template(
+            "[{bar:40.cyan/blue}] {bytes}/{total_bytes} ({binary_bytes_per_sec}, ETA {eta})"
+        )
+        .unwrap()
+        .progress_chars("=> ")
+    );
+
+    bar
+}
@@ -0,0 +1,34 @@
+use std::path::{Path, PathBuf};
+use crate::cargo_info;
+use crate::error::Result;
+use crate::settings::ConfigFile;
+
+/// Find `.skyline/config.toml` in the given directory or any of its parents
+fn find(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(".skyline").join("config.toml"))
+        .find(|path| path.exists())
+}
+
+/// Where the project's config is, or would be created: next to the workspace's Cargo.toml,
+/// or in the current directory outside of a workspace
+pub fn path() -> Result<PathBuf> {
+    let dir = std::env::current_dir()?;
+
+    if let Some(path) = find(&dir) {
+        return Ok(path);
+    }
+
+    let root = cargo_info::get_metadata().map(|metadata| metadata.workspace_root).unwrap_or(dir);
+
+    Ok(root.join(".skyline").join("config.toml"))
+}
+
+/// Load the settings checked into a project as `.skyline/config.toml`, which is empty if there
+/// isn't one
+pub fn load() -> Result<ConfigFile> {
+    match std::env::current_dir().ok().as_deref().and_then(find) {
+        Some(path) => ConfigFile::load(path),
+        None => Ok(ConfigFile::default())
+    }
+}
@@ -0,0 +1,377 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use owo_colors::OwoColorize;
+use crate::error::{Error, Result};
+use crate::ip_addr::{Device, DEVICES_KEY};
+use crate::project_config;
+
+/// Get the `$HOME/.switch` directory, creating it if needed
+pub fn switch_home_dir() -> Result<PathBuf> {
+    let home = dirs::home_dir().ok_or(Error::NoHomeDir)?;
+
+    if !home.exists() {
+        return Err(Error::NoHomeDir)
+    }
+
+    let switch_home_dir = home.join(".switch");
+
+    if !switch_home_dir.exists() {
+        std::fs::create_dir(&switch_home_dir).map_err(|_| Error::CreateSwitchDirDenied)?;
+    }
+
+    Ok(switch_home_dir)
+}
+
+/// Every setting read through `get`, with what it does
+pub static KNOWN_SETTINGS: &[(&str, &str)] = &[
+    ("ip_addr", "IP address or hostname of the switch"),
+    ("default_device", "Saved device to use when none is given"),
+    ("transport", "How to talk to the switch: 'ftp', 'usb' or 'sftp'"),
+    ("ftp_port", "Port of the FTP server on the switch"),
+    ("ftp_user", "Username to log in to the FTP server with"),
+    ("ftp_pass", "Password to log in to the FTP server with"),
+    ("ftp_mode", "How FTP data connections are opened: 'passive', 'epsv', 'active' or 'eprt'"),
+    ("ftp_retries", "How many times to attempt FTP operations before giving up"),
+    ("ftp_keepalive", "Seconds between keepalives on idle FTP connections, 0 to turn off"),
+    ("limit_rate", "Cap on FTP upload speed, e.g. '500K' or '2M'"),
+    ("sftp_port", "Port of the SFTP server on the switch"),
+    ("sftp_user", "Username to log in to the SFTP server with"),
+    ("sftp_identity", "Private key to log in to the SFTP server with"),
+    ("connect_timeout", "Seconds to wait when connecting"),
+    ("read_timeout", "Seconds to wait for the switch to send anything"),
+    ("write_timeout", "Seconds to wait for the switch to accept anything"),
+    ("plugin_dirs.<title id>", "Where to install plugins for a game, e.g. 'sd:/atmosphere/contents/<title id>/romfs/plugins'"),
+];
+
+/// Check a name against `KNOWN_SETTINGS`, where a `<...>` part of a known name stands in for any
+/// one key
+fn is_known(name: &str) -> bool {
+    KNOWN_SETTINGS.iter().any(|(known, _)| match known.split_once('<') {
+        Some((prefix, _)) => name.len() > prefix.len() && name.starts_with(prefix) && !name[prefix.len()..].contains('.'),
+        None => *known == name
+    })
+}
+
+/// The layout of `$HOME/.switch/config.toml` written by this version, which is bumped whenever
+/// older configs need migrating
+const CONFIG_VERSION: i64 = 1;
+
+/// Which layer a setting was found in
+pub enum Source {
+    Env,
+    Project(PathBuf),
+    Global(PathBuf),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Env => write!(f, "environment"),
+            Source::Project(path) | Source::Global(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// A TOML file of settings, such as `$HOME/.switch/config.toml` or a project's
+/// `.skyline/config.toml`
+#[derive(Default)]
+pub struct ConfigFile {
+    path: Option<PathBuf>,
+    values: toml::value::Table,
+}
+
+impl ConfigFile {
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let values = match std::fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text).map_err(|_| Error::BadConfigFile(path.clone()))?,
+            Err(_) => toml::value::Table::new(),
+        };
+
+        Ok(ConfigFile { path: Some(path), values })
+    }
+
+    pub fn path(&self) -> Option<&PathBuf> {
+        self.path.as_ref()
+    }
+
+    /// Find a value by name, where dots go into tables, so `plugin_dirs.<title id>` reads from
+    /// a `[plugin_dirs]` table
+    fn find(&self, name: &str) -> Option<&toml::Value> {
+        let mut parts = name.split('.');
+        let mut value = self.values.get(parts.next()?)?;
+
+        for part in parts {
+            value = value.as_table()?.get(part)?;
+        }
+
+        Some(value)
+    }
+
+    pub fn get(&self, name: &str) -> Option<String> {
+        match self.find(name)? {
+            toml::Value::String(value) => Some(value.clone()),
+            toml::Value::Integer(value) => Some(value.to_string()),
+            toml::Value::Float(value) => Some(value.to_string()),
+            toml::Value::Boolean(value) => Some(value.to_string()),
+            _ => None
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) {
+        let mut parts: Vec<&str> = name.split('.').collect();
+        let last = parts.pop().unwrap_or(name);
+
+        let mut table = &mut self.values;
+        for part in parts {
+            let entry = table.entry(part.to_owned())
+                .or_insert_with(|| toml::Value::Table(toml::value::Table::new()));
+
+            if !entry.is_table() {
+                *entry = toml::Value::Table(toml::value::Table::new());
+            }
+
+            table = entry.as_table_mut().unwrap();
+        }
+
+        table.insert(last.to_owned(), toml::Value::String(value.to_owned()));
+    }
+
+    /// Get a structured value, such as the list of saved devices
+    pub fn get_value(&self, name: &str) -> Option<&toml::Value> {
+        self.values.get(name)
+    }
+
+    pub fn set_value(&mut self, name: &str, value: toml::Value) {
+        self.values.insert(name.to_owned(), value);
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let (table, last) = match name.rsplit_once('.') {
+            Some((table, last)) => (table, last),
+            None => return self.values.remove(name).is_some()
+        };
+
+        let removed = match self.find_table_mut(table) {
+            Some(values) => values.remove(last).is_some(),
+            None => false
+        };
+
+        // don't leave an empty table behind once its last entry is gone
+        if !table.contains('.') && self.find_table_mut(table).is_some_and(|values| values.is_empty()) {
+            self.values.remove(table);
+        }
+
+        removed
+    }
+
+    fn find_table_mut(&mut self, name: &str) -> Option<&mut toml::value::Table> {
+        let mut table = &mut self.values;
+
+        for part in name.split('.') {
+            table = table.get_mut(part)?.as_table_mut()?;
+        }
+
+        Some(table)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(())
+        };
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        // serializing as a value makes sure plain values are written before any tables
+        let text = toml::to_string(&toml::Value::Table(self.values.clone())).unwrap();
+
+        std::fs::write(path, text)
+            .map_err(|_| Error::WriteSettingDenied(path.display().to_string()))
+    }
+}
+
+/// Load `$HOME/.switch/config.toml`, migrating whatever an older version left behind first
+pub fn global_config() -> Result<ConfigFile> {
+    let dir = switch_home_dir()?;
+    let mut config = ConfigFile::load(dir.join("config.toml"))?;
+
+    let version = match config.get_value("version") {
+        Some(toml::Value::Integer(version)) => *version,
+        Some(_) => return Err(Error::BadConfigFile(dir.join("config.toml"))),
+        None => 0
+    };
+
+    if version > CONFIG_VERSION {
+        return Err(Error::ConfigTooNew(dir.join("config.toml")));
+    }
+
+    if version < CONFIG_VERSION {
+        migrate_legacy(&dir, &mut config);
+        config.set_value("version", toml::Value::Integer(CONFIG_VERSION));
+        config.save()?;
+    }
+
+    Ok(config)
+}
+
+/// Before there was a config file, each setting was kept in its own `<name>.txt` and devices were
+/// kept in `devices.json`, or `devices.txt` before that. These are left in place so older versions
+/// keep working.
+fn migrate_legacy(dir: &Path, config: &mut ConfigFile) {
+    for (name, _) in KNOWN_SETTINGS.iter().filter(|(name, _)| !name.contains('<')) {
+        let value = std::fs::read_to_string(dir.join(format!("{}.txt", name)))
+            .ok()
+            .map(|value| value.trim().to_owned())
+            .filter(|value| !value.is_empty());
+
+        if let (Some(value), None) = (value, config.get_value(name)) {
+            config.set(name, &value);
+        }
+    }
+
+    if config.get_value(DEVICES_KEY).is_some() {
+        return;
+    }
+
+    let devices = std::fs::read(dir.join("devices.json"))
+        .ok()
+        .and_then(|data| serde_json::from_slice::<Vec<Device>>(&data).ok())
+        .unwrap_or_else(|| {
+            std::fs::read_to_string(dir.join("devices.txt"))
+                .unwrap_or_default()
+                .lines()
+                .filter_map(|line| {
+                    let mut parts = line.split_whitespace();
+                    let name = parts.next()?.to_owned();
+                    let ip = parts.next()?.to_owned();
+
+                    Some(Device { name, ip, port: None, user: None, pass: None, transport: None, ftp_mode: None })
+                })
+                .collect()
+        });
+
+    if !devices.is_empty() {
+        if let Ok(devices) = toml::Value::try_from(devices) {
+            config.set_value(DEVICES_KEY, devices);
+        }
+    }
+}
+
+/// Read an override for a setting from the environment, such as `CARGO_SKYLINE_FTP_PORT` for
+/// `ftp_port`
+pub fn env(name: &str) -> Option<String> {
+    let var = format!("CARGO_SKYLINE_{}", name.to_ascii_uppercase().replace(['-', '.'], "_"));
+
+    std::env::var(var).ok().filter(|value| !value.is_empty())
+}
+
+/// Find a setting along with where it came from, checking the environment, then the project's
+/// `.skyline/config.toml`, then `$HOME/.switch/config.toml`
+pub fn lookup(name: &str) -> Result<Option<(String, Source)>> {
+    if let Some(value) = env(name) {
+        return Ok(Some((value, Source::Env)));
+    }
+
+    let project = project_config::load()?;
+    if let (Some(value), Some(path)) = (project.get(name), project.path()) {
+        return Ok(Some((value, Source::Project(path.clone()))));
+    }
+
+    let global = global_config()?;
+
+    Ok(global.get(name).zip(global.path().cloned()).map(|(value, path)| (value, Source::Global(path))))
+}
+
+/// Read a setting, ignoring any config file which can't be parsed
+pub fn get(name: &str) -> Option<String> {
+    lookup(name).ok().flatten().map(|(value, _)| value)
+}
+
+/// Store a setting in `$HOME/.switch/config.toml`
+pub fn set(name: &str, value: &str) -> Result<()> {
+    let mut config = global_config()?;
+    config.set(name, value);
+    config.save()
+}
+
+fn open(project: bool) -> Result<ConfigFile> {
+    if project {
+        ConfigFile::load(project_config::path()?)
+    } else {
+        global_config()
+    }
+}
+
+/// Print the effective value of a setting and where it came from
+pub fn config_get(name: &str) -> Result<()> {
+    match lookup(name)? {
+        Some((value, source)) => println!("{} {}", value, format!("({})", source).bright_black()),
+        None => println!("{} is not set", name),
+    }
+
+    Ok(())
+}
+
+/// Store a setting in the global config, or the project's when `project` is set
+pub fn config_set(name: &str, value: &str, project: bool) -> Result<()> {
+    if !is_known(name) {
+        return Err(Error::UnknownSetting(name.to_owned()));
+    }
+
+    if project {
+        let mut config = open(true)?;
+        config.set(name, value);
+        config.save()
+    } else {
+        set(name, value)
+    }
+}
+
+pub fn config_unset(name: &str, project: bool) -> Result<()> {
+    let mut config = open(project)?;
+
+    if config.remove(name) {
+        config.save()?;
+    } else {
+        println!("{} was not set", name);
+    }
+
+    Ok(())
+}
+
+/// Print every known setting with its effective value and where it came from
+pub fn config_list() -> Result<()> {
+    for (name, about) in KNOWN_SETTINGS {
+        if let Some((table, _)) = name.split_once(".<") {
+            println!("{} {}", name, format!("- {}", about).bright_black());
+
+            let mut keys = vec![];
+            for config in [project_config::load()?, global_config()?] {
+                if let Some(toml::Value::Table(values)) = config.get_value(table) {
+                    keys.extend(values.keys().map(|key| format!("{}.{}", table, key)));
+                }
+            }
+            keys.sort();
+            keys.dedup();
+
+            for key in keys {
+                if let Some((value, source)) = lookup(&key)? {
+                    println!("{} = {} {}", key.green(), value, format!("({})", source).bright_black());
+                }
+            }
+
+            continue;
+        }
+
+        match lookup(name)? {
+            Some((value, source)) => {
+                println!("{} = {} {}", name.green(), value, format!("({})", source).bright_black())
+            }
+            None => println!("{} {}", name, format!("- {}", about).bright_black()),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,269 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+use owo_colors::OwoColorize;
+use crate::build::{self, format_size, format_size_change};
+use crate::cargo_info::{self, Metadata};
+use crate::error::{Error, Result};
+use crate::hash::sha256_hex;
+use crate::installer::history;
+use crate::nxo::Module;
+use crate::symbols::{self, Elf, Symbol, SymbolKind};
+
+/// Flags for `size-diff`
+#[derive(StructOpt)]
+pub struct SizeDiffOptions {
+    #[structopt(
+        parse(from_str = crate::wsl::translate_path),
+        help = "The NRO, NSO or ELF to compare against, the last installed copy of the plugin by default"
+    )]
+    pub old: Option<PathBuf>,
+
+    #[structopt(long)]
+    pub release: bool,
+
+    #[structopt(long, conflicts_with = "release", help = "Build with a custom cargo profile, such as one tuned for size")]
+    pub profile: Option<String>,
+
+    #[structopt(short, long, help = "Package in the workspace to compare")]
+    pub package: Option<String>,
+
+    #[structopt(short = "n", long, default_value = "20", help = "How many of the symbols which changed the most to list")]
+    pub count: usize,
+}
+
+/// Where copies of what was last installed are kept, so later builds have something to compare with
+fn deployed_dir(metadata: &Metadata) -> PathBuf {
+    metadata.target_dir.join("skyline-deployed")
+}
+
+/// Keep a copy of an NRO which was just installed, along with the ELF it was made from if it's still
+/// there, replacing whatever was kept from the last install
+pub fn keep_deployed(metadata: &Metadata, nro_path: &Path) -> Result<()> {
+    let dir = deployed_dir(metadata);
+    let name = nro_path.file_name().ok_or(Error::FailWriteNro)?;
+    fs::create_dir_all(&dir)?;
+
+    let kept = dir.join(name);
+    fs::copy(nro_path, &kept)?;
+
+    // the plugin might have been stripped, in which case its symbols are only in the .sym
+    let _ = fs::remove_file(kept.with_extension("so"));
+    let _ = fs::remove_file(kept.with_extension("sym"));
+    for sidecar in ["so", "sym"] {
+        let path = nro_path.with_extension(sidecar);
+        if path.is_file() {
+            fs::copy(&path, kept.with_extension(sidecar))?;
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// The ELF, or failing that the .sym, kept from the last time a plugin was installed
+pub fn deployed_symbols(metadata: &Metadata, nro_name: &std::ffi::OsStr) -> Option<PathBuf> {
+    let kept = deployed_dir(metadata).join(nro_name);
+
+    existing(kept.with_extension("so")).or_else(|| existing(kept.with_extension("sym")))
+}
+
+/// How big each part of a module is
+struct Sizes {
+    file: u64,
+    /// .text, .rodata, .data and .bss, each rounded up to a page as they are once loaded
+    segments: [u64; 4],
+    /// Every section which gets loaded, if there was an ELF to read them from
+    sections: Option<HashMap<String, u64>>,
+    /// Demangled symbol names to their sizes, if there was an ELF or .sym to read them from
+    symbols: Option<HashMap<String, u64>>,
+}
+
+const SEGMENT_NAMES: [&str; 4] = [".text", ".rodata", ".data", ".bss"];
+
+fn existing(path: PathBuf) -> Option<PathBuf> {
+    Some(path).filter(|path| path.is_file())
+}
+
+fn page_align(size: u64) -> u64 {
+    (size + 0xFFF) & !0xFFF
+}
+
+fn symbol_sizes(symbols: Vec<Symbol>) -> HashMap<String, u64> {
+    let mut sizes = HashMap::new();
+    for symbol in symbols.into_iter().filter(|symbol| symbol.size != 0 && symbol.kind != SymbolKind::Other) {
+        // without the hash, a symbol keeps its name between builds
+        *sizes.entry(format!("{:#}", rustc_demangle::demangle(&symbol.name))).or_default() += symbol.size;
+    }
+
+    sizes
+}
+
+impl Sizes {
+    /// Read the sizes of an NRO or NSO, taking sections and symbols from its ELF or .sym if given
+    fn read(module_path: &Path, elf_path: Option<&Path>, sym_path: Option<&Path>) -> Result<Self> {
+        let file = fs::read(module_path)?;
+        let module = Module::read(&file)?;
+
+        let mut sizes = Sizes {
+            file: file.len() as u64,
+            segments: [
+                page_align(module.text.data.len() as u64),
+                page_align(module.rodata.data.len() as u64),
+                page_align(module.data.data.len() as u64),
+                page_align(module.bss_size as u64),
+            ],
+            sections: None,
+            symbols: None,
+        };
+
+        if let Some(elf_path) = elf_path {
+            let data = fs::read(elf_path)?;
+            let elf = Elf::parse(&data)?;
+
+            sizes.sections = Some(
+                elf.sections.iter()
+                    .filter(|section| section.is_loaded() && section.size != 0)
+                    .map(|section| (section.name.clone(), section.size))
+                    .collect()
+            );
+            sizes.symbols = Some(symbol_sizes(elf.symbols()));
+        } else if let Some(sym_path) = sym_path {
+            sizes.symbols = Some(symbol_sizes(symbols::read_sym_file(sym_path)?));
+        }
+
+        Ok(sizes)
+    }
+}
+
+/// One row of the comparison, with the change as a percentage if there was something to compare to
+fn print_row(name: &str, old: u64, new: u64) {
+    let change = new as i64 - old as i64;
+    let percent = if old == 0 { String::new() } else { format!(" ({:+.1}%)", change as f64 * 100.0 / old as f64) };
+    let change = format!("{}{}", format_size_change(change), percent);
+
+    let change = match new.cmp(&old) {
+        std::cmp::Ordering::Greater => change.red().to_string(),
+        std::cmp::Ordering::Less => change.green().to_string(),
+        std::cmp::Ordering::Equal => change.bright_black().to_string(),
+    };
+
+    println!("{:<24} {:>12} {:>12}  {}", name, format_size(old), format_size(new), change);
+}
+
+/// The sizes of the last installed copy of a plugin, and a description of which install it was from
+fn last_deployed(metadata: &Metadata, nro_name: &std::ffi::OsStr) -> Result<(Sizes, String)> {
+    let kept = deployed_dir(metadata).join(nro_name);
+    if !kept.is_file() {
+        return Err(Error::NoDeployedCopy(nro_name.to_string_lossy().into_owned()));
+    }
+
+    let sizes = Sizes::read(&kept, existing(kept.with_extension("so")).as_deref(), existing(kept.with_extension("sym")).as_deref())?;
+
+    let hash = sha256_hex(&fs::read(&kept)?);
+    let description = match history::load()?.into_iter().rev().find(|deployment| deployment.sha256 == hash) {
+        Some(deployment) => format!(
+            "the copy installed to {}{} at {}{}",
+            deployment.location,
+            deployment.path,
+            crate::timestamps::format_time(deployment.time),
+            deployment.git_hash.map(|hash| format!(" from {}", hash)).unwrap_or_default(),
+        ),
+        None => String::from("the copy installed last"),
+    };
+
+    Ok((sizes, description))
+}
+
+/// Build the plugin and compare its size with an older build, by segment, section and symbol
+pub fn size_diff(options: SizeDiffOptions) -> Result<()> {
+    let metadata = cargo_info::get_metadata()?;
+
+    let mut args = vec![];
+    build::profile_args(&mut args, options.release, options.profile.as_deref());
+    if let Some(package) = &options.package {
+        build::package_args(&mut args, std::slice::from_ref(package));
+    }
+
+    let (elf_path, nro_path) = build::build_get_elf_and_nro(args, false)?;
+    let new = Sizes::read(&nro_path, Some(&elf_path), None)?;
+    let nro_name = nro_path.file_name().ok_or(Error::FailWriteNro)?;
+
+    let (old, description) = match &options.old {
+        Some(old_path) => {
+            let is_elf = fs::read(old_path)?.starts_with(b"\x7fELF");
+            let description = old_path.display().to_string();
+
+            if is_elf {
+                // segments are compared as they are in an NRO, so the ELF needs converting first
+                let converted = metadata.target_dir.join("size-diff-old.nro");
+                build::write_nro(old_path, &converted, None)?;
+                let old = Sizes::read(&converted, Some(old_path), None);
+                let _ = fs::remove_file(converted);
+
+                (old?, description)
+            } else {
+                (Sizes::read(old_path, None, existing(old_path.with_extension("sym")).as_deref())?, description)
+            }
+        }
+        None => last_deployed(&metadata, nro_name)?,
+    };
+
+    println!("Comparing {} with {}\n", nro_name.to_string_lossy().bright_blue(), description);
+
+    println!("{:<24} {:>12} {:>12}  Change", "", "Old", "New");
+    print_row("File", old.file, new.file);
+    for (index, name) in SEGMENT_NAMES.iter().enumerate() {
+        print_row(name, old.segments[index], new.segments[index]);
+    }
+
+    if let (Some(old_sections), Some(new_sections)) = (&old.sections, &new.sections) {
+        println!("\n{:<24} {:>12} {:>12}  Change", "Section", "Old", "New");
+
+        let names: BTreeSet<&String> = old_sections.keys().chain(new_sections.keys()).collect();
+        for name in names {
+            let (old_size, new_size) = (old_sections.get(name).copied().unwrap_or(0), new_sections.get(name).copied().unwrap_or(0));
+            if old_size != new_size {
+                print_row(name, old_size, new_size);
+            }
+        }
+    }
+
+    let (old_symbols, new_symbols) = match (&old.symbols, &new.symbols) {
+        (Some(old_symbols), Some(new_symbols)) => (old_symbols, new_symbols),
+        _ => {
+            println!("\nThere are no symbols for the old build to compare, pass its ELF or keep its .sym next to it");
+            return Ok(());
+        }
+    };
+
+    let mut changed: Vec<(&str, u64, u64)> = old_symbols.keys().chain(new_symbols.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|name| (name.as_str(), old_symbols.get(name).copied().unwrap_or(0), new_symbols.get(name).copied().unwrap_or(0)))
+        .filter(|(_, old_size, new_size)| old_size != new_size)
+        .collect();
+
+    if changed.is_empty() {
+        println!("\nNo symbols changed size");
+        return Ok(());
+    }
+
+    changed.sort_by_key(|(_, old_size, new_size)| std::cmp::Reverse((*new_size as i64 - *old_size as i64).abs()));
+
+    println!("\nSymbols which changed the most ({} changed in all)", changed.len());
+    for (name, old_size, new_size) in changed.iter().take(options.count) {
+        let status = match (old_size, new_size) {
+            (0, _) => "added ",
+            (_, 0) => "removed ",
+            _ => "",
+        };
+        let change = format!("{:>12}", format_size_change(*new_size as i64 - *old_size as i64));
+        let change = if new_size > old_size { change.red().to_string() } else { change.green().to_string() };
+
+        println!("  {}  {}{}", change, status.bright_black(), name);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A Skyline version such as `1.2.0`, where anything missing counts as 0
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version(u64, u64, u64);
+
+impl FromStr for Version {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = s.strip_prefix('v').or_else(|| s.strip_prefix('V')).unwrap_or(s);
+
+        let mut parts = s.split('.');
+        let mut next = || parts.next().map(|part| part.parse::<u64>().map_err(|_| ())).transpose();
+
+        let major = next()?.ok_or(())?;
+        let minor = next()?.unwrap_or(0);
+        let patch = next()?.unwrap_or(0);
+
+        if next()?.is_some() {
+            return Err(());
+        }
+
+        Ok(Version(major, minor, patch))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// Get the version of a release from its url, which GitHub puts the tag of in as
+/// `releases/download/<tag>/skyline.zip`
+pub fn from_release(release: &str) -> Option<Version> {
+    release.split('/').rev().find_map(|part| part.parse().ok())
+}
+
+/// Look for a version string such as `skyline v1.2.0` in a build of Skyline
+pub fn from_binary(subsdk: &[u8]) -> Option<Version> {
+    const NAME: &[u8] = b"skyline";
+
+    subsdk.windows(NAME.len())
+        .enumerate()
+        .filter(|(_, window)| window.eq_ignore_ascii_case(NAME))
+        .find_map(|(start, _)| {
+            let rest = &subsdk[start + NAME.len()..];
+            let rest = &rest[..rest.len().min(24)];
+
+            // skip whatever separates the name from the version, like " v" or " version "
+            let start = rest.iter().position(u8::is_ascii_digit)?;
+            let separator = String::from_utf8_lossy(&rest[..start]).to_lowercase();
+            if !matches!(separator.trim_matches(|c: char| c.is_ascii_punctuation() || c == ' '), "" | "v" | "version") {
+                return None;
+            }
+
+            let len = rest[start..].iter().take_while(|c| c.is_ascii_digit() || **c == b'.').count();
+            let version = std::str::from_utf8(&rest[start..start + len]).ok()?.trim_end_matches('.');
+
+            // a lone number is much more likely to be something else
+            if !version.contains('.') {
+                return None;
+            }
+
+            version.parse().ok()
+        })
+}
@@ -0,0 +1,365 @@
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Path, PathBuf};
+use addr2line::gimli::{self, EndianSlice, LittleEndian};
+use structopt::StructOpt;
+use owo_colors::OwoColorize;
+use crate::build;
+use crate::cargo_info;
+use crate::error::Result;
+use crate::symbols::{self, Elf, Symbol, SymbolKind};
+
+/// Read an address the way they're usually pasted, in hex with or without the 0x
+pub fn parse_address(address: &str) -> std::result::Result<u64, String> {
+    let digits = address.trim().trim_start_matches("0x").trim_start_matches("0X");
+
+    u64::from_str_radix(digits, 16).map_err(|_| format!("'{}' isn't a hex address", address))
+}
+
+/// Flags for `addr2line`
+#[derive(StructOpt)]
+pub struct Addr2lineOptions {
+    #[structopt(
+        required = true, parse(try_from_str = parse_address),
+        help = "Addresses to look up, as offsets into the plugin or runtime addresses if --base is given"
+    )]
+    pub addresses: Vec<u64>,
+
+    #[structopt(short, long, parse(try_from_str = parse_address), help = "Where the plugin was loaded in memory")]
+    pub base: Option<u64>,
+
+    #[structopt(
+        long, parse(from_str = crate::wsl::translate_path),
+        help = "The unstripped ELF or .sym to use, the copy from the last install or build by default"
+    )]
+    pub elf: Option<PathBuf>,
+
+    #[structopt(long)]
+    pub release: bool,
+
+    #[structopt(long, conflicts_with = "release", help = "Build with a custom cargo profile")]
+    pub profile: Option<String>,
+
+    #[structopt(short, long, help = "Package in the workspace to build")]
+    pub package: Option<String>,
+}
+
+/// Flags for `addr`
+#[derive(StructOpt)]
+pub struct AddrOptions {
+    #[structopt(
+        required = true, parse(try_from_str = parse_address),
+        help = "Addresses to convert, taken as runtime addresses if they're past --base and offsets otherwise"
+    )]
+    pub values: Vec<u64>,
+
+    #[structopt(short, long, parse(try_from_str = parse_address), help = "Where the plugin's .text was loaded, as Skyline logs it")]
+    pub base: Option<u64>,
+
+    #[structopt(long, requires = "base", help = "Take every value as an offset into the plugin, even if it's past the base")]
+    pub offset: bool,
+
+    #[structopt(
+        short, long, parse(from_str = crate::wsl::translate_path),
+        help = "The plugin's NRO, NSO or ELF, to also show which segment and section each address is in"
+    )]
+    pub file: Option<PathBuf>,
+}
+
+/// The parts of a plugin an offset can be in, from whichever of its files was given
+enum Layout {
+    /// Segments by name, address and size
+    Module(Vec<(&'static str, u64, u64)>),
+    /// Loaded sections by name, address, size and offset into the ELF, which is None for .bss
+    Elf(Vec<(String, u64, u64, Option<u64>)>),
+}
+
+impl Layout {
+    fn read(path: &Path) -> Result<Self> {
+        let data = fs::read(path)?;
+        if data.starts_with(b"\x7fELF") {
+            let elf = Elf::parse(&data)?;
+            let sections = elf.sections.iter()
+                .filter(|section| section.is_loaded() && section.size != 0)
+                .map(|section| (section.name.clone(), section.addr, section.size, Some(section.offset).filter(|_| section.has_data())))
+                .collect();
+
+            return Ok(Layout::Elf(sections));
+        }
+
+        let module = crate::nxo::Module::read(&data)?;
+        let data_end = module.data.addr as u64 + module.data.data.len() as u64;
+
+        Ok(Layout::Module(vec![
+            (".text", module.text.addr as u64, module.text.data.len() as u64),
+            (".rodata", module.rodata.addr as u64, module.rodata.data.len() as u64),
+            (".data", module.data.addr as u64, module.data.data.len() as u64),
+            (".bss", data_end, module.bss_size as u64),
+        ]))
+    }
+
+    /// Describe where an offset is, or that it's outside of the plugin
+    fn describe(&self, offset: u64) -> String {
+        let within = |addr: u64, size: u64| offset >= addr && offset < addr + size;
+
+        match self {
+            Layout::Module(segments) => match segments.iter().find(|(_, addr, size)| within(*addr, *size)) {
+                Some((name, addr, _)) => format!("{} + 0x{:x}", name, offset - addr),
+                None => String::from("outside of the plugin"),
+            },
+            Layout::Elf(sections) => match sections.iter().find(|(_, addr, size, _)| within(*addr, *size)) {
+                Some((name, addr, _, Some(file_offset))) => {
+                    format!("{} + 0x{:x}, 0x{:x} into the ELF", name, offset - addr, file_offset + offset - addr)
+                }
+                Some((name, addr, _, None)) => format!("{} + 0x{:x}, which isn't stored in the file", name, offset - addr),
+                None => String::from("outside of the plugin"),
+            },
+        }
+    }
+}
+
+/// Convert between runtime addresses and offsets into a plugin. Plugins are linked at 0 and .text
+/// comes first, so an offset is the same from the start of .text, into the NRO and as an ELF address.
+pub fn addr(options: AddrOptions) -> Result<()> {
+    let layout = options.file.as_deref().map(Layout::read).transpose()?;
+
+    for &value in &options.values {
+        let (runtime, offset) = match options.base {
+            Some(base) if value >= base && !options.offset => (Some(value), value - base),
+            Some(base) => (base.checked_add(value), value),
+            None => (None, value),
+        };
+
+        println!("{}", format!("0x{:x}", value).bright_blue());
+        if let Some(runtime) = runtime {
+            println!("  Runtime address  0x{:x}", runtime);
+        }
+        println!("  Offset           0x{:x}", offset);
+        if let Some(layout) = &layout {
+            println!("  In               {}", layout.describe(offset));
+        }
+    }
+
+    if options.base.is_none() {
+        eprintln!("\nPass --base with the address Skyline logged for the plugin to get runtime addresses");
+    }
+
+    Ok(())
+}
+
+/// One function an address is in, innermost first when functions were inlined into their callers
+pub struct Frame {
+    pub function: Option<String>,
+    /// `file:line:column`, as much of it as the debug info has
+    pub location: Option<String>,
+}
+
+/// Everything known about where an address is in a plugin
+pub struct Resolved {
+    /// The symbol the address is in and how far into it, if there was a symbol table
+    pub symbol: Option<(String, u64)>,
+    /// Where it is in the source, if there was debug info
+    pub frames: Vec<Frame>,
+}
+
+type Context<'a> = addr2line::Context<EndianSlice<'a, LittleEndian>>;
+
+/// What's needed to find where addresses in a plugin are, from its ELF or .sym
+pub struct Symbolizer<'a> {
+    symbols: Vec<Symbol>,
+    context: Option<Context<'a>>,
+}
+
+impl<'a> Symbolizer<'a> {
+    /// Use an ELF's symbol table, and its debug info if it was built with any
+    pub fn from_elf(data: &'a [u8]) -> Result<Self> {
+        let elf = Elf::parse(data)?;
+
+        let dwarf = gimli::Dwarf::load(|id| -> std::result::Result<_, gimli::Error> {
+            let data = elf.section(id.name()).map(|section| elf.section_data(section)).unwrap_or_default();
+            Ok(EndianSlice::new(data, LittleEndian))
+        });
+        let context = match elf.section(".debug_info") {
+            Some(_) => dwarf.ok().and_then(|dwarf| Context::from_dwarf(dwarf).ok()),
+            None => None,
+        };
+
+        Ok(Symbolizer { symbols: elf.symbols(), context })
+    }
+
+    /// Use the symbols kept in a .sym, which only gets as far as which function an address is in
+    pub fn from_sym_file(path: &Path) -> Result<Self> {
+        Ok(Symbolizer { symbols: symbols::read_sym_file(path)?, context: None })
+    }
+
+    pub fn has_debug_info(&self) -> bool {
+        self.context.is_some()
+    }
+
+    /// The function or object an address is in, going by the symbols, which are sorted by address
+    fn symbol(&self, address: u64) -> Option<&Symbol> {
+        let candidates = self.symbols.iter()
+            .filter(|symbol| symbol.kind != SymbolKind::Other && symbol.addr <= address);
+
+        // hand written functions sometimes have no size, in which case the closest one before is the best
+        // guess, but objects without one are markers like _DYNAMIC rather than anything an address is in
+        candidates.rev().find(|symbol| {
+            address < symbol.addr + symbol.size || (symbol.size == 0 && symbol.kind == SymbolKind::Function)
+        })
+    }
+
+    fn frames(&self, address: u64) -> Vec<Frame> {
+        let mut frames = vec![];
+        let context = match &self.context {
+            Some(context) => context,
+            None => return frames,
+        };
+
+        if let Ok(mut iter) = context.find_frames(address).skip_all_loads() {
+            while let Ok(Some(frame)) = iter.next() {
+                let function = frame.function
+                    .as_ref()
+                    .and_then(|function| function.raw_name().ok())
+                    .map(|name| format!("{:#}", rustc_demangle::demangle(&name)));
+
+                let location = frame.location.and_then(|location| {
+                    let mut text = location.file?.to_owned();
+                    for part in [location.line, location.column].iter().map_while(|part| *part) {
+                        text += &format!(":{}", part);
+                    }
+                    Some(text)
+                });
+
+                frames.push(Frame { function, location });
+            }
+        }
+
+        frames
+    }
+
+    pub fn lookup(&self, address: u64) -> Resolved {
+        Resolved {
+            symbol: self.symbol(address)
+                .map(|symbol| (format!("{:#}", rustc_demangle::demangle(&symbol.name)), address - symbol.addr)),
+            frames: self.frames(address),
+        }
+    }
+}
+
+impl Resolved {
+    /// The lines to print for an address, one for each function it's in
+    pub fn describe(&self) -> Vec<String> {
+        if self.frames.is_empty() {
+            return vec![match &self.symbol {
+                Some((name, offset)) => format!("{}+0x{:x}", name, offset),
+                None => String::from("??"),
+            }];
+        }
+
+        self.frames.iter()
+            .enumerate()
+            .map(|(index, frame)| {
+                // the outermost function's name is better taken from the symbol, which has the offset
+                let name: Cow<str> = match (&self.symbol, index + 1 == self.frames.len()) {
+                    (Some((name, offset)), true) => format!("{}+0x{:x}", name, offset).into(),
+                    _ => frame.function.as_deref().unwrap_or("??").into(),
+                };
+                let location = frame.location.as_deref().unwrap_or("??");
+
+                if index == 0 {
+                    format!("{} at {}", name, location)
+                } else {
+                    format!("inlined into {} at {}", name, location)
+                }
+            })
+            .collect()
+    }
+}
+
+/// The ELF or .sym to look addresses up in, preferring the copy from the last install since that's
+/// what a crash would have come from
+pub fn find_symbols(elf: Option<&Path>, release: bool, profile: Option<&str>, package: Option<&str>) -> Result<PathBuf> {
+    if let Some(elf) = elf {
+        return Ok(elf.to_owned());
+    }
+
+    let mut args = vec![];
+    build::profile_args(&mut args, release, profile);
+    if let Some(package) = package {
+        build::package_args(&mut args, &[package.to_owned()]);
+    }
+
+    let (elf_path, nro_path) = build::build_get_elf_and_nro(args, false)?;
+    let metadata = cargo_info::get_metadata()?;
+    let deployed = nro_path.file_name().and_then(|name| crate::size_diff::deployed_symbols(&metadata, name));
+
+    Ok(deployed.unwrap_or(elf_path))
+}
+
+/// Read the ELF or .sym at a path into the data a `Symbolizer` borrows from, None for a .sym
+pub fn read_elf(path: &Path) -> Result<Option<Vec<u8>>> {
+    if path.extension().is_some_and(|ext| ext == "sym") {
+        Ok(None)
+    } else {
+        Ok(Some(fs::read(path)?))
+    }
+}
+
+/// Print the function, file and line of each address
+pub fn addr2line(options: Addr2lineOptions) -> Result<()> {
+    let path = find_symbols(options.elf.as_deref(), options.release, options.profile.as_deref(), options.package.as_deref())?;
+    let data = read_elf(&path)?;
+    let symbolizer = match &data {
+        Some(data) => Symbolizer::from_elf(data)?,
+        None => Symbolizer::from_sym_file(&path)?,
+    };
+
+    eprintln!("Using {}", path.display());
+    if !symbolizer.has_debug_info() {
+        eprintln!("{}: There's no debug info, so only the function of each address can be found", "Warning".yellow());
+    }
+
+    for &address in &options.addresses {
+        let offset = match options.base {
+            Some(base) if address < base => {
+                println!("{} {}", format!("0x{:x}:", address).bright_blue(), "before the plugin's base".bright_black());
+                continue;
+            }
+            Some(base) => address - base,
+            None => address,
+        };
+
+        let label = match options.base {
+            Some(_) => format!("0x{:x} (+0x{:x}):", address, offset),
+            None => format!("0x{:x}:", offset),
+        };
+
+        for (index, line) in symbolizer.lookup(offset).describe().into_iter().enumerate() {
+            if index == 0 {
+                println!("{} {}", label.bright_blue(), line);
+            } else {
+                println!("{:width$} {}", "", line, width = label.len());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_addresses() {
+        assert_eq!(parse_address("0x1234"), Ok(0x1234));
+        assert_eq!(parse_address("0X1234"), Ok(0x1234));
+        assert_eq!(parse_address(" 0000000812300104 "), Ok(0x812300104));
+        assert_eq!(parse_address("deadBEEF"), Ok(0xDEADBEEF));
+
+        assert!(parse_address("").is_err());
+        assert!(parse_address("0x").is_err());
+        assert!(parse_address("main+0x10").is_err());
+        assert!(parse_address("10000000000000000").is_err());
+    }
+}
@@ -0,0 +1,374 @@
+use std::convert::TryInto;
+use std::fs;
+use std::path::Path;
+use crate::error::{Error, Result};
+
+const SHT_NOBITS: u32 = 8;
+const SHF_ALLOC: u64 = 0x2;
+const SHF_INFO_LINK: u64 = 0x40;
+const PT_LOAD: u32 = 1;
+
+/// A section header from a 64-bit little endian ELF, which is all the switch uses
+#[derive(Clone)]
+pub struct Section {
+    pub name: String,
+    pub kind: u32,
+    pub flags: u64,
+    pub addr: u64,
+    pub offset: u64,
+    pub size: u64,
+    pub link: u32,
+    pub info: u32,
+    pub align: u64,
+    pub entsize: u64,
+}
+
+impl Section {
+    /// Whether the section is part of the module once it's loaded, rather than only in the ELF
+    pub fn is_loaded(&self) -> bool {
+        self.flags & SHF_ALLOC != 0
+    }
+
+    /// Whether the section takes up space in the file, which .bss doesn't
+    pub fn has_data(&self) -> bool {
+        self.kind != SHT_NOBITS
+    }
+}
+
+/// Just enough of an ELF to read its symbols and drop sections from it
+pub struct Elf<'a> {
+    pub data: &'a [u8],
+    pub sections: Vec<Section>,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    data.get(offset..offset + 2).map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap())).ok_or(Error::BadElf)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    data.get(offset..offset + 4).map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap())).ok_or(Error::BadElf)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64> {
+    data.get(offset..offset + 8).map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap())).ok_or(Error::BadElf)
+}
+
+fn read_cstr(data: &[u8], offset: usize) -> String {
+    let bytes = data.get(offset..).unwrap_or_default();
+    let end = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+impl<'a> Elf<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        // only ELF64, little endian
+        if data.get(..6) != Some(b"\x7fELF\x02\x01") {
+            return Err(Error::BadElf);
+        }
+
+        let shoff = read_u64(data, 0x28)? as usize;
+        let shentsize = read_u16(data, 0x3A)? as usize;
+        let shnum = read_u16(data, 0x3C)? as usize;
+        let shstrndx = read_u16(data, 0x3E)? as usize;
+
+        let mut sections = (0..shnum)
+            .map(|index| {
+                let header = shoff + index * shentsize;
+
+                Ok((read_u32(data, header)?, Section {
+                    name: String::new(),
+                    kind: read_u32(data, header + 0x4)?,
+                    flags: read_u64(data, header + 0x8)?,
+                    addr: read_u64(data, header + 0x10)?,
+                    offset: read_u64(data, header + 0x18)?,
+                    size: read_u64(data, header + 0x20)?,
+                    link: read_u32(data, header + 0x28)?,
+                    info: read_u32(data, header + 0x2C)?,
+                    align: read_u64(data, header + 0x30)?,
+                    entsize: read_u64(data, header + 0x38)?,
+                }))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let names_offset = sections.get(shstrndx).map(|(_, section)| section.offset as usize).unwrap_or(0);
+        for (name, section) in &mut sections {
+            section.name = read_cstr(data, names_offset + *name as usize);
+        }
+
+        Ok(Elf { data, sections: sections.into_iter().map(|(_, section)| section).collect() })
+    }
+
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    pub fn section_data(&self, section: &Section) -> &'a [u8] {
+        if section.kind == SHT_NOBITS {
+            return &[];
+        }
+
+        self.data.get(section.offset as usize..(section.offset + section.size) as usize).unwrap_or_default()
+    }
+
+    /// Every named symbol with an address, from the full symbol table if it's still there and the
+    /// dynamic one otherwise
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let table = self.section(".symtab").or_else(|| self.section(".dynsym"));
+        let table = match table {
+            Some(table) => table,
+            None => return vec![]
+        };
+        let names = self.sections.get(table.link as usize).map(|names| self.section_data(names)).unwrap_or_default();
+
+        let mut symbols: Vec<Symbol> = self.section_data(table)
+            .chunks_exact(0x18)
+            .filter_map(|entry| {
+                let name = read_cstr(names, u32::from_le_bytes(entry[..4].try_into().unwrap()) as usize);
+                let kind = match entry[4] & 0xF {
+                    1 => SymbolKind::Object,
+                    2 => SymbolKind::Function,
+                    _ => SymbolKind::Other,
+                };
+                let section_index = u16::from_le_bytes(entry[6..8].try_into().unwrap());
+                let addr = u64::from_le_bytes(entry[8..16].try_into().unwrap());
+                let size = u64::from_le_bytes(entry[16..24].try_into().unwrap());
+
+                // leave out imports, which have no address in this module, and section/file markers
+                if name.is_empty() || section_index == 0 || matches!(entry[4] & 0xF, 3 | 4) {
+                    return None;
+                }
+
+                Some(Symbol { addr, size, kind, name })
+            })
+            .collect();
+
+        symbols.sort_by_key(|symbol| symbol.addr);
+
+        symbols
+    }
+
+    /// The symbols the ELF exports and imports, from its .dynsym
+    pub fn dynamic_symbols(&self) -> Vec<DynamicSymbol> {
+        match self.section(".dynsym") {
+            Some(table) => {
+                let names = self.sections.get(table.link as usize).map(|names| self.section_data(names)).unwrap_or_default();
+                dynamic_symbols(self.section_data(table), names)
+            }
+            None => vec![]
+        }
+    }
+
+    /// Rewrite the ELF without its symbol table and debug info, keeping everything that gets loaded
+    pub fn strip(&self) -> Result<Vec<u8>> {
+        let stripped = |section: &Section| {
+            section.flags & SHF_ALLOC == 0
+                && (section.name == ".symtab" || section.name == ".strtab" || section.name.starts_with(".debug"))
+        };
+
+        // segments come first in the file, so keep everything up to the end of the last one as-is
+        let phoff = read_u64(self.data, 0x20)? as usize;
+        let phentsize = read_u16(self.data, 0x36)? as usize;
+        let phnum = read_u16(self.data, 0x38)? as usize;
+        let mut loaded_end = phoff + phentsize * phnum;
+        for index in 0..phnum {
+            let header = phoff + index * phentsize;
+            if read_u32(self.data, header)? == PT_LOAD {
+                loaded_end = loaded_end.max((read_u64(self.data, header + 0x8)? + read_u64(self.data, header + 0x20)?) as usize);
+            }
+        }
+
+        let mut output = self.data.get(..loaded_end).ok_or(Error::BadElf)?.to_vec();
+
+        // indices shift as sections are dropped, so anything pointing at a section has to follow
+        let mut new_index = vec![0u32; self.sections.len()];
+        let mut kept = vec![];
+        for (index, section) in self.sections.iter().enumerate() {
+            if !stripped(section) {
+                new_index[index] = kept.len() as u32;
+                kept.push(section.clone());
+            }
+        }
+
+        for section in &mut kept {
+            let end = (section.offset + section.size) as usize;
+            if section.kind != SHT_NOBITS && section.size != 0 && end > loaded_end {
+                let data = self.section_data(section).to_vec();
+                let align = section.align.max(1) as usize;
+                output.resize(output.len().div_ceil(align) * align, 0);
+                section.offset = output.len() as u64;
+                output.extend(data);
+            }
+
+            section.link = new_index.get(section.link as usize).copied().unwrap_or(0);
+            if section.flags & SHF_INFO_LINK != 0 {
+                section.info = new_index.get(section.info as usize).copied().unwrap_or(0);
+            }
+        }
+
+        // the names of the sections are still in .shstrtab, so their offsets into it are reused
+        let shoff = read_u64(self.data, 0x28)? as usize;
+        let shentsize = read_u16(self.data, 0x3A)? as usize;
+        let name_offsets: Vec<u32> = (0..self.sections.len())
+            .map(|index| read_u32(self.data, shoff + index * shentsize))
+            .collect::<Result<_>>()?;
+
+        output.resize(output.len().div_ceil(8) * 8, 0);
+        let new_shoff = output.len() as u64;
+        for (index, section) in self.sections.iter().enumerate() {
+            if stripped(section) {
+                continue;
+            }
+
+            let section = &kept[new_index[index] as usize];
+            output.extend(name_offsets[index].to_le_bytes());
+            output.extend(section.kind.to_le_bytes());
+            output.extend(section.flags.to_le_bytes());
+            output.extend(section.addr.to_le_bytes());
+            output.extend(section.offset.to_le_bytes());
+            output.extend(section.size.to_le_bytes());
+            output.extend(section.link.to_le_bytes());
+            output.extend(section.info.to_le_bytes());
+            output.extend(section.align.to_le_bytes());
+            output.extend(section.entsize.to_le_bytes());
+        }
+
+        let shstrndx = new_index.get(read_u16(self.data, 0x3E)? as usize).copied().unwrap_or(0) as u16;
+        output[0x28..0x30].copy_from_slice(&new_shoff.to_le_bytes());
+        output[0x3A..0x3C].copy_from_slice(&0x40u16.to_le_bytes());
+        output[0x3C..0x3E].copy_from_slice(&(kept.len() as u16).to_le_bytes());
+        output[0x3E..0x40].copy_from_slice(&shstrndx.to_le_bytes());
+
+        Ok(output)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum SymbolKind {
+    Function,
+    Object,
+    Other,
+}
+
+impl SymbolKind {
+    /// The letter `nm` uses for the kind of symbol
+    pub fn letter(self) -> char {
+        match self {
+            SymbolKind::Function => 'T',
+            SymbolKind::Object => 'D',
+            SymbolKind::Other => '?',
+        }
+    }
+
+    fn from_letter(letter: &str) -> Self {
+        match letter {
+            "T" => SymbolKind::Function,
+            "D" => SymbolKind::Object,
+            _ => SymbolKind::Other,
+        }
+    }
+}
+
+pub struct Symbol {
+    pub addr: u64,
+    pub size: u64,
+    pub kind: SymbolKind,
+    /// The mangled name
+    pub name: String,
+}
+
+/// An entry of a module's dynamic symbol table, which is what it exports to and imports from
+/// other modules
+pub struct DynamicSymbol {
+    pub addr: u64,
+    pub kind: SymbolKind,
+    /// The mangled name
+    pub name: String,
+    pub weak: bool,
+    /// Whether it has to be found in another module when loaded, rather than being defined in this one
+    pub imported: bool,
+}
+
+impl DynamicSymbol {
+    /// The letter `nm` uses for it, telling imports, weak symbols and the kind of an export apart
+    pub fn letter(&self) -> char {
+        match (self.imported, self.weak) {
+            (true, true) => 'w',
+            (true, false) => 'U',
+            (false, true) => 'W',
+            (false, false) => self.kind.letter(),
+        }
+    }
+}
+
+/// Read a .dynsym, given it and the .dynstr its names are in, leaving out the empty first entry and
+/// anything local to the module
+pub fn dynamic_symbols(dynsym: &[u8], dynstr: &[u8]) -> Vec<DynamicSymbol> {
+    dynsym.chunks_exact(0x18)
+        .filter_map(|entry| {
+            let name = read_cstr(dynstr, u32::from_le_bytes(entry[..4].try_into().unwrap()) as usize);
+            let bind = entry[4] >> 4;
+            let kind = match entry[4] & 0xF {
+                1 => SymbolKind::Object,
+                2 => SymbolKind::Function,
+                _ => SymbolKind::Other,
+            };
+            let section_index = u16::from_le_bytes(entry[6..8].try_into().unwrap());
+
+            // 1 is global and 2 is weak
+            if name.is_empty() || !matches!(bind, 1 | 2) {
+                return None;
+            }
+
+            Some(DynamicSymbol {
+                addr: u64::from_le_bytes(entry[8..16].try_into().unwrap()),
+                kind,
+                name,
+                weak: bind == 2,
+                imported: section_index == 0,
+            })
+        })
+        .collect()
+}
+
+/// Write symbols out as a `.sym` file, one `address size kind name` per line like `nm -S`
+pub fn write_sym_file(path: &Path, symbols: &[Symbol]) -> Result<()> {
+    let text: String = symbols.iter()
+        .map(|symbol| format!("{:016x} {:016x} {} {}\n", symbol.addr, symbol.size, symbol.kind.letter(), symbol.name))
+        .collect();
+
+    Ok(fs::write(path, text)?)
+}
+
+/// Read the symbols back from a `.sym` file, skipping any lines which aren't in the format it's written in
+pub fn read_sym_file(path: &Path) -> Result<Vec<Symbol>> {
+    let text = fs::read_to_string(path)?;
+
+    Ok(
+        text.lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(4, ' ');
+                let addr = u64::from_str_radix(fields.next()?, 16).ok()?;
+                let size = u64::from_str_radix(fields.next()?, 16).ok()?;
+                let kind = SymbolKind::from_letter(fields.next()?);
+                let name = fields.next()?.to_owned();
+
+                Some(Symbol { addr, size, kind, name })
+            })
+            .collect()
+    )
+}
+
+/// Strip an ELF before it's converted, keeping its symbols in a `.sym` file next to it, and give
+/// back where the stripped copy is
+pub fn strip_with_sidecar(elf_path: &Path) -> Result<std::path::PathBuf> {
+    let data = fs::read(elf_path)?;
+    let elf = Elf::parse(&data)?;
+
+    write_sym_file(&elf_path.with_extension("sym"), &elf.symbols())?;
+
+    let stripped_path = elf_path.with_extension("stripped");
+    fs::write(&stripped_path, elf.strip()?)?;
+
+    Ok(stripped_path)
+}
@@ -1,17 +1,42 @@
-use std::net::TcpStream;
-use crate::error::Result;
+use std::net::SocketAddr;
+use std::time::Duration;
+use crate::error::{Error, Result};
 use crate::ip_addr::{verify_ip, get_ip};
+use crate::timeouts::Timeouts;
 
-pub fn listen(ip: Option<String>) -> Result<()> {
+/// The port skyline's logger sends output on
+pub const LOG_PORT: u16 = 6969;
+
+/// Print whatever skyline's logger sends. Unless `wait` is set this gives up if nothing is logging
+/// yet, and stops once the game closes the connection.
+pub fn listen(ip: Option<String>, timeouts: Timeouts, wait: bool) -> Result<()> {
     let ip = verify_ip(get_ip(ip)?)?;
     
     println!("---------------------------------------------------------------");
 
     let stdout = std::io::stdout();
+    let mut waiting = false;
 
+    // the logger only listens while the game is running, so keep trying until it is
     loop {
-        if let Ok(mut logger) = TcpStream::connect((ip, 6969)) {
-            let _ = std::io::copy(&mut logger, &mut stdout.lock());
+        match timeouts.connect(SocketAddr::new(ip, LOG_PORT)) {
+            Ok(mut logger) => {
+                waiting = false;
+                let _ = std::io::copy(&mut logger, &mut stdout.lock());
+
+                if !wait {
+                    return Ok(());
+                }
+            }
+            Err(_) if !wait => return Err(Error::NotLogging(ip)),
+            Err(_) => {
+                if !waiting {
+                    println!("Waiting for {} to start logging...", ip);
+                    waiting = true;
+                }
+
+                std::thread::sleep(Duration::from_millis(250));
+            }
         }
     }
 }
@@ -0,0 +1,79 @@
+use std::io;
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+use structopt::StructOpt;
+use crate::error::{Error, Result};
+use crate::settings;
+
+/// Flags for how long to wait on the switch, each of which falls back to `$HOME/.switch`
+#[derive(StructOpt, Clone, Default)]
+pub struct TimeoutArgs {
+    #[structopt(long, help = "Seconds to wait while connecting to the switch (default 5)")]
+    pub connect_timeout: Option<f64>,
+
+    #[structopt(long, help = "Seconds to wait for the switch to send data before giving up")]
+    pub read_timeout: Option<f64>,
+
+    #[structopt(long, help = "Seconds to wait for the switch to accept data before giving up (default 30)")]
+    pub write_timeout: Option<f64>,
+}
+
+/// How long to wait on connections to the switch before giving up
+#[derive(Copy, Clone)]
+pub struct Timeouts {
+    pub connect: Duration,
+    pub read: Option<Duration>,
+    pub write: Option<Duration>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            connect: Duration::from_secs(5),
+            read: None,
+            write: Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+fn resolve(flag: Option<f64>, name: &'static str) -> Result<Option<Duration>> {
+    let secs = match flag {
+        Some(secs) => Some(secs),
+        None => settings::get(name)
+            .map(|secs| secs.parse().map_err(|_| Error::BadSetting(name)))
+            .transpose()?
+    };
+
+    match secs {
+        Some(secs) if secs > 0.0 && secs.is_finite() => Ok(Some(Duration::from_secs_f64(secs))),
+        Some(_) => Err(Error::BadSetting(name)),
+        None => Ok(None)
+    }
+}
+
+impl TimeoutArgs {
+    pub fn resolve(&self) -> Result<Timeouts> {
+        let defaults = Timeouts::default();
+
+        Ok(Timeouts {
+            connect: resolve(self.connect_timeout, "connect_timeout")?.unwrap_or(defaults.connect),
+            read: resolve(self.read_timeout, "read_timeout")?.or(defaults.read),
+            write: resolve(self.write_timeout, "write_timeout")?.or(defaults.write),
+        })
+    }
+}
+
+impl Timeouts {
+    /// Open a connection with these timeouts applied
+    pub fn connect(&self, addr: SocketAddr) -> io::Result<TcpStream> {
+        let stream = TcpStream::connect_timeout(&addr, self.connect)?;
+        self.apply(&stream)?;
+
+        Ok(stream)
+    }
+
+    pub fn apply(&self, stream: &TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(self.read)?;
+        stream.set_write_timeout(self.write)
+    }
+}
@@ -0,0 +1,43 @@
+use std::convert::TryFrom;
+
+/// Format a unix timestamp as a UTC date and time
+pub fn format_time(time: u64) -> String {
+    let (days, secs) = ((time / 86400) as i64, time % 86400);
+
+    // converts days since the epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year, month, day, secs / 3600, secs / 60 % 60, secs % 60
+    )
+}
+
+/// Get the unix timestamp of a UTC date and time
+pub fn from_civil(year: i64, month: i64, day: i64, hour: i64, minute: i64, second: i64) -> Option<u64> {
+    // the inverse of the conversion above, from the same source
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    u64::try_from(days * 86400 + hour * 3600 + minute * 60 + second).ok()
+}
+
+/// Parse the `YYYYMMDDHHMMSS` timestamp given back by the FTP `MDTM` command
+pub fn parse_mdtm(time: &str) -> Option<u64> {
+    let digits = |range: std::ops::Range<usize>| time.get(range)?.parse::<i64>().ok();
+
+    from_civil(digits(0..4)?, digits(4..6)?, digits(6..8)?, digits(8..10)?, digits(10..12)?, digits(12..14)?)
+}
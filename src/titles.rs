@@ -0,0 +1,117 @@
+use owo_colors::OwoColorize;
+
+/// Title IDs of games people commonly write plugins for, along with their names
+static KNOWN_TITLES: &[(&str, &str)] = &[
+    ("01006A800016E000", "Super Smash Bros. Ultimate"),
+    ("0100000000010000", "Super Mario Odyssey"),
+    ("010028600EBDA000", "Super Mario 3D World + Bowser's Fury"),
+    ("01009B90006DC000", "Super Mario Maker 2"),
+    ("0100152000022000", "Mario Kart 8 Deluxe"),
+    ("01007EF00011E000", "The Legend of Zelda: Breath of the Wild"),
+    ("0100F2C0115B6000", "The Legend of Zelda: Tears of the Kingdom"),
+    ("01006F8002326000", "Animal Crossing: New Horizons"),
+    ("01003BC0000A0000", "Splatoon 2"),
+    ("0100C2500FC20000", "Splatoon 3"),
+    ("0100E95004038000", "Xenoblade Chronicles 2"),
+    ("0100ABF008968000", "Pokémon Sword"),
+    ("01008DB008C2C000", "Pokémon Shield"),
+    ("0100A3D008C5C000", "Pokémon Scarlet"),
+    ("01008F6008C5E000", "Pokémon Violet"),
+];
+
+/// Look up the name of a game from its title ID
+pub fn game_name(title_id: &str) -> Option<&'static str> {
+    KNOWN_TITLES.iter()
+        .find(|(id, _)| id.eq_ignore_ascii_case(title_id))
+        .map(|(_, name)| *name)
+}
+
+/// Check whether a name is formatted like a title ID
+pub fn is_title_id(name: &str) -> bool {
+    name.len() == 16 && name.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Short names people tend to use for games, mapped to their title IDs
+static ALIASES: &[(&str, &str)] = &[
+    ("smash", "01006A800016E000"),
+    ("ssbu", "01006A800016E000"),
+    ("ultimate", "01006A800016E000"),
+    ("smo", "0100000000010000"),
+    ("odyssey", "0100000000010000"),
+    ("mk8", "0100152000022000"),
+    ("mk8dx", "0100152000022000"),
+    ("botw", "01007EF00011E000"),
+    ("totk", "0100F2C0115B6000"),
+    ("acnh", "01006F8002326000"),
+    ("xc2", "0100E95004038000"),
+];
+
+/// Find every known game whose name or alias contains the query, ignoring case
+pub fn search(query: &str) -> Vec<(&'static str, &'static str)> {
+    let query = query.trim().to_lowercase();
+
+    KNOWN_TITLES.iter()
+        .filter(|(title_id, name)| {
+            name.to_lowercase().contains(&query)
+                || title_id.eq_ignore_ascii_case(&query)
+                || ALIASES.iter().any(|(alias, id)| id == title_id && alias.contains(&*query))
+        })
+        .copied()
+        .collect()
+}
+
+/// Turn a title ID or the name of a game into a title ID, for use as a command line parser
+pub fn resolve_title_id(game: &str) -> Result<String, String> {
+    if is_title_id(game) {
+        return Ok(game.to_uppercase());
+    }
+
+    let query = game.trim().to_lowercase();
+
+    // exact matches win, so a name which happens to contain another game's name still works
+    let exact = KNOWN_TITLES.iter()
+        .find(|(_, name)| name.to_lowercase() == query)
+        .map(|(title_id, _)| *title_id)
+        .or_else(|| ALIASES.iter().find(|(alias, _)| *alias == query).map(|(_, title_id)| *title_id));
+
+    if let Some(title_id) = exact {
+        return Ok(title_id.to_owned());
+    }
+
+    match &search(game)[..] {
+        [(title_id, _)] => Ok((*title_id).to_owned()),
+        [] => Err(format!(
+            "'{}' is neither a title ID nor a known game, use `cargo skyline games search` to find one", game
+        )),
+        matches => Err(format!(
+            "'{}' matches several games: {}",
+            game,
+            matches.iter().map(|(_, name)| *name).collect::<Vec<_>>().join(", ")
+        )),
+    }
+}
+
+/// Print every known game matching the query, or all of them without one
+pub fn games_search(query: Option<String>) -> crate::error::Result<()> {
+    let games = match &query {
+        Some(query) => search(query),
+        None => KNOWN_TITLES.to_vec(),
+    };
+
+    if games.is_empty() {
+        println!("No known games match '{}'", query.unwrap_or_default());
+        return Ok(());
+    }
+
+    for (title_id, name) in games {
+        let aliases: Vec<_> = ALIASES.iter().filter(|(_, id)| *id == title_id).map(|(alias, _)| *alias).collect();
+
+        if aliases.is_empty() {
+            println!("{}  {}", title_id, name);
+        } else {
+            println!("{}  {} {}", title_id, name, format!("({})", aliases.join(", ")).bright_black());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use std::fmt;
+
+#[cfg(feature = "usb")]
+pub use client::UsbClient;
+
+#[cfg(feature = "usb")]
+mod client;
+
+#[derive(Debug)]
+#[cfg_attr(not(feature = "usb"), allow(dead_code))]
+pub enum UsbError {
+    #[cfg(feature = "usb")]
+    Usb(rusb::Error),
+    NoDevice,
+    WrongVersion(u32),
+    BadResponse,
+    Failed(String),
+    NotFound(String),
+    BadName(String),
+    TooLarge(u64),
+    #[cfg(not(feature = "usb"))]
+    Unsupported,
+}
+
+#[cfg(feature = "usb")]
+impl From<rusb::Error> for UsbError {
+    fn from(err: rusb::Error) -> Self {
+        Self::Usb(err)
+    }
+}
+
+impl fmt::Display for UsbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            #[cfg(feature = "usb")]
+            Self::Usb(err) => write!(f, "UsbError: {}", err),
+            Self::NoDevice => write!(f, "No switch found, make sure it is plugged in and the cargo-skyline receiver is running"),
+            Self::WrongVersion(version) => write!(
+                f,
+                "The switch is running version {} of the cargo-skyline receiver, rebuild it from this version of cargo-skyline's receiver folder",
+                version
+            ),
+            Self::BadResponse => write!(f, "Malformed response"),
+            Self::Failed(message) => write!(f, "The switch reported an error: {}", message),
+            Self::NotFound(path) => write!(f, "'{}' does not exist on the switch", path),
+            Self::BadName(path) => write!(f, "'{}' is too long to send over USB", path),
+            Self::TooLarge(len) => write!(f, "{} bytes is too large to receive over USB", len),
+            #[cfg(not(feature = "usb"))]
+            Self::Unsupported => write!(
+                f,
+                "cargo-skyline was built without USB support, reinstall it with `cargo install cargo-skyline --features usb`"
+            ),
+        }
+    }
+}
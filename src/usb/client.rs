@@ -0,0 +1,383 @@
+use rusb::{Direction, TransferType, DeviceHandle, GlobalContext};
+use std::convert::{TryFrom, TryInto};
+use std::time::Duration;
+use indicatif::ProgressBar;
+use crate::installer::FileInfo;
+use crate::progress;
+use super::UsbError;
+
+type Result<T> = std::result::Result<T, UsbError>;
+
+// the receiver keeps libnx's usbComms defaults, a vendor specific interface with a bulk endpoint
+// each way under Nintendo's vendor id
+const VENDOR_ID: u16 = 0x057E;
+const PRODUCT_ID: u16 = 0x3000;
+const VENDOR_SPECIFIC_CLASS: u8 = 0xFF;
+
+/// Commands and responses are each sent as a block of exactly this many bytes, with any file
+/// contents following separately
+const BLOCK_LEN: usize = 0x1000;
+
+const CHUNK_SIZE: usize = 0x10_0000;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+const COMMAND_MAGIC: [u8; 4] = *b"SKYI";
+const RESPONSE_MAGIC: [u8; 4] = *b"SKYO";
+
+/// Bumped whenever the commands change, so an old receiver is caught before it gets confused
+const PROTOCOL_VERSION: u32 = 1;
+
+// largest file or listing accepted from the switch, so a corrupt length can't make us allocate
+// gigabytes
+const MAX_DOWNLOAD: u64 = 0x4000_0000;
+
+#[derive(Copy, Clone)]
+enum Command {
+    Version = 0,
+    MakeDir = 1,
+    Put = 2,
+    Stat = 3,
+    List = 4,
+    Rename = 5,
+    Remove = 6,
+    Get = 7,
+    FreeSpace = 8,
+}
+
+// what Stat says is at a path
+const MISSING: u32 = 0;
+const DIRECTORY: u32 = 2;
+
+/// A command block being filled in with arguments
+struct Request {
+    block: Vec<u8>,
+}
+
+impl Request {
+    fn new(command: Command) -> Self {
+        let mut block = Vec::with_capacity(BLOCK_LEN);
+        block.extend_from_slice(&COMMAND_MAGIC);
+        block.extend_from_slice(&(command as u32).to_le_bytes());
+
+        Request { block }
+    }
+
+    fn u64(mut self, value: u64) -> Self {
+        self.block.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    /// Add a path on the SD card, as a u32 length followed by UTF-8
+    fn path(mut self, path: &str) -> Result<Self> {
+        let path = if path.starts_with('/') { path.to_owned() } else { format!("/{}", path) };
+
+        let len = u32::try_from(path.len()).map_err(|_| UsbError::BadName(path.clone()))?;
+        if self.block.len() + 4 + path.len() > BLOCK_LEN {
+            return Err(UsbError::BadName(path))
+        }
+
+        self.block.extend_from_slice(&len.to_le_bytes());
+        self.block.extend_from_slice(path.as_bytes());
+
+        Ok(self)
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.block.resize(BLOCK_LEN, 0);
+        self.block
+    }
+}
+
+/// Reads the results out of a response block, in the order the receiver wrote them
+struct Response {
+    block: Vec<u8>,
+    offset: usize,
+}
+
+impl Response {
+    /// Check a response block is well formed and reports success
+    fn parse(block: Vec<u8>) -> Result<Self> {
+        if block.len() != BLOCK_LEN || block[..4] != RESPONSE_MAGIC {
+            return Err(UsbError::BadResponse)
+        }
+
+        let mut response = Response { block, offset: 4 };
+        match response.u32()? {
+            0 => Ok(response),
+            _ => Err(UsbError::Failed(response.string()?))
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&[u8]> {
+        let bytes = self.block.get(self.offset..self.offset + len).ok_or(UsbError::BadResponse)?;
+        self.offset += len;
+
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.u32()? as usize;
+
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+}
+
+/// A client for the cargo-skyline receiver (in `receiver/`), a homebrew app which carries out
+/// file operations on the SD card for commands sent to it over USB
+///
+/// The protocol follows Goldleaf's: a fixed size command block holding a magic, the command and
+/// its arguments, answered by a response block holding a result and any return values. File
+/// contents are streamed after the block rather than inside it. Uploads get an extra response
+/// before the contents, so a file that can't be opened is reported before any of it is sent.
+pub struct UsbClient {
+    handle: DeviceHandle<GlobalContext>,
+    interface: u8,
+    in_endpoint: u8,
+    out_endpoint: u8,
+    show_progress: bool,
+}
+
+impl UsbClient {
+    pub fn connect() -> Result<Self> {
+        let (device, interface, in_endpoint, out_endpoint) = rusb::devices()?
+            .iter()
+            .filter(|device| {
+                device.device_descriptor()
+                    .map(|desc| desc.vendor_id() == VENDOR_ID && desc.product_id() == PRODUCT_ID)
+                    .unwrap_or(false)
+            })
+            .find_map(|device| {
+                let config = device.active_config_descriptor().ok()?;
+
+                let found = config.interfaces()
+                    .flat_map(|interface| interface.descriptors())
+                    .filter(|desc| desc.class_code() == VENDOR_SPECIFIC_CLASS)
+                    .find_map(|desc| {
+                        let bulk = |dir| desc.endpoint_descriptors()
+                            .find(|ep| ep.transfer_type() == TransferType::Bulk && ep.direction() == dir);
+
+                        Some((desc.interface_number(), bulk(Direction::In)?.address(), bulk(Direction::Out)?.address()))
+                    });
+
+                found.map(|(interface, input, output)| (device, interface, input, output))
+            })
+            .ok_or(UsbError::NoDevice)?;
+
+        let handle = device.open()?;
+
+        // not every platform lets another process' driver be detached, which is fine if there isn't one
+        let _ = handle.set_auto_detach_kernel_driver(true);
+        handle.claim_interface(interface)?;
+
+        let mut client = UsbClient { handle, interface, in_endpoint, out_endpoint, show_progress: false };
+
+        let version = client.request(Request::new(Command::Version))?.u32()?;
+        if version != PROTOCOL_VERSION {
+            return Err(UsbError::WrongVersion(version))
+        }
+
+        Ok(client)
+    }
+
+    /// Show a progress bar while uploading files
+    pub fn set_show_progress(&mut self, show: bool) {
+        self.show_progress = show;
+    }
+
+    fn write(&mut self, data: &[u8], bar: &ProgressBar) -> Result<()> {
+        for chunk in data.chunks(CHUNK_SIZE) {
+            let mut written = 0;
+            while written < chunk.len() {
+                written += self.handle.write_bulk(self.out_endpoint, &chunk[written..], TIMEOUT)?;
+            }
+            bar.inc(chunk.len() as u64);
+        }
+
+        Ok(())
+    }
+
+    /// Read exactly `len` bytes, which the receiver always sends without any padding
+    fn read(&mut self, len: usize) -> Result<Vec<u8>> {
+        let mut data = vec![0; len];
+
+        let mut read = 0;
+        while read < len {
+            let end = (read + CHUNK_SIZE).min(len);
+            match self.handle.read_bulk(self.in_endpoint, &mut data[read..end], TIMEOUT)? {
+                0 => return Err(UsbError::BadResponse),
+                count => read += count,
+            }
+        }
+
+        Ok(data)
+    }
+
+    fn response(&mut self) -> Result<Response> {
+        Response::parse(self.read(BLOCK_LEN)?)
+    }
+
+    fn request(&mut self, request: Request) -> Result<Response> {
+        self.write(&request.finish(), &ProgressBar::hidden())?;
+
+        self.response()
+    }
+
+    /// Read the contents of a file or listing, which is sent as its length and then the bytes
+    fn download(&mut self, response: &mut Response) -> Result<Vec<u8>> {
+        let len = response.u64()?;
+        if len > MAX_DOWNLOAD {
+            return Err(UsbError::TooLarge(len))
+        }
+
+        self.read(len as usize)
+    }
+
+    /// Create a folder and any missing parents
+    pub fn mkdir<S: AsRef<str>>(&mut self, dir: S) -> Result<()> {
+        self.request(Request::new(Command::MakeDir).path(dir.as_ref())?).map(|_| ())
+    }
+
+    pub fn put<S: AsRef<str>, D: AsRef<[u8]>>(&mut self, path: S, file: D) -> Result<()> {
+        let file = file.as_ref();
+
+        // the receiver answers once the file is open, and again once it's all written
+        self.request(Request::new(Command::Put).path(path.as_ref())?.u64(file.len() as u64))?;
+
+        let bar = progress::transfer_bar(file.len() as u64, self.show_progress);
+        self.write(file, &bar)?;
+        bar.finish_and_clear();
+
+        self.response().map(|_| ())
+    }
+
+    fn kind(&mut self, path: &str) -> Result<(u32, Response)> {
+        let mut response = self.request(Request::new(Command::Stat).path(path)?)?;
+
+        Ok((response.u32()?, response))
+    }
+
+    pub fn file_exists<S: AsRef<str>>(&mut self, path: S) -> Result<bool> {
+        Ok(self.kind(path.as_ref())?.0 != MISSING)
+    }
+
+    pub fn get(&mut self, path: &str) -> Result<Vec<u8>> {
+        let mut response = self.request(Request::new(Command::Get).path(path)?)?;
+        let file = self.download(&mut response)?;
+
+        // reading the file can still fail part way, which the receiver says after the contents
+        self.response()?;
+
+        Ok(file)
+    }
+
+    pub fn is_dir(&mut self, path: &str) -> Result<bool> {
+        Ok(self.kind(path)?.0 == DIRECTORY)
+    }
+
+    pub fn stat(&mut self, path: &str) -> Result<FileInfo> {
+        let (kind, mut response) = self.kind(path)?;
+        if kind == MISSING {
+            return Err(UsbError::NotFound(path.to_owned()))
+        }
+
+        let size = response.u64()?;
+        let modified = response.u64()?;
+
+        Ok(FileInfo {
+            is_dir: kind == DIRECTORY,
+            size: Some(size).filter(|_| kind != DIRECTORY),
+            modified: Some(modified).filter(|&time| time != 0),
+        })
+    }
+
+    pub fn remove(&mut self, path: &str) -> Result<()> {
+        self.request(Request::new(Command::Remove).path(path)?).map(|_| ())
+    }
+
+    /// Move a file into place, replacing anything already there
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        self.request(Request::new(Command::Rename).path(from)?.path(to)?).map(|_| ())
+    }
+
+    pub fn ls(&mut self, dir: &str) -> Result<String> {
+        let mut response = self.request(Request::new(Command::List).path(dir)?)?;
+        let names = self.download(&mut response)?;
+
+        String::from_utf8(names).map_err(|_| UsbError::BadResponse)
+    }
+
+    /// Bytes free on the SD card
+    pub fn free_space(&mut self) -> Result<u64> {
+        self.request(Request::new(Command::FreeSpace))?.u64()
+    }
+}
+
+impl Drop for UsbClient {
+    fn drop(&mut self) {
+        let _ = self.handle.release_interface(self.interface);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: u32, results: &[u8]) -> Vec<u8> {
+        let mut block = [&RESPONSE_MAGIC[..], &status.to_le_bytes(), results].concat();
+        block.resize(BLOCK_LEN, 0);
+        block
+    }
+
+    #[test]
+    fn command_blocks() {
+        let block = Request::new(Command::Put).path("atmosphere/a.nro").unwrap().u64(5).finish();
+
+        assert_eq!(block.len(), BLOCK_LEN);
+        assert_eq!(&block[..4], b"SKYI");
+        assert_eq!(block[4..8], 2u32.to_le_bytes());
+        assert_eq!(block[8..12], 17u32.to_le_bytes());
+        assert_eq!(&block[12..29], b"/atmosphere/a.nro");
+        assert_eq!(block[29..37], 5u64.to_le_bytes());
+        assert!(block[37..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn paths_must_fit() {
+        let long = "a".repeat(BLOCK_LEN);
+
+        assert!(matches!(Request::new(Command::Stat).path(&long), Err(UsbError::BadName(_))));
+        assert!(Request::new(Command::Stat).path(&long[..BLOCK_LEN - 13]).is_ok());
+    }
+
+    #[test]
+    fn parse_responses() {
+        let results = [&DIRECTORY.to_le_bytes()[..], &7u64.to_le_bytes()].concat();
+        let mut parsed = Response::parse(response(0, &results)).unwrap();
+        assert_eq!(parsed.u32().unwrap(), DIRECTORY);
+        assert_eq!(parsed.u64().unwrap(), 7);
+
+        let message = [&4u32.to_le_bytes()[..], b"full"].concat();
+        assert!(matches!(Response::parse(response(28, &message)), Err(UsbError::Failed(message)) if message == "full"));
+    }
+
+    #[test]
+    fn rejects_bad_responses() {
+        let mut block = response(0, &[]);
+        block[0] = b'X';
+        assert!(matches!(Response::parse(block), Err(UsbError::BadResponse)));
+
+        assert!(matches!(Response::parse(vec![0; 16]), Err(UsbError::BadResponse)));
+
+        // a message length running past the end of the block
+        let message = u32::MAX.to_le_bytes();
+        assert!(matches!(Response::parse(response(1, &message)), Err(UsbError::BadResponse)));
+    }
+}
@@ -0,0 +1,454 @@
+use std::path::{Path, PathBuf};
+use owo_colors::OwoColorize;
+use toml::Value;
+use crate::error::{Error, Result};
+use crate::titles::is_title_id;
+use crate::wsl;
+
+/// What each key in `[package.metadata.skyline]` should hold
+#[derive(Copy, Clone)]
+enum Expected {
+    Str,
+    Bool,
+    TitleIds,
+    SubsdkSlot,
+    Strings,
+    /// A file which should exist, relative to the package
+    File,
+    /// A table of remote paths to files, which are either urls or local files
+    Sources,
+    /// A table of remote paths to local directories
+    Dirs,
+    /// A table of exefs file names to 'skyline', 'plugin' or a local file
+    ExefsFiles,
+    /// The name, author, version and icon of homebrew
+    Nacp,
+    DepList,
+    DepTable,
+    FtpMode,
+    InstallPath,
+    /// A file name for the plugin, which Skyline needs to end in .nro
+    NroName,
+    /// A table of title ids to install paths
+    InstallPaths,
+    Layout,
+    Version,
+    /// A table of title ids or game names to features
+    TitleFeatures,
+    /// `rustflags` and `link-args` to build with
+    BuildFlags,
+    /// A number of bytes, or a string such as "512K" or "1.5 MiB"
+    Size,
+}
+
+static KEYS: &[(&str, Expected)] = &[
+    ("titleid", Expected::TitleIds),
+    ("display-name", Expected::Str),
+    ("nro-name", Expected::NroName),
+    ("install-dir", Expected::InstallPath),
+    ("plugin-dirs", Expected::InstallPaths),
+    ("plugin-subdir", Expected::Str),
+    ("layout", Expected::Layout),
+    ("features", Expected::TitleFeatures),
+    ("install-features", Expected::Strings),
+    ("no-default-features", Expected::Bool),
+    ("custom-npdm", Expected::File),
+    ("target", Expected::File),
+    ("build", Expected::BuildFlags),
+    ("max-nro-size", Expected::Size),
+    ("deny-oversized", Expected::Bool),
+    ("build-info", Expected::Bool),
+    ("subsdk-name", Expected::Str),
+    ("subsdk-slot", Expected::SubsdkSlot),
+    ("mod-name", Expected::Str),
+    ("ftp-port", Expected::Str),
+    ("ftp-user", Expected::Str),
+    ("ftp-pass", Expected::Str),
+    ("ftp-mode", Expected::FtpMode),
+    ("skyline-release", Expected::Str),
+    ("min-skyline-version", Expected::Version),
+    ("device", Expected::Str),
+    ("skip", Expected::Bool),
+    ("ip", Expected::Str),
+    ("plugin-dependencies", Expected::DepList),
+    ("dependencies", Expected::DepTable),
+    ("protected-plugins", Expected::Strings),
+    ("deploy", Expected::Sources),
+    ("assets", Expected::Dirs),
+    ("exefs", Expected::ExefsFiles),
+    ("nacp", Expected::Nacp),
+];
+
+/// A problem found in a manifest, pointing at the key it's about
+struct Problem {
+    key: String,
+    message: String,
+    warning: bool,
+}
+
+fn problem(key: &str, message: impl Into<String>) -> Problem {
+    Problem { key: key.to_owned(), message: message.into(), warning: false }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::String(_) => "a string",
+        Value::Integer(_) => "an integer",
+        Value::Float(_) => "a float",
+        Value::Boolean(_) => "a boolean",
+        Value::Datetime(_) => "a date",
+        Value::Array(_) => "an array",
+        Value::Table(_) => "a table",
+    }
+}
+
+fn check_title_id(key: &str, title_id: &str, problems: &mut Vec<Problem>) {
+    if !is_title_id(title_id) {
+        problems.push(problem(key, format!("'{}' is not a title id, which should be 16 hex digits", title_id)));
+    }
+}
+
+fn check(key: &str, expected: Expected, value: &Value, package_dir: &Path, problems: &mut Vec<Problem>) {
+    let wrong_type = |what: &str| problem(key, format!("expected {}, found {}", what, type_name(value)));
+
+    match (expected, value) {
+        (Expected::Str, Value::String(_)) => {}
+        (Expected::Str, _) => problems.push(wrong_type("a string")),
+
+        (Expected::Bool, Value::Boolean(_)) => {}
+        (Expected::Bool, _) => problems.push(wrong_type("a boolean")),
+
+        (Expected::TitleIds, Value::String(title_id)) => check_title_id(key, title_id, problems),
+        (Expected::TitleIds, Value::Array(title_ids)) => {
+            for title_id in title_ids {
+                match title_id {
+                    Value::String(title_id) => check_title_id(key, title_id, problems),
+                    other => problems.push(problem(key, format!("expected title ids to be strings, found {}", type_name(other)))),
+                }
+            }
+        }
+        (Expected::TitleIds, _) => problems.push(wrong_type("a string or an array of strings")),
+
+        (Expected::SubsdkSlot, Value::Integer(0..=9)) => {}
+        (Expected::SubsdkSlot, Value::Integer(slot)) => {
+            problems.push(problem(key, format!("subsdk slot {} doesn't exist, it should be between 0 and 9", slot)))
+        }
+        (Expected::SubsdkSlot, Value::String(_)) => {}
+        (Expected::SubsdkSlot, _) => problems.push(wrong_type("an integer")),
+
+        (Expected::Strings, Value::Array(values)) => {
+            if let Some(other) = values.iter().find(|value| !value.is_str()) {
+                problems.push(problem(key, format!("expected an array of strings, found {} in it", type_name(other))));
+            }
+        }
+        (Expected::Strings, _) => problems.push(wrong_type("an array of strings")),
+
+        (Expected::File, Value::String(path)) => {
+            let path = package_dir.join(wsl::translate_path(path));
+            if !path.is_file() {
+                problems.push(problem(key, format!("'{}' doesn't exist", path.display())));
+            }
+        }
+        (Expected::File, _) => problems.push(wrong_type("a path")),
+
+        (Expected::Sources, Value::Table(files)) => {
+            for (path, source) in files {
+                match source {
+                    Value::String(source) if source.starts_with("http://") || source.starts_with("https://") => {}
+                    Value::String(source) => {
+                        let source = package_dir.join(wsl::translate_path(source));
+                        if !source.exists() {
+                            let mut problem = problem(key, format!("'{}' for '{}' doesn't exist", source.display(), path));
+                            // deploy often lists build output, which won't be there until it's built
+                            problem.warning = true;
+                            problems.push(problem);
+                        }
+                    }
+                    other => problems.push(problem(key, format!("expected '{}' to be a url or path, found {}", path, type_name(other)))),
+                }
+            }
+        }
+        (Expected::Sources, _) => problems.push(wrong_type("a table of paths")),
+
+        (Expected::Dirs, Value::Table(dirs)) => {
+            for (path, source) in dirs {
+                match source {
+                    Value::String(source) => {
+                        let source = package_dir.join(wsl::translate_path(source));
+                        if !source.is_dir() {
+                            problems.push(problem(key, format!("'{}' for '{}' isn't a directory", source.display(), path)));
+                        }
+                    }
+                    other => problems.push(problem(key, format!("expected '{}' to be a directory, found {}", path, type_name(other)))),
+                }
+            }
+        }
+        (Expected::Dirs, _) => problems.push(wrong_type("a table of directories")),
+
+        (Expected::ExefsFiles, Value::Table(files)) => {
+            for (name, source) in files {
+                if !crate::cargo_info::EXEFS_NAMES.contains(&name.as_str()) {
+                    problems.push(problem(key, format!("'{}' isn't loaded from an exefs, expected one of {}", name, crate::cargo_info::EXEFS_NAMES.join(", "))));
+                }
+
+                match source {
+                    Value::String(source) if source == "skyline" || source == "plugin" => {}
+                    Value::String(source) => {
+                        let source = package_dir.join(wsl::translate_path(source));
+                        if !source.is_file() {
+                            problems.push(problem(key, format!("'{}' for '{}' doesn't exist", source.display(), name)));
+                        }
+                    }
+                    other => problems.push(problem(key, format!("expected '{}' to be 'skyline', 'plugin' or a path, found {}", name, type_name(other)))),
+                }
+            }
+        }
+        (Expected::ExefsFiles, _) => problems.push(wrong_type("a table of exefs files")),
+
+        (Expected::Nacp, Value::Table(fields)) => {
+            for (name, value) in fields {
+                match (name.as_str(), value) {
+                    ("icon", Value::String(icon)) => {
+                        let icon = package_dir.join(wsl::translate_path(icon));
+                        if !icon.is_file() {
+                            problems.push(problem(key, format!("icon '{}' doesn't exist", icon.display())));
+                        }
+                    }
+                    ("name" | "author" | "version", Value::String(_)) => {}
+                    ("name" | "author" | "version" | "icon", other) => {
+                        problems.push(problem(key, format!("expected '{}' to be a string, found {}", name, type_name(other))))
+                    }
+                    _ => {
+                        let mut unknown = problem(key, format!("unknown key '{}', expected 'name', 'author', 'version' or 'icon'", name));
+                        unknown.warning = true;
+                        problems.push(unknown);
+                    }
+                }
+            }
+        }
+        (Expected::Nacp, _) => problems.push(wrong_type("a table")),
+
+        (Expected::DepList, Value::Array(deps)) => {
+            for dep in deps {
+                let table = match dep.as_table() {
+                    Some(table) => table,
+                    None => {
+                        problems.push(problem(key, format!("expected each dependency to be a table, found {}", type_name(dep))));
+                        continue;
+                    }
+                };
+
+                for required in &["name", "url"] {
+                    if !table.get(*required).is_some_and(Value::is_str) {
+                        problems.push(problem(key, format!("each dependency needs a '{}' string", required)));
+                    }
+                }
+            }
+        }
+        (Expected::DepList, _) => problems.push(wrong_type("an array of tables")),
+
+        (Expected::DepTable, Value::Table(deps)) => {
+            for (name, dep) in deps {
+                match dep {
+                    Value::String(_) => {}
+                    Value::Table(dep) if dep.get("url").is_some_and(Value::is_str) => {}
+                    Value::Table(_) => problems.push(problem(key, format!("dependency '{}' needs a 'url' string", name))),
+                    other => problems.push(problem(key, format!("expected dependency '{}' to be a url or table, found {}", name, type_name(other)))),
+                }
+            }
+        }
+        (Expected::DepTable, _) => problems.push(wrong_type("a table of dependencies")),
+
+        (Expected::FtpMode, Value::String(mode)) => {
+            if mode.parse::<crate::ftp::DataMode>().is_err() {
+                problems.push(problem(key, format!("unknown mode '{}', expected 'passive', 'epsv', 'active' or 'eprt'", mode)));
+            }
+        }
+        (Expected::FtpMode, _) => problems.push(wrong_type("a string")),
+
+        (Expected::InstallPath, Value::String(path)) => {
+            if !path.starts_with("rom:/") && !path.starts_with("sd:/") {
+                problems.push(problem(key, format!("'{}' should start with 'rom:/' or 'sd:/'", path)));
+            }
+        }
+        (Expected::InstallPath, _) => problems.push(wrong_type("a string")),
+
+        (Expected::InstallPaths, Value::Table(dirs)) => {
+            for (title_id, dir) in dirs {
+                check_title_id(key, title_id, problems);
+                check(key, Expected::InstallPath, dir, package_dir, problems);
+            }
+        }
+        (Expected::InstallPaths, _) => problems.push(wrong_type("a table of title ids to install paths")),
+
+        (Expected::NroName, Value::String(name)) => {
+            if crate::cargo_info::check_nro_name(name).is_err() {
+                problems.push(problem(key, format!("'{}' should be a file name ending in .nro, such as 'libmy_plugin.nro'", name)));
+            } else if !name.starts_with("lib") {
+                let mut problem = problem(key, format!("'{}' doesn't start with 'lib' like the plugins cargo builds", name));
+                problem.warning = true;
+                problems.push(problem);
+            }
+        }
+        (Expected::NroName, _) => problems.push(wrong_type("a string")),
+
+        (Expected::Layout, Value::String(layout)) => {
+            if layout.parse::<crate::game_paths::GameLayout>().is_err() {
+                problems.push(problem(key, format!("unknown layout '{}', expected 'romfs', 'exefs' or 'npdm-patch'", layout)));
+            }
+        }
+        (Expected::Layout, _) => problems.push(wrong_type("a string")),
+
+        (Expected::Version, Value::String(version)) => {
+            if version.parse::<crate::skyline_version::Version>().is_err() {
+                problems.push(problem(key, format!("'{}' is not a version, such as '1.2.0'", version)));
+            }
+        }
+        (Expected::Version, _) => problems.push(wrong_type("a string")),
+
+        (Expected::TitleFeatures, Value::Table(games)) => {
+            for (game, features) in games {
+                if let Err(err) = crate::titles::resolve_title_id(game) {
+                    problems.push(problem(key, err));
+                }
+
+                match features {
+                    Value::String(_) => {}
+                    Value::Array(features) if features.iter().all(Value::is_str) => {}
+                    other => problems.push(problem(key, format!("expected the features for '{}' to be a string or an array of strings, found {}", game, type_name(other)))),
+                }
+            }
+        }
+        (Expected::TitleFeatures, _) => problems.push(wrong_type("a table of title ids to features")),
+
+        (Expected::BuildFlags, Value::Table(flags)) => {
+            for (name, value) in flags {
+                match (name.as_str(), value) {
+                    ("rustflags" | "link-args", Value::Array(values)) if values.iter().all(Value::is_str) => {}
+                    ("rustflags" | "link-args", other) => {
+                        problems.push(problem(key, format!("expected '{}' to be an array of strings, found {}", name, type_name(other))))
+                    }
+                    _ => {
+                        let mut unknown = problem(key, format!("unknown key '{}', expected 'rustflags' or 'link-args'", name));
+                        unknown.warning = true;
+                        problems.push(unknown);
+                    }
+                }
+            }
+        }
+        (Expected::BuildFlags, _) => problems.push(wrong_type("a table")),
+
+        (Expected::Size, Value::Integer(size)) if *size > 0 => {}
+        (Expected::Size, Value::Integer(size)) => problems.push(problem(key, format!("{} isn't a size, it should be more than 0", size))),
+        (Expected::Size, Value::String(size)) => {
+            if crate::cargo_info::parse_size(size).is_none() {
+                problems.push(problem(key, format!("'{}' isn't a size, such as 524288, '512K' or '1.5 MiB'", size)));
+            }
+        }
+        (Expected::Size, _) => problems.push(wrong_type("a number of bytes or a string")),
+    }
+}
+
+/// Find the line and column of a key in the skyline metadata, looking for both `key = ` inside
+/// `[package.metadata.skyline]` and subtables like `[package.metadata.skyline.key]`
+fn locate(text: &str, key: &str) -> Option<(usize, usize)> {
+    let mut in_skyline = false;
+
+    for (number, line) in text.lines().enumerate() {
+        let trimmed = line.trim_start();
+        let column = line.len() - trimmed.len() + 1;
+
+        if trimmed.starts_with('[') {
+            let header: String = trimmed.chars().filter(|c| !matches!(c, '[' | ']' | ' ' | '"')).collect();
+            let header = header.split('#').next().unwrap_or("");
+
+            if header == format!("package.metadata.skyline.{}", key) {
+                return Some((number + 1, column));
+            }
+
+            in_skyline = header == "package.metadata.skyline";
+            continue;
+        }
+
+        if in_skyline {
+            let name = trimmed.split('=').next().unwrap_or("").trim().trim_matches('"');
+            if name == key && trimmed.contains('=') {
+                return Some((number + 1, column));
+            }
+        }
+    }
+
+    None
+}
+
+fn validate_manifest(manifest_path: &Path, problems: &mut Vec<(PathBuf, usize, usize, Problem)>) -> Result<()> {
+    let text = std::fs::read_to_string(manifest_path)?;
+    let manifest: Value = match toml::from_str(&text) {
+        Ok(manifest) => manifest,
+        // cargo metadata would have already failed if it was invalid
+        Err(_) => return Err(Error::BadConfigFile(manifest_path.to_owned())),
+    };
+
+    let skyline = match manifest.get("package").and_then(|package| package.get("metadata")).and_then(|md| md.get("skyline")) {
+        Some(Value::Table(skyline)) => skyline,
+        Some(other) => {
+            let (line, column) = locate(&text, "").unwrap_or((1, 1));
+            problems.push((manifest_path.to_owned(), line, column, problem("", format!("expected a table, found {}", type_name(other)))));
+            return Ok(());
+        }
+        None => return Ok(())
+    };
+
+    let package_dir = manifest_path.parent().unwrap_or(Path::new("."));
+    let mut found = vec![];
+
+    for (key, value) in skyline {
+        match KEYS.iter().find(|(known, _)| known == key) {
+            Some((_, expected)) => check(key, *expected, value, package_dir, &mut found),
+            None => {
+                let mut unknown = problem(key, format!("unknown key '{}'", key));
+                unknown.warning = true;
+                found.push(unknown);
+            }
+        }
+    }
+
+    for problem in found {
+        let (line, column) = locate(&text, &problem.key).unwrap_or((1, 1));
+        problems.push((manifest_path.to_owned(), line, column, problem));
+    }
+
+    Ok(())
+}
+
+/// Check the skyline metadata of every package in the workspace, printing anything wrong with it
+pub fn validate() -> Result<()> {
+    let metadata = cargo_metadata::MetadataCommand::new().no_deps().exec()?;
+
+    let mut problems = vec![];
+    for package in metadata.packages.iter().filter(|package| metadata.workspace_members.contains(&package.id)) {
+        validate_manifest(&package.manifest_path, &mut problems)?;
+    }
+
+    problems.sort_by(|a, b| (&a.0, a.1, a.2).cmp(&(&b.0, b.1, b.2)));
+
+    let errors = problems.iter().filter(|(_, _, _, problem)| !problem.warning).count();
+
+    for (path, line, column, problem) in &problems {
+        let level = if problem.warning {
+            "warning".yellow().to_string()
+        } else {
+            "error".red().to_string()
+        };
+
+        println!("{}:{}:{}: {}: {}", path.display(), line, column, level, problem.message);
+    }
+
+    if errors > 0 {
+        return Err(Error::InvalidMetadata(errors));
+    }
+
+    println!("{}", "Metadata is valid".green());
+
+    Ok(())
+}
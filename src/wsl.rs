@@ -0,0 +1,97 @@
+//! Help for building inside WSL while managing the console from Windows
+use std::path::PathBuf;
+use std::process::Command;
+use crate::error::{Error, Result};
+
+/// Whether this is running inside the Windows Subsystem for Linux
+pub fn is_wsl() -> bool {
+    cfg!(target_os = "linux") && (
+        std::env::var_os("WSL_DISTRO_NAME").is_some()
+            || std::fs::read_to_string("/proc/sys/kernel/osrelease")
+                .map(|release| release.to_ascii_lowercase().contains("microsoft"))
+                .unwrap_or(false)
+    )
+}
+
+/// Turn a Windows path such as `C:\Users\me\sd` or `\\wsl$\Ubuntu\home\me` into the path WSL
+/// mounts it at, leaving anything else as it is
+pub fn translate_path(path: &str) -> PathBuf {
+    if !is_wsl() {
+        return PathBuf::from(path);
+    }
+
+    let bytes = path.as_bytes();
+    if bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && (bytes[2] == b'\\' || bytes[2] == b'/') {
+        let drive = (bytes[0] as char).to_ascii_lowercase();
+        return PathBuf::from(format!("/mnt/{}/{}", drive, path[3..].replace('\\', "/")));
+    }
+
+    // paths into this distro from the Windows side
+    for prefix in &["\\\\wsl$\\", "\\\\wsl.localhost\\"] {
+        if let Some(rest) = path.strip_prefix(prefix) {
+            let rest = rest.split_once('\\').map(|(_, rest)| rest).unwrap_or("");
+            return PathBuf::from(format!("/{}", rest.replace('\\', "/")));
+        }
+    }
+
+    PathBuf::from(path)
+}
+
+/// The home directory of the Windows user, as seen from WSL
+fn windows_home() -> Option<PathBuf> {
+    let mut command = Command::new("cmd.exe");
+    command.args(["/c", "echo %USERPROFILE%"]);
+
+    // cmd.exe complains when started from a directory inside the distro
+    if std::path::Path::new("/mnt/c").is_dir() {
+        command.current_dir("/mnt/c");
+    }
+
+    let output = command.output().ok()?;
+
+    let home = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    if !output.status.success() || home.is_empty() || home.contains('%') {
+        return None;
+    }
+
+    Some(translate_path(&home))
+}
+
+/// Replace `~/.switch` with a link to the Windows user's `.switch`, so both sides share the same
+/// settings, devices and caches
+#[cfg(unix)]
+pub fn share(force: bool) -> Result<()> {
+    if !is_wsl() {
+        return Err(Error::NotWsl);
+    }
+
+    let windows = windows_home().ok_or(Error::NoWindowsHome)?.join(".switch");
+    let linux = dirs::home_dir().ok_or(Error::NoHomeDir)?.join(".switch");
+
+    if std::fs::read_link(&linux).ok().as_ref() == Some(&windows) {
+        println!("{} is already shared with {}", linux.display(), windows.display());
+        return Ok(());
+    }
+
+    if std::fs::symlink_metadata(&linux).is_ok() {
+        if !force {
+            return Err(Error::SwitchDirExists(linux));
+        }
+
+        let backup = linux.with_file_name(".switch.bak");
+        std::fs::rename(&linux, &backup)?;
+        println!("Moved the existing config to {}", backup.display());
+    }
+
+    std::fs::create_dir_all(&windows)?;
+    std::os::unix::fs::symlink(&windows, &linux)?;
+
+    println!("{} now shares the config in {}", linux.display(), windows.display());
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn share(_force: bool) -> Result<()> {
+    Err(Error::NotWsl)
+}